@@ -0,0 +1,599 @@
+// This file is part auf the dual-kawase-demo package.
+//
+// (c) 2019 Bernd Busse
+//
+// For the full copyright and license information, please view the README.md file
+// that was distributed with this source code.
+//
+
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::blur::{BlurContext, MAX_ITERATIONS};
+use crate::encoder::{self, Completion, EncoderPool};
+use crate::export;
+use crate::image_io::{self, LoadedImage};
+use crate::kernel::UserKernel;
+use crate::options::Options;
+use crate::preset::{self, Preset};
+use crate::renderer_gl::{Error, Pixels, ReadbackQueue, Result};
+use crate::shader_source::ShaderSource;
+
+/// Blur parameters of batch results if none are given.
+const DEFAULT_ITERATIONS: u32 = 4;
+const DEFAULT_OFFSET: f32 = 3.0;
+
+/// File extensions of images picked up from directories.
+const IMAGE_EXTENSIONS: &[&str] =
+    &["png", "jpg", "jpeg", "tif", "tiff", "webp", "bmp", "tga", "hdr", "exr"];
+
+/// Template of the file names of batch results.
+///
+/// `{stem}` is replaced by the file name of the input without extension, `{iter}` and
+/// `{offset}` by the blur parameters and `{ext}` by the extension of the save format.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NameTemplate(String);
+
+impl NameTemplate {
+    pub fn format(&self, stem: &str, iterations: u32, offset: f32, ext: &str) -> String {
+        self.0
+            .replace("{stem}", stem)
+            .replace("{iter}", &iterations.to_string())
+            .replace("{offset}", &format!("{:.2}", offset))
+            .replace("{ext}", ext)
+    }
+}
+
+impl Default for NameTemplate {
+    fn default() -> Self {
+        NameTemplate(String::from("{stem}_{iter}_{offset}.{ext}"))
+    }
+}
+
+impl FromStr for NameTemplate {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let mut rest = s;
+        while let Some(start) = rest.find('{') {
+            let end = rest[start..].find('}')
+                                   .ok_or_else(|| format!("Unclosed placeholder in '{}'", s))?;
+            match &rest[start..start + end + 1] {
+                "{stem}" | "{iter}" | "{offset}" | "{ext}" => (),
+                name => {
+                    return Err(format!("Unknown placeholder '{}', expected {{stem}}, {{iter}}, \
+                                        {{offset}} or {{ext}}",
+                                       name))
+                },
+            }
+            rest = &rest[start + end + 1..];
+        }
+        if !s.contains("{stem}") {
+            return Err(format!("Name template '{}' must contain {{stem}}", s));
+        }
+
+        Ok(NameTemplate(String::from(s)))
+    }
+}
+
+/// Image of the batch and the file its result is saved to.
+struct Job {
+    input: PathBuf,
+    output: PathBuf,
+}
+
+/// Decoded image of the job at `index`.
+struct Decoded {
+    index: usize,
+    image: Result<LoadedImage>,
+}
+
+/// Counts of a finished batch.
+#[derive(Default)]
+pub struct Summary {
+    pub saved: usize,
+    pub skipped: usize,
+    pub failures: Vec<(PathBuf, String)>,
+}
+
+/// Blur all images given by `options.inputs` and save the results into the output directory.
+///
+/// Needs a current GL context. Images are decoded in parallel ahead of the blur and encoded
+/// by the encoder pool, while one `BlurContext` is reused for all of them.
+pub fn run(options: &Options) -> Result<Summary> {
+    let start = Instant::now();
+    let preset = preset::selected(options)?;
+    let (iterations, offset) = blur_settings(options, preset.as_ref());
+    let iterations = iterations.min(MAX_ITERATIONS as u32);
+
+    let mut summary = Summary::default();
+    let inputs = collect_inputs(&options.inputs, &mut summary);
+    let jobs = plan_jobs(inputs, options, iterations, offset, &mut summary);
+    log::info!(target: "batch",
+               "Blur {} images with {} iterations and offset {:.2} into '{}'",
+               jobs.len(),
+               iterations,
+               offset,
+               options.output_dir.display());
+    if jobs.is_empty() {
+        report(&summary, start.elapsed());
+        return Ok(summary);
+    }
+    fs::create_dir_all(&options.output_dir).map_err(|err| Error::write(&options.output_dir, err))?;
+
+    let mut blur_ctx = init_blur(options, preset.as_ref(), iterations, offset)?;
+    let jobs = Arc::new(jobs);
+    let decoded = spawn_decoders(&jobs, options);
+    let mut readbacks = ReadbackQueue::new();
+    let encoder = EncoderPool::with_available_cpus(options.save_options);
+    let mut waiting: Option<Pixels> = None;
+
+    for Decoded { index, image } in decoded {
+        let job = &jobs[index];
+        let LoadedImage { image, icc_profile } = match image {
+            Ok(loaded) => loaded,
+            Err(err) => {
+                fail(&mut summary, &job.input, &err.to_string());
+                continue;
+            },
+        };
+
+        // keep the number of readbacks bounded while the encoders catch up
+        while !readbacks.has_capacity() {
+            encoder::process_saves(&mut readbacks, &mut waiting, &encoder, |done| {
+                count_save(&mut summary, done)
+            });
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        let size = options.export_size.resolve(image.dimensions());
+        log::info!(target: "batch",
+                   "[{}/{}] '{}' -> '{}'",
+                   index + 1,
+                   jobs.len(),
+                   job.input.display(),
+                   job.output.display());
+        readbacks.set_icc_profile(icc_profile);
+        // the offset is given in pixels of the result
+        if let Err(err) = export::export(&mut blur_ctx,
+                                         &mut readbacks,
+                                         &image,
+                                         size,
+                                         size.0,
+                                         job.output.clone(),
+                                         options.save_format.sample_format(image.blur_format()))
+        {
+            fail(&mut summary, &job.input, &err.to_string());
+        }
+        encoder::process_saves(&mut readbacks, &mut waiting, &encoder, |done| {
+            count_save(&mut summary, done)
+        });
+    }
+
+    // Finish pending saves
+    unsafe {
+        gl::Finish();
+    }
+    while !readbacks.is_empty() || waiting.is_some() || !encoder.is_idle() {
+        encoder::process_saves(&mut readbacks, &mut waiting, &encoder, |done| {
+            count_save(&mut summary, done)
+        });
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    report(&summary, start.elapsed());
+    Ok(summary)
+}
+
+/// Blur the single input of `options` and save it to `options.output`, `-` writes the image
+/// to stdout in the save format.
+///
+/// Needs a current GL context.
+pub fn run_headless(options: &Options) -> Result<()> {
+    let input = &options.inputs[0];
+    let LoadedImage { image, icc_profile } = image_io::load_image(input, options.color_space)?;
+    let preset = preset::selected(options)?;
+    let (iterations, offset) = blur_settings(options, preset.as_ref());
+    let mut blur_ctx = init_blur(options, preset.as_ref(), iterations, offset)?;
+
+    let size = options.export_size.resolve(image.dimensions());
+    let mut readbacks = ReadbackQueue::new();
+    readbacks.set_icc_profile(icc_profile);
+    export::export(&mut blur_ctx,
+                   &mut readbacks,
+                   &image,
+                   size,
+                   size.0,
+                   options.output.clone(),
+                   options.save_format.sample_format(image.blur_format()))?;
+
+    unsafe {
+        gl::Finish();
+    }
+    let pixels = loop {
+        match readbacks.poll() {
+            Some(Ok(pixels)) => break pixels,
+            Some(Err(path)) => return Err(Error::Readback(path)),
+            None => thread::sleep(Duration::from_millis(1)),
+        }
+    };
+
+    if image_io::is_stdio(&options.output) {
+        let stdout = io::stdout();
+        image_io::write_pixels(&pixels,
+                               options.save_format.extension(),
+                               stdout.lock(),
+                               &options.save_options)
+    } else {
+        image_io::save_pixels(&pixels, &options.save_options)
+    }
+}
+
+/// Iterations and offset given by `options`, falling back to `preset` and the defaults.
+fn blur_settings(options: &Options, preset: Option<&Preset>) -> (u32, f32) {
+    let iterations = options.iterations
+                            .or_else(|| preset.map(|preset| preset.iterations))
+                            .unwrap_or(DEFAULT_ITERATIONS);
+    let offset = options.offset
+                        .or_else(|| preset.map(|preset| preset.offset))
+                        .unwrap_or(DEFAULT_OFFSET);
+
+    (iterations, offset)
+}
+
+/// Blur context for offscreen work with the blur parameters and kernels of `options`, the
+/// opacity and kernel parameters are taken from `preset`.
+fn init_blur(options: &Options,
+             preset: Option<&Preset>,
+             iterations: u32,
+             offset: f32)
+             -> Result<BlurContext> {
+    // the framebuffers are resized to each image, start with one pixel in the smallest level
+    let min_size = 1 << MAX_ITERATIONS;
+    let mut blur_ctx = BlurContext::new((min_size, min_size))?;
+    blur_ctx.set_iterations(iterations);
+    blur_ctx.set_offset(offset);
+
+    let shader_source = match options.shader_dir {
+        Some(ref dir) => ShaderSource::from_dir(dir),
+        None => ShaderSource::embedded(),
+    };
+    if options.shader_dir.is_some() {
+        if let Err(errors) = blur_ctx.reload_programs(&shader_source, &shader_source.names()) {
+            return Err(errors.into_iter().next().unwrap());
+        }
+    }
+    if let Some(ref path) = options.down_kernel {
+        blur_ctx.set_down_kernel(&shader_source, UserKernel::load(path)?)?;
+    }
+    if let Some(ref path) = options.up_kernel {
+        blur_ctx.set_up_kernel(&shader_source, UserKernel::load(path)?)?;
+    }
+    if let Some(preset) = preset {
+        blur_ctx.set_opacity(preset.opacity);
+        preset.apply_params(&mut blur_ctx);
+    }
+
+    Ok(blur_ctx)
+}
+
+/// Expand directories and glob patterns of `args` into image files.
+fn collect_inputs(args: &[PathBuf], summary: &mut Summary) -> Vec<PathBuf> {
+    let mut inputs = Vec::new();
+    for arg in args {
+        let pattern = arg.to_string_lossy();
+        let result = if pattern.contains(&['*', '?'][..]) {
+            expand_glob(arg)
+        } else if arg.is_dir() {
+            list_images(arg)
+        } else {
+            inputs.push(arg.clone());
+            continue;
+        };
+
+        match result {
+            Ok(files) if files.is_empty() => {
+                log::warn!(target: "batch", "No images found for '{}'", arg.display());
+            },
+            Ok(files) => inputs.extend(files),
+            Err(err) => fail(summary, arg, &err),
+        }
+    }
+
+    inputs
+}
+
+/// Image files in `dir`, sorted by name.
+pub fn list_images(dir: &Path) -> std::result::Result<Vec<PathBuf>, String> {
+    let is_image = |path: &Path| {
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| IMAGE_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+    };
+
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir).map_err(|err| err.to_string())? {
+        let path = entry.map_err(|err| err.to_string())?.path();
+        if path.is_file() && is_image(&path) {
+            files.push(path);
+        }
+    }
+    files.sort();
+
+    Ok(files)
+}
+
+/// Files matching `pattern`, wildcards are only supported in the file name.
+fn expand_glob(pattern: &Path) -> std::result::Result<Vec<PathBuf>, String> {
+    let name = pattern.file_name()
+                      .map(|name| name.to_string_lossy().into_owned())
+                      .unwrap_or_default();
+    let dir = match pattern.parent() {
+        Some(dir) if dir.as_os_str().is_empty() => Path::new("."),
+        Some(dir) => dir,
+        None => Path::new("."),
+    };
+    if dir.to_string_lossy().contains(&['*', '?'][..]) {
+        return Err(String::from("wildcards are only supported in the file name"));
+    }
+
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir).map_err(|err| err.to_string())? {
+        let entry = entry.map_err(|err| err.to_string())?;
+        let matched = entry.file_name()
+                           .to_str()
+                           .is_some_and(|file| glob_match(name.as_bytes(), file.as_bytes()));
+        if matched && entry.path().is_file() {
+            // keep the directory as given, `read_dir` of "." prefixes it
+            files.push(pattern.with_file_name(entry.file_name()));
+        }
+    }
+    files.sort();
+
+    Ok(files)
+}
+
+/// Match `name` against a pattern with `*` and `?` wildcards.
+fn glob_match(pattern: &[u8], name: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some((b'*', rest)) => (0..=name.len()).any(|skip| glob_match(rest, &name[skip..])),
+        Some((b'?', rest)) => !name.is_empty() && glob_match(rest, &name[1..]),
+        Some((c, rest)) => name.first() == Some(c) && glob_match(rest, &name[1..]),
+    }
+}
+
+/// Assign the output files, inputs whose result exists or collides are skipped.
+fn plan_jobs(inputs: Vec<PathBuf>,
+             options: &Options,
+             iterations: u32,
+             offset: f32,
+             summary: &mut Summary)
+             -> Vec<Job> {
+    let mut outputs = HashSet::new();
+    let mut jobs = Vec::with_capacity(inputs.len());
+    for input in inputs {
+        let stem = input.file_stem()
+                        .map(|stem| stem.to_string_lossy().into_owned())
+                        .unwrap_or_default();
+        let name = options.name_template
+                          .format(&stem, iterations, offset, options.save_format.extension());
+        let output = options.output_dir.join(name);
+
+        if !outputs.insert(output.clone()) {
+            log::warn!(target: "batch",
+                       "Skip '{}', its result '{}' is already written by another input",
+                       input.display(),
+                       output.display());
+            summary.skipped += 1;
+        } else if options.skip_existing && output.exists() {
+            log::info!(target: "batch",
+                       "Skip '{}', '{}' exists",
+                       input.display(),
+                       output.display());
+            summary.skipped += 1;
+        } else {
+            jobs.push(Job { input, output });
+        }
+    }
+
+    jobs
+}
+
+/// Decode the images of `jobs` on a thread per CPU.
+///
+/// Decoded images are passed through a bounded channel, so at most a few of them wait in
+/// memory for the blur.
+fn spawn_decoders(jobs: &Arc<Vec<Job>>, options: &Options) -> Receiver<Decoded> {
+    let num_workers = thread::available_parallelism().map_or(2, |n| n.get())
+                                                     .min(jobs.len());
+    let (tx, rx) = mpsc::sync_channel(num_workers);
+    let next = Arc::new(AtomicUsize::new(0));
+    let color_space = options.color_space;
+
+    for i in 0..num_workers {
+        let jobs = jobs.clone();
+        let next = next.clone();
+        let tx = tx.clone();
+        thread::Builder::new().name(format!("decoder-{}", i))
+                              .spawn(move || loop {
+                                  let index = next.fetch_add(1, Ordering::Relaxed);
+                                  let job = match jobs.get(index) {
+                                      Some(job) => job,
+                                      None => break,
+                                  };
+                                  let image = image_io::load_image(&job.input, color_space);
+                                  if tx.send(Decoded { index, image }).is_err() {
+                                      break;
+                                  }
+                              })
+                              .expect("Cannot spawn decoder thread");
+    }
+
+    rx
+}
+
+/// Count an encoded image or its failure.
+fn count_save(summary: &mut Summary, completion: Completion) {
+    match completion.result {
+        Ok(()) => summary.saved += 1,
+        Err(err) => fail(summary, &completion.path, &err.to_string()),
+    }
+}
+
+fn fail(summary: &mut Summary, path: &Path, error: &str) {
+    log::error!(target: "batch", "'{}': {}", path.display(), error);
+    summary.failures.push((path.to_owned(), error.to_owned()));
+}
+
+fn report(summary: &Summary, elapsed: Duration) {
+    log::info!(target: "batch",
+               "Batch done in {:.1}s: {} saved, {} skipped, {} failed",
+               elapsed.as_secs_f32(),
+               summary.saved,
+               summary.skipped,
+               summary.failures.len());
+    for (path, error) in summary.failures.iter() {
+        log::error!(target: "batch", "  failed '{}': {}", path.display(), error);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Empty directory for the files of the test `name`.
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("dual-kawase-batch-{}-{}",
+                                                    std::process::id(),
+                                                    name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn touch(path: &Path) {
+        fs::write(path, b"").unwrap();
+    }
+
+    fn options(args: &[&str]) -> Options {
+        let args = ["--batch", "input.png"].iter().chain(args.iter());
+        Options::from_args(args.map(|arg| arg.to_string())).unwrap()
+    }
+
+    fn outputs(jobs: &[Job]) -> Vec<PathBuf> {
+        jobs.iter().map(|job| job.output.clone()).collect()
+    }
+
+    #[test]
+    fn format_placeholders() {
+        let template = NameTemplate::default();
+        assert_eq!(template.format("wall", 4, 3.0, "png"), "wall_4_3.00.png");
+
+        let format = |template: &str| {
+            template.parse::<NameTemplate>()
+                    .unwrap()
+                    .format("wall", 5, 2.25, "tiff")
+        };
+        assert_eq!(format("{stem}"), "wall");
+        assert_eq!(format("{stem}-{iter}"), "wall-5");
+        assert_eq!(format("{stem}-{offset}"), "wall-2.25");
+        assert_eq!(format("{stem}.{ext}"), "wall.tiff");
+        assert_eq!(format("blur/{stem}_{iter}x{iter}.{ext}"), "blur/wall_5x5.tiff");
+    }
+
+    #[test]
+    fn reject_invalid_templates() {
+        assert!("{stem}_{size}.{ext}".parse::<NameTemplate>().is_err());
+        assert!("{STEM}.png".parse::<NameTemplate>().is_err());
+        assert!("{stem".parse::<NameTemplate>().is_err());
+        assert!("{stem}_{iter".parse::<NameTemplate>().is_err());
+        assert!("{iter}_{offset}.{ext}".parse::<NameTemplate>().is_err());
+    }
+
+    #[test]
+    fn match_wildcards() {
+        let matches = |pattern: &str, name: &str| glob_match(pattern.as_bytes(), name.as_bytes());
+        assert!(matches("*.png", "wall.png"));
+        assert!(matches("*.png", ".png"));
+        assert!(!matches("*.png", "wall.jpg"));
+        assert!(!matches("*.png", "wall.png.bak"));
+        assert!(matches("wall?.png", "wall1.png"));
+        assert!(!matches("wall?.png", "wall.png"));
+        assert!(!matches("wall?.png", "wall12.png"));
+        assert!(matches("*_*.jp?g", "a_b.jpeg"));
+        assert!(matches("*", ""));
+        assert!(matches("wall.png", "wall.png"));
+        assert!(!matches("wall.png", "Wall.png"));
+    }
+
+    #[test]
+    fn expand_wildcards() {
+        let dir = temp_dir("glob");
+        for name in &["b.png", "a.png", "c.jpg", "ab.png"] {
+            touch(&dir.join(name));
+        }
+        fs::create_dir(dir.join("d.png")).unwrap();
+
+        assert_eq!(expand_glob(&dir.join("*.png")),
+                   Ok(vec![dir.join("a.png"), dir.join("ab.png"), dir.join("b.png")]));
+        assert_eq!(expand_glob(&dir.join("?.*")),
+                   Ok(vec![dir.join("a.png"), dir.join("b.png"), dir.join("c.jpg")]));
+        assert_eq!(expand_glob(&dir.join("*.gif")), Ok(vec![]));
+        assert!(expand_glob(&dir.join("*").join("*.png")).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn skip_colliding_outputs() {
+        let options = options(&["--output-dir", "out", "--name", "{stem}.{ext}"]);
+        let inputs = vec![PathBuf::from("a/wall.png"),
+                          PathBuf::from("b/wall.jpg"),
+                          PathBuf::from("b/sky.png")];
+        let mut summary = Summary::default();
+        let jobs = plan_jobs(inputs, &options, 4, 3.0, &mut summary);
+
+        assert_eq!(outputs(&jobs),
+                   vec![PathBuf::from("out/wall.png"), PathBuf::from("out/sky.png")]);
+        assert_eq!(jobs[0].input, PathBuf::from("a/wall.png"));
+        assert_eq!(summary.skipped, 1);
+    }
+
+    #[test]
+    fn skip_existing_outputs() {
+        let dir = temp_dir("skip");
+        touch(&dir.join("a_4_3.00.png"));
+        let inputs = vec![PathBuf::from("a.png"), PathBuf::from("b.png")];
+        let output_dir = dir.to_str().unwrap();
+
+        let mut summary = Summary::default();
+        let jobs = plan_jobs(inputs.clone(),
+                             &options(&["--output-dir", output_dir]),
+                             4,
+                             3.0,
+                             &mut summary);
+        assert_eq!(outputs(&jobs),
+                   vec![dir.join("a_4_3.00.png"), dir.join("b_4_3.00.png")]);
+        assert_eq!(summary.skipped, 0);
+
+        let mut summary = Summary::default();
+        let jobs = plan_jobs(inputs,
+                             &options(&["--output-dir", output_dir, "--skip-existing"]),
+                             4,
+                             3.0,
+                             &mut summary);
+        assert_eq!(outputs(&jobs), vec![dir.join("b_4_3.00.png")]);
+        assert_eq!(summary.skipped, 1);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
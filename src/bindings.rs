@@ -0,0 +1,621 @@
+// This file is part auf the dual-kawase-demo package.
+//
+// (c) 2019 Bernd Busse
+//
+// For the full copyright and license information, please view the README.md file
+// that was distributed with this source code.
+//
+
+use std::path::Path;
+use std::str::FromStr;
+
+use sdl2::keyboard::{Keycode, Mod, Scancode};
+
+use crate::config;
+use crate::renderer_gl::{Error, Result};
+
+const HELP_TITLE: &str = "Key Bindings [H]";
+
+/// Name of the key binding file in the configuration directory.
+pub const CONFIG_FILE: &str = "keys.toml";
+
+/// Action name which removes a binding.
+const UNBIND: &str = "none";
+
+const ACTION_NAMES: &[(&str, Action)] = &[("quit", Action::Quit),
+                                          ("toggle-fullscreen", Action::ToggleFullscreen),
+                                          ("toggle-help", Action::ToggleHelp),
+                                          ("toggle-controls", Action::ToggleControls),
+                                          ("toggle-graph", Action::ToggleGraph),
+                                          ("toggle-overlay", Action::ToggleOverlay),
+                                          ("toggle-image-info", Action::ToggleImageInfo),
+                                          ("next-image", Action::NextImage),
+                                          ("prev-image", Action::PrevImage),
+                                          ("inc-iterations", Action::IncIterations),
+                                          ("dec-iterations", Action::DecIterations),
+                                          ("inc-offset", Action::IncOffset),
+                                          ("dec-offset", Action::DecOffset),
+                                          ("inc-offset-coarse", Action::IncOffsetCoarse),
+                                          ("dec-offset-coarse", Action::DecOffsetCoarse),
+                                          ("reset", Action::Reset),
+                                          ("undo", Action::Undo),
+                                          ("redo", Action::Redo),
+                                          ("redraw", Action::Redraw),
+                                          ("save", Action::Save),
+                                          ("save-shown", Action::SaveShown),
+                                          ("verify-tiling", Action::VerifyTiling),
+                                          ("sweep", Action::Sweep),
+                                          ("toggle-continuous", Action::ToggleContinuous),
+                                          ("save-preset", Action::SavePreset),
+                                          ("inc-exposure", Action::IncExposure),
+                                          ("dec-exposure", Action::DecExposure),
+                                          ("next-tonemap", Action::NextTonemap),
+                                          ("next-param", Action::NextParam),
+                                          ("prev-param", Action::PrevParam),
+                                          ("inc-param", Action::IncParam),
+                                          ("dec-param", Action::DecParam)];
+
+/// Everything the demo does in response to a key press.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Action {
+    Quit,
+    ToggleFullscreen,
+    ToggleHelp,
+    ToggleControls,
+    ToggleGraph,
+    ToggleOverlay,
+    ToggleImageInfo,
+    NextImage,
+    PrevImage,
+    IncIterations,
+    DecIterations,
+    IncOffset,
+    DecOffset,
+    IncOffsetCoarse,
+    DecOffsetCoarse,
+    Preset(usize),
+    Reset,
+    Undo,
+    Redo,
+    Redraw,
+    Save,
+    SaveShown,
+    VerifyTiling,
+    Sweep,
+    ToggleContinuous,
+    SavePreset,
+    IncExposure,
+    DecExposure,
+    NextTonemap,
+    NextParam,
+    PrevParam,
+    IncParam,
+    DecParam,
+}
+
+impl Action {
+    /// Whether the action is repeated while the key is held down.
+    pub fn repeats(self) -> bool {
+        !matches!(self,
+                  Action::ToggleFullscreen
+                  | Action::ToggleHelp
+                  | Action::ToggleControls
+                  | Action::ToggleGraph
+                  | Action::ToggleOverlay
+                  | Action::ToggleImageInfo
+                  | Action::Preset(_)
+                  | Action::Reset
+                  | Action::Save
+                  | Action::SaveShown
+                  | Action::VerifyTiling
+                  | Action::Sweep
+                  | Action::ToggleContinuous
+                  | Action::SavePreset)
+    }
+
+    /// Name of the action in the key binding file, e.g. `inc-iterations`.
+    pub fn name(self) -> String {
+        match self {
+            Action::Preset(index) => format!("preset-{}", index + 1),
+            _ => ACTION_NAMES.iter()
+                             .find(|(_, action)| *action == self)
+                             .map(|(name, _)| String::from(*name))
+                             .unwrap_or_default(),
+        }
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            Action::Quit => "Quit",
+            Action::ToggleFullscreen => "Toggle fullscreen",
+            Action::ToggleHelp => "Show/hide this help",
+            Action::ToggleControls => "Show/hide mouse controls",
+            Action::ToggleGraph => "Show/hide timing graph",
+            Action::ToggleOverlay => "Show/hide the whole overlay",
+            Action::ToggleImageInfo => "Show/hide image information",
+            Action::NextImage => "Next image in the directory",
+            Action::PrevImage => "Previous image in the directory",
+            Action::IncIterations => "More iterations",
+            Action::DecIterations => "Less iterations",
+            Action::IncOffset => "Increase offset by 0.25",
+            Action::DecOffset => "Decrease offset by 0.25",
+            Action::IncOffsetCoarse => "Increase offset by 1",
+            Action::DecOffsetCoarse => "Decrease offset by 1",
+            Action::Preset(_) => "Load preset",
+            Action::Reset => "Reset parameters",
+            Action::Undo => "Undo parameter change",
+            Action::Redo => "Redo parameter change",
+            Action::Redraw => "Redraw",
+            Action::Save => "Save blurred image",
+            Action::SaveShown => "Save image as shown",
+            Action::VerifyTiling => "Verify tiled blur",
+            Action::Sweep => "Save parameter sweep",
+            Action::ToggleContinuous => "Blur every frame (stress test)",
+            Action::SavePreset => "Save settings as preset",
+            Action::IncExposure => "Increase exposure (HDR)",
+            Action::DecExposure => "Decrease exposure (HDR)",
+            Action::NextTonemap => "Next tone mapping operator (HDR)",
+            Action::NextParam => "Select next kernel parameter",
+            Action::PrevParam => "Select previous kernel parameter",
+            Action::IncParam => "Increase kernel parameter",
+            Action::DecParam => "Decrease kernel parameter",
+        }
+    }
+}
+
+impl FromStr for Action {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        if let Some(number) = s.strip_prefix("preset-") {
+            return match number.parse::<usize>() {
+                Ok(n) if n >= 1 => Ok(Action::Preset(n - 1)),
+                _ => Err(format!("Unknown preset '{}'", number)),
+            };
+        }
+
+        ACTION_NAMES.iter()
+                    .find(|(name, _)| *name == s)
+                    .map(|(_, action)| *action)
+                    .ok_or_else(|| format!("Unknown action '{}'", s))
+    }
+}
+
+/// Physical keys are used for letters, so they stay in place on other keyboard layouts.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Key {
+    Code(Keycode),
+    Scan(Scancode),
+}
+
+impl Key {
+    fn name(self) -> String {
+        match self {
+            Key::Code(code) => code.name(),
+            Key::Scan(scan) => scan.name().to_owned(),
+        }
+    }
+}
+
+impl FromStr for Key {
+    type Err = String;
+
+    /// Parse an SDL key name, single letters are bound to their physical key.
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let is_letter = s.len() == 1 && s.chars().all(|c| c.is_ascii_alphabetic());
+        let key = if is_letter {
+            Scancode::from_name(s).map(Key::Scan)
+        } else {
+            Keycode::from_name(s).map(Key::Code)
+        };
+
+        key.ok_or_else(|| format!("Unknown key '{}'", s))
+    }
+}
+
+/// Modifier keys which have to be held down.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Modifiers {
+    /// Ignore all modifiers
+    Any,
+    /// No modifier at all
+    None,
+    Shift,
+    Ctrl,
+    CtrlShift,
+}
+
+impl Modifiers {
+    fn matches(self, keymod: Mod) -> bool {
+        let shift = keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD);
+        let ctrl = keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD);
+        match self {
+            Modifiers::Any => true,
+            Modifiers::None => keymod == Mod::NOMOD,
+            Modifiers::Shift => shift,
+            Modifiers::Ctrl => ctrl,
+            Modifiers::CtrlShift => ctrl && shift,
+        }
+    }
+
+    fn prefix(self) -> &'static str {
+        match self {
+            Modifiers::Any | Modifiers::None => "",
+            Modifiers::Shift => "Shift-",
+            Modifiers::Ctrl => "Ctrl-",
+            Modifiers::CtrlShift => "Ctrl-Shift-",
+        }
+    }
+
+    /// Rank of the modifiers, chords with more modifiers held down rank first.
+    fn specificity(self) -> u8 {
+        match self {
+            Modifiers::CtrlShift => 0,
+            Modifiers::Ctrl | Modifiers::Shift => 1,
+            Modifiers::None => 2,
+            Modifiers::Any => 3,
+        }
+    }
+}
+
+/// Key chord triggering an action.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Binding {
+    pub key: Key,
+    pub modifiers: Modifiers,
+    pub action: Action,
+}
+
+impl Binding {
+    fn new(key: Key, modifiers: Modifiers, action: Action) -> Self {
+        Self { key,
+               modifiers,
+               action }
+    }
+
+    fn matches(&self, keycode: Option<Keycode>, scancode: Option<Scancode>, keymod: Mod) -> bool {
+        let key = match self.key {
+            Key::Code(code) => keycode == Some(code),
+            Key::Scan(scan) => scancode == Some(scan),
+        };
+        key && self.modifiers.matches(keymod)
+    }
+
+    /// Name of the chord, e.g. `Ctrl-S`.
+    pub fn chord(&self) -> String {
+        format!("{}{}", self.modifiers.prefix(), self.key.name())
+    }
+
+    fn has_chord(&self, chord: (Key, Modifiers)) -> bool {
+        self.key == chord.0 && self.modifiers == chord.1
+    }
+}
+
+/// Parse a chord like `Ctrl-Shift-S`. Without modifiers no modifier may be held down, the
+/// prefix `Any-` ignores them.
+fn parse_chord(chord: &str) -> std::result::Result<(Key, Modifiers), String> {
+    let mut rest = chord;
+    let (mut any, mut ctrl, mut shift) = (false, false, false);
+    // the key itself may be '-'
+    while let Some(pos) = rest[..rest.len().saturating_sub(1)].find('-') {
+        match rest[..pos].to_ascii_lowercase().as_str() {
+            "any" => any = true,
+            "ctrl" => ctrl = true,
+            "shift" => shift = true,
+            _ => break,
+        }
+        rest = &rest[pos + 1..];
+    }
+
+    let modifiers = match (any, ctrl, shift) {
+        (false, false, false) => Modifiers::None,
+        (true, false, false) => Modifiers::Any,
+        (false, false, true) => Modifiers::Shift,
+        (false, true, false) => Modifiers::Ctrl,
+        (false, true, true) => Modifiers::CtrlShift,
+        _ => return Err(format!("Cannot combine 'Any' with other modifiers in '{}'", chord)),
+    };
+
+    Ok((rest.parse()?, modifiers))
+}
+
+/// Default bindings, the matching chord with the most modifiers wins and ties go to the first.
+#[rustfmt::skip]
+fn default_bindings() -> Vec<Binding> {
+    use self::Action::*;
+    use self::Key::{Code, Scan};
+    use self::Modifiers::{Any, Ctrl, CtrlShift, Shift};
+
+    vec![
+        Binding::new(Scan(Scancode::H), Modifiers::None, ToggleHelp),
+        Binding::new(Code(Keycode::F1), Modifiers::None, ToggleHelp),
+        Binding::new(Scan(Scancode::C), Modifiers::None, ToggleControls),
+        Binding::new(Scan(Scancode::T), Modifiers::None, ToggleGraph),
+        Binding::new(Scan(Scancode::V), Modifiers::None, ToggleOverlay),
+        Binding::new(Scan(Scancode::I), Modifiers::None, ToggleImageInfo),
+        Binding::new(Code(Keycode::Escape), Any, Quit),
+        Binding::new(Scan(Scancode::Q), Modifiers::None, Quit),
+        Binding::new(Scan(Scancode::F), Modifiers::None, ToggleFullscreen),
+        Binding::new(Code(Keycode::F11), Modifiers::None, ToggleFullscreen),
+        Binding::new(Code(Keycode::PageDown), Modifiers::None, NextImage),
+        Binding::new(Code(Keycode::PageUp), Modifiers::None, PrevImage),
+        Binding::new(Code(Keycode::Right), Any, IncIterations),
+        Binding::new(Code(Keycode::Left), Any, DecIterations),
+        Binding::new(Code(Keycode::Up), Shift, IncOffsetCoarse),
+        Binding::new(Code(Keycode::Up), Any, IncOffset),
+        Binding::new(Code(Keycode::Down), Shift, DecOffsetCoarse),
+        Binding::new(Code(Keycode::Down), Any, DecOffset),
+        Binding::new(Code(Keycode::Num1), Modifiers::None, Preset(0)),
+        Binding::new(Code(Keycode::Num2), Modifiers::None, Preset(1)),
+        Binding::new(Code(Keycode::Num3), Modifiers::None, Preset(2)),
+        Binding::new(Code(Keycode::Num4), Modifiers::None, Preset(3)),
+        Binding::new(Code(Keycode::Num5), Modifiers::None, Preset(4)),
+        Binding::new(Code(Keycode::Num6), Modifiers::None, Preset(5)),
+        Binding::new(Code(Keycode::Num7), Modifiers::None, Preset(6)),
+        Binding::new(Code(Keycode::Num8), Modifiers::None, Preset(7)),
+        Binding::new(Code(Keycode::Num9), Modifiers::None, Preset(8)),
+        Binding::new(Scan(Scancode::R), Modifiers::None, Reset),
+        Binding::new(Code(Keycode::Num0), Modifiers::None, Reset),
+        Binding::new(Scan(Scancode::Z), CtrlShift, Redo),
+        Binding::new(Scan(Scancode::Z), Ctrl, Undo),
+        Binding::new(Scan(Scancode::R), Ctrl, Redraw),
+        Binding::new(Code(Keycode::Return), Any, Redraw),
+        Binding::new(Code(Keycode::Space), Any, Redraw),
+        Binding::new(Scan(Scancode::S), CtrlShift, SaveShown),
+        Binding::new(Scan(Scancode::S), Ctrl, Save),
+        Binding::new(Scan(Scancode::T), Ctrl, VerifyTiling),
+        Binding::new(Scan(Scancode::G), Ctrl, Sweep),
+        Binding::new(Scan(Scancode::B), Modifiers::None, ToggleContinuous),
+        Binding::new(Scan(Scancode::P), Ctrl, SavePreset),
+        Binding::new(Scan(Scancode::RightBracket), Any, IncExposure),
+        Binding::new(Scan(Scancode::LeftBracket), Any, DecExposure),
+        Binding::new(Scan(Scancode::O), Modifiers::None, NextTonemap),
+        Binding::new(Code(Keycode::Tab), Shift, PrevParam),
+        Binding::new(Code(Keycode::Tab), Any, NextParam),
+        Binding::new(Code(Keycode::Plus), Any, IncParam),
+        Binding::new(Code(Keycode::Equals), Any, IncParam),
+        Binding::new(Code(Keycode::KpPlus), Any, IncParam),
+        Binding::new(Code(Keycode::Minus), Any, DecParam),
+        Binding::new(Code(Keycode::KpMinus), Any, DecParam),
+    ]
+}
+
+/// Maps key chords to actions, shared by the event loop and the help overlay.
+pub struct KeyMap {
+    bindings: Vec<Binding>,
+}
+
+impl Default for KeyMap {
+    fn default() -> Self {
+        Self { bindings: default_bindings() }
+    }
+}
+
+impl KeyMap {
+    /// Default bindings, overridden by the `[keys]` table of `path` or the key binding file in
+    /// the configuration directory.
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let mut keymap = Self::default();
+        let path = match path.map(Path::to_owned)
+                             .or_else(|| config::default_file(CONFIG_FILE))
+        {
+            Some(path) => path,
+            None => return Ok(keymap),
+        };
+
+        log::info!(target: "ui", "Load key bindings from '{}' ...", path.display());
+        let table = config::read_toml(&path)?;
+        match table.get("keys") {
+            Some(toml::Value::Table(keys)) => keymap.apply(&path, keys)?,
+            Some(_) => return Err(Error::config(&path, "'keys' is not a table")),
+            None => (),
+        }
+
+        Ok(keymap)
+    }
+
+    /// Override the bindings with the `[keys]` table read from `path`.
+    fn apply(&mut self, path: &Path, keys: &toml::value::Table) -> Result<()> {
+        let mut overrides = Vec::new();
+        let mut replaced = Vec::new();
+        for (chord, value) in keys {
+            let name =
+                value.as_str()
+                     .ok_or_else(|| Error::config(path, format!("'{}' is not a string", chord)))?;
+            let (key, modifiers) =
+                parse_chord(chord.as_str()).map_err(|msg| Error::config(path, msg))?;
+            // e.g. `Ctrl-S` and `ctrl-s`
+            if replaced.contains(&(key, modifiers)) {
+                return Err(Error::config(path, format!("Chord '{}' is bound twice", chord)));
+            }
+            replaced.push((key, modifiers));
+            if name != UNBIND {
+                let action = name.parse().map_err(|msg| Error::config(path, msg))?;
+                overrides.push(Binding::new(key, modifiers, action));
+            }
+        }
+
+        self.bindings
+            .retain(|binding| !replaced.iter().any(|chord| binding.has_chord(*chord)));
+        overrides.append(&mut self.bindings);
+        self.bindings = overrides;
+
+        Ok(())
+    }
+
+    /// Action bound to a key press, held keys only repeat some actions.
+    ///
+    /// The chord with the most modifiers wins, so `Ctrl-Shift-Z` is not shadowed by `Ctrl-Z`
+    /// or `Any-Z`. Overrides come first and win over defaults with the same modifiers.
+    pub fn lookup(&self,
+                  keycode: Option<Keycode>,
+                  scancode: Option<Scancode>,
+                  keymod: Mod,
+                  repeat: bool)
+                  -> Option<Action> {
+        self.bindings
+            .iter()
+            .filter(|binding| binding.matches(keycode, scancode, keymod))
+            .min_by_key(|binding| binding.modifiers.specificity())
+            .map(|binding| binding.action)
+            .filter(|action| !repeat || action.repeats())
+    }
+
+    /// All chords with their description, chords of the same description are merged.
+    pub fn help_lines(&self) -> Vec<(String, &'static str)> {
+        let mut lines: Vec<(String, &'static str)> = Vec::new();
+        for binding in &self.bindings {
+            let description = binding.action.description();
+            match lines.iter_mut().find(|(_, desc)| *desc == description) {
+                Some((chords, _)) => {
+                    chords.push_str(", ");
+                    chords.push_str(&binding.chord());
+                },
+                None => lines.push((binding.chord(), description)),
+            }
+        }
+
+        lines
+    }
+
+    /// Markdown table of all bindings with their action names, as listed in the README.
+    pub fn markdown(&self) -> String {
+        let mut rows: Vec<(String, Action)> = Vec::new();
+        for binding in &self.bindings {
+            let chord = format!("`{}`", binding.chord());
+            match rows.iter_mut()
+                      .find(|(_, action)| *action == binding.action)
+            {
+                Some((chords, _)) => {
+                    chords.push_str(", ");
+                    chords.push_str(&chord);
+                },
+                None => rows.push((chord, binding.action)),
+            }
+        }
+
+        let mut text =
+            String::from("| Keys | Action | Description |\n|------|--------|-------------|");
+        for (chords, action) in rows {
+            text.push_str(&format!("\n| {} | `{}` | {} |",
+                                   chords,
+                                   action.name(),
+                                   action.description()));
+        }
+
+        text
+    }
+
+    /// Help text listing all bindings, aligned for a monospace font.
+    pub fn help_text(&self) -> String {
+        let lines = self.help_lines();
+        let width = lines.iter()
+                         .map(|(chords, _)| chords.len())
+                         .max()
+                         .unwrap_or(0);
+
+        let mut text = String::from(HELP_TITLE);
+        for (chords, description) in lines {
+            text.push_str(&format!("\n{:<width$}  {}", chords, description, width = width));
+        }
+
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(entries: &[(&str, &str)]) -> toml::value::Table {
+        entries.iter()
+               .map(|(chord, action)| (chord.to_string(), toml::Value::from(*action)))
+               .collect()
+    }
+
+    fn apply(entries: &[(&str, &str)]) -> Result<KeyMap> {
+        let mut keymap = KeyMap::default();
+        keymap.apply(Path::new("keys.toml"), &keys(entries))?;
+        Ok(keymap)
+    }
+
+    #[test]
+    fn readme_lists_default_bindings() {
+        let readme = include_str!("../README.md");
+        let start = readme.find("| Keys | Action | Description |")
+                          .expect("README has no key binding table");
+        let table: Vec<&str> = readme[start..].lines()
+                                              .take_while(|line| line.starts_with('|'))
+                                              .collect();
+
+        assert_eq!(table.join("\n"), KeyMap::default().markdown());
+    }
+
+    #[test]
+    fn parse_modifiers() {
+        let s = Key::Scan(Scancode::S);
+        assert_eq!(parse_chord("S"), Ok((s, Modifiers::None)));
+        assert_eq!(parse_chord("Any-S"), Ok((s, Modifiers::Any)));
+        assert_eq!(parse_chord("Shift-S"), Ok((s, Modifiers::Shift)));
+        assert_eq!(parse_chord("ctrl-s"), Ok((s, Modifiers::Ctrl)));
+        assert_eq!(parse_chord("Ctrl-Shift-S"), Ok((s, Modifiers::CtrlShift)));
+        assert_eq!(parse_chord("Shift-Ctrl-S"), Ok((s, Modifiers::CtrlShift)));
+        assert!(parse_chord("Any-Ctrl-S").is_err());
+    }
+
+    #[test]
+    fn parse_keys() {
+        assert_eq!(parse_chord("Keypad 6"),
+                   Ok((Key::Code(Keycode::Kp6), Modifiers::None)));
+        assert_eq!(parse_chord("Ctrl--"),
+                   Ok((Key::Code(Keycode::Minus), Modifiers::Ctrl)));
+        assert_eq!(parse_chord("-"), Ok((Key::Code(Keycode::Minus), Modifiers::None)));
+        assert!(parse_chord("Nokey").is_err());
+        assert!(parse_chord("Ctrl-").is_err());
+        assert!(parse_chord("Alt-S").is_err());
+    }
+
+    #[test]
+    fn override_bindings() {
+        let keymap = apply(&[("Ctrl-Shift-Q", "quit"), ("Q", "none")]).unwrap();
+        let quit = |chord: &str| {
+            let chord = parse_chord(chord).unwrap();
+            keymap.bindings
+                  .iter()
+                  .any(|binding| binding.has_chord(chord) && binding.action == Action::Quit)
+        };
+        assert!(quit("Ctrl-Shift-Q"));
+        assert!(quit("Any-Escape"));
+        assert!(!quit("Q"));
+
+        assert!(apply(&[("Q", "no-such-action")]).is_err());
+        assert!(apply(&[("Q", "preset-0")]).is_err());
+    }
+
+    #[test]
+    fn specific_chords_win() {
+        let lookup = |keymap: &KeyMap, scancode: Scancode, keymod: Mod| {
+            keymap.lookup(None, Some(scancode), keymod, false)
+        };
+        let ctrl = Mod::LCTRLMOD;
+        let ctrl_shift = Mod::LCTRLMOD | Mod::LSHIFTMOD;
+
+        let keymap = apply(&[("Ctrl-Z", "redraw"), ("Ctrl-S", "sweep")]).unwrap();
+        assert_eq!(lookup(&keymap, Scancode::Z, ctrl), Some(Action::Redraw));
+        assert_eq!(lookup(&keymap, Scancode::Z, ctrl_shift), Some(Action::Redo));
+        assert_eq!(lookup(&keymap, Scancode::S, ctrl), Some(Action::Sweep));
+        assert_eq!(lookup(&keymap, Scancode::S, ctrl_shift), Some(Action::SaveShown));
+
+        let keymap = apply(&[("Any-Z", "reset")]).unwrap();
+        assert_eq!(lookup(&keymap, Scancode::Z, Mod::NOMOD), Some(Action::Reset));
+        assert_eq!(lookup(&keymap, Scancode::Z, ctrl), Some(Action::Undo));
+        assert_eq!(lookup(&keymap, Scancode::Z, ctrl_shift), Some(Action::Redo));
+    }
+
+    #[test]
+    fn reject_duplicate_chords() {
+        assert!(apply(&[("Ctrl-S", "save"), ("ctrl-s", "quit")]).is_err());
+        assert!(apply(&[("Ctrl-Shift-S", "save"), ("Shift-Ctrl-S", "save")]).is_err());
+        assert!(apply(&[("Ctrl-S", "save"), ("Any-S", "save")]).is_ok());
+    }
+}
@@ -6,14 +6,106 @@
 // that was distributed with this source code.
 //
 
+use std::collections::BTreeMap;
+
 use cpu_time::ProcessTime;
 use gl::types::{GLint, GLuint};
 
-use crate::renderer_gl::{FragmentShader, GLQuad, ImgSurface, Program, Quad, TextureQuad,
-                         VertexShader, Viewport};
+use crate::kernel::{KernelParam, UserKernel};
+use crate::logger::{self, Value};
+use crate::renderer_gl::{debug, Error, GLQuad, ImgSurface, Program, Quad, Result, TextureQuad,
+                         Viewport};
+use crate::shader_source::{ProgramSpec, ShaderSource};
 
 pub const MAX_ITERATIONS: usize = 8;
 
+const COPY_PROGRAM: ProgramSpec = ProgramSpec { vert: "tex_quad.vert",
+                                                frag: "blur_copy.frag",
+                                                uniforms: &["transform", "opacity"] };
+const DOWN_PROGRAM: ProgramSpec = ProgramSpec { vert: "dual_kawase_down.vert",
+                                                frag: "dual_kawase_down.frag",
+                                                uniforms: &["iteration", "halfpixel", "offset"] };
+const UP_PROGRAM: ProgramSpec = ProgramSpec { vert: "dual_kawase_up.vert",
+                                              frag: "dual_kawase_up.frag",
+                                              uniforms: &["iteration", "halfpixel", "offset"] };
+
+fn init_copy_program(program: &mut Program) -> Result<()> {
+    program.activate();
+    let result = program.set_uniform_mat4f("transform", &crate::utils::matrix4f_identity());
+    program.unbind();
+
+    result
+}
+
+fn build_kernel_program(spec: &ProgramSpec,
+                        source: &ShaderSource,
+                        kernel: Option<&UserKernel>)
+                        -> Result<Program> {
+    // the built-in shader is used until the kernel file can be read
+    let kernel = kernel.filter(|kernel| kernel.is_loaded());
+    let mut program = match kernel {
+        Some(kernel) => spec.build_with_frag(source, &kernel.name(), kernel.source())?,
+        None => spec.build(source)?,
+    };
+
+    // optional uniforms, e.g. unused kernel parameters, are silently skipped
+    program.lookup_uniform("opacity");
+    for param in kernel.map_or(&[][..], UserKernel::params) {
+        program.lookup_uniform(&param.name);
+    }
+
+    Ok(program)
+}
+
+/// Reload the source of `kernel` and build the program with it, or with the built-in shader
+/// if it is not `enabled`.
+fn reload_kernel_program(spec: &ProgramSpec,
+                         source: &ShaderSource,
+                         kernel: Option<&mut UserKernel>,
+                         enabled: bool)
+                         -> Result<Program> {
+    match kernel {
+        Some(kernel) => {
+            kernel.reload()?;
+            build_kernel_program(spec, source, Some(&*kernel).filter(|_| enabled))
+        },
+        None => build_kernel_program(spec, source, None),
+    }
+}
+
+/// Unbind the program, framebuffer and texture of an aborted pass.
+///
+/// A pass may fail with any of the copy, down or up programs and any level framebuffer
+/// bound, so the defaults are restored instead of the objects of a single pass.
+fn reset_bindings() {
+    unsafe {
+        gl::UseProgram(0);
+        gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+        gl::DrawBuffer(gl::BACK);
+        gl::BindTexture(gl::TEXTURE_2D, 0);
+    }
+}
+
+fn set_kernel_params(program: &mut Program, kernel: Option<&UserKernel>) -> Result<()> {
+    for param in kernel.map_or(&[][..], UserKernel::params) {
+        if program.has_uniform(&param.name) {
+            program.set_uniform_1f(&param.name, param.value)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn kernel_changed(spec: &ProgramSpec, kernel: Option<&UserKernel>, changed: &[String]) -> bool {
+    match kernel {
+        Some(kernel) => {
+            let name = kernel.name();
+            changed.iter().any(|n| *n == name || n == spec.vert)
+        },
+        None => spec.uses_any(changed),
+    }
+}
+
 pub struct Framebuffer {
     fbo: GLuint,
     tex: GLuint,
@@ -27,7 +119,7 @@ impl Framebuffer {
                size: (0, 0) }
     }
 
-    pub fn attach_texture(&mut self, tex: GLuint) -> Result<(), GLuint> {
+    pub fn attach_texture(&mut self, tex: GLuint) -> Result<()> {
         self.tex = tex;
 
         unsafe {
@@ -46,17 +138,25 @@ impl Framebuffer {
         }
 
         self.size = crate::renderer_gl::get_texture_size(self.tex);
+        debug::check_error("Framebuffer::attach_texture");
 
         if status != gl::FRAMEBUFFER_COMPLETE {
-            return Err(status);
+            return Err(Error::IncompleteFramebuffer(status));
         }
 
         Ok(())
     }
 
-    pub fn resize(&mut self, width: u32, height: u32) {
-        crate::renderer_gl::resize_texture_bgra(self.tex, width, height, None);
+    pub fn resize(&mut self, width: u32, height: u32) -> Result<()> {
+        crate::renderer_gl::resize_texture_float(self.tex, width, height)?;
         self.size = (width, height);
+        Ok(())
+    }
+
+    /// Label the framebuffer and its attached texture.
+    pub fn set_label(&self, label: &str) {
+        debug::label(gl::FRAMEBUFFER, self.fbo, label);
+        debug::label(gl::TEXTURE, self.tex, &format!("{} texture", label));
     }
 
     pub fn size(&self) -> (u32, u32) {
@@ -102,16 +202,25 @@ impl Drop for Framebuffer {
 pub struct BlurContext {
     iterations: u32,
     offset: f32,
+    /// Size of the source the framebuffers are sized for.
+    size: (u32, u32),
     framebuffers: Vec<Framebuffer>,
     copy_program: Program,
     down_program: Program,
     up_program: Program,
+    down_kernel: Option<UserKernel>,
+    up_kernel: Option<UserKernel>,
+    /// Whether the user kernels are used instead of the built-in shaders.
+    kernels_enabled: bool,
+    opacity: f32,
+    /// Whether every blur is logged as a record.
+    log_records: bool,
     time_cpu: u128,
     time_gpu: u64,
 }
 
 impl BlurContext {
-    pub fn new(vp_size: (u32, u32)) -> Self {
+    pub fn new(vp_size: (u32, u32)) -> Result<Self> {
         // init framebuffers for background rendering
         let mut fbos: Vec<GLuint> = Vec::with_capacity(MAX_ITERATIONS + 1);
         unsafe {
@@ -119,72 +228,190 @@ impl BlurContext {
             fbos.set_len(MAX_ITERATIONS + 1);
         }
 
-        let mut framebuffers = Vec::with_capacity(MAX_ITERATIONS);
-        framebuffers.push(Framebuffer::from_fbo(fbos[0]));
-
-        // init framebuffers with target textures
-        for (i, fbo) in fbos.iter().enumerate().skip(1) {
-            let mut fb = Framebuffer::from_fbo(*fbo);
-            let tex = crate::renderer_gl::create_texture_bgra(vp_size.0 / (1 << i),
-                                                              vp_size.1 / (1 << i),
-                                                              None);
-
-            fb.attach_texture(tex)
-              .expect("Failed to attach texture to framebuffer");
-            framebuffers.push(fb);
+        // framebuffers take ownership of the fbos and are freed on error
+        let mut framebuffers: Vec<Framebuffer> =
+            fbos.iter().map(|fbo| Framebuffer::from_fbo(*fbo)).collect();
+
+        // init framebuffers with half float textures, so intermediate levels keep the
+        // precision of 16 bit images
+        for (i, fb) in framebuffers.iter_mut().enumerate().skip(1) {
+            let tex = crate::renderer_gl::create_texture_float(vp_size.0 / (1 << i),
+                                                               vp_size.1 / (1 << i))?;
+            fb.attach_texture(tex)?;
+            fb.set_label(&format!("blur level {}", i));
         }
+        framebuffers[0].set_label("blur target");
 
         // init shader and program
-        let vert_shader = VertexShader::from_source(include_str!("shaders/tex_quad.vert"))
-            .expect("Cannot compile copy vertex shader");
-        let frag_shader = FragmentShader::from_source(include_str!("shaders/tex_quad.frag"))
-            .expect("Cannot compile copy fragment shader");
-        let mut copy_program =
-            Program::from_shaders(&[vert_shader.into(), frag_shader.into()],
-                                  Some(&["transform"])).expect("Cannot link copy program");
-
-        copy_program.activate();
-        copy_program.set_uniform_mat4f("transform", &crate::utils::matrix4f_identity())
-                    .expect("Cannot set 'transform' in copy program");
-        copy_program.unbind();
-
-        let down_vert_shader =
-            VertexShader::from_source(include_str!("shaders/dual_kawase_down.vert"))
-                .expect("Cannot compile downsample vertex shader");
-        let down_frag_shader =
-            FragmentShader::from_source(include_str!("shaders/dual_kawase_down.frag"))
-                .expect("Cannot compile downsample fragment shader");
-        let down_program = Program::from_shaders(
-            &[down_vert_shader.into(), down_frag_shader.into()],
-            Some(&["iteration", "halfpixel", "offset"]),
-        )
-        .expect("Cannot link downsample program");
-
-        let up_vert_shader = VertexShader::from_source(include_str!("shaders/dual_kawase_up.vert"))
-            .expect("Cannot compile upsample vertex shader");
-        let up_frag_shader =
-            FragmentShader::from_source(include_str!("shaders/dual_kawase_up.frag"))
-                .expect("Cannot compile upsample fragment shader");
-        let up_program = Program::from_shaders(
-            &[up_vert_shader.into(), up_frag_shader.into()],
-            Some(&["iteration", "halfpixel", "offset", "opacity"]),
-        )
-        .expect("Cannot link upsample program");
-
-        Self { iterations: 0,
-               offset: 0.0,
-               framebuffers,
-               copy_program,
-               down_program,
-               up_program,
-               time_cpu: 0,
-               time_gpu: 0 }
-    }
-
-    pub fn resize(&mut self, width: u32, height: u32) {
+        let source = ShaderSource::embedded();
+        let mut copy_program = COPY_PROGRAM.build(&source)?;
+        init_copy_program(&mut copy_program)?;
+        let down_program = build_kernel_program(&DOWN_PROGRAM, &source, None)?;
+        let up_program = build_kernel_program(&UP_PROGRAM, &source, None)?;
+
+        Ok(Self { iterations: 0,
+                  offset: 0.0,
+                  size: vp_size,
+                  framebuffers,
+                  copy_program,
+                  down_program,
+                  up_program,
+                  down_kernel: None,
+                  up_kernel: None,
+                  kernels_enabled: true,
+                  opacity: 1.0,
+                  log_records: true,
+                  time_cpu: 0,
+                  time_gpu: 0 })
+    }
+
+    pub fn resize(&mut self, width: u32, height: u32) -> Result<()> {
         // resize target textures
+        self.size = (width, height);
         for (i, fb) in self.framebuffers.iter_mut().enumerate().skip(1) {
-            fb.resize(width / (1 << i), height / (1 << i));
+            fb.resize(width / (1 << i), height / (1 << i))?;
+        }
+
+        Ok(())
+    }
+
+    /// Run the offscreen blurs of `f`, then restore the framebuffer sizes and timings of the
+    /// displayed blur.
+    pub fn offscreen<T, F>(&mut self, f: F) -> Result<T>
+        where F: FnOnce(&mut Self) -> Result<T>
+    {
+        let (size, time_cpu, time_gpu) = (self.size, self.time_cpu, self.time_gpu);
+        let result = f(self);
+
+        self.time_cpu = time_cpu;
+        self.time_gpu = time_gpu;
+        let resized = self.resize(size.0, size.1);
+        result.and_then(|value| resized.map(|()| value))
+    }
+
+    /// Rebuild all programs affected by the `changed` shaders.
+    ///
+    /// Programs are only replaced if they compile and link, the old ones are kept otherwise.
+    pub fn reload_programs(&mut self,
+                           source: &ShaderSource,
+                           changed: &[String])
+                           -> std::result::Result<(), Vec<Error>> {
+        let mut errors = Vec::new();
+
+        if COPY_PROGRAM.uses_any(changed) {
+            match COPY_PROGRAM.build(source).and_then(|mut program| {
+                                                init_copy_program(&mut program)?;
+                                                Ok(program)
+                                            }) {
+                Ok(program) => self.copy_program = program,
+                Err(err) => errors.push(err),
+            }
+        }
+        if kernel_changed(&DOWN_PROGRAM, self.down_kernel.as_ref(), changed) {
+            match reload_kernel_program(&DOWN_PROGRAM,
+                                        source,
+                                        self.down_kernel.as_mut(),
+                                        self.kernels_enabled)
+            {
+                Ok(program) => self.down_program = program,
+                Err(err) => errors.push(err),
+            }
+        }
+        if kernel_changed(&UP_PROGRAM, self.up_kernel.as_ref(), changed) {
+            match reload_kernel_program(&UP_PROGRAM,
+                                        source,
+                                        self.up_kernel.as_mut(),
+                                        self.kernels_enabled)
+            {
+                Ok(program) => self.up_program = program,
+                Err(err) => errors.push(err),
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Replace the built-in downsample fragment shader with a user kernel.
+    ///
+    /// The kernel is kept even if it fails to build, so it can be fixed and reloaded.
+    pub fn set_down_kernel(&mut self, source: &ShaderSource, kernel: UserKernel) -> Result<()> {
+        let used = Some(&kernel).filter(|_| self.kernels_enabled);
+        let program = build_kernel_program(&DOWN_PROGRAM, source, used);
+        self.down_kernel = Some(kernel);
+        self.down_program = program?;
+        Ok(())
+    }
+
+    /// Replace the built-in upsample fragment shader with a user kernel.
+    pub fn set_up_kernel(&mut self, source: &ShaderSource, kernel: UserKernel) -> Result<()> {
+        let used = Some(&kernel).filter(|_| self.kernels_enabled);
+        let program = build_kernel_program(&UP_PROGRAM, source, used);
+        self.up_kernel = Some(kernel);
+        self.up_program = program?;
+        Ok(())
+    }
+
+    pub fn has_kernels(&self) -> bool {
+        self.down_kernel.is_some() || self.up_kernel.is_some()
+    }
+
+    pub fn kernels_enabled(&self) -> bool {
+        self.kernels_enabled
+    }
+
+    /// Switch between the user kernels and the built-in shaders, the kernels are kept.
+    pub fn set_kernels_enabled(&mut self, source: &ShaderSource, enabled: bool) -> Result<()> {
+        self.kernels_enabled = enabled;
+        let down_kernel = self.down_kernel.as_ref().filter(|_| enabled);
+        let up_kernel = self.up_kernel.as_ref().filter(|_| enabled);
+        self.down_program = build_kernel_program(&DOWN_PROGRAM, source, down_kernel)?;
+        self.up_program = build_kernel_program(&UP_PROGRAM, source, up_kernel)?;
+        Ok(())
+    }
+
+    /// Parameters of all enabled user kernels, downsample first.
+    pub fn kernel_params(&self) -> Vec<&KernelParam> {
+        self.down_kernel
+            .iter()
+            .chain(self.up_kernel.iter())
+            .filter(|_| self.kernels_enabled)
+            .flat_map(|k| k.params())
+            .collect()
+    }
+
+    pub fn kernel_param_mut(&mut self, index: usize) -> Option<&mut KernelParam> {
+        let enabled = self.kernels_enabled;
+        self.down_kernel
+            .iter_mut()
+            .chain(self.up_kernel.iter_mut())
+            .filter(|_| enabled)
+            .flat_map(|k| k.params_mut())
+            .nth(index)
+    }
+
+    /// Values of the kernel parameters by name.
+    pub fn kernel_values(&self) -> BTreeMap<String, f32> {
+        self.kernel_params()
+            .iter()
+            .map(|param| (param.name.clone(), param.value))
+            .collect()
+    }
+
+    /// Set the kernel parameters named in `values` within their limits, other names are
+    /// ignored.
+    pub fn set_kernel_values(&mut self, values: &BTreeMap<String, f32>) {
+        for param in self.down_kernel
+                         .iter_mut()
+                         .chain(self.up_kernel.iter_mut())
+                         .flat_map(|k| k.params_mut())
+        {
+            if let Some(&value) = values.get(&param.name) {
+                param.value = value.max(param.min).min(param.max);
+            }
         }
     }
 
@@ -212,6 +439,28 @@ impl BlurContext {
         self.offset += off_delta;
     }
 
+    pub fn opacity(&self) -> f32 {
+        self.opacity
+    }
+
+    /// Alpha of the blurred image, written by the last upsample pass.
+    pub fn set_opacity(&mut self, opacity: f32) {
+        self.opacity = opacity.clamp(0.0, 1.0);
+    }
+
+    /// Log a record of every blur, continuous blurring reports a summary instead.
+    pub fn set_log_records(&mut self, enabled: bool) {
+        self.log_records = enabled;
+    }
+
+    /// Sizes of the downsampled levels of the current iterations, the largest first.
+    pub fn level_sizes(&self) -> Vec<(u32, u32)> {
+        let iterations = self.iterations.min(MAX_ITERATIONS as u32) as usize;
+        self.framebuffers[1..=iterations].iter()
+                                         .map(Framebuffer::size)
+                                         .collect()
+    }
+
     pub fn time_cpu(&self) -> f32 {
         (self.time_cpu as f64 / 1000f64).round() as f32 / 1000.0
     }
@@ -220,12 +469,33 @@ impl BlurContext {
         (self.time_gpu as f64 / 1000f64).round() as f32 / 1000.0
     }
 
-    fn copy(&mut self, rect: &mut Quad, src: GLuint, tgt: GLuint) {
+    fn log_record(&self, src_size: (u32, u32), tgt_size: (u32, u32)) {
+        let kernel_name = |kernel: &Option<UserKernel>| {
+            kernel.as_ref()
+                  .map_or_else(|| String::from("builtin"), UserKernel::name)
+        };
+
+        logger::record("blur",
+                       "Blurred texture",
+                       &[("iterations", Value::from(self.iterations())),
+                         ("offset", Value::from(self.offset())),
+                         ("src_width", Value::from(src_size.0)),
+                         ("src_height", Value::from(src_size.1)),
+                         ("tgt_width", Value::from(tgt_size.0)),
+                         ("tgt_height", Value::from(tgt_size.1)),
+                         ("down_kernel", Value::Str(kernel_name(&self.down_kernel))),
+                         ("up_kernel", Value::Str(kernel_name(&self.up_kernel))),
+                         ("time_cpu_ms", Value::from(self.time_cpu())),
+                         ("time_gpu_ms", Value::from(self.time_gpu()))]);
+    }
+
+    fn copy(&mut self, rect: &mut Quad, src: GLuint, tgt: GLuint) -> Result<()> {
         let fb = &mut self.framebuffers[0];
-        fb.attach_texture(tgt)
-          .expect("Failed to attach target texture to framebuffer");
+        fb.attach_texture(tgt)?;
         fb.bind_fbo();
         self.copy_program.activate();
+        // zero iterations still apply the opacity of the blurred image
+        self.copy_program.set_uniform_1f("opacity", self.opacity)?;
 
         unsafe {
             gl::Clear(gl::COLOR_BUFFER_BIT);
@@ -238,13 +508,99 @@ impl BlurContext {
 
         self.copy_program.unbind();
         fb.unbind_fbo();
+
+        Ok(())
     }
 
-    pub fn blur(&mut self, source_tex: &ImgSurface, target_quad: &GLQuad) {
-        let (src_width, src_height) = source_tex.size();
+    fn dual_filter(&mut self, quad: &mut Quad, src: GLuint, tgt: GLuint) -> Result<()> {
+        // Attach target texture to framebuffer
+        self.framebuffers[0].attach_texture(tgt)?;
+
+        // Downsample
+        self.down_program.activate();
+        self.down_program
+            .set_uniform_1f("offset", self.offset() as f32)?;
+        set_kernel_params(&mut self.down_program, self.down_kernel.as_ref())?;
+
+        for iteration in 0..MAX_ITERATIONS.min(self.iterations() as usize) {
+            let (tgt_width, tgt_height) = self.framebuffers[iteration + 1].size();
+
+            self.down_program
+                .set_uniform_1i("iteration", iteration as i32)?;
+            self.down_program.set_uniform_2f("halfpixel",
+                                              (0.5 / tgt_width as f32, 0.5 / tgt_height as f32))?;
+
+            self.framebuffers[iteration + 1].bind_fbo();
+
+            if iteration == 0 {
+                // first iteration: copy from source
+                unsafe {
+                    gl::BindTexture(gl::TEXTURE_2D, src);
+                }
+            } else {
+                // copy from last iteration
+                self.framebuffers[iteration].bind_tex();
+            }
+            unsafe {
+                gl::Clear(gl::COLOR_BUFFER_BIT);
+            }
+
+            // draw texture to fbo
+            quad.draw(false);
+        }
+
+        self.down_program.unbind();
+
+        // Upsample
+        self.up_program.activate();
+        self.up_program
+            .set_uniform_1f("offset", self.offset() as f32)?;
+        set_kernel_params(&mut self.up_program, self.up_kernel.as_ref())?;
+
+        for iteration in (0..MAX_ITERATIONS.min(self.iterations() as usize)).rev() {
+            let (tgt_width, tgt_height) = self.framebuffers[iteration].size();
+
+            self.up_program
+                .set_uniform_1i("iteration", iteration as i32)?;
+            self.up_program.set_uniform_2f("halfpixel",
+                                            (0.5 / tgt_width as f32, 0.5 / tgt_height as f32))?;
+            if self.up_program.has_uniform("opacity") {
+                let opacity = if iteration == 0 { self.opacity } else { 1.0 };
+                self.up_program.set_uniform_1f("opacity", opacity)?;
+            }
+
+            self.framebuffers[iteration].bind_fbo();
+
+            // draw texture to fbo
+            unsafe {
+                gl::Clear(gl::COLOR_BUFFER_BIT);
+            }
+            self.framebuffers[iteration + 1].bind_tex();
+            quad.draw(false);
+        }
+
+        self.framebuffers[0].unbind_fbo();
+        self.framebuffers[0].unbind_tex();
+        self.up_program.unbind();
+
+        Ok(())
+    }
+
+    pub fn blur(&mut self, source: &ImgSurface, target_quad: &GLQuad) -> Result<()> {
+        self.blur_texture(source.texture(), source.size(), target_quad)
+    }
+
+    /// Blur the texture `src_tex` of `src_size` into the texture of `target_quad`.
+    pub fn blur_texture(&mut self,
+                        src_tex: GLuint,
+                        src_size: (u32, u32),
+                        target_quad: &GLQuad)
+                        -> Result<()> {
+        let (src_width, src_height) = src_size;
 
         let vp = Viewport::from_window(target_quad.width(), target_quad.height());
         let mut quad = Quad::new(0, 0, src_width, src_height, vp.size(), true, true);
+        quad.set_label("blur quad");
 
         vp.activate();
 
@@ -257,87 +613,14 @@ impl BlurContext {
 
         let cpu_time_start = ProcessTime::now();
 
-        if self.iterations() == 0 {
-            self.copy(&mut quad, source_tex.texture(), *target_quad.texture());
+        let result = if self.iterations() == 0 {
+            self.copy(&mut quad, src_tex, *target_quad.texture())
         } else {
-            // Attach target texture to framebuffer
-            self.framebuffers[0].attach_texture(*target_quad.texture())
-                                .expect("Failed to attach target texture to framebuffer");
+            self.dual_filter(&mut quad, src_tex, *target_quad.texture())
+        };
 
-            // Downsample
-            self.down_program.activate();
-            self.down_program
-                .set_uniform_1f("offset", self.offset() as f32)
-                .expect("Cannot set downsample uniform");
-
-            for iteration in 0..MAX_ITERATIONS.min(self.iterations() as usize) {
-                let (tgt_width, tgt_height) = self.framebuffers[iteration + 1].size();
-
-                self.down_program
-                    .set_uniform_1i("iteration", iteration as i32)
-                    .expect("Cannot set downsample uniform");
-                self.down_program
-                    .set_uniform_2f("halfpixel",
-                                    (0.5 / tgt_width as f32, 0.5 / tgt_height as f32))
-                    .expect("Cannot set downsample uniform");
-
-                self.framebuffers[iteration + 1].bind_fbo();
-
-                if iteration == 0 {
-                    // first iteration: copy from source
-                    unsafe {
-                        gl::BindTexture(gl::TEXTURE_2D, source_tex.texture());
-                    }
-                } else {
-                    // copy from last iteration
-                    self.framebuffers[iteration].bind_tex();
-                }
-                unsafe {
-                    gl::Clear(gl::COLOR_BUFFER_BIT);
-                }
-
-                // draw texture to fbo
-                quad.draw(false);
-            }
-
-            self.down_program.unbind();
-
-            // Upsample
-            self.up_program.activate();
-            self.up_program
-                .set_uniform_1f("offset", self.offset() as f32)
-                .expect("Cannot set upsample uniform");
-            self.up_program
-                .set_uniform_1f("opacity", 1.0)
-                .expect("Cannot set upsample uniform");
-
-            for iteration in (0..MAX_ITERATIONS.min(self.iterations() as usize)).rev() {
-                let (tgt_width, tgt_height) = self.framebuffers[iteration].size();
-
-                self.up_program
-                    .set_uniform_1i("iteration", iteration as i32)
-                    .expect("Cannot set upsample uniform");
-                self.up_program
-                    .set_uniform_2f("halfpixel",
-                                    (0.5 / tgt_width as f32, 0.5 / tgt_height as f32))
-                    .expect("Cannot set upsample uniform");
-                // self.up_program
-                //    .set_uniform_1f("opacity", 1.0)
-                //    .expect("Cannot set upsample uniform");
-
-                self.framebuffers[iteration].bind_fbo();
-
-                // draw texture to fbo
-                unsafe {
-                    gl::Clear(gl::COLOR_BUFFER_BIT);
-                }
-                self.framebuffers[iteration + 1].bind_tex();
-                quad.draw(false);
-            }
-
-            self.framebuffers[0].unbind_fbo();
-            self.framebuffers[0].unbind_tex();
-            self.up_program.unbind();
+        if result.is_err() {
+            reset_bindings();
         }
 
         // Stop timer
@@ -357,5 +640,12 @@ impl BlurContext {
             gl::GetQueryObjectui64v(gpu_time_query, gl::QUERY_RESULT, &mut self.time_gpu);
             gl::DeleteQueries(1, &gpu_time_query);
         }
+        debug::check_error("BlurContext::blur");
+
+        if result.is_ok() && self.log_records {
+            self.log_record((src_width, src_height), target_quad.size());
+        }
+
+        result
     }
 }
@@ -0,0 +1,61 @@
+// This file is part auf the dual-kawase-demo package.
+//
+// (c) 2019 Bernd Busse
+//
+// For the full copyright and license information, please view the README.md file
+// that was distributed with this source code.
+//
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use toml::value::Table;
+
+use crate::renderer_gl::{Error, Result};
+
+const APP_NAME: &str = "dual-kawase-demo";
+
+/// Directory of the configuration files, `$XDG_CONFIG_HOME/dual-kawase-demo`.
+pub fn config_dir() -> Option<PathBuf> {
+    let base = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(env::var_os("HOME")?).join(".config"),
+    };
+
+    Some(base.join(APP_NAME))
+}
+
+/// Directory of the saved session, `$XDG_STATE_HOME/dual-kawase-demo`.
+pub fn state_dir() -> Option<PathBuf> {
+    let base = match env::var_os("XDG_STATE_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(env::var_os("HOME")?).join(".local").join("state"),
+    };
+
+    Some(base.join(APP_NAME))
+}
+
+/// Configuration file `name` in the configuration directory, if it exists.
+pub fn default_file(name: &str) -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join(name))
+                .filter(|path| path.is_file())
+}
+
+/// Read the top level table of a TOML file.
+pub fn read_toml(path: &Path) -> Result<Table> {
+    let text = fs::read_to_string(path).map_err(|err| Error::io(path, err))?;
+    text.parse::<toml::Value>()
+        .map_err(|err| Error::config(path, err.to_string()))
+        .and_then(|value| match value {
+            toml::Value::Table(table) => Ok(table),
+            _ => Err(Error::config(path, "Expected a table")),
+        })
+}
+
+/// TOML allows integers where floats are expected in hand written files.
+pub fn as_float(value: &toml::Value) -> Option<f32> {
+    value.as_float()
+         .or_else(|| value.as_integer().map(|n| n as f64))
+         .map(|v| v as f32)
+}
@@ -0,0 +1,152 @@
+// This file is part auf the dual-kawase-demo package.
+//
+// (c) 2019 Bernd Busse
+//
+// For the full copyright and license information, please view the README.md file
+// that was distributed with this source code.
+//
+
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use crate::image_io::{self, SaveOptions};
+use crate::renderer_gl::{Error, Pixels, ReadbackQueue, Result};
+
+/// Number of encoded images which may wait for a free worker.
+const QUEUE_SIZE: usize = 8;
+
+/// Result of an encoding job.
+pub struct Completion {
+    pub path: PathBuf,
+    pub result: Result<()>,
+}
+
+/// Pool of worker threads encoding image files.
+///
+/// Jobs are passed through a bounded queue, `submit` fails instead of blocking if it is full.
+pub struct EncoderPool {
+    jobs: Option<SyncSender<Pixels>>,
+    completed: Receiver<Completion>,
+    in_flight: Arc<AtomicUsize>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl EncoderPool {
+    pub fn new(num_workers: usize, options: SaveOptions) -> Self {
+        let (job_tx, job_rx) = mpsc::sync_channel::<Pixels>(QUEUE_SIZE);
+        let (done_tx, done_rx) = mpsc::channel();
+        let job_rx = Arc::new(Mutex::new(job_rx));
+
+        let mut workers = Vec::with_capacity(num_workers.max(1));
+        for i in 0..num_workers.max(1) {
+            let jobs = job_rx.clone();
+            let done = done_tx.clone();
+            let handle = thread::Builder::new().name(format!("encoder-{}", i))
+                                               .spawn(move || worker(&jobs, &done, options))
+                                               .expect("Cannot spawn encoder thread");
+            workers.push(handle);
+        }
+
+        Self { jobs: Some(job_tx),
+               completed: done_rx,
+               in_flight: Arc::new(AtomicUsize::new(0)),
+               workers }
+    }
+
+    /// Pool with one worker per available CPU.
+    pub fn with_available_cpus(options: SaveOptions) -> Self {
+        Self::new(thread::available_parallelism().map_or(2, |n| n.get()),
+                  options)
+    }
+
+    pub fn has_capacity(&self) -> bool {
+        self.in_flight.load(Ordering::Relaxed) < QUEUE_SIZE
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.in_flight.load(Ordering::Relaxed) == 0
+    }
+
+    /// Queue `pixels` for encoding, returns them back if the queue is full.
+    pub fn submit(&self, pixels: Pixels) -> std::result::Result<(), Pixels> {
+        let jobs = self.jobs.as_ref().expect("Encoder pool already shut down");
+        match jobs.try_send(pixels) {
+            Ok(()) => {
+                self.in_flight.fetch_add(1, Ordering::Relaxed);
+                Ok(())
+            },
+            Err(TrySendError::Full(pixels)) | Err(TrySendError::Disconnected(pixels)) => {
+                Err(pixels)
+            },
+        }
+    }
+
+    /// Return all jobs finished since the last call.
+    pub fn poll(&self) -> Vec<Completion> {
+        let completed: Vec<Completion> = self.completed.try_iter().collect();
+        self.in_flight.fetch_sub(completed.len(), Ordering::Relaxed);
+
+        completed
+    }
+}
+
+impl Drop for EncoderPool {
+    fn drop(&mut self) {
+        // closing the queue stops the workers after the remaining jobs
+        self.jobs = None;
+        for handle in self.workers.drain(..) {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// Hand finished readbacks to the encoder pool and pass the finished jobs to `done`.
+///
+/// A readback which does not fit into the full queue is kept in `waiting` for the next call.
+/// Failed readbacks are passed to `done` right away.
+pub fn process_saves<F>(readbacks: &mut ReadbackQueue,
+                        waiting: &mut Option<Pixels>,
+                        encoder: &EncoderPool,
+                        mut done: F)
+    where F: FnMut(Completion)
+{
+    while encoder.has_capacity() {
+        let pixels = match waiting.take().map(Ok).or_else(|| readbacks.poll()) {
+            Some(Ok(pixels)) => pixels,
+            Some(Err(path)) => {
+                done(Completion { result: Err(Error::Readback(path.clone())),
+                                  path });
+                continue;
+            },
+            None => break,
+        };
+        if let Err(pixels) = encoder.submit(pixels) {
+            *waiting = Some(pixels);
+            break;
+        }
+    }
+
+    for completion in encoder.poll() {
+        done(completion);
+    }
+}
+
+fn worker(jobs: &Mutex<Receiver<Pixels>>, done: &mpsc::Sender<Completion>, options: SaveOptions) {
+    loop {
+        let pixels = match jobs.lock().unwrap().recv() {
+            Ok(pixels) => pixels,
+            Err(_) => break,
+        };
+
+        let result = image_io::save_pixels(&pixels, &options);
+        if done.send(Completion { path: pixels.path,
+                                  result })
+               .is_err()
+        {
+            break;
+        }
+    }
+}
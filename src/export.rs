@@ -0,0 +1,135 @@
+// This file is part auf the dual-kawase-demo package.
+//
+// (c) 2019 Bernd Busse
+//
+// For the full copyright and license information, please view the README.md file
+// that was distributed with this source code.
+//
+
+use std::borrow::Cow;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use image::FilterType;
+
+use crate::blur::BlurContext;
+use crate::image_io::SourceImage;
+use crate::renderer_gl::{self, Pixels, ReadbackQueue, Result, SampleFormat, TextureQuad};
+use crate::tiling;
+
+/// Size of exported images.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportSize {
+    /// Resolution of the source image.
+    Source,
+    /// Width in pixels, the height keeps the aspect ratio of the source image.
+    Width(u32),
+    /// Exact width and height in pixels.
+    Exact(u32, u32),
+}
+
+impl ExportSize {
+    pub fn resolve(self, source: (u32, u32)) -> (u32, u32) {
+        match self {
+            ExportSize::Source => source,
+            ExportSize::Width(width) => {
+                let height = (u64::from(width) * u64::from(source.1) / u64::from(source.0)) as u32;
+                (width, height.max(1))
+            },
+            ExportSize::Exact(width, height) => (width, height),
+        }
+    }
+}
+
+impl FromStr for ExportSize {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let error = || {
+            format!("Invalid export size '{}', expected 'source', WIDTH or WIDTHxHEIGHT",
+                    s)
+        };
+        let parse = |v: &str| match v.parse::<u32>() {
+            Ok(value) if value > 0 => Ok(value),
+            _ => Err(error()),
+        };
+
+        if s == "source" {
+            return Ok(ExportSize::Source);
+        }
+        match s.find('x') {
+            Some(idx) => Ok(ExportSize::Exact(parse(&s[..idx])?, parse(&s[idx + 1..])?)),
+            None => Ok(ExportSize::Width(parse(s)?)),
+        }
+    }
+}
+
+/// Blur `img` offscreen at `size` and queue the result to be saved to `path` with samples of
+/// `format`.
+///
+/// The offset is scaled by the ratio of `size` to the `display_width` the image is shown at,
+/// so the export looks like the preview. Images larger than `GL_MAX_TEXTURE_SIZE` are blurred
+/// in overlapping tiles.
+pub fn export(blur_ctx: &mut BlurContext,
+              readbacks: &mut ReadbackQueue,
+              img: &SourceImage,
+              size: (u32, u32),
+              display_width: u32,
+              path: PathBuf,
+              format: SampleFormat)
+              -> Result<()> {
+    let display_offset = blur_ctx.offset();
+    blur_ctx.set_offset(display_offset * size.0 as f32 / display_width.max(1) as f32);
+
+    log::info!(target: "blur",
+               "Export {}x{} image with offset {:.02}",
+               size.0,
+               size.1,
+               blur_ctx.offset());
+    // the displayed blur keeps its framebuffers and timings
+    let result =
+        blur_ctx.offscreen(|blur_ctx| render(blur_ctx, readbacks, img, size, path, format));
+
+    blur_ctx.set_offset(display_offset);
+    result
+}
+
+fn render(blur_ctx: &mut BlurContext,
+          readbacks: &mut ReadbackQueue,
+          img: &SourceImage,
+          size: (u32, u32),
+          path: PathBuf,
+          format: SampleFormat)
+          -> Result<()> {
+    let img = if img.dimensions() == size {
+        Cow::Borrowed(img)
+    } else {
+        Cow::Owned(img.resize_exact(size.0, size.1, FilterType::CatmullRom))
+    };
+
+    let overlap = tiling::blur_overlap(blur_ctx.iterations(), blur_ctx.offset());
+    let tiles = tiling::split(size,
+                              renderer_gl::max_texture_size(),
+                              overlap,
+                              tiling::tile_alignment(blur_ctx.iterations()))?;
+
+    if tiles.len() == 1 {
+        let target = tiling::blur_tile(blur_ctx, &img, format)?;
+        return readbacks.queue(*target.texture(), path, format);
+    }
+
+    log::info!(target: "blur",
+               "Blur in {} tiles with {}px overlap",
+               tiles.len(),
+               overlap);
+    let data = tiling::blur_tiled(blur_ctx, &img, &tiles, format)?;
+
+    readbacks.push_ready(Pixels { path,
+                                  data,
+                                  width: size.0,
+                                  height: size.1,
+                                  format,
+                                  icc_profile: readbacks.icc_profile() });
+
+    Ok(())
+}
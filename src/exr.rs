@@ -0,0 +1,740 @@
+// This file is part auf the dual-kawase-demo package.
+//
+// (c) 2019 Bernd Busse
+//
+// For the full copyright and license information, please view the README.md file
+// that was distributed with this source code.
+//
+
+use std::convert::{TryFrom, TryInto};
+
+use image::{ImageBuffer, ImageError, ImageResult};
+
+use crate::image_io::Rgba32FImage;
+
+const MAGIC: [u8; 4] = [0x76, 0x2f, 0x31, 0x01];
+/// File format version, the flags are stored in the upper bits of the version field.
+const VERSION: u32 = 2;
+const FLAG_TILED: u32 = 0x200;
+const FLAG_DEEP: u32 = 0x800;
+const FLAG_MULTIPART: u32 = 0x1000;
+
+/// Largest decoded image, in pixels.
+const MAX_PIXELS: i64 = 1 << 28;
+
+/// Channel names of the RGBA samples in the order of the channel list of saved files.
+const SAVED_CHANNELS: [(&str, usize); 4] = [("A", 3), ("B", 2), ("G", 1), ("R", 0)];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum PixelType {
+    Uint,
+    Half,
+    Float,
+}
+
+impl PixelType {
+    fn from_i32(value: i32) -> ImageResult<Self> {
+        match value {
+            0 => Ok(PixelType::Uint),
+            1 => Ok(PixelType::Half),
+            2 => Ok(PixelType::Float),
+            _ => Err(format_error(&format!("unknown pixel type {}", value))),
+        }
+    }
+
+    fn size(self) -> usize {
+        match self {
+            PixelType::Half => 2,
+            PixelType::Uint | PixelType::Float => 4,
+        }
+    }
+
+    /// Sample at the start of `data` as float.
+    fn sample(self, data: &[u8]) -> f32 {
+        match self {
+            PixelType::Uint => le_u32(data) as f32,
+            PixelType::Half => half_to_f32(u16::from_le_bytes([data[0], data[1]])),
+            PixelType::Float => f32::from_bits(le_u32(data)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Compression {
+    None,
+    Rle,
+    Zips,
+    Zip,
+}
+
+impl Compression {
+    fn from_u8(value: u8) -> ImageResult<Self> {
+        let name = match value {
+            0 => return Ok(Compression::None),
+            1 => return Ok(Compression::Rle),
+            2 => return Ok(Compression::Zips),
+            3 => return Ok(Compression::Zip),
+            4 => "PIZ",
+            5 => "PXR24",
+            6 => "B44",
+            7 => "B44A",
+            8 => "DWAA",
+            9 => "DWAB",
+            _ => return Err(format_error(&format!("unknown compression {}", value))),
+        };
+
+        Err(ImageError::UnsupportedError(format!("OpenEXR {} compression is not supported, \
+                                                  use ZIP, RLE or no compression",
+                                                 name)))
+    }
+
+    /// Number of scanlines compressed together.
+    fn lines_per_block(self) -> i32 {
+        match self {
+            Compression::None | Compression::Rle | Compression::Zips => 1,
+            Compression::Zip => 16,
+        }
+    }
+}
+
+struct Channel {
+    name: String,
+    kind: PixelType,
+}
+
+/// Attributes of the header needed to decode the pixels.
+struct Header {
+    channels: Vec<Channel>,
+    compression: Compression,
+    /// `x_min`, `y_min`, `x_max` and `y_max` of the data window, inclusive.
+    data_window: [i32; 4],
+}
+
+impl Header {
+    fn width(&self) -> usize {
+        (self.data_window[2] - self.data_window[0] + 1) as usize
+    }
+
+    fn height(&self) -> usize {
+        (self.data_window[3] - self.data_window[1] + 1) as usize
+    }
+
+    /// Bytes of one uncompressed scanline of all channels.
+    fn line_size(&self) -> usize {
+        self.channels
+            .iter()
+            .map(|channel| channel.kind.size() * self.width())
+            .sum()
+    }
+}
+
+/// Bounds checked reader of little endian values.
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8], pos: usize) -> Self {
+        Self { data, pos }
+    }
+
+    fn bytes(&mut self, len: usize) -> ImageResult<&'a [u8]> {
+        let bytes = self.pos
+                        .checked_add(len)
+                        .and_then(|end| self.data.get(self.pos..end))
+                        .ok_or_else(|| format_error("unexpected end of file"))?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn i32(&mut self) -> ImageResult<i32> {
+        Ok(le_u32(self.bytes(4)?) as i32)
+    }
+
+    fn u64(&mut self) -> ImageResult<u64> {
+        Ok(u64::from_le_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+
+    /// Null terminated string.
+    fn string(&mut self) -> ImageResult<&'a str> {
+        let rest = self.data.get(self.pos..).unwrap_or(&[]);
+        let len = rest.iter()
+                      .position(|&b| b == 0)
+                      .ok_or_else(|| format_error("unterminated string"))?;
+        let bytes = self.bytes(len + 1)?;
+        std::str::from_utf8(&bytes[..len]).map_err(|_| format_error("invalid string"))
+    }
+}
+
+/// Whether `data` starts like an OpenEXR file.
+pub fn is_exr(data: &[u8]) -> bool {
+    data.starts_with(&MAGIC)
+}
+
+/// Decode a single part scanline OpenEXR file into linear RGBA floats.
+///
+/// The `R`, `G`, `B` and `A` channels are read, images with a `Y` channel only are gray. All
+/// other channels are ignored, missing color channels are zero and missing alpha is opaque.
+pub fn decode(data: &[u8]) -> ImageResult<Rgba32FImage> {
+    let (header, table) = read_header(data)?;
+    let mut reader = Reader::new(data, table);
+
+    let targets: Vec<&[usize]> = header.channels
+                                       .iter()
+                                       .map(|channel| match channel.name.as_str() {
+                                           "R" => &[0][..],
+                                           "G" => &[1][..],
+                                           "B" => &[2][..],
+                                           "A" => &[3][..],
+                                           "Y" => &[0, 1, 2][..],
+                                           _ => &[][..],
+                                       })
+                                       .collect();
+    if !targets.iter().flat_map(|t| t.iter()).any(|&i| i < 3) {
+        let msg = "OpenEXR image has no RGB or Y channels";
+        return Err(ImageError::UnsupportedError(msg.to_owned()));
+    }
+
+    let (width, height) = (header.width(), header.height());
+    let lines = header.compression.lines_per_block();
+    let num_blocks = height.div_ceil(lines as usize);
+    let mut offsets = Vec::with_capacity(num_blocks);
+    for _ in 0..num_blocks {
+        offsets.push(reader.u64()?);
+    }
+
+    let mut samples = vec![0.0f32; width * height * 4];
+    for pixel in samples.chunks_exact_mut(4) {
+        pixel[3] = 1.0;
+    }
+
+    let (y_min, y_max) = (header.data_window[1], header.data_window[3]);
+    for offset in offsets {
+        let offset = usize::try_from(offset).map_err(|_| format_error("invalid chunk offset"))?;
+        let mut chunk = Reader::new(data, offset);
+        let y = chunk.i32()?;
+        if y < y_min || y > y_max || (y - y_min) % lines != 0 {
+            return Err(format_error(&format!("invalid chunk at line {}", y)));
+        }
+        let size = usize::try_from(chunk.i32()?).map_err(|_| format_error("invalid chunk"))?;
+        let packed = chunk.bytes(size)?;
+
+        let block_lines = lines.min(y_max - y + 1) as usize;
+        let raw = uncompress(header.compression, packed, header.line_size() * block_lines)?;
+
+        let mut pos = 0;
+        for line in 0..block_lines {
+            let row = (y - y_min) as usize + line;
+            for (channel, target) in header.channels.iter().zip(targets.iter()) {
+                let size = channel.kind.size();
+                for x in 0..width {
+                    let value = channel.kind.sample(&raw[pos + x * size..]);
+                    for &i in target.iter() {
+                        samples[(row * width + x) * 4 + i] = value;
+                    }
+                }
+                pos += width * size;
+            }
+        }
+    }
+
+    Ok(ImageBuffer::from_raw(width as u32, height as u32, samples).unwrap())
+}
+
+/// Header of `data` and the position of the offset table behind it.
+fn read_header(data: &[u8]) -> ImageResult<(Header, usize)> {
+    let mut reader = Reader::new(data, 0);
+    if reader.bytes(4)? != MAGIC {
+        return Err(format_error("not an OpenEXR file"));
+    }
+    let version = le_u32(reader.bytes(4)?);
+    if version & 0xff != VERSION {
+        return Err(format_error(&format!("unknown version {}", version & 0xff)));
+    }
+    if version & (FLAG_TILED | FLAG_DEEP | FLAG_MULTIPART) != 0 {
+        let msg = "Tiled, deep and multi-part OpenEXR images are not supported";
+        return Err(ImageError::UnsupportedError(msg.to_owned()));
+    }
+
+    let mut channels = None;
+    let mut compression = None;
+    let mut data_window = None;
+    loop {
+        let name = reader.string()?;
+        if name.is_empty() {
+            break;
+        }
+        let _kind = reader.string()?;
+        let size = usize::try_from(reader.i32()?).map_err(|_| format_error("invalid attribute"))?;
+        let value = reader.bytes(size)?;
+
+        match name {
+            "channels" => channels = Some(read_channels(value)?),
+            "compression" => {
+                let value = value.first().ok_or_else(|| format_error("invalid compression"))?;
+                compression = Some(Compression::from_u8(*value)?);
+            },
+            "dataWindow" => {
+                let mut window = Reader::new(value, 0);
+                data_window = Some([window.i32()?, window.i32()?, window.i32()?, window.i32()?]);
+            },
+            _ => (),
+        }
+    }
+
+    let missing = |name: &str| format_error(&format!("missing {}", name));
+    let header = Header { channels: channels.ok_or_else(|| missing("channels"))?,
+                          compression: compression.ok_or_else(|| missing("compression"))?,
+                          data_window: data_window.ok_or_else(|| missing("data window"))? };
+
+    let [x_min, y_min, x_max, y_max] = header.data_window;
+    let width = i64::from(x_max) - i64::from(x_min) + 1;
+    let height = i64::from(y_max) - i64::from(y_min) + 1;
+    if width < 1 || height < 1 {
+        return Err(format_error("empty data window"));
+    }
+    if width > MAX_PIXELS / height {
+        return Err(ImageError::DimensionError);
+    }
+
+    Ok((header, reader.pos))
+}
+
+fn read_channels(value: &[u8]) -> ImageResult<Vec<Channel>> {
+    let mut reader = Reader::new(value, 0);
+    let mut channels = Vec::new();
+    loop {
+        let name = reader.string()?;
+        if name.is_empty() {
+            break;
+        }
+        let kind = PixelType::from_i32(reader.i32()?)?;
+        // linear flag and reserved bytes
+        reader.bytes(4)?;
+        let sampling = (reader.i32()?, reader.i32()?);
+        if sampling != (1, 1) {
+            let msg = format!("Subsampled OpenEXR channel '{}' is not supported", name);
+            return Err(ImageError::UnsupportedError(msg));
+        }
+
+        channels.push(Channel { name: name.to_owned(),
+                                kind });
+    }
+
+    Ok(channels)
+}
+
+/// Uncompress the data of a chunk into `size` bytes.
+fn uncompress(compression: Compression, packed: &[u8], size: usize) -> ImageResult<Vec<u8>> {
+    // chunks which do not get smaller are stored uncompressed
+    if packed.len() == size {
+        return Ok(packed.to_vec());
+    }
+
+    let data = match compression {
+        Compression::None => return Err(format_error("invalid chunk size")),
+        Compression::Rle => rle_uncompress(packed, size)?,
+        Compression::Zips | Compression::Zip => {
+            inflate::inflate_bytes_zlib(packed).map_err(|err| format_error(&err))?
+        },
+    };
+    if data.len() != size {
+        return Err(format_error("invalid chunk size"));
+    }
+
+    Ok(unpredict(data))
+}
+
+fn rle_uncompress(packed: &[u8], size: usize) -> ImageResult<Vec<u8>> {
+    let mut data = Vec::with_capacity(size);
+    let mut pos = 0;
+    while pos < packed.len() && data.len() <= size {
+        let count = packed[pos] as i8;
+        pos += 1;
+        if count < 0 {
+            let literal = packed.get(pos..pos + count.unsigned_abs() as usize)
+                                .ok_or_else(|| format_error("truncated RLE data"))?;
+            data.extend_from_slice(literal);
+            pos += literal.len();
+        } else {
+            let value = *packed.get(pos)
+                               .ok_or_else(|| format_error("truncated RLE data"))?;
+            data.resize(data.len() + count as usize + 1, value);
+            pos += 1;
+        }
+    }
+
+    Ok(data)
+}
+
+/// Reorder the bytes into the even and odd halves and store their differences, so they
+/// compress better. Both ZIP and RLE compression do this.
+fn predict(raw: &[u8]) -> Vec<u8> {
+    let half = raw.len().div_ceil(2);
+    let mut data = vec![0; raw.len()];
+    for (i, &value) in raw.iter().enumerate() {
+        data[i / 2 + if i % 2 == 0 { 0 } else { half }] = value;
+    }
+    for i in (1..data.len()).rev() {
+        data[i] = data[i].wrapping_sub(data[i - 1]).wrapping_add(128);
+    }
+
+    data
+}
+
+/// Reverse `predict`.
+fn unpredict(mut data: Vec<u8>) -> Vec<u8> {
+    for i in 1..data.len() {
+        data[i] = data[i - 1].wrapping_add(data[i]).wrapping_sub(128);
+    }
+
+    let (even, odd) = data.split_at(data.len().div_ceil(2));
+    let mut raw = Vec::with_capacity(data.len());
+    for (i, &value) in even.iter().enumerate() {
+        raw.push(value);
+        raw.extend(odd.get(i));
+    }
+
+    raw
+}
+
+/// Encode linear RGBA float samples as OpenEXR file with 32 bit float channels and ZIP
+/// compression.
+pub fn encode(width: u32, height: u32, samples: &[f32]) -> Vec<u8> {
+    let window = |out: &mut Vec<u8>| {
+        for value in &[0, 0, width as i32 - 1, height as i32 - 1] {
+            out.extend_from_slice(&value.to_le_bytes());
+        }
+    };
+
+    let mut out = MAGIC.to_vec();
+    out.extend_from_slice(&VERSION.to_le_bytes());
+
+    let mut channels = Vec::new();
+    for (name, _) in SAVED_CHANNELS.iter() {
+        channels.extend_from_slice(name.as_bytes());
+        channels.push(0);
+        channels.extend_from_slice(&2i32.to_le_bytes());
+        // not linear, reserved, x and y sampling
+        channels.extend_from_slice(&[0; 4]);
+        channels.extend_from_slice(&1i32.to_le_bytes());
+        channels.extend_from_slice(&1i32.to_le_bytes());
+    }
+    channels.push(0);
+    let mut data_window = Vec::new();
+    window(&mut data_window);
+
+    write_attribute(&mut out, "channels", "chlist", &channels);
+    write_attribute(&mut out, "compression", "compression", &[3]);
+    write_attribute(&mut out, "dataWindow", "box2i", &data_window);
+    write_attribute(&mut out, "displayWindow", "box2i", &data_window);
+    // increasing y
+    write_attribute(&mut out, "lineOrder", "lineOrder", &[0]);
+    write_attribute(&mut out, "pixelAspectRatio", "float", &1.0f32.to_le_bytes());
+    write_attribute(&mut out, "screenWindowCenter", "v2f", &[0; 8]);
+    write_attribute(&mut out, "screenWindowWidth", "float", &1.0f32.to_le_bytes());
+    out.push(0);
+
+    let (width, height) = (width as usize, height as usize);
+    let lines = Compression::Zip.lines_per_block() as usize;
+    let table = out.len();
+    out.resize(table + height.div_ceil(lines) * 8, 0);
+
+    for (block, y) in (0..height).step_by(lines).enumerate() {
+        let mut raw = Vec::with_capacity(lines * width * 16);
+        for row in y..height.min(y + lines) {
+            let pixels = &samples[row * width * 4..(row + 1) * width * 4];
+            for (_, i) in SAVED_CHANNELS.iter() {
+                for pixel in pixels.chunks_exact(4) {
+                    raw.extend_from_slice(&pixel[*i].to_le_bytes());
+                }
+            }
+        }
+
+        let packed = deflate::deflate_bytes_zlib(&predict(&raw));
+        let data = if packed.len() < raw.len() { &packed } else { &raw };
+
+        let offset = out.len() as u64;
+        out[table + block * 8..table + block * 8 + 8].copy_from_slice(&offset.to_le_bytes());
+        out.extend_from_slice(&(y as i32).to_le_bytes());
+        out.extend_from_slice(&(data.len() as i32).to_le_bytes());
+        out.extend_from_slice(data);
+    }
+
+    out
+}
+
+fn write_attribute(out: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+    out.extend_from_slice(name.as_bytes());
+    out.push(0);
+    out.extend_from_slice(kind.as_bytes());
+    out.push(0);
+    out.extend_from_slice(&(value.len() as i32).to_le_bytes());
+    out.extend_from_slice(value);
+}
+
+/// Convert an IEEE 754 half float to `f32`.
+fn half_to_f32(half: u16) -> f32 {
+    let sign = if half & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = u32::from(half >> 10 & 0x1f);
+    let mantissa = u32::from(half & 0x3ff);
+    match exponent {
+        // zero and subnormal values
+        0 => sign * mantissa as f32 * 2f32.powi(-24),
+        0x1f if mantissa == 0 => sign * f32::INFINITY,
+        0x1f => f32::NAN,
+        _ => {
+            let bits = u32::from(half & 0x8000) << 16 | (exponent + 112) << 23 | mantissa << 13;
+            f32::from_bits(bits)
+        },
+    }
+}
+
+fn format_error(msg: &str) -> ImageError {
+    ImageError::FormatError(format!("Invalid OpenEXR file: {}", msg))
+}
+
+fn le_u32(data: &[u8]) -> u32 {
+    u32::from_le_bytes(data[..4].try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HALF: i32 = 1;
+    const FLOAT: i32 = 2;
+
+    /// Scanline file with `channels` and the raw or packed data of each chunk.
+    fn file(compression: u8,
+            channels: &[(&str, i32)],
+            window: [i32; 4],
+            chunks: &[(i32, Vec<u8>)])
+            -> Vec<u8> {
+        let mut list = Vec::new();
+        for (name, kind) in channels {
+            list.extend_from_slice(name.as_bytes());
+            list.push(0);
+            list.extend_from_slice(&kind.to_le_bytes());
+            list.extend_from_slice(&[0, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0]);
+        }
+        list.push(0);
+        let window: Vec<u8> = window.iter().flat_map(|v| v.to_le_bytes().to_vec()).collect();
+
+        let mut out = MAGIC.to_vec();
+        out.extend_from_slice(&VERSION.to_le_bytes());
+        write_attribute(&mut out, "channels", "chlist", &list);
+        write_attribute(&mut out, "compression", "compression", &[compression]);
+        write_attribute(&mut out, "dataWindow", "box2i", &window);
+        write_attribute(&mut out, "displayWindow", "box2i", &window);
+        out.push(0);
+
+        let mut offset = out.len() + chunks.len() * 8;
+        for (_, data) in chunks {
+            out.extend_from_slice(&(offset as u64).to_le_bytes());
+            offset += 8 + data.len();
+        }
+        for (y, data) in chunks {
+            out.extend_from_slice(&y.to_le_bytes());
+            out.extend_from_slice(&(data.len() as i32).to_le_bytes());
+            out.extend_from_slice(data);
+        }
+
+        out
+    }
+
+    fn halves(values: &[u16]) -> Vec<u8> {
+        values.iter().flat_map(|v| v.to_le_bytes().to_vec()).collect()
+    }
+
+    /// RLE data of `raw`, a run for repeated bytes and literals for the rest.
+    fn rle_compress(raw: &[u8]) -> Vec<u8> {
+        let data = predict(raw);
+        let mut packed = Vec::new();
+        let mut pos = 0;
+        while pos < data.len() {
+            let run = data[pos..].iter()
+                                 .take(128)
+                                 .take_while(|&&b| b == data[pos])
+                                 .count();
+            if run >= 3 {
+                packed.extend_from_slice(&[(run - 1) as u8, data[pos]]);
+                pos += run;
+            } else {
+                let len = (data.len() - pos).min(127);
+                packed.push((-(len as i8)) as u8);
+                packed.extend_from_slice(&data[pos..pos + len]);
+                pos += len;
+            }
+        }
+
+        packed
+    }
+
+    /// Test pattern with values above one, negative values and a varying alpha.
+    fn pattern(width: u32, height: u32) -> Vec<f32> {
+        (0..width * height * 4).map(|i| {
+                                   let x = (i / 4 % width) as f32;
+                                   match i % 4 {
+                                       3 => (i % 7) as f32 / 6.0,
+                                       c => x * 0.37 - c as f32 + 1.5e-3 * i as f32,
+                                   }
+                               })
+                               .collect()
+    }
+
+    #[test]
+    fn convert_halves() {
+        assert_eq!(half_to_f32(0x0000), 0.0);
+        assert_eq!(half_to_f32(0x3c00), 1.0);
+        assert_eq!(half_to_f32(0xc000), -2.0);
+        assert_eq!(half_to_f32(0x3555), 0.333_251_95);
+        assert_eq!(half_to_f32(0x7bff), 65504.0);
+        assert_eq!(half_to_f32(0x0400), 2f32.powi(-14));
+        assert_eq!(half_to_f32(0x0001), 2f32.powi(-24));
+        assert_eq!(half_to_f32(0x83ff), -1023.0 * 2f32.powi(-24));
+        assert_eq!(half_to_f32(0x7c00), f32::INFINITY);
+        assert_eq!(half_to_f32(0xfc00), f32::NEG_INFINITY);
+        assert!(half_to_f32(0x7e00).is_nan());
+    }
+
+    #[test]
+    fn predictor_round_trip() {
+        for len in 0..20 {
+            let raw: Vec<u8> = (0..len).map(|i| (i * 37 + 200) as u8).collect();
+            assert_eq!(unpredict(predict(&raw)), raw);
+        }
+    }
+
+    #[test]
+    fn encode_round_trip() {
+        for &(width, height) in &[(1, 1), (37, 21), (16, 16), (5, 33)] {
+            let samples = pattern(width, height);
+            let decoded = decode(&encode(width, height, &samples)).unwrap();
+            assert_eq!(decoded.dimensions(), (width, height));
+            assert_eq!(decoded.into_raw(), samples);
+        }
+    }
+
+    #[test]
+    fn decode_half_channels() {
+        // 2x2 pixels, channels in alphabetical order and each line stored channel by channel
+        let chunks = [(5, halves(&[0x3c00, 0x0000, 0x3800, 0x4000, 0x0000, 0xbc00])),
+                      (6, halves(&[0x4400, 0x3c00, 0x0000, 0x0000, 0x3c00, 0x3c00]))];
+        let data = file(0, &[("B", HALF), ("G", HALF), ("R", HALF)], [-2, 5, -1, 6], &chunks);
+
+        let image = decode(&data).unwrap();
+        assert_eq!(image.dimensions(), (2, 2));
+        assert_eq!(image.into_raw(),
+                   vec![0.0, 0.5, 1.0, 1.0, -1.0, 2.0, 0.0, 1.0, //
+                        1.0, 0.0, 4.0, 1.0, 1.0, 0.0, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn decode_luminance_and_extra_channels() {
+        let floats = |values: &[f32]| -> Vec<u8> {
+            values.iter().flat_map(|v| v.to_le_bytes().to_vec()).collect()
+        };
+        let mut raw = floats(&[0.25, 0.5, 0.75]);
+        raw.extend(floats(&[7.0, 8.0, 9.0]));
+        let data = file(0, &[("Y", FLOAT), ("Z", FLOAT)], [0, 0, 2, 0], &[(0, raw)]);
+
+        assert_eq!(decode(&data).unwrap().into_raw(),
+                   vec![0.25, 0.25, 0.25, 1.0, 0.5, 0.5, 0.5, 1.0, 0.75, 0.75, 0.75, 1.0]);
+    }
+
+    #[test]
+    fn decode_rle_and_zips() {
+        let (width, height) = (40, 3);
+        let samples = pattern(width, height);
+        // half samples of each line, channel by channel in the order A, B, G, R
+        let lines: Vec<Vec<u8>> = samples.chunks_exact(width as usize * 4)
+                                         .map(|line| {
+                                             let mut values = Vec::new();
+                                             for c in (0..4).rev() {
+                                                 values.extend(line.iter()
+                                                                   .skip(c)
+                                                                   .step_by(4)
+                                                                   .map(|v| half_bits(*v)));
+                                             }
+                                             halves(&values)
+                                         })
+                                         .collect();
+        let channels = [("A", HALF), ("B", HALF), ("G", HALF), ("R", HALF)];
+        let window = [0, 0, width as i32 - 1, height as i32 - 1];
+
+        let rle: Vec<(i32, Vec<u8>)> =
+            lines.iter()
+                 .enumerate()
+                 .map(|(y, line)| (y as i32, rle_compress(line)))
+                 .collect();
+        let zips: Vec<(i32, Vec<u8>)> =
+            lines.iter()
+                 .enumerate()
+                 .map(|(y, line)| (y as i32, deflate::deflate_bytes_zlib(&predict(line))))
+                 .collect();
+        assert!(rle.iter().zip(lines.iter()).all(|((_, packed), line)| packed.len() != line.len()));
+
+        let expected: Vec<f32> = samples.iter().map(|v| half_to_f32(half_bits(*v))).collect();
+        for (compression, chunks) in &[(1, rle), (2, zips)] {
+            let image = decode(&file(*compression, &channels, window, chunks)).unwrap();
+            assert_eq!(image.into_raw(), expected, "compression {}", compression);
+        }
+    }
+
+    /// Half float of `value` rounded towards zero, for values in the normal range.
+    fn half_bits(value: f32) -> u16 {
+        let bits = value.to_bits();
+        if value == 0.0 {
+            return (bits >> 16) as u16 & 0x8000;
+        }
+        let exponent = ((bits >> 23) & 0xff) as i32 - 127 + 15;
+        (bits >> 16) as u16 & 0x8000 | (exponent as u16) << 10 | ((bits >> 13) & 0x3ff) as u16
+    }
+
+    #[test]
+    fn reject_unsupported_files() {
+        let unsupported = |data: &[u8]| match decode(data) {
+            Err(ImageError::UnsupportedError(_)) => (),
+            Err(err) => panic!("unexpected error {}", err),
+            Ok(_) => panic!("decoded an unsupported file"),
+        };
+
+        let raw = halves(&[0x3c00; 3]);
+        unsupported(&file(4, &[("R", HALF)], [0, 0, 2, 0], &[(0, raw.clone())]));
+        unsupported(&file(0, &[("Z", HALF)], [0, 0, 2, 0], &[(0, raw.clone())]));
+
+        let mut tiled = file(0, &[("R", HALF)], [0, 0, 2, 0], &[(0, raw)]);
+        tiled[5] |= (FLAG_TILED >> 8) as u8;
+        unsupported(&tiled);
+    }
+
+    #[test]
+    fn reject_broken_files() {
+        let data = encode(9, 20, &pattern(9, 20));
+        for len in 0..data.len() {
+            assert!(decode(&data[..len]).is_err(), "decoded {} of {} bytes", len, data.len());
+        }
+
+        // garbage behind the version, deterministic pseudo random bytes
+        let mut state = 0x1234_5678_u32;
+        for step in 1..200 {
+            let mut broken = data.clone();
+            for byte in broken[8..].iter_mut().step_by(step) {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                *byte ^= state as u8;
+            }
+            let _ = decode(&broken);
+        }
+
+        assert!(decode(&file(0, &[("R", HALF)], [0, 0, -1, 0], &[])).is_err());
+        assert!(decode(&file(0, &[("R", HALF)], [i32::MIN, 0, i32::MAX, 0], &[])).is_err());
+        // the chunk is one byte short
+        assert!(decode(&file(0, &[("R", HALF)], [0, 0, 2, 0], &[(0, vec![0; 5])])).is_err());
+        // line outside of the data window
+        assert!(decode(&file(0, &[("R", HALF)], [0, 0, 2, 0], &[(1, vec![0; 6])])).is_err());
+    }
+}
@@ -0,0 +1,330 @@
+// This file is part auf the dual-kawase-demo package.
+//
+// (c) 2019 Bernd Busse
+//
+// For the full copyright and license information, please view the README.md file
+// that was distributed with this source code.
+//
+
+use sdl2::event::Event;
+use sdl2::mouse::MouseButton;
+
+use crate::style::Corner;
+
+const PANEL_WIDTH: f32 = 260.0;
+const PANEL_PADDING: f32 = 8.0;
+const ROW_HEIGHT: f32 = 22.0;
+const ROW_SPACING: f32 = 4.0;
+const TEXT_INDENT: f32 = 6.0;
+/// Distance of the panel to the window edges.
+const MARGIN: (f32, f32) = (20.0, 50.0);
+
+const COLOR_PANEL: [f32; 4] = [0.0, 0.0, 0.0, 0.5];
+const COLOR_WIDGET: [f32; 4] = [1.0, 1.0, 1.0, 0.15];
+const COLOR_HOVER: [f32; 4] = [1.0, 1.0, 1.0, 0.3];
+const COLOR_FILL: [f32; 4] = [0.3, 0.5, 0.9, 0.8];
+const COLOR_TEXT: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+
+/// Rectangle of a single color, `[left, top, right, bottom]` in window coordinates.
+#[derive(Clone, Copy, Debug)]
+pub struct FlatRect {
+    pub bounds: [f32; 4],
+    pub color: [f32; 4],
+}
+
+/// Line of text, vertically centered on `position`.
+#[derive(Clone, Debug)]
+pub struct Label {
+    pub text: String,
+    pub position: (f32, f32),
+    pub color: [f32; 4],
+}
+
+/// Rectangles and labels of the widgets of one frame, drawn by the overlay.
+#[derive(Default)]
+pub struct DrawList {
+    pub rects: Vec<FlatRect>,
+    pub labels: Vec<Label>,
+}
+
+/// Immediate mode controls in a panel at a corner of the window, the top right by default.
+///
+/// Mouse events are fed in with `handle_event`, then the widgets are declared between `begin`
+/// and `end` every frame and report whether they were changed or clicked.
+pub struct Gui {
+    shown: bool,
+    enabled: bool,
+    corner: Corner,
+    /// Height of the panel in the last frame, which places a panel at the bottom.
+    height: f32,
+    origin: (f32, f32),
+    cursor: f32,
+    /// Bounds of the panel in the last frame, mouse events inside are consumed.
+    panel: [f32; 4],
+    mouse: (f32, f32),
+    down: bool,
+    pressed: bool,
+    released: bool,
+    /// Widget which got the mouse press, e.g. a dragged slider.
+    active: Option<&'static str>,
+    open_dropdown: Option<&'static str>,
+    draw_list: DrawList,
+}
+
+impl Gui {
+    pub fn new() -> Self {
+        Self { shown: true,
+               enabled: false,
+               corner: Corner::TopRight,
+               height: 0.0,
+               origin: (0.0, 0.0),
+               cursor: 0.0,
+               panel: [0.0; 4],
+               mouse: (-1.0, -1.0),
+               down: false,
+               pressed: false,
+               released: false,
+               active: None,
+               open_dropdown: None,
+               draw_list: DrawList::default() }
+    }
+
+    pub fn toggle(&mut self) {
+        self.shown = !self.shown;
+    }
+
+    pub fn shown(&self) -> bool {
+        self.shown
+    }
+
+    pub fn set_corner(&mut self, corner: Corner) {
+        self.corner = corner;
+    }
+
+    /// Track the mouse, returns `true` if the event belongs to the controls.
+    pub fn handle_event(&mut self, event: &Event) -> bool {
+        if !self.enabled {
+            return false;
+        }
+
+        match *event {
+            Event::MouseMotion { x, y, .. } => {
+                self.mouse = (x as f32, y as f32);
+                self.active.is_some()
+            },
+            Event::MouseButtonDown { mouse_btn: MouseButton::Left,
+                                     x,
+                                     y,
+                                     .. } => {
+                self.mouse = (x as f32, y as f32);
+                if contains(self.panel, self.mouse) {
+                    self.down = true;
+                    self.pressed = true;
+                    true
+                } else {
+                    self.open_dropdown = None;
+                    false
+                }
+            },
+            Event::MouseButtonUp { mouse_btn: MouseButton::Left,
+                                   x,
+                                   y,
+                                   .. } => {
+                self.mouse = (x as f32, y as f32);
+                let consumed = self.down;
+                self.down = false;
+                self.released = consumed;
+                consumed
+            },
+            _ => false,
+        }
+    }
+
+    /// Start the widgets of a frame in a window of `size`, `enabled` is `false` while the
+    /// controls are hidden by other parts of the overlay.
+    pub fn begin(&mut self, (width, height): (u32, u32), enabled: bool) {
+        self.enabled = enabled && self.shown;
+        let x = if self.corner.right() { width as f32 - MARGIN.0 - PANEL_WIDTH } else { MARGIN.0 };
+        let y = if self.corner.bottom() {
+            height as f32 - MARGIN.1 - self.height
+        } else {
+            MARGIN.1
+        };
+        self.origin = (x, y);
+        self.cursor = self.origin.1 + PANEL_PADDING;
+        self.draw_list.rects.clear();
+        self.draw_list.labels.clear();
+        if self.enabled {
+            // the size of the panel is set in `end`
+            self.draw_list.rects.push(FlatRect { bounds: [0.0; 4],
+                                                 color: COLOR_PANEL });
+        }
+    }
+
+    /// Finish the frame, the widgets are drawn from `draw_list`.
+    pub fn end(&mut self) {
+        if self.enabled {
+            self.panel = [self.origin.0,
+                          self.origin.1,
+                          self.origin.0 + PANEL_WIDTH,
+                          self.cursor - ROW_SPACING + PANEL_PADDING];
+            self.draw_list.rects[0].bounds = self.panel;
+            self.height = self.panel[3] - self.panel[1];
+        } else {
+            self.panel = [0.0; 4];
+            self.active = None;
+            self.open_dropdown = None;
+        }
+
+        if self.released {
+            self.active = None;
+        }
+        self.pressed = false;
+        self.released = false;
+    }
+
+    /// Whether a widget is held with the mouse, e.g. a slider which is dragged.
+    pub fn dragging(&self) -> bool {
+        self.active.is_some() && self.down
+    }
+
+    pub fn draw_list(&self) -> &DrawList {
+        &self.draw_list
+    }
+
+    /// Slider over `min..=max`, snapped to multiples of `step`. Returns `true` if `value` was
+    /// changed.
+    pub fn slider(&mut self,
+                  id: &'static str,
+                  label: &str,
+                  value: &mut f32,
+                  min: f32,
+                  max: f32,
+                  step: f32)
+                  -> bool {
+        if !self.enabled {
+            return false;
+        }
+
+        let bounds = self.next_row();
+        let hovered = contains(bounds, self.mouse);
+        if self.pressed && hovered {
+            self.active = Some(id);
+        }
+
+        let mut changed = false;
+        if self.active == Some(id) && self.down {
+            let t = ((self.mouse.0 - bounds[0]) / (bounds[2] - bounds[0])).clamp(0.0, 1.0);
+            let new_value = (min + t * (max - min)) / step;
+            let new_value = (new_value.round() * step).max(min).min(max);
+            if (new_value - *value).abs() > f32::EPSILON {
+                *value = new_value;
+                changed = true;
+            }
+        }
+
+        let fill = ((*value - min) / (max - min)).clamp(0.0, 1.0);
+        let color = if hovered || self.active == Some(id) { COLOR_HOVER } else { COLOR_WIDGET };
+        self.rect(bounds, color);
+        self.rect([bounds[0],
+                   bounds[1],
+                   bounds[0] + fill * (bounds[2] - bounds[0]),
+                   bounds[3]],
+                  COLOR_FILL);
+        let decimals = if step < 0.1 { 2 } else if step < 1.0 { 1 } else { 0 };
+        self.label(bounds, format!("{}: {:.*}", label, decimals, value));
+
+        changed
+    }
+
+    /// Dropdown of `items`, the open list pushes the following widgets down. Returns `true` if
+    /// another item was selected.
+    pub fn dropdown(&mut self,
+                    id: &'static str,
+                    label: &str,
+                    items: &[&str],
+                    selected: &mut usize)
+                    -> bool {
+        if !self.enabled {
+            return false;
+        }
+
+        let bounds = self.next_row();
+        let hovered = contains(bounds, self.mouse);
+        if self.pressed && hovered {
+            self.open_dropdown = match self.open_dropdown {
+                Some(open) if open == id => None,
+                _ => Some(id),
+            };
+        }
+        self.rect(bounds, if hovered { COLOR_HOVER } else { COLOR_WIDGET });
+        let marker = if self.open_dropdown == Some(id) { '^' } else { 'v' };
+        self.label(bounds,
+                   format!("{}: {}  {}",
+                           label,
+                           items.get(*selected).copied().unwrap_or_default(),
+                           marker));
+
+        let mut changed = false;
+        if self.open_dropdown == Some(id) {
+            for (index, item) in items.iter().enumerate() {
+                let mut bounds = self.next_row();
+                bounds[0] += 2.0 * TEXT_INDENT;
+                let hovered = contains(bounds, self.mouse);
+                if self.pressed && hovered {
+                    changed = index != *selected;
+                    *selected = index;
+                    self.open_dropdown = None;
+                }
+                let color = if hovered || index == *selected { COLOR_HOVER } else { COLOR_WIDGET };
+                self.rect(bounds, color);
+                self.label(bounds, String::from(*item));
+            }
+        }
+
+        changed
+    }
+
+    /// Button which returns `true` when it is clicked.
+    pub fn button(&mut self, id: &'static str, label: &str) -> bool {
+        if !self.enabled {
+            return false;
+        }
+
+        let bounds = self.next_row();
+        let hovered = contains(bounds, self.mouse);
+        if self.pressed && hovered {
+            self.active = Some(id);
+        }
+        let held = self.active == Some(id) && self.down;
+        self.rect(bounds,
+                  if held { COLOR_FILL } else if hovered { COLOR_HOVER } else { COLOR_WIDGET });
+        self.label(bounds, String::from(label));
+
+        self.released && self.active == Some(id) && hovered
+    }
+
+    fn next_row(&mut self) -> [f32; 4] {
+        let top = self.cursor;
+        self.cursor += ROW_HEIGHT + ROW_SPACING;
+        [self.origin.0 + PANEL_PADDING,
+         top,
+         self.origin.0 + PANEL_WIDTH - PANEL_PADDING,
+         top + ROW_HEIGHT]
+    }
+
+    fn rect(&mut self, bounds: [f32; 4], color: [f32; 4]) {
+        self.draw_list.rects.push(FlatRect { bounds, color });
+    }
+
+    fn label(&mut self, bounds: [f32; 4], text: String) {
+        self.draw_list.labels.push(Label { text,
+                                           position: (bounds[0] + TEXT_INDENT,
+                                                      (bounds[1] + bounds[3]) / 2.0),
+                                           color: COLOR_TEXT });
+    }
+}
+
+fn contains(bounds: [f32; 4], (x, y): (f32, f32)) -> bool {
+    x >= bounds[0] && x < bounds[2] && y >= bounds[1] && y < bounds[3]
+}
@@ -0,0 +1,112 @@
+// This file is part auf the dual-kawase-demo package.
+//
+// (c) 2019 Bernd Busse
+//
+// For the full copyright and license information, please view the README.md file
+// that was distributed with this source code.
+//
+
+use std::collections::BTreeMap;
+
+use crate::blur::BlurContext;
+
+/// Number of settings kept for undo, older ones are dropped.
+const MAX_ENTRIES: usize = 100;
+
+/// Blur settings which can be undone.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Settings {
+    pub iterations: u32,
+    pub offset: f32,
+    pub opacity: f32,
+    /// Values of kernel parameters by name.
+    pub params: BTreeMap<String, f32>,
+}
+
+impl Settings {
+    pub fn capture(blur_ctx: &BlurContext) -> Self {
+        Self { iterations: blur_ctx.iterations(),
+               offset: blur_ctx.offset(),
+               opacity: blur_ctx.opacity(),
+               params: blur_ctx.kernel_values() }
+    }
+
+    pub fn apply(&self, blur_ctx: &mut BlurContext) {
+        blur_ctx.set_iterations(self.iterations);
+        blur_ctx.set_offset(self.offset);
+        blur_ctx.set_opacity(self.opacity);
+        blur_ctx.set_kernel_values(&self.params);
+    }
+}
+
+/// Settings of a blurred frame with its GPU time in milliseconds.
+#[derive(Clone, Debug)]
+pub struct Entry {
+    pub settings: Settings,
+    pub time_gpu: f32,
+}
+
+/// Undo/redo stack of the blurred settings.
+///
+/// Every blur is recorded, so stepping through the history records nothing new, while a
+/// changed setting drops the entries which were undone. A slider drag is recorded once when it
+/// ends.
+#[derive(Default)]
+pub struct History {
+    entries: Vec<Entry>,
+    current: usize,
+}
+
+impl History {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record the settings of the last blur of `blur_ctx`.
+    pub fn record(&mut self, blur_ctx: &BlurContext) {
+        let settings = Settings::capture(blur_ctx);
+        let time_gpu = blur_ctx.time_gpu();
+        if let Some(entry) = self.entries.get_mut(self.current) {
+            if entry.settings == settings {
+                entry.time_gpu = time_gpu;
+                return;
+            }
+            self.entries.truncate(self.current + 1);
+        }
+
+        self.entries.push(Entry { settings, time_gpu });
+        if self.entries.len() > MAX_ENTRIES {
+            self.entries.remove(0);
+        }
+        self.current = self.entries.len() - 1;
+    }
+
+    /// Step back to the previous settings.
+    pub fn undo(&mut self) -> Option<&Settings> {
+        if self.current == 0 {
+            return None;
+        }
+        self.jump(self.current - 1)
+    }
+
+    /// Step forward to the settings which were undone.
+    pub fn redo(&mut self) -> Option<&Settings> {
+        self.jump(self.current + 1)
+    }
+
+    /// Go to the settings of entry `index`, keeping the entries after it for redo.
+    pub fn jump(&mut self, index: usize) -> Option<&Settings> {
+        let entry = self.entries.get(index)?;
+        self.current = index;
+        Some(&entry.settings)
+    }
+
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    /// Index of the entry of the shown settings.
+    pub fn current(&self) -> usize {
+        self.current
+    }
+}
@@ -0,0 +1,342 @@
+// This file is part auf the dual-kawase-demo package.
+//
+// (c) 2019 Bernd Busse
+//
+// For the full copyright and license information, please view the README.md file
+// that was distributed with this source code.
+//
+
+use std::convert::TryInto;
+use std::str::FromStr;
+
+use crate::image_io::{self, SourceImage};
+
+/// Conversion of PCS XYZ (D50) to linear sRGB, Bradford adapted.
+const XYZ_D50_TO_SRGB: [[f32; 3]; 3] = [[3.133_856, -1.616_867, -0.490_615],
+                                        [-0.978_768, 1.916_142, 0.033_454],
+                                        [0.071_945, -0.228_991, 1.405_243]];
+
+/// Colorants of sRGB in PCS XYZ (D50), Bradford adapted.
+const SRGB_COLORANTS: [[f32; 3]; 3] = [[0.436_074_7, 0.222_504_5, 0.013_932_2],
+                                       [0.385_064_9, 0.716_878_6, 0.097_104_5],
+                                       [0.143_080_4, 0.060_616_9, 0.714_173_3]];
+
+/// White point of the profile connection space.
+const D50: [f32; 3] = [0.964_2, 1.0, 0.824_9];
+
+/// Number of entries of the tone response curve of the sRGB profile.
+const SRGB_CURVE_SIZE: usize = 1024;
+
+/// Color space the pixels of loaded images are kept in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WorkingSpace {
+    /// Convert images with an ICC profile to sRGB.
+    Srgb,
+    /// Keep the pixels and write the profile of the source image into saved images.
+    Source,
+}
+
+impl FromStr for WorkingSpace {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "srgb" => Ok(WorkingSpace::Srgb),
+            "source" => Ok(WorkingSpace::Source),
+            _ => Err(format!("Unknown color space '{}', expected srgb or source", s)),
+        }
+    }
+}
+
+/// Tone response curve of one channel.
+enum Curve {
+    Gamma(f32),
+    Table(Vec<f32>),
+    /// Parametric curve `Y = (aX + b)^g + e` for `X >= d`, `Y = cX + f` otherwise.
+    Parametric {
+        g: f32,
+        a: f32,
+        b: f32,
+        c: f32,
+        d: f32,
+        e: f32,
+        f: f32,
+    },
+}
+
+impl Curve {
+    fn parse(profile: &[u8], offset: usize) -> Option<Self> {
+        let data = profile.get(offset..)?;
+        match data.get(..4)? {
+            b"curv" => {
+                let count = be_u32(data.get(8..12)?) as usize;
+                match count {
+                    0 => Some(Curve::Gamma(1.0)),
+                    1 => Some(Curve::Gamma(f32::from(be_u16(data.get(12..14)?)) / 256.0)),
+                    _ => {
+                        let table = data.get(12..12 + count * 2)?
+                                        .chunks_exact(2)
+                                        .map(|v| f32::from(be_u16(v)) / 65535.0)
+                                        .collect();
+                        Some(Curve::Table(table))
+                    },
+                }
+            },
+            b"para" => {
+                let kind = be_u16(data.get(8..10)?);
+                let param = |i: usize| data.get(12 + i * 4..16 + i * 4).map(s15_fixed16);
+                let g = param(0)?;
+                let (a, b, c, d, e, f) = match kind {
+                    0 => (1.0, 0.0, 0.0, 0.0, 0.0, 0.0),
+                    1 => (param(1)?, param(2)?, 0.0, -param(2)? / param(1)?, 0.0, 0.0),
+                    2 => {
+                        let (a, b, c) = (param(1)?, param(2)?, param(3)?);
+                        (a, b, 0.0, -b / a, c, c)
+                    },
+                    3 => (param(1)?, param(2)?, param(3)?, param(4)?, 0.0, 0.0),
+                    4 => (param(1)?, param(2)?, param(3)?, param(4)?, param(5)?, param(6)?),
+                    _ => return None,
+                };
+                Some(Curve::Parametric { g,
+                                         a,
+                                         b,
+                                         c,
+                                         d,
+                                         e,
+                                         f })
+            },
+            _ => None,
+        }
+    }
+
+    /// Linear value of the encoded `value` in `0.0..=1.0`.
+    fn eval(&self, value: f32) -> f32 {
+        match self {
+            Curve::Gamma(gamma) => value.powf(*gamma),
+            Curve::Table(table) => {
+                let pos = value.clamp(0.0, 1.0) * (table.len() - 1) as f32;
+                let idx = (pos as usize).min(table.len() - 2);
+                let frac = pos - idx as f32;
+                table[idx] * (1.0 - frac) + table[idx + 1] * frac
+            },
+            Curve::Parametric { g,
+                                a,
+                                b,
+                                c,
+                                d,
+                                e,
+                                f, } => {
+                if value >= *d {
+                    (a * value + b).max(0.0).powf(*g) + e
+                } else {
+                    c * value + f
+                }
+            },
+        }
+    }
+}
+
+/// RGB matrix/TRC profile which can be converted to sRGB.
+pub struct Profile {
+    curves: [Curve; 3],
+    /// Conversion of linear profile RGB to linear sRGB.
+    matrix: [[f32; 3]; 3],
+}
+
+impl Profile {
+    /// Parse an RGB display profile, profiles based on lookup tables are not supported.
+    pub fn parse(profile: &[u8]) -> Option<Self> {
+        if profile.get(16..20)? != b"RGB " {
+            return None;
+        }
+
+        let tag_count = be_u32(profile.get(128..132)?) as usize;
+        let find = |sig: &[u8]| {
+            (0..tag_count).find_map(|i| {
+                              let entry = profile.get(132 + i * 12..144 + i * 12)?;
+                              if &entry[..4] == sig {
+                                  Some(be_u32(&entry[4..8]) as usize)
+                              } else {
+                                  None
+                              }
+                          })
+        };
+        let xyz = |sig: &[u8]| -> Option<[f32; 3]> {
+            let data = profile.get(find(sig)?..)?;
+            if data.get(..4)? != b"XYZ " {
+                return None;
+            }
+            Some([s15_fixed16(data.get(8..12)?),
+                  s15_fixed16(data.get(12..16)?),
+                  s15_fixed16(data.get(16..20)?)])
+        };
+
+        let (r, g, b) = (xyz(b"rXYZ")?, xyz(b"gXYZ")?, xyz(b"bXYZ")?);
+        let curves = [Curve::parse(profile, find(b"rTRC")?)?,
+                      Curve::parse(profile, find(b"gTRC")?)?,
+                      Curve::parse(profile, find(b"bTRC")?)?];
+
+        // the colorants are the columns of the RGB to XYZ matrix
+        let to_xyz = [[r[0], g[0], b[0]], [r[1], g[1], b[1]], [r[2], g[2], b[2]]];
+        let mut matrix = [[0.0; 3]; 3];
+        for (row, out) in matrix.iter_mut().enumerate() {
+            for (col, value) in out.iter_mut().enumerate() {
+                *value = (0..3).map(|k| XYZ_D50_TO_SRGB[row][k] * to_xyz[k][col])
+                               .sum();
+            }
+        }
+
+        Some(Self { curves, matrix })
+    }
+
+    /// Convert the pixels of `img` from the profile to sRGB, HDR images are left untouched.
+    pub fn convert_to_srgb(&self, img: &mut SourceImage) {
+        match img {
+            SourceImage::Bgra8(img) => {
+                let luts = self.luts(256);
+                for p in img.chunks_exact_mut(4) {
+                    let rgb = self.to_srgb(&luts, [p[2], p[1], p[0]].map(usize::from));
+                    p[2] = (rgb[0] * 255.0).round() as u8;
+                    p[1] = (rgb[1] * 255.0).round() as u8;
+                    p[0] = (rgb[2] * 255.0).round() as u8;
+                }
+            },
+            SourceImage::Rgba16(img) => {
+                let luts = self.luts(65536);
+                for p in img.chunks_exact_mut(4) {
+                    let rgb = self.to_srgb(&luts, [p[0], p[1], p[2]].map(usize::from));
+                    for (sample, value) in p.iter_mut().zip(rgb.iter()) {
+                        *sample = (value * 65535.0).round() as u16;
+                    }
+                }
+            },
+            SourceImage::Rgba32F(_) => (),
+        }
+    }
+
+    /// Linear values of all `size` encoded values of each channel.
+    fn luts(&self, size: usize) -> Vec<Vec<f32>> {
+        let max = (size - 1) as f32;
+        self.curves
+            .iter()
+            .map(|curve| (0..size).map(|v| curve.eval(v as f32 / max)).collect())
+            .collect()
+    }
+
+    /// sRGB encoded color of the encoded profile color `rgb`.
+    fn to_srgb(&self, luts: &[Vec<f32>], rgb: [usize; 3]) -> [f32; 3] {
+        let linear = [luts[0][rgb[0]], luts[1][rgb[1]], luts[2][rgb[2]]];
+        let mut out = [0.0; 3];
+        for (value, row) in out.iter_mut().zip(self.matrix.iter()) {
+            let srgb = row[0] * linear[0] + row[1] * linear[1] + row[2] * linear[2];
+            *value = image_io::encode_srgb(srgb);
+        }
+
+        out
+    }
+}
+
+/// ICC v2 display profile of sRGB, embedded into saved images without a profile of their own.
+pub fn srgb_profile() -> Vec<u8> {
+    let xyz = |value: [f32; 3]| {
+        let mut tag = b"XYZ \0\0\0\0".to_vec();
+        for v in value.iter() {
+            tag.extend_from_slice(&((v * 65536.0).round() as i32).to_be_bytes());
+        }
+        tag
+    };
+    let description = b"sRGB\0";
+    let mut desc = b"desc\0\0\0\0".to_vec();
+    desc.extend_from_slice(&(description.len() as u32).to_be_bytes());
+    desc.extend_from_slice(description);
+    // empty Unicode and ScriptCode descriptions
+    desc.extend_from_slice(&[0; 4 + 4 + 2 + 1 + 67]);
+    let mut curve = b"curv\0\0\0\0".to_vec();
+    curve.extend_from_slice(&(SRGB_CURVE_SIZE as u32).to_be_bytes());
+    for i in 0..SRGB_CURVE_SIZE {
+        let value = image_io::decode_srgb(i as f32 / (SRGB_CURVE_SIZE - 1) as f32);
+        curve.extend_from_slice(&((value * 65535.0).round() as u16).to_be_bytes());
+    }
+
+    let tags: [(&[u8], Vec<u8>); 7] = [(b"desc", desc),
+                                        (b"cprt", b"text\0\0\0\0No copyright\0".to_vec()),
+                                        (b"wtpt", xyz(D50)),
+                                        (b"rXYZ", xyz(SRGB_COLORANTS[0])),
+                                        (b"gXYZ", xyz(SRGB_COLORANTS[1])),
+                                        (b"bXYZ", xyz(SRGB_COLORANTS[2])),
+                                        (b"rTRC", curve)];
+    // the green and blue curves point to the data of the red one
+    let tag_count = tags.len() + 2;
+
+    let mut table = (tag_count as u32).to_be_bytes().to_vec();
+    let mut data = Vec::new();
+    for (sig, tag) in tags.iter() {
+        let offset = 128 + 4 + tag_count * 12 + data.len();
+        table.extend_from_slice(sig);
+        table.extend_from_slice(&(offset as u32).to_be_bytes());
+        table.extend_from_slice(&(tag.len() as u32).to_be_bytes());
+
+        data.extend_from_slice(tag);
+        // tags start at multiples of 4 bytes
+        data.resize(data.len().div_ceil(4) * 4, 0);
+    }
+    let curve_entry = table[table.len() - 8..].to_vec();
+    for sig in [b"gTRC", b"bTRC"].iter() {
+        table.extend_from_slice(*sig);
+        table.extend_from_slice(&curve_entry);
+    }
+
+    let mut header = vec![0; 128];
+    let size = (header.len() + table.len() + data.len()) as u32;
+    header[..4].copy_from_slice(&size.to_be_bytes());
+    header[8..12].copy_from_slice(&[2, 0x10, 0, 0]);
+    header[12..16].copy_from_slice(b"mntr");
+    header[16..20].copy_from_slice(b"RGB ");
+    header[20..24].copy_from_slice(b"XYZ ");
+    header[36..40].copy_from_slice(b"acsp");
+    header[68..80].copy_from_slice(&xyz(D50)[8..]);
+
+    header.extend_from_slice(&table);
+    header.extend_from_slice(&data);
+    header
+}
+
+fn s15_fixed16(data: &[u8]) -> f32 {
+    i32::from_be_bytes(data[..4].try_into().unwrap()) as f32 / 65536.0
+}
+
+fn be_u16(data: &[u8]) -> u16 {
+    u16::from_be_bytes([data[0], data[1]])
+}
+
+fn be_u32(data: &[u8]) -> u32 {
+    u32::from_be_bytes(data[..4].try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn srgb_profile_converts_to_itself() {
+        let profile = srgb_profile();
+        assert_eq!(be_u32(&profile) as usize, profile.len());
+
+        let parsed = Profile::parse(&profile).expect("Cannot parse sRGB profile");
+        for (row, out) in parsed.matrix.iter().enumerate() {
+            for (col, value) in out.iter().enumerate() {
+                let expected = if row == col { 1.0 } else { 0.0 };
+                assert!((value - expected).abs() < 1e-3, "matrix[{}][{}] = {}", row, col, value);
+            }
+        }
+
+        let luts = parsed.luts(256);
+        for v in 0..256 {
+            let rgb = parsed.to_srgb(&luts, [v, v, v]);
+            assert!(rgb.iter().all(|c| (c * 255.0 - v as f32).abs() < 0.5),
+                    "{} converts to {:?}",
+                    v,
+                    rgb);
+        }
+    }
+}
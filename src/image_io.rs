@@ -0,0 +1,713 @@
+// This file is part auf the dual-kawase-demo package.
+//
+// (c) 2019 Bernd Busse
+//
+// For the full copyright and license information, please view the README.md file
+// that was distributed with this source code.
+//
+
+use std::borrow::Cow;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Cursor, Read, Write};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use gl::types::GLuint;
+use image::{Bgra, ColorType, DynamicImage, FilterType, GenericImageView, ImageBuffer,
+            ImageDecoder, ImageError, ImageFormat, Pixel, Rgb, Rgba};
+
+use crate::exr;
+use crate::icc::{self, WorkingSpace};
+use crate::metadata;
+use crate::renderer_gl::{self, Error, Pixels, Result, SampleFormat};
+use crate::webp;
+
+pub type BgraImage = ImageBuffer<Bgra<u8>, Vec<u8>>;
+pub type Rgba16Image = ImageBuffer<Rgba<u16>, Vec<u16>>;
+pub type Rgba32FImage = ImageBuffer<Rgba<f32>, Vec<f32>>;
+
+/// Default quality of saved JPEG images.
+pub const DEFAULT_JPEG_QUALITY: u8 = 90;
+
+/// Decoded image file which keeps the precision of 16 bit and HDR images.
+///
+/// Integer samples are sRGB encoded, float samples of HDR images are linear.
+#[derive(Clone)]
+pub enum SourceImage {
+    Bgra8(BgraImage),
+    Rgba16(Rgba16Image),
+    Rgba32F(Rgba32FImage),
+}
+
+impl SourceImage {
+    pub fn dimensions(&self) -> (u32, u32) {
+        match self {
+            SourceImage::Bgra8(img) => img.dimensions(),
+            SourceImage::Rgba16(img) => img.dimensions(),
+            SourceImage::Rgba32F(img) => img.dimensions(),
+        }
+    }
+
+    /// Bits per channel of the decoded file.
+    pub fn bit_depth(&self) -> u32 {
+        match self {
+            SourceImage::Bgra8(_) => 8,
+            SourceImage::Rgba16(_) => 16,
+            SourceImage::Rgba32F(_) => 32,
+        }
+    }
+
+    /// Short description of the sample format, e.g. `8 bit per channel`.
+    pub fn format_name(&self) -> &'static str {
+        match self {
+            SourceImage::Bgra8(_) => "8 bit per channel",
+            SourceImage::Rgba16(_) => "16 bit per channel",
+            SourceImage::Rgba32F(_) => "32 bit float per channel (HDR)",
+        }
+    }
+
+    pub fn is_hdr(&self) -> bool {
+        matches!(self, SourceImage::Rgba32F(_))
+    }
+
+    /// Sample format of the textures the image is blurred into.
+    ///
+    /// HDR images are blurred in linear float and tone mapped for display.
+    pub fn blur_format(&self) -> SampleFormat {
+        if self.is_hdr() {
+            SampleFormat::F32
+        } else {
+            SampleFormat::U8
+        }
+    }
+
+    pub fn resize_exact(&self, width: u32, height: u32, filter: FilterType) -> Self {
+        match self {
+            SourceImage::Bgra8(img) => {
+                SourceImage::Bgra8(image::imageops::resize(img, width, height, filter))
+            },
+            SourceImage::Rgba16(img) => {
+                SourceImage::Rgba16(image::imageops::resize(img, width, height, filter))
+            },
+            SourceImage::Rgba32F(img) => {
+                SourceImage::Rgba32F(image::imageops::resize(img, width, height, filter))
+            },
+        }
+    }
+
+    pub fn crop(&self, x: u32, y: u32, width: u32, height: u32) -> Self {
+        match self {
+            SourceImage::Bgra8(img) => SourceImage::Bgra8(img.view(x, y, width, height).to_image()),
+            SourceImage::Rgba16(img) => {
+                SourceImage::Rgba16(img.view(x, y, width, height).to_image())
+            },
+            SourceImage::Rgba32F(img) => {
+                SourceImage::Rgba32F(img.view(x, y, width, height).to_image())
+            },
+        }
+    }
+
+    /// Rotate and flip the image as given by an EXIF `orientation` from 1 to 8.
+    pub fn oriented(self, orientation: u16) -> Self {
+        match self {
+            SourceImage::Bgra8(img) => SourceImage::Bgra8(orient(img, orientation)),
+            SourceImage::Rgba16(img) => SourceImage::Rgba16(orient(img, orientation)),
+            SourceImage::Rgba32F(img) => SourceImage::Rgba32F(orient(img, orientation)),
+        }
+    }
+
+    /// Upload the image into a new texture.
+    pub fn create_texture(&self) -> Result<GLuint> {
+        let (width, height) = self.dimensions();
+        match self {
+            SourceImage::Bgra8(img) => {
+                renderer_gl::create_texture_bgra(width, height, Some(img.to_vec()))
+            },
+            SourceImage::Rgba16(img) => {
+                renderer_gl::create_texture_rgba16(width, height, Some(img.to_vec()))
+            },
+            SourceImage::Rgba32F(img) => {
+                renderer_gl::create_texture_rgba32f(width, height, Some(img.to_vec()))
+            },
+        }
+    }
+
+    /// Replace the contents of `tex` with the image.
+    pub fn upload(&self, tex: GLuint) -> Result<()> {
+        let (width, height) = self.dimensions();
+        match self {
+            SourceImage::Bgra8(img) => {
+                renderer_gl::resize_texture_bgra(tex, width, height, Some(img.to_vec()))
+            },
+            SourceImage::Rgba16(img) => {
+                renderer_gl::resize_texture_rgba16(tex, width, height, Some(img.to_vec()))
+            },
+            SourceImage::Rgba32F(img) => {
+                renderer_gl::resize_texture_rgba32f(tex, width, height, Some(img.to_vec()))
+            },
+        }
+    }
+
+    /// Convert the image to 8 bit, HDR images are clamped.
+    pub fn to_bgra8(&self) -> BgraImage {
+        let (width, height) = self.dimensions();
+        let data = match self {
+            SourceImage::Bgra8(img) => return img.clone(),
+            SourceImage::Rgba16(img) => {
+                img.chunks_exact(4)
+                   .flat_map(|p| vec![narrow(p[2]), narrow(p[1]), narrow(p[0]), narrow(p[3])])
+                   .collect()
+            },
+            SourceImage::Rgba32F(img) => img.chunks_exact(4)
+                                            .flat_map(|p| {
+                                                vec![encode_srgb8(p[2]),
+                                                     encode_srgb8(p[1]),
+                                                     encode_srgb8(p[0]),
+                                                     quantize8(p[3])]
+                                            })
+                                            .collect(),
+        };
+
+        ImageBuffer::from_raw(width, height, data).unwrap()
+    }
+
+    /// 8 bit RGBA samples of the image.
+    pub fn to_rgba8(&self) -> Vec<u8> {
+        DynamicImage::ImageBgra8(self.to_bgra8()).to_rgba()
+                                                 .into_raw()
+    }
+}
+
+fn orient<P: Pixel + 'static>(img: ImageBuffer<P, Vec<P::Subpixel>>,
+                              orientation: u16)
+                              -> ImageBuffer<P, Vec<P::Subpixel>> {
+    use image::imageops::{flip_horizontal, flip_vertical, rotate180, rotate270, rotate90};
+
+    match orientation {
+        2 => flip_horizontal(&img),
+        3 => rotate180(&img),
+        4 => flip_vertical(&img),
+        5 => flip_horizontal(&rotate90(&img)),
+        6 => rotate90(&img),
+        7 => flip_horizontal(&rotate270(&img)),
+        8 => rotate270(&img),
+        _ => img,
+    }
+}
+
+impl From<DynamicImage> for SourceImage {
+    fn from(img: DynamicImage) -> Self {
+        match img {
+            DynamicImage::ImageBgra8(img) => SourceImage::Bgra8(img),
+            img => SourceImage::Bgra8(img.to_bgra()),
+        }
+    }
+}
+
+/// Format of saved images.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    Png,
+    Png16,
+    Jpeg,
+    Tiff,
+    Tiff16,
+    /// Radiance HDR with linear float samples.
+    Hdr,
+    /// OpenEXR with linear float samples and alpha.
+    Exr,
+}
+
+impl OutputFormat {
+    pub const ALL: [OutputFormat; 7] = [OutputFormat::Png,
+                                        OutputFormat::Png16,
+                                        OutputFormat::Jpeg,
+                                        OutputFormat::Tiff,
+                                        OutputFormat::Tiff16,
+                                        OutputFormat::Hdr,
+                                        OutputFormat::Exr];
+
+    /// Name of the format as given to `--save-format`.
+    pub fn name(self) -> &'static str {
+        match self {
+            OutputFormat::Png => "png",
+            OutputFormat::Png16 => "png16",
+            OutputFormat::Jpeg => "jpg",
+            OutputFormat::Tiff => "tiff",
+            OutputFormat::Tiff16 => "tiff16",
+            OutputFormat::Hdr => "hdr",
+            OutputFormat::Exr => "exr",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Png | OutputFormat::Png16 => "png",
+            OutputFormat::Jpeg => "jpg",
+            OutputFormat::Tiff | OutputFormat::Tiff16 => "tiff",
+            OutputFormat::Hdr => "hdr",
+            OutputFormat::Exr => "exr",
+        }
+    }
+
+    /// Sample format the pixels are read back with from a texture of `target` format.
+    ///
+    /// Float targets are always read back as float and converted by the encoder, because
+    /// they hold linear values.
+    pub fn sample_format(self, target: SampleFormat) -> SampleFormat {
+        match (target, self) {
+            (SampleFormat::F32, _) => SampleFormat::F32,
+            (_, OutputFormat::Png16)
+            | (_, OutputFormat::Tiff16)
+            | (_, OutputFormat::Hdr)
+            | (_, OutputFormat::Exr) => SampleFormat::U16,
+            _ => SampleFormat::U8,
+        }
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "png" => Ok(OutputFormat::Png),
+            "png16" => Ok(OutputFormat::Png16),
+            "jpg" | "jpeg" => Ok(OutputFormat::Jpeg),
+            "tif" | "tiff" => Ok(OutputFormat::Tiff),
+            "tif16" | "tiff16" => Ok(OutputFormat::Tiff16),
+            "hdr" => Ok(OutputFormat::Hdr),
+            "exr" => Ok(OutputFormat::Exr),
+            _ => Err(format!("Unknown save format '{}', expected png, png16, jpg, tiff, tiff16, \
+                              hdr or exr",
+                             s)),
+        }
+    }
+}
+
+/// Encoder settings of saved images.
+#[derive(Debug, Clone, Copy)]
+pub struct SaveOptions {
+    pub jpeg_quality: u8,
+}
+
+impl Default for SaveOptions {
+    fn default() -> Self {
+        Self { jpeg_quality: DEFAULT_JPEG_QUALITY }
+    }
+}
+
+/// Path which stands for stdin or stdout.
+pub const STDIO_PATH: &str = "-";
+
+pub fn is_stdio(path: &Path) -> bool {
+    path == Path::new(STDIO_PATH)
+}
+
+/// Decoded image and the color profile its pixels are encoded in.
+pub struct LoadedImage {
+    pub image: SourceImage,
+    /// ICC profile of the pixels, `None` for sRGB. It is embedded into saved images.
+    pub icc_profile: Option<Arc<Vec<u8>>>,
+}
+
+/// Load an image file, 16 bit PNG and TIFF images and Radiance HDR and OpenEXR images keep their
+/// precision.
+///
+/// The path `-` reads the image from stdin, its format is guessed from the data.
+///
+/// The EXIF orientation is applied. Images with an embedded ICC profile are converted to sRGB
+/// if `space` asks for it and the profile is supported, otherwise the profile is kept.
+pub fn load_image(path: &Path, space: WorkingSpace) -> Result<LoadedImage> {
+    let load_error = |error| Error::ImageLoad { path: path.to_owned(),
+                                                error };
+
+    let data = if is_stdio(path) {
+        let mut data = Vec::new();
+        io::stdin().lock().read_to_end(&mut data).map(|_| data)
+    } else {
+        fs::read(path)
+    };
+    let data = data.map_err(|err| load_error(ImageError::IoError(err)))?;
+    let mut image = decode(path, &data).map_err(load_error)?;
+
+    let meta = metadata::read(&data);
+    if let Some(orientation) = meta.orientation.filter(|&o| o > 1 && o <= 8) {
+        log::info!(target: "io", "Apply EXIF orientation {}", orientation);
+        image = image.oriented(orientation);
+    }
+
+    let icc_profile = match meta.icc_profile {
+        Some(profile) if space == WorkingSpace::Srgb && !image.is_hdr() => {
+            match icc::Profile::parse(&profile) {
+                Some(parsed) => {
+                    log::info!(target: "io", "Convert embedded ICC profile to sRGB");
+                    parsed.convert_to_srgb(&mut image);
+                    None
+                },
+                None => {
+                    log::warn!(target: "io",
+                               "Unsupported ICC profile, keep the source color space");
+                    Some(Arc::new(profile))
+                },
+            }
+        },
+        Some(profile) => {
+            log::info!(target: "io", "Keep embedded ICC profile ({} bytes)", profile.len());
+            Some(Arc::new(profile))
+        },
+        None => None,
+    };
+
+    Ok(LoadedImage { image, icc_profile })
+}
+
+fn decode(path: &Path, data: &[u8]) -> image::ImageResult<SourceImage> {
+    // OpenEXR is not known to `image`
+    if exr::is_exr(data) {
+        return exr::decode(data).map(SourceImage::Rgba32F);
+    }
+
+    // the WebP decoder of `image` only yields grayscale
+    if webp::is_webp(data) {
+        return webp::decode(data).map(|img| SourceImage::from(DynamicImage::ImageRgba8(img)));
+    }
+
+    let format = ImageFormat::from_path(path).or_else(|_| image::guess_format(data));
+    let deep = match format {
+        Ok(ImageFormat::PNG) => load_png16(data)?,
+        Ok(ImageFormat::TIFF) => load_tiff16(data)?,
+        Ok(ImageFormat::HDR) => return load_hdr(data).map(SourceImage::Rgba32F),
+        _ => None,
+    };
+    if let Some(img) = deep {
+        return Ok(SourceImage::Rgba16(img));
+    }
+
+    match format {
+        Ok(format) => image::load_from_memory_with_format(data, format),
+        Err(_) => image::load_from_memory(data),
+    }.map(SourceImage::from)
+}
+
+fn load_png16(data: &[u8]) -> image::ImageResult<Option<Rgba16Image>> {
+    let mut decoder = png::Decoder::new(data);
+    // keep 16 bit samples, the default transformations strip them
+    decoder.set_transformations(png::Transformations::EXPAND);
+    let (info, mut reader) = decoder.read_info()?;
+
+    let (color, depth) = reader.output_color_type();
+    if depth != png::BitDepth::Sixteen {
+        return Ok(None);
+    }
+
+    let mut data = vec![0; reader.output_buffer_size()];
+    reader.next_frame(&mut data)?;
+    let samples = data.chunks_exact(2)
+                      .map(|s| u16::from_be_bytes([s[0], s[1]]))
+                      .collect();
+
+    Ok(expand_rgba16(samples,
+                     info.width,
+                     info.height,
+                     (color, depth).into()))
+}
+
+fn load_hdr(data: &[u8]) -> image::ImageResult<Rgba32FImage> {
+    let decoder = image::hdr::HDRDecoder::new(data)?;
+    let metadata = decoder.metadata();
+
+    let samples = decoder.read_image_hdr()?
+                         .iter()
+                         .flat_map(|p| vec![p[0], p[1], p[2], 1.0])
+                         .collect();
+
+    Ok(ImageBuffer::from_raw(metadata.width, metadata.height, samples).unwrap())
+}
+
+fn load_tiff16(data: &[u8]) -> image::ImageResult<Option<Rgba16Image>> {
+    let decoder = image::tiff::TIFFDecoder::new(Cursor::new(data))?;
+
+    let color = decoder.colortype();
+    let (width, height) = decoder.dimensions();
+    match color {
+        ColorType::Gray(16) | ColorType::GrayA(16) | ColorType::RGB(16) | ColorType::RGBA(16) => (),
+        _ => return Ok(None),
+    }
+
+    let data = decoder.read_image()?;
+    let samples = data.chunks_exact(2)
+                      .map(|s| u16::from_ne_bytes([s[0], s[1]]))
+                      .collect();
+
+    Ok(expand_rgba16(samples, width as u32, height as u32, color))
+}
+
+/// Convert 16 bit gray, gray-alpha or RGB samples to RGBA.
+fn expand_rgba16(samples: Vec<u16>,
+                 width: u32,
+                 height: u32,
+                 color: ColorType)
+                 -> Option<Rgba16Image> {
+    let samples = match color {
+        ColorType::RGBA(16) => samples,
+        ColorType::RGB(16) => samples.chunks_exact(3)
+                                     .flat_map(|p| vec![p[0], p[1], p[2], u16::MAX])
+                                     .collect(),
+        ColorType::GrayA(16) => samples.chunks_exact(2)
+                                       .flat_map(|p| vec![p[0], p[0], p[0], p[1]])
+                                       .collect(),
+        ColorType::Gray(16) => samples.iter()
+                                      .flat_map(|&v| vec![v, v, v, u16::MAX])
+                                      .collect(),
+        _ => return None,
+    };
+
+    ImageBuffer::from_raw(width, height, samples)
+}
+
+/// Save `pixels` in the format given by the extension of their path.
+///
+/// PNG and TIFF files are written with 16 bit per channel if the pixels have 16 bit samples,
+/// HDR and EXR files with linear float samples and all other formats with 8 bit. Float pixels are
+/// clamped and sRGB encoded for integer formats. The ICC profile of the pixels is embedded
+/// into PNG, JPEG and TIFF files, pixels without a profile are marked as sRGB.
+pub fn save_pixels(pixels: &Pixels, options: &SaveOptions) -> Result<()> {
+    let extension = pixels.path
+                          .extension()
+                          .and_then(|ext| ext.to_str())
+                          .map(str::to_lowercase)
+                          .unwrap_or_default();
+
+    let result = match extension.as_str() {
+        "png" | "jpg" | "jpeg" | "tif" | "tiff" | "hdr" | "exr" => {
+            File::create(&pixels.path).map_err(ImageError::IoError)
+                                      .and_then(|file| {
+                                          encode(pixels, &extension, BufWriter::new(file), options)
+                                      })
+        },
+        _ => image::save_buffer(&pixels.path,
+                                &rgba8(pixels),
+                                pixels.width,
+                                pixels.height,
+                                ColorType::RGBA(8)).map_err(ImageError::IoError),
+    };
+
+    result.map_err(|error| Error::ImageSave { path: pixels.path.clone(),
+                                              error })
+}
+
+/// Encode `pixels` as PNG, JPEG, TIFF, HDR or EXR image given by `extension` into `writer`.
+pub fn write_pixels<W: Write>(pixels: &Pixels,
+                              extension: &str,
+                              writer: W,
+                              options: &SaveOptions)
+                              -> Result<()> {
+    encode(pixels, extension, writer, options).map_err(|error| {
+                                                   Error::ImageSave { path: pixels.path.clone(),
+                                                                      error }
+                                               })
+}
+
+fn encode<W: Write>(pixels: &Pixels,
+                    extension: &str,
+                    mut writer: W,
+                    options: &SaveOptions)
+                    -> image::ImageResult<()> {
+    let data = match (extension, pixels.format) {
+        ("png", SampleFormat::U16) => encode_png16(pixels)?,
+        ("png", _) => encode_png8(pixels)?,
+        ("tif", SampleFormat::U16) | ("tiff", SampleFormat::U16) => {
+            encode_tiff::<tiff::encoder::colortype::RGBA16>(pixels, &samples16(pixels))?
+        },
+        ("tif", _) | ("tiff", _) => {
+            encode_tiff::<tiff::encoder::colortype::RGBA8>(pixels, &rgba8(pixels))?
+        },
+        ("jpg", _) | ("jpeg", _) => encode_jpeg(pixels, options.jpeg_quality)?,
+        ("hdr", _) => encode_hdr(pixels)?,
+        ("exr", _) => exr::encode(pixels.width, pixels.height, &samples_linear(pixels)),
+        _ => {
+            let msg = format!("Cannot encode '{}' images", extension);
+            return Err(ImageError::UnsupportedError(msg));
+        },
+    };
+
+    writer.write_all(&data)?;
+    writer.flush()?;
+    Ok(())
+}
+
+fn encode_png8(pixels: &Pixels) -> image::ImageResult<Vec<u8>> {
+    encode_png(pixels, &rgba8(pixels), ColorType::RGBA(8))
+}
+
+fn encode_png16(pixels: &Pixels) -> image::ImageResult<Vec<u8>> {
+    // PNG stores samples in big endian byte order
+    let data: Vec<u8> = samples16(pixels).iter()
+                                         .flat_map(|v| v.to_be_bytes().to_vec())
+                                         .collect();
+    encode_png(pixels, &data, ColorType::RGBA(16))
+}
+
+fn encode_png(pixels: &Pixels, data: &[u8], color: ColorType) -> image::ImageResult<Vec<u8>> {
+    let mut png = Vec::new();
+    image::png::PNGEncoder::new(&mut png).encode(data, pixels.width, pixels.height, color)?;
+    let png = match pixels.icc_profile {
+        Some(ref profile) => metadata::embed_png_icc(&png, profile),
+        // pixels without a profile are sRGB, converted on load or untagged
+        None => metadata::embed_png_srgb(&png),
+    };
+
+    Ok(png)
+}
+
+fn encode_tiff<C>(pixels: &Pixels, data: &[C::Inner]) -> image::ImageResult<Vec<u8>>
+    where C: tiff::encoder::colortype::ColorType,
+          [C::Inner]: tiff::encoder::TiffValue
+{
+    // the TIFF encoder seeks back to write the directory
+    let mut tiff = Cursor::new(Vec::new());
+    let mut encoder = tiff::encoder::TiffEncoder::new(&mut tiff)?;
+    let mut image = encoder.new_image::<C>(pixels.width, pixels.height)?;
+    let tag = tiff::decoder::ifd::Tag::Unknown(metadata::TAG_ICC_PROFILE);
+    image.encoder().write_tag(tag, &icc_profile(pixels)[..]);
+
+    let mut idx = 0;
+    while image.next_strip_sample_count() > 0 {
+        let count = image.next_strip_sample_count() as usize;
+        image.write_strip(&data[idx..idx + count])?;
+        idx += count;
+    }
+    image.finish()?;
+
+    Ok(tiff.into_inner())
+}
+
+fn encode_hdr(pixels: &Pixels) -> image::ImageResult<Vec<u8>> {
+    // Radiance HDR has no alpha channel
+    let rgb: Vec<Rgb<f32>> = samples_linear(pixels).chunks_exact(4)
+                                                   .map(|p| Rgb([p[0], p[1], p[2]]))
+                                                   .collect();
+    let mut hdr = Vec::new();
+    image::hdr::HDREncoder::new(&mut hdr).encode(&rgb,
+                                                 pixels.width as usize,
+                                                 pixels.height as usize)?;
+
+    Ok(hdr)
+}
+
+fn encode_jpeg(pixels: &Pixels, quality: u8) -> image::ImageResult<Vec<u8>> {
+    // JPEG has no alpha channel
+    let rgb: Vec<u8> = rgba8(pixels).chunks_exact(4)
+                                    .flat_map(|p| p[..3].to_vec())
+                                    .collect();
+    let mut jpeg = Vec::new();
+    image::jpeg::JPEGEncoder::new_with_quality(&mut jpeg, quality).encode(&rgb,
+                                                                          pixels.width,
+                                                                          pixels.height,
+                                                                          ColorType::RGB(8))?;
+    Ok(metadata::embed_jpeg_icc(&jpeg, &icc_profile(pixels)))
+}
+
+/// Profile of the pixels, sRGB if they have none.
+fn icc_profile(pixels: &Pixels) -> Cow<'_, [u8]> {
+    match pixels.icc_profile {
+        Some(ref profile) => Cow::Borrowed(&profile[..]),
+        None => Cow::Owned(icc::srgb_profile()),
+    }
+}
+
+fn samples16(pixels: &Pixels) -> Vec<u16> {
+    match pixels.format {
+        SampleFormat::U8 => pixels.data.iter().map(|&v| u16::from(v) * 257).collect(),
+        SampleFormat::U16 => pixels.data
+                                   .chunks_exact(2)
+                                   .map(|s| u16::from_ne_bytes([s[0], s[1]]))
+                                   .collect(),
+        SampleFormat::F32 => floats(&pixels.data).enumerate()
+                                                 .map(|(i, v)| match i % 4 {
+                                                     3 => quantize16(v),
+                                                     _ => quantize16(encode_srgb(v)),
+                                                 })
+                                                 .collect(),
+    }
+}
+
+fn rgba8(pixels: &Pixels) -> Cow<'_, [u8]> {
+    match pixels.format {
+        SampleFormat::U8 => Cow::Borrowed(&pixels.data),
+        SampleFormat::U16 => Cow::Owned(samples16(pixels).iter().map(|&v| narrow(v)).collect()),
+        SampleFormat::F32 => Cow::Owned(floats(&pixels.data).enumerate()
+                                                            .map(|(i, v)| match i % 4 {
+                                                                3 => quantize8(v),
+                                                                _ => encode_srgb8(v),
+                                                            })
+                                                            .collect()),
+    }
+}
+
+/// Linear float samples of `pixels`.
+fn samples_linear(pixels: &Pixels) -> Vec<f32> {
+    let decode = |(i, v): (usize, f32)| match i % 4 {
+        3 => v,
+        _ => decode_srgb(v),
+    };
+
+    match pixels.format {
+        SampleFormat::U8 => pixels.data
+                                  .iter()
+                                  .map(|&v| f32::from(v) / 255.0)
+                                  .enumerate()
+                                  .map(decode)
+                                  .collect(),
+        SampleFormat::U16 => samples16(pixels).iter()
+                                              .map(|&v| f32::from(v) / 65535.0)
+                                              .enumerate()
+                                              .map(decode)
+                                              .collect(),
+        SampleFormat::F32 => floats(&pixels.data).collect(),
+    }
+}
+
+/// Float samples in native byte order.
+fn floats(data: &[u8]) -> impl Iterator<Item = f32> + '_ {
+    data.chunks_exact(4)
+        .map(|s| f32::from_ne_bytes([s[0], s[1], s[2], s[3]]))
+}
+
+/// Round a 16 bit sample to 8 bit.
+fn narrow(value: u16) -> u8 {
+    ((u32::from(value) * 255 + 32767) / 65535) as u8
+}
+
+fn quantize8(value: f32) -> u8 {
+    (value.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+fn quantize16(value: f32) -> u16 {
+    (value.clamp(0.0, 1.0) * 65535.0).round() as u16
+}
+
+fn encode_srgb8(value: f32) -> u8 {
+    quantize8(encode_srgb(value))
+}
+
+/// Convert a linear value to the sRGB transfer curve, clamped to `0.0..=1.0`.
+pub fn encode_srgb(value: f32) -> f32 {
+    let value = value.clamp(0.0, 1.0);
+    if value <= 0.003_130_8 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Convert an sRGB encoded value to linear.
+pub fn decode_srgb(value: f32) -> f32 {
+    if value <= 0.040_45 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
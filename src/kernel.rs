@@ -0,0 +1,142 @@
+// This file is part auf the dual-kawase-demo package.
+//
+// (c) 2019 Bernd Busse
+//
+// For the full copyright and license information, please view the README.md file
+// that was distributed with this source code.
+//
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::renderer_gl::{Error, Result};
+
+/// Uniforms provided by `BlurContext` which are no user parameters.
+const RESERVED_UNIFORMS: &[&str] = &["tex_src", "halfpixel", "offset", "iteration", "opacity"];
+
+/// Adjustable `uniform float` declared by a user kernel.
+///
+/// Defaults and limits are read from a trailing comment of the declaration, e.g.
+/// `uniform float weight; // default: 1.0, min: 0.0, max: 4.0, step: 0.1`.
+#[derive(Clone)]
+pub struct KernelParam {
+    pub name: String,
+    pub value: f32,
+    pub min: f32,
+    pub max: f32,
+    pub step: f32,
+}
+
+impl KernelParam {
+    fn parse(line: &str) -> Option<Self> {
+        let (decl, comment) = match line.find("//") {
+            Some(idx) => (&line[..idx], &line[idx + 2..]),
+            None => (line, ""),
+        };
+
+        let mut tokens = decl.trim().trim_end_matches(';').split_whitespace();
+        if tokens.next() != Some("uniform") || tokens.next() != Some("float") {
+            return None;
+        }
+        let name = tokens.next()?;
+        if tokens.next().is_some() || RESERVED_UNIFORMS.contains(&name) {
+            return None;
+        }
+
+        let mut param = Self { name: String::from(name),
+                               value: 0.0,
+                               min: 0.0,
+                               max: 1.0,
+                               step: 0.1 };
+        for kv in comment.split(',') {
+            let mut it = kv.splitn(2, ':');
+            let key = it.next().unwrap_or("").trim();
+            let value = match it.next().and_then(|v| v.trim().parse::<f32>().ok()) {
+                Some(value) => value,
+                None => continue,
+            };
+
+            match key {
+                "default" => param.value = value,
+                "min" => param.min = value,
+                "max" => param.max = value,
+                "step" => param.step = value,
+                _ => (),
+            }
+        }
+        param.value = param.value.max(param.min).min(param.max);
+
+        Some(param)
+    }
+
+    pub fn inc(&mut self, steps: i32) {
+        self.value = (self.value + steps as f32 * self.step).max(self.min)
+                                                            .min(self.max);
+    }
+}
+
+/// User supplied downsample or upsample fragment shader.
+///
+/// The shader is combined with the built-in vertex shader and has to declare the same
+/// interface as the built-in kernels: `sampler2D tex_src`, `vec2 halfpixel`, `float offset`
+/// and optionally `int iteration` and `float opacity`. All other `uniform float`
+/// declarations are exposed as adjustable parameters.
+pub struct UserKernel {
+    path: PathBuf,
+    source: String,
+    params: Vec<KernelParam>,
+    loaded: bool,
+}
+
+impl UserKernel {
+    pub fn load(path: &Path) -> Result<Self> {
+        let source = fs::read_to_string(path).map_err(|error| Error::io(path, error))?;
+        let params = source.lines().filter_map(KernelParam::parse).collect();
+
+        Ok(Self { path: path.to_owned(),
+                  source,
+                  params,
+                  loaded: true })
+    }
+
+    /// Kernel of a file which cannot be read yet, it is loaded by the next `reload`.
+    pub fn unloaded(path: &Path) -> Self {
+        Self { path: path.to_owned(),
+               source: String::new(),
+               params: Vec::new(),
+               loaded: false }
+    }
+
+    /// Re-read the shader source and keep the values of known parameters.
+    pub fn reload(&mut self) -> Result<()> {
+        let mut kernel = Self::load(&self.path)?;
+        for param in kernel.params.iter_mut() {
+            if let Some(old) = self.params.iter().find(|p| p.name == param.name) {
+                param.value = old.value.max(param.min).min(param.max);
+            }
+        }
+
+        *self = kernel;
+        Ok(())
+    }
+
+    pub fn is_loaded(&self) -> bool {
+        self.loaded
+    }
+
+    pub fn name(&self) -> String {
+        self.path.display().to_string()
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn params(&self) -> &[KernelParam] {
+        &self.params
+    }
+
+    pub fn params_mut(&mut self) -> &mut [KernelParam] {
+        &mut self.params
+    }
+}
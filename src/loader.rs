@@ -0,0 +1,189 @@
+// This file is part auf the dual-kawase-demo package.
+//
+// (c) 2019 Bernd Busse
+//
+// For the full copyright and license information, please view the README.md file
+// that was distributed with this source code.
+//
+
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+
+use image::FilterType;
+
+use crate::batch;
+use crate::icc::WorkingSpace;
+use crate::image_io::{self, LoadedImage};
+use crate::renderer_gl::{ImgSurface, Result};
+
+/// Image file which replaced the image of the surface.
+pub struct Loaded {
+    pub path: PathBuf,
+    pub icc_profile: Option<Arc<Vec<u8>>>,
+}
+
+/// Latest load request, older ones are overwritten.
+#[derive(Default)]
+struct Request {
+    load: Option<(PathBuf, (u32, u32))>,
+    generation: usize,
+    shutdown: bool,
+}
+
+struct Shared {
+    request: Mutex<Request>,
+    wakeup: Condvar,
+}
+
+impl Shared {
+    fn is_stale(&self, generation: usize) -> bool {
+        let request = self.request.lock().unwrap();
+        request.generation != generation || request.shutdown
+    }
+}
+
+/// Background worker which loads image files and swaps them into the surface.
+///
+/// Only the latest request replaces the image, older requests are dropped when a newer one is
+/// made while they are decoded or scaled. The image is scaled without holding a lock and only
+/// swapped in under the lock; the texture update is done in the main thread.
+pub struct ImageLoader {
+    shared: Arc<Shared>,
+    worker: Option<JoinHandle<()>>,
+    done_rx: Receiver<Result<Loaded>>,
+}
+
+impl ImageLoader {
+    pub fn new(surface: Arc<Mutex<ImgSurface>>,
+               redraw: Arc<Mutex<bool>>,
+               color_space: WorkingSpace)
+               -> Self {
+        let shared = Arc::new(Shared { request: Mutex::new(Request::default()),
+                                       wakeup: Condvar::new() });
+        let (done_tx, done_rx) = mpsc::channel();
+
+        let worker_shared = shared.clone();
+        let worker =
+            thread::Builder::new().name(String::from("loader"))
+                                  .spawn(move || {
+                                      worker(&worker_shared,
+                                             &surface,
+                                             &redraw,
+                                             color_space,
+                                             &done_tx)
+                                  })
+                                  .expect("Cannot spawn loader thread");
+
+        Self { shared,
+               worker: Some(worker),
+               done_rx }
+    }
+
+    /// Decode `path` and scale it to fit into `size`, then emit a redraw event.
+    pub fn load(&self, path: PathBuf, size: (u32, u32)) {
+        log::info!(target: "io", "Load image '{}' ...", path.display());
+        let mut request = self.shared.request.lock().unwrap();
+        request.load = Some((path, size));
+        request.generation += 1;
+        self.shared.wakeup.notify_one();
+    }
+
+    /// Return all loads finished since the last call.
+    pub fn poll(&self) -> Vec<Result<Loaded>> {
+        self.done_rx.try_iter().collect()
+    }
+}
+
+impl Drop for ImageLoader {
+    fn drop(&mut self) {
+        self.shared.request.lock().unwrap().shutdown = true;
+        self.shared.wakeup.notify_one();
+        if let Some(handle) = self.worker.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+fn worker(shared: &Shared,
+          surface: &Mutex<ImgSurface>,
+          redraw: &Mutex<bool>,
+          color_space: WorkingSpace,
+          done: &Sender<Result<Loaded>>) {
+    loop {
+        let ((path, size), generation) = {
+            let mut request = shared.request.lock().unwrap();
+            while request.load.is_none() && !request.shutdown {
+                request = shared.wakeup.wait(request).unwrap();
+            }
+            if request.shutdown {
+                break;
+            }
+            (request.load.take().unwrap(), request.generation)
+        };
+
+        let _ = match load(shared, surface, redraw, color_space, path, size, generation) {
+            Ok(Some(loaded)) => done.send(Ok(loaded)),
+            Ok(None) => Ok(()),
+            Err(err) => done.send(Err(err)),
+        };
+    }
+}
+
+/// Load and scale the image, returns `None` if a newer request was made meanwhile.
+fn load(shared: &Shared,
+        surface: &Mutex<ImgSurface>,
+        redraw: &Mutex<bool>,
+        color_space: WorkingSpace,
+        path: PathBuf,
+        size: (u32, u32),
+        generation: usize)
+        -> Result<Option<Loaded>> {
+    let LoadedImage { image, icc_profile } = image_io::load_image(&path, color_space)?;
+    let outdated = || {
+        log::debug!(target: "io", "Drop outdated image '{}'", path.display());
+        Ok(None)
+    };
+    if shared.is_stale(generation) {
+        return outdated();
+    }
+
+    // scale without holding a lock, so the window keeps drawing meanwhile
+    let max_size = surface.lock().unwrap().max_size();
+    let scaled = ImgSurface::scale_to_fit(&image, size, max_size, FilterType::CatmullRom);
+
+    // Block further redraw events
+    let mut update_lock = redraw.lock().unwrap();
+    if shared.is_stale(generation) {
+        return outdated();
+    }
+    surface.lock().unwrap().set_image(image, scaled);
+    *update_lock = true;
+
+    Ok(Some(Loaded { path, icc_profile }))
+}
+
+/// Image file `step` files after `path` in its directory, wrapping around at the ends.
+pub fn sibling(path: &Path, step: isize) -> Option<PathBuf> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() && !image_io::is_stdio(path) => dir,
+        _ => Path::new("."),
+    };
+    let files = batch::list_images(dir).ok()?;
+    if files.is_empty() {
+        return None;
+    }
+
+    let len = files.len() as isize;
+    let index = match files.iter()
+                           .position(|file| file.file_name() == path.file_name())
+    {
+        Some(index) => (index as isize + step).rem_euclid(len),
+        // start at either end if the file is not in the list, e.g. for stdin
+        None if step > 0 => 0,
+        None => len - 1,
+    };
+
+    Some(files[index as usize].clone())
+}
@@ -0,0 +1,161 @@
+// This file is part auf the dual-kawase-demo package.
+//
+// (c) 2019 Bernd Busse
+//
+// For the full copyright and license information, please view the README.md file
+// that was distributed with this source code.
+//
+
+use std::fmt::{self, Write as FmtWrite};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use lazy_static::lazy_static;
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+lazy_static! {
+    static ref LOG_FILE: Mutex<Option<BufWriter<File>>> = Mutex::new(None);
+}
+
+static LOGGER: Logger = Logger;
+
+/// Value of a structured log field.
+pub enum Value {
+    Int(i64),
+    Float(f32),
+    Str(String),
+}
+
+impl From<u32> for Value {
+    fn from(value: u32) -> Self {
+        Value::Int(i64::from(value))
+    }
+}
+
+impl From<f32> for Value {
+    fn from(value: f32) -> Self {
+        Value::Float(value)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Value::Str(String::from(value))
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Int(value) => write!(f, "{}", value),
+            Value::Float(value) => write!(f, "{:.03}", value),
+            Value::Str(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+/// Logger writing human readable lines to stderr and JSON lines to an optional log file.
+struct Logger;
+
+impl Logger {
+    fn write(&self, level: Level, target: &str, message: &str, fields: &[(&str, Value)]) {
+        let mut line = format!("[{:<5} {}] {}", level, target, message);
+        for (key, value) in fields {
+            write!(line, " {}={}", key, value).unwrap();
+        }
+        eprintln!("{}", line);
+
+        if let Some(ref mut file) = *LOG_FILE.lock().unwrap() {
+            let json = json_line(level, target, message, fields);
+            // a broken log file must not abort the demo
+            let _ = writeln!(file, "{}", json).and_then(|_| file.flush());
+        }
+    }
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            self.write(record.level(),
+                       record.target(),
+                       &record.args().to_string(),
+                       &[]);
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(ref mut file) = *LOG_FILE.lock().unwrap() {
+            let _ = file.flush();
+        }
+    }
+}
+
+/// Install the logger with the maximum `level` and an optional JSON-lines `log_file`.
+pub fn init(level: LevelFilter, log_file: Option<&Path>) -> io::Result<()> {
+    if let Some(path) = log_file {
+        let file = File::create(path)?;
+        *LOG_FILE.lock().unwrap() = Some(BufWriter::new(file));
+    }
+
+    log::set_logger(&LOGGER).map_err(|err| io::Error::other(err.to_string()))?;
+    log::set_max_level(level);
+
+    Ok(())
+}
+
+/// Log a structured record with additional `fields` at info level.
+pub fn record(target: &str, message: &str, fields: &[(&str, Value)]) {
+    if log::log_enabled!(target: target, Level::Info) {
+        LOGGER.write(Level::Info, target, message, fields);
+    }
+}
+
+fn json_line(level: Level, target: &str, message: &str, fields: &[(&str, Value)]) -> String {
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)
+                                     .map(|d| d.as_secs_f64())
+                                     .unwrap_or(0.0);
+
+    let mut line = format!("{{\"ts\":{:.06},\"level\":\"{}\",\"target\":{},\"msg\":{}",
+                           timestamp,
+                           level,
+                           json_string(target),
+                           json_string(message));
+    for (key, value) in fields {
+        let value = match value {
+            Value::Int(value) => value.to_string(),
+            Value::Float(value) if value.is_finite() => value.to_string(),
+            Value::Float(_) => String::from("null"),
+            Value::Str(value) => json_string(value),
+        };
+        write!(line, ",{}:{}", json_string(key), value).unwrap();
+    }
+    line.push('}');
+
+    line
+}
+
+fn json_string(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len() + 2);
+    escaped.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(escaped, "\\u{:04x}", c as u32).unwrap(),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+
+    escaped
+}
@@ -6,81 +6,228 @@
 // that was distributed with this source code.
 //
 
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 use std::thread;
+use std::time::{Duration, Instant};
 
 use sdl2::event::{Event, WindowEvent};
 use sdl2::gfx::framerate::FPSManager;
-use sdl2::keyboard::{Keycode, Mod, Scancode};
-use sdl2::video::FullscreenType;
+use sdl2::mouse::MouseButton;
+use sdl2::video::{FullscreenType, GLContext, SwapInterval, Window};
+use sdl2::VideoSubsystem;
 
+mod batch;
+mod bindings;
 mod blur;
+mod config;
+mod encoder;
+mod export;
+mod exr;
+mod gui;
+mod history;
+mod icc;
+mod image_io;
+mod kernel;
+mod loader;
+mod logger;
+mod metadata;
+mod options;
 mod overlay;
+mod preset;
+mod reference;
 mod renderer_gl;
+mod resizer;
+mod session;
+mod shader_source;
+mod style;
+mod tiling;
+mod timing;
+mod tonemap;
 mod utils;
+mod webp;
 
-use blur::BlurContext;
+use bindings::{Action, KeyMap};
+use blur::{BlurContext, MAX_ITERATIONS};
+use encoder::{Completion, EncoderPool};
+use gui::Gui;
+use history::History;
+use image_io::{LoadedImage, OutputFormat};
+use kernel::UserKernel;
+use loader::ImageLoader;
+use options::Options;
 use overlay::InfoOverlay;
-use renderer_gl::{FragmentShader, GLQuad, ImgSurface, Program, TextureQuad, VertexShader, Viewport};
+use preset::Presets;
+use renderer_gl::{GLQuad, ImgSurface, Pixels, Program, ReadbackQueue, SampleFormat, TextureQuad,
+                  Viewport};
+use resizer::SurfaceResizer;
+use session::{Geometry, Session};
+use shader_source::{ProgramSpec, ShaderSource, ShaderWatcher};
+use style::{Corner, OverlayStyle};
+use timing::{FrameRate, Timings};
+use tonemap::Tonemapper;
 
 const WINDOW_TITLE: &str = "Dual-Filter Kawase Blur — Demo";
 const WIN_WIDTH: u32 = 1280;
 const WIN_HEIGHT: u32 = 720;
 
-fn run(image_file: &Path) {
-    println!("Load base image '{}' ...", image_file.display());
-    let base_image = image::open(image_file).expect("Cannot load base image");
-
-    let input_filename = image_file.file_name()
-                                   .map(std::ffi::OsStr::to_string_lossy)
-                                   .unwrap()
-                                   .to_owned();
-    let input_basename = input_filename.split('.')
-                                       .nth(0)
-                                       .unwrap()
-                                       .split('_')
-                                       .last()
-                                       .unwrap();
+/// Upper end of the offset slider, the keys go beyond it.
+const MAX_OFFSET: f32 = 10.0;
 
-    // Init SDL2 with subsystems
-    let sdl = sdl2::init().expect("Cannot initialize SDL2");
-    let video_subsystem = sdl.video().expect("Cannot initialize video subsystem");
+/// Entries of the algorithm dropdown, the second one only with user kernels.
+const ALGORITHMS: [&str; 2] = ["Dual Kawase", "User kernels"];
 
-    let mut fps_manager = FPSManager::new();
-    fps_manager.set_framerate(60)
-               .expect("Cannot set target framerate");
+const MAIN_PROGRAM: ProgramSpec = ProgramSpec { vert: "tex_quad.vert",
+                                                frag: "tex_quad.frag",
+                                                uniforms: &["transform"] };
+
+fn init_main_program(program: &mut Program) -> renderer_gl::Result<()> {
+    program.activate();
+    let result = program.set_uniform_mat4f("transform", &utils::matrix4f_identity());
+    program.unbind();
 
+    result
+}
+
+/// Create the window with `geometry` and load its GL context, hidden windows are used for
+/// offscreen work.
+fn create_window(video_subsystem: &VideoSubsystem,
+                 options: &Options,
+                 geometry: &Geometry,
+                 hidden: bool)
+                 -> (Window, GLContext) {
     let gl_attr = video_subsystem.gl_attr();
     gl_attr.set_context_profile(sdl2::video::GLProfile::Core);
     gl_attr.set_context_version(3, 3);
     gl_attr.set_double_buffer(true);
-    gl_attr.set_context_flags().forward_compatible().set();
+    if options.gl_debug.is_some() {
+        gl_attr.set_context_flags()
+               .forward_compatible()
+               .debug()
+               .set();
+    } else {
+        gl_attr.set_context_flags().forward_compatible().set();
+    }
+
+    let mut builder = video_subsystem.window(WINDOW_TITLE, geometry.width, geometry.height);
+    builder.resizable().opengl().allow_highdpi();
+    if let Some((x, y)) = geometry.position {
+        builder.position(x, y);
+    }
+    if geometry.fullscreen {
+        builder.fullscreen_desktop();
+    }
+    if hidden {
+        builder.hidden();
+    }
+    let window = builder.build().expect("Cannot create OpenGL window");
+
+    let gl_context = window.gl_create_context().expect("Cannot load GL context");
+    if options.fps == 0 && !hidden {
+        if let Err(err) = video_subsystem.gl_set_swap_interval(SwapInterval::Immediate) {
+            log::warn!(target: "renderer", "Cannot disable vsync: {}", err);
+        }
+    }
+    gl::load_with(|s| video_subsystem.gl_get_proc_address(s) as *const std::os::raw::c_void);
+    log::info!(target: "renderer",
+               "GL version/profile: {}.{} / {:?}",
+               gl_attr.context_version().0,
+               gl_attr.context_version().1,
+               gl_attr.context_profile());
+    if let Some(severity) = options.gl_debug {
+        if renderer_gl::debug::enable(severity) {
+            log::info!(target: "renderer", "GL debug output enabled (severity >= {})", severity);
+        } else {
+            log::warn!(target: "renderer",
+                       "GL debug output not supported, checking glGetError (severity >= {})",
+                       severity);
+        }
+    }
+
+    (window, gl_context)
+}
+
+/// Ratio of the drawable size to the size of `window`, above 1 on HiDPI displays.
+fn hidpi_scale(window: &Window) -> f32 {
+    let (width, _) = window.size();
+    let (drawable_width, _) = window.drawable_size();
+    if width == 0 {
+        1.0
+    } else {
+        drawable_width as f32 / width as f32
+    }
+}
+
+/// Blur the inputs offscreen in batch or headless mode, returns `false` if any of them failed.
+fn run_offscreen(options: &Options) -> renderer_gl::Result<bool> {
+    let sdl = sdl2::init().expect("Cannot initialize SDL2");
+    let video_subsystem = sdl.video().expect("Cannot initialize video subsystem");
+    let (_window, _gl_context) =
+        create_window(&video_subsystem, options, &Geometry::default(), true);
+
+    if options.headless {
+        batch::run_headless(options)?;
+        return Ok(true);
+    }
+    let summary = batch::run(options)?;
+    Ok(summary.failures.is_empty())
+}
+
+/// Window size and position, `window` has to be in windowed mode.
+fn window_geometry(window: &Window) -> Geometry {
+    let (width, height) = window.size();
+    Geometry { width,
+               height,
+               position: Some(window.position()),
+               fullscreen: false }
+}
+
+fn run(options: &Options, session: &Session) -> renderer_gl::Result<()> {
+    // without an image file main() made sure the session has one
+    let image_file = options.inputs
+                            .first()
+                            .or(session.image.as_ref())
+                            .unwrap()
+                            .as_path();
+    log::info!(target: "io", "Load base image '{}' ...", image_file.display());
+    let LoadedImage { image: base_image,
+                      icc_profile, } = image_io::load_image(image_file, options.color_space)?;
+    log::info!(target: "io",
+               "Loaded {}x{} image with {} bit per channel",
+               base_image.dimensions().0,
+               base_image.dimensions().1,
+               base_image.bit_depth());
+
+    let mut input_basename = basename(image_file);
+
+    // Init SDL2 with subsystems
+    let sdl = sdl2::init().expect("Cannot initialize SDL2");
+    let video_subsystem = sdl.video().expect("Cannot initialize video subsystem");
+
+    // A frame rate of 0 renders as fast as possible
+    let mut fps_manager = FPSManager::new();
+    if options.fps > 0 {
+        fps_manager.set_framerate(options.fps)
+                   .expect("Cannot set target framerate");
+    }
 
     // Create window
-    let mut window = video_subsystem.window(WINDOW_TITLE, WIN_WIDTH, WIN_HEIGHT)
-                                    .resizable()
-                                    .opengl()
-                                    .build()
-                                    .expect("Cannot create OpenGL window");
-    let mut viewport = Viewport::from_window(WIN_WIDTH, WIN_HEIGHT);
+    let (mut window, _gl_context) =
+        create_window(&video_subsystem, options, &session.window, false);
+    let (win_width, win_height) = window.drawable_size();
+    let mut viewport = Viewport::from_window(win_width, win_height);
+    let mut windowed = session.window;
 
     // TODO: Make use of transform matrix and don't rely on recalulating quad vertices
 
-    let _gl_context = window.gl_create_context().expect("Cannot load GL context");
-    gl::load_with(|s| video_subsystem.gl_get_proc_address(s) as *const std::os::raw::c_void);
-    println!("GL version/profile: {}.{} / {:?}",
-             gl_attr.context_version().0,
-             gl_attr.context_version().1,
-             gl_attr.context_profile());
-
     // Load image as texture
-    let base_surface = Arc::new(Mutex::new(ImgSurface::new_from_image(&base_image,
+    let base_surface = Arc::new(Mutex::new(ImgSurface::new_from_image(base_image,
                                                                       viewport.width(),
-                                                                      viewport.height())));
+                                                                      viewport.height())?));
 
     // parameters for parameter comparison
     let mut save_image = false;
+    let mut save_count = 0;
     let mut config_index = std::usize::MAX;
     let mut configs: Vec<(u32, f32)> = Vec::with_capacity(125);
     for base in 0..((5 - 1) * 4 + 1) {
@@ -105,34 +252,120 @@ fn run(image_file: &Path) {
     }
 
     // Init full-screen image display
-    let mut background_img = {
-        let base = base_surface.lock().unwrap();
-        let mut quad = GLQuad::new_with_texture(0, 0, base.width(), base.height(), viewport.size());
-        quad.fit_center(viewport.size());
-
-        quad
-    };
+    let mut background_img = create_background(&base_surface.lock().unwrap(), &viewport)?;
 
     // Init blur context
-    let mut blur_ctx = BlurContext::new(background_img.size());
+    let mut blur_ctx = BlurContext::new(background_img.size())?;
+    let mut presets = Presets::load(options.presets_file.as_deref())?;
+    let start_preset = match options.preset {
+        Some(ref name) => Some(presets.find(name)?.clone()),
+        None => None,
+    };
+    let (iterations, offset, opacity) = match start_preset {
+        Some(ref preset) => (preset.iterations, preset.offset, preset.opacity),
+        None => (session.iterations, session.offset, session.opacity),
+    };
+    blur_ctx.set_iterations(options.iterations.unwrap_or(iterations));
+    blur_ctx.set_offset(options.offset.unwrap_or(offset));
+    blur_ctx.set_opacity(opacity);
 
     // Init overlay text
-    let mut overlay = InfoOverlay::new(&blur_ctx, &viewport);
+    let style = OverlayStyle::load(options.overlay_style.as_deref())?;
+    let controls_corner = Corner::TopRight.mirrored(style.corner);
+    let mut overlay = InfoOverlay::new(&blur_ctx, &viewport, style, hidpi_scale(&window))?;
+    if !session.show_overlay {
+        overlay.toggle();
+    }
+    overlay.set_image_file(image_file);
+    if !session.show_image_info {
+        overlay.toggle_image();
+    }
+
+    // Key bindings, also listed in the help overlay
+    let keymap = KeyMap::load(options.key_config.as_deref())?;
+    overlay.set_help(format!("{}\n\n{}", keymap.help_text(), presets.help_text()));
+    if session.show_help {
+        overlay.toggle_help();
+    }
 
     // Init main shader and program
-    let vert_shader = VertexShader::from_source(include_str!("shaders/tex_quad.vert"))
-        .expect("Cannot compile vertex shader");
-    let frag_shader = FragmentShader::from_source(include_str!("shaders/tex_quad.frag"))
-        .expect("Cannot compile fragment shader");
+    let mut main_program = MAIN_PROGRAM.build(&ShaderSource::embedded())?;
+    init_main_program(&mut main_program)?;
+    // tone mapping is kept while switching to 8 bit images and back
+    let mut tonemap = options.tonemap.unwrap_or(session.tonemap);
+    let mut exposure = options.exposure.unwrap_or(session.exposure);
+    let mut tonemapper = match background_img.format() {
+        SampleFormat::F32 => Some(Tonemapper::new(&ShaderSource::embedded(), tonemap, exposure)?),
+        _ => None,
+    };
 
-    let mut main_program =
-        Program::from_shaders(&[vert_shader.into(), frag_shader.into()],
-                              Some(&["transform"])).expect("Cannot link main program");
+    // Load user supplied shaders and watch them for changes
+    let shader_source = match options.shader_dir {
+        Some(ref dir) => ShaderSource::from_dir(dir),
+        None => ShaderSource::embedded(),
+    };
+    let mut shader_watcher = None;
+    if let Some(ref dir) = options.shader_dir {
+        log::info!(target: "io", "Watch shaders in '{}' ...", dir.display());
+        let mut watcher = ShaderWatcher::new();
+        watcher.watch_dir(dir);
+        shader_watcher = Some(watcher);
+        reload_shaders(&shader_source,
+                       &shader_source.names(),
+                       &mut main_program,
+                       tonemapper.as_mut(),
+                       &mut blur_ctx,
+                       &mut overlay,
+                       &viewport);
+    }
 
-    main_program.activate();
-    main_program.set_uniform_mat4f("transform", &utils::matrix4f_identity())
-                .expect("Cannot set 'transform' in main program");
-    main_program.unbind();
+    // Load user supplied blur kernels
+    if let Some(ref path) = options.down_kernel {
+        log::info!(target: "io", "Load downsample kernel '{}' ...", path.display());
+        // a kernel which cannot be read is kept, so it is loaded once the file is fixed
+        let result = match UserKernel::load(path) {
+            Ok(kernel) => blur_ctx.set_down_kernel(&shader_source, kernel),
+            Err(err) => blur_ctx.set_down_kernel(&shader_source, UserKernel::unloaded(path))
+                                .and(Err(err)),
+        };
+        if let Err(err) = result {
+            log::error!(target: "blur", "Cannot use downsample kernel:\n{}", err);
+            overlay.set_error(&err.to_string());
+        }
+        shader_watcher.get_or_insert_with(ShaderWatcher::new)
+                      .watch_file(path);
+    }
+    if let Some(ref path) = options.up_kernel {
+        log::info!(target: "io", "Load upsample kernel '{}' ...", path.display());
+        let result = match UserKernel::load(path) {
+            Ok(kernel) => blur_ctx.set_up_kernel(&shader_source, kernel),
+            Err(err) => blur_ctx.set_up_kernel(&shader_source, UserKernel::unloaded(path))
+                                .and(Err(err)),
+        };
+        if let Err(err) = result {
+            log::error!(target: "blur", "Cannot use upsample kernel:\n{}", err);
+            overlay.set_error(&err.to_string());
+        }
+        shader_watcher.get_or_insert_with(ShaderWatcher::new)
+                      .watch_file(path);
+    }
+    // kernel parameters of the session and preset are only known with the kernels loaded
+    blur_ctx.set_kernel_values(&session.params);
+    if let Some(ref preset) = start_preset {
+        preset.apply_params(&mut blur_ctx);
+        overlay.set_preset(Some(preset));
+    }
+    overlay.update(&blur_ctx);
+
+    // Undo history of the blurred settings
+    let mut history = History::new();
+
+    // Init async image saving
+    let mut readbacks = ReadbackQueue::new();
+    readbacks.set_icc_profile(icc_profile);
+    let encoder = EncoderPool::with_available_cpus(options.save_options);
+    let mut save_format = options.save_format;
+    let mut waiting: Option<Pixels> = None;
 
     // Init GL state
     unsafe {
@@ -164,71 +397,182 @@ fn run(image_file: &Path) {
         }};
     }
 
+    // Load dropped and neighbouring images in the background
+    let loader = ImageLoader::new(base_surface.clone(), redraw.clone(), options.color_space);
+    let mut current_file = image_file.to_owned();
+
+    // Rescale the image to the window size in the background
+    let resizer = SurfaceResizer::new(base_surface.clone(), redraw.clone());
+
+    // Mouse controls, the key presses and clicked buttons of a frame
+    let mut gui = Gui::new();
+    gui.set_corner(controls_corner);
+    if !session.show_controls {
+        gui.toggle();
+    }
+
+    // Timings of the last blurs and frames, plotted in the overlay
+    let mut timings = Timings::new();
+    if !session.show_graph {
+        overlay.toggle_graph();
+    }
+    // Blur in every frame to measure the sustained cost
+    let mut continuous = options.continuous;
+    let mut frame_rate = FrameRate::new();
+    blur_ctx.set_log_records(!continuous);
+    let mut actions: Vec<Action> = Vec::new();
+    let mut dragging = false;
+
     // Main loop
-    println!("Init done. Start main loop ...");
+    log::info!(target: "ui", "Init done. Start main loop ...");
     let mut ev_pump = sdl.event_pump().unwrap();
     'mainloop: loop {
+        let frame_start = Instant::now();
+
         // Handle all queued events
         for event in ev_pump.poll_iter() {
+            // the controls take the mouse events inside their panel
+            if gui.handle_event(&event) {
+                continue;
+            }
+
             match event {
                 Event::Quit { .. } => break 'mainloop,
-                Event::Window { win_event: WindowEvent::Resized(w, h),
+                Event::Window { win_event: WindowEvent::Resized(..),
                                 .. } => {
-                    // Update viewport
-                    viewport.update_size(w as u32, h as u32);
+                    // Update viewport, which has more pixels than the window on HiDPI displays
+                    let (width, height) = window.drawable_size();
+                    viewport.update_size(width, height);
                     viewport.activate();
 
                     // Update overlay
-                    overlay.resize(&viewport);
+                    overlay.resize(&viewport, hidpi_scale(&window))?;
 
                     // Resize base image
-                    let (new_w, new_h) = viewport.size();
-                    let surf_ref = base_surface.clone();
-                    let redraw_ref = redraw.clone();
-
-                    thread::spawn(move || {
-                        // Block further redraw events
-                        let mut update_lock = redraw_ref.lock().unwrap();
-
-                        // XXX Just rescale image and emit redraw event. Texture update is done in
-                        //     main thread
-                        let mut surf = surf_ref.lock().unwrap();
-                        surf.resize_image(new_w, new_h);
-
-                        // Sent redraw blur event
-                        *update_lock = true;
-                    });
+                    resizer.resize(viewport.size());
                 },
-                Event::KeyDown { keycode: Some(Keycode::Escape),
-                                 .. }
-                | Event::KeyDown { keycode: Some(Keycode::Q),
-                                 keymod: Mod::NOMOD,
-                                 .. } => {
-                    break 'mainloop;
+                Event::MouseButtonDown { mouse_btn: MouseButton::Left,
+                                         x,
+                                         y,
+                                         .. } => {
+                    if let Some(index) = overlay.history_entry_at(x, y) {
+                        if let Some(settings) = history.jump(index) {
+                            sync_redraw!(
+                                         redraw_ref | {
+                                settings.apply(&mut blur_ctx);
+                                *redraw_ref = true;
+                            }
+                            );
+                        }
+                    }
                 },
-                Event::KeyDown { scancode: Some(Scancode::F),
-                                 keymod: Mod::NOMOD,
-                                 .. }
-                | Event::KeyDown { keycode: Some(Keycode::F11),
-                                 keymod: Mod::NOMOD,
-                                 .. } => {
-                    match window.fullscreen_state() {
-                        FullscreenType::Off => {
-                            window.set_fullscreen(FullscreenType::Desktop)
-                                  .unwrap_or_else(|err| {
-                                      eprintln!("Cannot enter fullscreen mode: {}", err)
-                                  });
-                        },
-                        FullscreenType::True | FullscreenType::Desktop => {
-                            window.set_fullscreen(FullscreenType::Off)
-                                  .unwrap_or_else(|err| {
-                                      eprintln!("Cannot leave fullscreen mode: {}", err)
-                                  });
-                        },
-                    };
+                Event::DropFile { filename, .. } => {
+                    loader.load(PathBuf::from(filename), viewport.size());
                 },
-                Event::KeyDown { keycode: Some(Keycode::Left),
+                Event::KeyDown { keycode,
+                                 scancode,
+                                 keymod,
+                                 repeat,
                                  .. } => {
+                    if let Some(action) = keymap.lookup(keycode, scancode, keymod, repeat) {
+                        actions.push(action);
+                    }
+                },
+                _ => (),
+            }
+        }
+
+        // Mouse controls, changed parameters are blurred like after a key press
+        gui.begin(window.size(), overlay.shown() && !overlay.help_shown());
+        let mut iterations = blur_ctx.iterations() as f32;
+        let mut offset = blur_ctx.offset();
+        let mut opacity = blur_ctx.opacity();
+        let mut changed = gui.slider("iterations",
+                                     "Iterations",
+                                     &mut iterations,
+                                     0.0,
+                                     MAX_ITERATIONS as f32,
+                                     1.0);
+        changed |= gui.slider("offset", "Offset", &mut offset, 0.0, MAX_OFFSET, 0.05);
+        changed |= gui.slider("opacity", "Opacity", &mut opacity, 0.0, 1.0, 0.01);
+        let algorithms = if blur_ctx.has_kernels() { &ALGORITHMS[..] } else { &ALGORITHMS[..1] };
+        let mut algorithm = (blur_ctx.has_kernels() && blur_ctx.kernels_enabled()) as usize;
+        if gui.dropdown("algorithm", "Algorithm", algorithms, &mut algorithm) {
+            if let Err(err) = blur_ctx.set_kernels_enabled(&shader_source, algorithm == 1) {
+                log::error!(target: "blur", "Cannot switch blur kernels:\n{}", err);
+                overlay.set_error(&err.to_string());
+            }
+            overlay.select_param(&blur_ctx, 0);
+            changed = true;
+        }
+        let formats: Vec<&str> = OutputFormat::ALL.iter().map(|f| f.name()).collect();
+        let mut format = OutputFormat::ALL.iter()
+                                          .position(|f| *f == save_format)
+                                          .unwrap_or(0);
+        if gui.dropdown("format", "Save format", &formats, &mut format) {
+            save_format = OutputFormat::ALL[format];
+        }
+        for &action in &[Action::Save, Action::Sweep] {
+            if gui.button(action.description(), action.description()) {
+                actions.push(action);
+            }
+        }
+        gui.end();
+        let drag_ended = dragging && !gui.dragging();
+        dragging = gui.dragging();
+        if changed {
+            sync_redraw!(
+                         redraw_ref | {
+                blur_ctx.set_iterations(iterations as u32);
+                blur_ctx.set_offset(offset);
+                blur_ctx.set_opacity(opacity);
+                *redraw_ref = true;
+            }
+            );
+        }
+
+        // Handle the actions of key presses and clicked buttons
+        for action in actions.drain(..) {
+            match action {
+                Action::Quit => break 'mainloop,
+                Action::ToggleHelp => overlay.toggle_help(),
+                Action::ToggleControls => gui.toggle(),
+                Action::ToggleGraph => overlay.toggle_graph(),
+                Action::ToggleOverlay => overlay.toggle(),
+                Action::ToggleImageInfo => overlay.toggle_image(),
+                Action::ToggleContinuous => {
+                    continuous = !continuous;
+                    frame_rate.reset();
+                    blur_ctx.set_log_records(!continuous);
+                    overlay.set_frame_rate(None);
+                    overlay.update(&blur_ctx);
+                    log::info!(target: "blur",
+                               "Continuous blurring {}",
+                               if continuous { "started" } else { "stopped" });
+                },
+                Action::ToggleFullscreen => match window.fullscreen_state() {
+                    FullscreenType::Off => {
+                        windowed = window_geometry(&window);
+                        window.set_fullscreen(FullscreenType::Desktop)
+                              .unwrap_or_else(|err| {
+                                  log::error!(target: "ui", "Cannot enter fullscreen mode: {}", err)
+                              });
+                    },
+                    FullscreenType::True | FullscreenType::Desktop => {
+                        window.set_fullscreen(FullscreenType::Off)
+                              .unwrap_or_else(|err| {
+                                  log::error!(target: "ui", "Cannot leave fullscreen mode: {}", err)
+                              });
+                    },
+                },
+                Action::NextImage | Action::PrevImage => {
+                    let step = if action == Action::NextImage { 1 } else { -1 };
+                    match loader::sibling(&current_file, step) {
+                        Some(path) => loader.load(path, viewport.size()),
+                        None => overlay.set_error("No images in the directory"),
+                    }
+                },
+                Action::DecIterations => {
                     sync_redraw!(
                                  redraw_ref | {
                         if blur_ctx.iterations() > 0 {
@@ -238,8 +582,7 @@ fn run(image_file: &Path) {
                     }
                     );
                 },
-                Event::KeyDown { keycode: Some(Keycode::Right),
-                                 .. } => {
+                Action::IncIterations => {
                     sync_redraw!(
                                  redraw_ref | {
                         let scale = 1 << (blur_ctx.iterations() + 1);
@@ -253,239 +596,252 @@ fn run(image_file: &Path) {
                     }
                     );
                 },
-                Event::KeyDown { keycode: Some(Keycode::Up),
-                                 keymod,
-                                 .. } if (keymod & Mod::RSHIFTMOD) | (keymod & Mod::LSHIFTMOD) != Mod::NOMOD =>
-                {
-                    sync_redraw!(
-                                 redraw_ref | {
-                        if blur_ctx.offset() <= 24.0 {
-                            blur_ctx.inc_offset(1.0);
-                            *redraw_ref = true;
-                        } else {
-                            blur_ctx.set_offset(25.0);
-                            *redraw_ref = true;
-                        }
-                    }
-                    );
-                }
-                Event::KeyDown { keycode: Some(Keycode::Up),
-                                 .. } => {
+                Action::IncOffset | Action::IncOffsetCoarse => {
+                    let step = if action == Action::IncOffset {
+                        0.25
+                    } else {
+                        1.0
+                    };
                     sync_redraw!(
                                  redraw_ref | {
-                        if blur_ctx.offset() <= 24.75 {
-                            blur_ctx.inc_offset(0.25);
-                            *redraw_ref = true;
+                        if blur_ctx.offset() <= 25.0 - step {
+                            blur_ctx.inc_offset(step);
                         } else {
                             blur_ctx.set_offset(25.0);
-                            *redraw_ref = true;
                         }
+                        *redraw_ref = true;
                     }
                     );
                 },
-                Event::KeyDown { keycode: Some(Keycode::Down),
-                                 keymod,
-                                 .. } if (keymod & Mod::RSHIFTMOD) | (keymod & Mod::LSHIFTMOD) != Mod::NOMOD =>
-                {
+                Action::DecOffset | Action::DecOffsetCoarse => {
+                    let step = if action == Action::DecOffset {
+                        0.25
+                    } else {
+                        1.0
+                    };
                     sync_redraw!(
                                  redraw_ref | {
-                        if blur_ctx.offset() >= 1.0 {
-                            blur_ctx.inc_offset(-1.0);
+                        if blur_ctx.offset() >= step {
+                            blur_ctx.inc_offset(-step);
                         } else {
                             blur_ctx.set_offset(0.0);
                         }
                         *redraw_ref = true;
                     }
                     );
-                }
-                Event::KeyDown { keycode: Some(Keycode::Down),
-                                 .. } => {
+                },
+                Action::Preset(index) => {
+                    if let Some(preset) = presets.get(index) {
+                        log::info!(target: "ui", "Preset {}", preset.summary());
+                        overlay.set_preset(Some(preset));
+                        sync_redraw!(
+                                     redraw_ref | {
+                            preset.apply(&mut blur_ctx);
+                            *redraw_ref = true;
+                        }
+                        );
+                    }
+                },
+                Action::SavePreset => match presets.add(&blur_ctx) {
+                    Ok(preset) => {
+                        let msg = format!("Saved preset {}", preset.summary());
+                        log::info!(target: "ui", "{}", msg);
+                        overlay.set_info(&msg);
+                        overlay.set_preset(Some(preset));
+                        overlay.set_help(format!("{}\n\n{}",
+                                                 keymap.help_text(),
+                                                 presets.help_text()));
+                        overlay.update(&blur_ctx);
+                    },
+                    Err(err) => {
+                        log::error!(target: "ui", "Cannot save preset: {}", err);
+                        overlay.set_error(&err.to_string());
+                    },
+                },
+                Action::Reset => {
                     sync_redraw!(
                                  redraw_ref | {
-                        if blur_ctx.offset() >= 0.25 {
-                            blur_ctx.inc_offset(-0.25);
-                        } else {
+                        if blur_ctx.offset() > 0.0 || blur_ctx.iterations() != 0 {
                             blur_ctx.set_offset(0.0);
+                            blur_ctx.set_iterations(0);
+                            *redraw_ref = true;
                         }
-                        *redraw_ref = true;
                     }
                     );
                 },
-                Event::KeyDown { scancode: Some(Scancode::S),
-                                 keymod,
-                                 repeat: false,
-                                 .. } if (keymod & Mod::RCTRLMOD) | (keymod & Mod::LCTRLMOD) != Mod::NOMOD =>
-                {
-                    let mut count = 0;
+                Action::Undo | Action::Redo => {
+                    let settings = if action == Action::Undo {
+                        history.undo()
+                    } else {
+                        history.redo()
+                    };
+                    if let Some(settings) = settings {
+                        sync_redraw!(
+                                     redraw_ref | {
+                            settings.apply(&mut blur_ctx);
+                            *redraw_ref = true;
+                        }
+                        );
+                    }
+                },
+                Action::Redraw => {
+                    // Force a redraw
+                    *redraw.lock().unwrap() = true;
+                },
+                Action::Save | Action::SaveShown => {
+                    if saves_busy(&readbacks, &mut overlay) {
+                        continue;
+                    }
+                    // pending saves are not on disk yet, so keep counting from the last one
                     let mut fname;
                     loop {
-                        count += 1;
-                        fname = format!("blurresult_{}.png", count);
+                        save_count += 1;
+                        fname = format!("blurresult_{}.{}",
+                                        save_count,
+                                        save_format.extension());
                         if !Path::new(&fname).exists() {
                             break;
                         }
                     }
                     let path = Path::new(&fname);
 
-                    println!("Save image to '{}' ...", path.display());
-                    renderer_gl::save_texture_to_png(*background_img.texture(), path, true);
-                }
-                Event::KeyDown { scancode: Some(Scancode::G),
-                                 keymod,
-                                 repeat: false,
-                                 .. } if (keymod & Mod::RCTRLMOD) | (keymod & Mod::LCTRLMOD) != Mod::NOMOD =>
-                {
-                    config_index = 0;
-                    println!("Automatically save result of different parameter sets.");
-                }
-                Event::KeyDown { keycode: Some(Keycode::Num1),
-                                 keymod: Mod::NOMOD,
-                                 repeat: false,
-                                 .. } => {
-                    sync_redraw!(
-                                 redraw_ref | {
-                        blur_ctx.set_iterations(1);
-                        blur_ctx.set_offset(1.5);
-                        *redraw_ref = true;
-                    }
-                    );
-                },
-                Event::KeyDown { keycode: Some(Keycode::Num2),
-                                 keymod: Mod::NOMOD,
-                                 repeat: false,
-                                 .. } => {
-                    sync_redraw!(
-                                 redraw_ref | {
-                        blur_ctx.set_iterations(1);
-                        blur_ctx.set_offset(2.0);
-                        *redraw_ref = true;
-                    }
-                    );
-                },
-                Event::KeyDown { keycode: Some(Keycode::Num3),
-                                 keymod: Mod::NOMOD,
-                                 repeat: false,
-                                 .. } => {
-                    sync_redraw!(
-                                 redraw_ref | {
-                        blur_ctx.set_iterations(2);
-                        blur_ctx.set_offset(2.5);
-                        *redraw_ref = true;
-                    }
-                    );
-                },
-                Event::KeyDown { keycode: Some(Keycode::Num4),
-                                 keymod: Mod::NOMOD,
-                                 repeat: false,
-                                 .. } => {
-                    sync_redraw!(
-                                 redraw_ref | {
-                        blur_ctx.set_iterations(2);
-                        blur_ctx.set_offset(3.0);
-                        *redraw_ref = true;
-                    }
-                    );
-                },
-                Event::KeyDown { keycode: Some(Keycode::Num5),
-                                 keymod: Mod::NOMOD,
-                                 repeat: false,
-                                 .. } => {
-                    sync_redraw!(
-                                 redraw_ref | {
-                        blur_ctx.set_iterations(3);
-                        blur_ctx.set_offset(2.75);
-                        *redraw_ref = true;
+                    if action == Action::SaveShown {
+                        // save as shown in the window
+                        queue_save(&mut readbacks,
+                                   &background_img,
+                                   path,
+                                   save_format,
+                                   &mut overlay);
+                    } else {
+                        let surf = base_surface.lock().unwrap();
+                        let size = options.export_size.resolve(surf.image().dimensions());
+                        let format = save_format.sample_format(surf.image().blur_format());
+                        log::info!(target: "io", "Save image to '{}' ...", path.display());
+                        if let Err(err) = export::export(&mut blur_ctx,
+                                                         &mut readbacks,
+                                                         surf.image(),
+                                                         size,
+                                                         surf.width(),
+                                                         path.to_owned(),
+                                                         format)
+                        {
+                            log::error!(target: "io",
+                                        "Cannot save image '{}': {}",
+                                        path.display(),
+                                        err);
+                            overlay.set_error(&err.to_string());
+                        }
                     }
-                    );
                 },
-                Event::KeyDown { keycode: Some(Keycode::Num6),
-                                 keymod: Mod::NOMOD,
-                                 repeat: false,
-                                 .. } => {
-                    sync_redraw!(
-                                 redraw_ref | {
-                        blur_ctx.set_iterations(3);
-                        blur_ctx.set_offset(3.5);
-                        *redraw_ref = true;
+                Action::VerifyTiling => {
+                    let surf = base_surface.lock().unwrap();
+                    log::info!(target: "blur",
+                               "Verify tiled blur against CPU reference ...");
+                    match reference::verify_tiling(&mut blur_ctx, surf.scaled_image()) {
+                        Ok(result) => {
+                            let status = if result.passed() { "passed" } else { "FAILED" };
+                            let msg = format!("Tiling check {}: {} tiles, {}px overlap, \
+                                               max diff {}/255, mean diff {:.03}",
+                                              status,
+                                              result.tiles,
+                                              result.overlap,
+                                              result.max_diff,
+                                              result.mean_diff);
+                            if result.passed() {
+                                log::info!(target: "blur", "{}", msg);
+                                overlay.set_info(&msg);
+                            } else {
+                                log::warn!(target: "blur", "{}", msg);
+                                overlay.set_error(&msg);
+                            }
+                        },
+                        Err(err) => {
+                            log::error!(target: "blur",
+                                        "Cannot verify tiled blur: {}",
+                                        err);
+                            overlay.set_error(&err.to_string());
+                        },
                     }
-                    );
                 },
-                Event::KeyDown { keycode: Some(Keycode::Num7),
-                                 keymod: Mod::NOMOD,
-                                 repeat: false,
-                                 .. } => {
-                    sync_redraw!(
-                                 redraw_ref | {
-                        blur_ctx.set_iterations(3);
-                        blur_ctx.set_offset(4.25);
-                        *redraw_ref = true;
-                    }
-                    );
+                Action::Sweep => {
+                    config_index = 0;
+                    log::info!(target: "ui",
+                               "Automatically save result of different parameter sets.");
                 },
-                Event::KeyDown { keycode: Some(Keycode::Num8),
-                                 keymod: Mod::NOMOD,
-                                 repeat: false,
-                                 .. } => {
-                    sync_redraw!(
-                                 redraw_ref | {
-                        blur_ctx.set_iterations(3);
-                        blur_ctx.set_offset(5.0);
-                        *redraw_ref = true;
+                Action::IncExposure | Action::DecExposure | Action::NextTonemap => {
+                    // exposure and tone mapping only apply to HDR images
+                    if let Some(ref mut tonemapper) = tonemapper {
+                        match action {
+                            Action::IncExposure => tonemapper.inc_exposure(0.5),
+                            Action::DecExposure => tonemapper.inc_exposure(-0.5),
+                            _ => tonemapper.next_operator(),
+                        }
+                        tonemap = tonemapper.operator();
+                        exposure = tonemapper.exposure();
+                        let msg = format!("Exposure {:+.1} EV, tonemap {}",
+                                          tonemapper.exposure(),
+                                          tonemapper.operator());
+                        log::info!(target: "ui", "{}", msg);
+                        overlay.set_info(&msg);
                     }
-                    );
                 },
-                Event::KeyDown { keycode: Some(Keycode::Num9),
-                                 keymod: Mod::NOMOD,
-                                 repeat: false,
-                                 .. } => {
-                    sync_redraw!(
-                                 redraw_ref | {
-                        blur_ctx.set_iterations(4);
-                        blur_ctx.set_offset(3.75);
-                        *redraw_ref = true;
-                    }
-                    );
+                Action::NextParam | Action::PrevParam => {
+                    let delta = if action == Action::NextParam { 1 } else { -1 };
+                    overlay.select_param(&blur_ctx, delta);
+                    overlay.update(&blur_ctx);
                 },
-                Event::KeyDown { keycode: Some(Keycode::Num0),
-                                 keymod: Mod::NOMOD,
-                                 repeat: false,
-                                 .. }
-                | Event::KeyDown { scancode: Some(Scancode::R),
-                                 keymod: Mod::NOMOD,
-                                 repeat: false,
-                                 .. } => {
+                Action::IncParam | Action::DecParam => {
+                    let delta = if action == Action::IncParam { 1 } else { -1 };
                     sync_redraw!(
                                  redraw_ref | {
-                        if blur_ctx.offset() > 0.0 || blur_ctx.iterations() != 0 {
-                            blur_ctx.set_offset(0.0);
-                            blur_ctx.set_iterations(0);
+                        if let Some(param) =
+                            blur_ctx.kernel_param_mut(overlay.selected_param())
+                        {
+                            param.inc(delta);
                             *redraw_ref = true;
                         }
                     }
                     );
                 },
-                Event::KeyDown { scancode: Some(Scancode::R),
-                                 keymod,
-                                 repeat: false,
-                                 .. } if (keymod & Mod::RCTRLMOD) | (keymod & Mod::LCTRLMOD) != Mod::NOMOD =>
-                {
-                    // Force a redraw
-                    *redraw.lock().unwrap() = true;
-                }
-                Event::KeyDown { keycode: Some(Keycode::Return),
-                                 .. }
-                | Event::KeyDown { keycode: Some(Keycode::Space),
-                                 .. } => {
-                    // Force a redraw
-                    *redraw.lock().unwrap() = true;
+            }
+        }
+
+        // Switch to loaded images
+        for result in loader.poll() {
+            match result {
+                Ok(loaded) => {
+                    log::info!(target: "io", "Loaded image '{}'", loaded.path.display());
+                    overlay.set_info(&format!("Loaded '{}'", loaded.path.display()));
+                    input_basename = basename(&loaded.path);
+                    overlay.set_image_file(&loaded.path);
+                    readbacks.set_icc_profile(loaded.icc_profile);
+                    current_file = loaded.path;
                 },
-                _ => (),
+                Err(err) => {
+                    log::error!(target: "io", "{}", err);
+                    overlay.set_error(&err.to_string());
+                },
+            }
+        }
+
+        // Recompile changed shaders
+        if let Some(ref mut watcher) = shader_watcher {
+            let changed = watcher.poll();
+            if !changed.is_empty() {
+                reload_shaders(&shader_source,
+                               &changed,
+                               &mut main_program,
+                               tonemapper.as_mut(),
+                               &mut blur_ctx,
+                               &mut overlay,
+                               &viewport);
+                *redraw.lock().unwrap() = true;
             }
         }
 
         sync_redraw!(
                      redraw_ref | {
-            if config_index < configs.len() {
+            if config_index < configs.len() && readbacks.has_capacity() {
                 let config = configs[config_index];
                 blur_ctx.set_iterations(config.0);
                 blur_ctx.set_offset(config.1);
@@ -500,46 +856,85 @@ fn run(image_file: &Path) {
         // check for redraw events
         try_sync_redraw!(
                          redraw_ref | {
-            if **redraw_ref {
+            // continuous blurring only repeats the blur of an unchanged image
+            let changed = **redraw_ref;
+            if changed || continuous {
                 **redraw_ref = false;
 
-                // Redraw blur texture
                 let mut surf = base_surface.lock().unwrap();
-                surf.refresh_texture();
-
-                // Update vertex positions
-                background_img.resize(surf.width(), surf.height());
-                background_img.fit_center(viewport.size());
-
-                blur_ctx.resize(surf.width(), surf.height());
-                blur_ctx.blur(&surf, &background_img);
-
-                // Update overlay
-                overlay.update(&blur_ctx);
-
-                println!("Blurred ({}x{}) texture with {{offset: {}, iterations: {:.02}}}",
-                         surf.width(),
-                         surf.height(),
-                         blur_ctx.offset(),
-                         blur_ctx.iterations());
-                println!("   => Time CPU: {:6.03}ms, GPU: {:6.03}ms",
-                         blur_ctx.time_cpu(),
-                         blur_ctx.time_gpu());
-
-                if save_image {
-                    save_image = false;
-                    let fname = format!("{:02}_{}-{:05.2}.png",
-                                        blur_ctx.iterations(),
-                                        input_basename,
-                                        blur_ctx.offset());
-                    let path = Path::new(&fname);
-                    println!("Save image to '{}' ...", path.display());
-                    renderer_gl::save_texture_to_png(*background_img.texture(), path, false);
+                if changed {
+                    // Redraw blur texture
+                    surf.refresh_texture()?;
+
+                    // Switch between 8 bit and HDR display after loading another image
+                    let format = surf.image().blur_format();
+                    if format != background_img.format() {
+                        background_img = create_background(&surf, &viewport)?;
+                        tonemapper = match format {
+                            SampleFormat::F32 => Some(create_tonemapper(&shader_source,
+                                                                        tonemap,
+                                                                        exposure,
+                                                                        &mut overlay)?),
+                            _ => None,
+                        };
+                    }
+
+                    // Update vertex positions
+                    background_img.resize(surf.width(), surf.height())?;
+                    background_img.fit_center(viewport.size());
+
+                    blur_ctx.resize(surf.width(), surf.height())?;
+                }
+                match blur_ctx.blur(&surf, &background_img) {
+                    Ok(()) => {
+                        timings.blur_cpu.push(blur_ctx.time_cpu());
+                        timings.blur_gpu.push(blur_ctx.time_gpu());
+                    },
+                    Err(err) => {
+                        log::error!(target: "blur", "Cannot blur image: {}", err);
+                        overlay.set_error(&err.to_string());
+                    },
+                }
+
+                if changed {
+                    // Update overlay, the frames of a parameter sweep are not undone one by one
+                    overlay.set_image(&surf);
+                    if !save_image && !dragging {
+                        history.record(&blur_ctx);
+                        overlay.update_history(&history);
+                    }
+                    overlay.update(&blur_ctx);
+
+                    if save_image {
+                        save_image = false;
+                        let fname = format!("{:02}_{}-{:05.2}.{}",
+                                            blur_ctx.iterations(),
+                                            &input_basename,
+                                            blur_ctx.offset(),
+                                            save_format.extension());
+                        let path = Path::new(&fname);
+                        queue_save(&mut readbacks,
+                                   &background_img,
+                                   path,
+                                   save_format,
+                                   &mut overlay);
+                    }
                 }
             }
         }
         );
 
+        // Record the settings of a finished drag once, not every value it passed
+        if drag_ended {
+            history.record(&blur_ctx);
+            overlay.update_history(&history);
+        }
+
+        // Encode finished readbacks
+        encoder::process_saves(&mut readbacks, &mut waiting, &encoder, |done| {
+            report_save(&mut overlay, done)
+        });
+
         // Draw window contents here
         viewport.activate();
         unsafe {
@@ -549,29 +944,259 @@ fn run(image_file: &Path) {
 
         // sync_redraw!({
         // Draw background texture
-        main_program.activate();
-        background_img.draw(true);
-        main_program.unbind();
+        match tonemapper {
+            Some(ref mut tonemapper) => {
+                tonemapper.activate()?;
+                background_img.draw(true);
+                tonemapper.unbind();
+            },
+            None => {
+                main_program.activate();
+                background_img.draw(true);
+                main_program.unbind();
+            },
+        }
         // Draw overlay text
-        overlay.draw(true);
+        if overlay.graph_shown() {
+            overlay.update_timings(&timings);
+        }
+        overlay.draw(true, gui.draw_list())?;
         //});
 
         // Display rendered scene
         window.gl_swap_window();
+        timings.frame
+               .push(frame_start.elapsed().as_secs_f32() * 1000.0);
+
+        if continuous && frame_rate.tick() {
+            overlay.set_frame_rate(frame_rate.fps());
+            let stats = |series: &timing::TimeSeries| {
+                series.stats().map_or(0.0, |stats| stats.avg)
+            };
+            logger::record("blur",
+                           "Continuous blur",
+                           &[("fps", logger::Value::from(frame_rate.fps().unwrap_or(0.0))),
+                             ("time_cpu_avg_ms", logger::Value::from(stats(&timings.blur_cpu))),
+                             ("time_gpu_avg_ms", logger::Value::from(stats(&timings.blur_gpu)))]);
+        }
+
+        if options.fps > 0 {
+            fps_manager.delay();
+        }
+    }
 
-        fps_manager.delay();
+    // Remember the state for the next start
+    let geometry = match window.fullscreen_state() {
+        FullscreenType::Off => window_geometry(&window),
+        _ => Geometry { fullscreen: true,
+                        ..windowed },
+    };
+    let image = if image_io::is_stdio(&current_file) {
+        None
+    } else {
+        Some(current_file.canonicalize().unwrap_or(current_file))
+    };
+    let session = Session { window: geometry,
+                            image,
+                            iterations: blur_ctx.iterations(),
+                            offset: blur_ctx.offset(),
+                            params: blur_ctx.kernel_values(),
+                            tonemap,
+                            exposure,
+                            opacity: blur_ctx.opacity(),
+                            show_help: overlay.help_shown(),
+                            show_controls: gui.shown(),
+                            show_graph: overlay.graph_shown(),
+                            show_overlay: overlay.shown(),
+                            show_image_info: overlay.image_shown() };
+    if let Err(err) = session.save() {
+        log::error!(target: "ui", "Cannot save session: {}", err);
+    }
+
+    // Finish pending saves
+    unsafe {
+        gl::Finish();
+    }
+    while !readbacks.is_empty() || waiting.is_some() || !encoder.is_idle() {
+        encoder::process_saves(&mut readbacks, &mut waiting, &encoder, |done| {
+            report_save(&mut overlay, done)
+        });
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    Ok(())
+}
+
+/// Quad showing the blurred image, HDR images need a float texture.
+fn create_background(surf: &ImgSurface, viewport: &Viewport) -> renderer_gl::Result<GLQuad> {
+    let mut quad = GLQuad::new_with_format(0,
+                                           0,
+                                           surf.width(),
+                                           surf.height(),
+                                           viewport.size(),
+                                           surf.image().blur_format())?;
+    quad.fit_center(viewport.size());
+    quad.set_label("background");
+
+    Ok(quad)
+}
+
+/// Tone mapping with the active shaders, falling back to the embedded ones if they are broken.
+fn create_tonemapper(source: &ShaderSource,
+                     operator: tonemap::Operator,
+                     exposure: f32,
+                     overlay: &mut InfoOverlay)
+                     -> renderer_gl::Result<Tonemapper> {
+    Tonemapper::new(source, operator, exposure).or_else(|err| {
+        log::error!(target: "renderer", "Cannot build tone mapping program:\n{}", err);
+        overlay.set_error(&err.to_string());
+        Tonemapper::new(&ShaderSource::embedded(), operator, exposure)
+    })
+}
+
+/// Short name of an image file used in the names of saved parameter sweeps.
+fn basename(path: &Path) -> String {
+    let filename = path.file_name()
+                       .map(std::ffi::OsStr::to_string_lossy)
+                       .unwrap_or_default();
+    filename.split('.')
+            .next()
+            .unwrap()
+            .split('_')
+            .next_back()
+            .unwrap()
+            .to_owned()
+}
+
+fn queue_save(readbacks: &mut ReadbackQueue,
+              quad: &GLQuad,
+              path: &Path,
+              format: OutputFormat,
+              overlay: &mut InfoOverlay) {
+    if saves_busy(readbacks, overlay) {
+        return;
+    }
+    log::info!(target: "io", "Save image to '{}' ...", path.display());
+    let sample_format = format.sample_format(quad.format());
+    if let Err(err) = readbacks.queue(*quad.texture(), path.to_owned(), sample_format) {
+        log::error!(target: "io", "Cannot save image '{}': {}", path.display(), err);
+        overlay.set_error(&err.to_string());
+    }
+}
+
+/// Check if another readback can be queued, reports a busy queue in the overlay.
+fn saves_busy(readbacks: &ReadbackQueue, overlay: &mut InfoOverlay) -> bool {
+    if readbacks.has_capacity() {
+        return false;
+    }
+
+    log::warn!(target: "io", "Cannot save image, too many saves are pending");
+    overlay.set_error("Busy: too many saves are pending");
+    true
+}
+
+/// Report an encoded image or its failure in the overlay.
+fn report_save(overlay: &mut InfoOverlay, completion: Completion) {
+    match completion.result {
+        Ok(()) => {
+            log::info!(target: "io", "Save complete '{}'", completion.path.display());
+            overlay.set_info(&format!("Saved '{}'", completion.path.display()));
+        },
+        Err(err) => {
+            log::error!(target: "io", "{}", err);
+            overlay.set_error(&err.to_string());
+        },
+    }
+}
+
+fn reload_shaders(source: &ShaderSource,
+                  changed: &[String],
+                  main_program: &mut Program,
+                  tonemapper: Option<&mut Tonemapper>,
+                  blur_ctx: &mut BlurContext,
+                  overlay: &mut InfoOverlay,
+                  viewport: &Viewport) {
+    log::info!(target: "renderer", "Reload shaders: {}", changed.join(", "));
+
+    let mut errors = Vec::new();
+    if MAIN_PROGRAM.uses_any(changed) {
+        match MAIN_PROGRAM.build(source) {
+            Ok(mut program) => match init_main_program(&mut program) {
+                Ok(()) => *main_program = program,
+                Err(err) => errors.push(err),
+            },
+            Err(err) => errors.push(err),
+        }
+    }
+    if let Some(tonemapper) = tonemapper {
+        if let Err(err) = tonemapper.reload_program(source, changed) {
+            errors.push(err);
+        }
+    }
+    if let Err(errs) = blur_ctx.reload_programs(source, changed) {
+        errors.extend(errs);
+    }
+    if let Err(err) = overlay.reload_program(source, changed, viewport) {
+        errors.push(err);
+    }
+
+    if errors.is_empty() {
+        overlay.clear_status();
+    } else {
+        let msg = errors.iter()
+                        .map(|err| err.to_string())
+                        .collect::<Vec<_>>()
+                        .join("\n");
+        log::error!(target: "renderer", "Cannot reload shaders:\n{}", msg);
+        overlay.set_error(&msg);
     }
 }
 
 fn main() {
-    let args: Vec<_> = std::env::args().collect();
+    let options = match Options::from_args(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(msg) => {
+            if !msg.is_empty() {
+                eprintln!("{}\n", msg);
+            }
+            println!("{}", options::USAGE);
+            std::process::exit(1);
+        },
+    };
 
-    if args.len() != 2 {
-        println!("Usage: cargo run /path/to/image.(png|jpg)");
+    // Init graphics and run main loop
+    if let Err(err) = logger::init(options.log_level, options.log_file.as_deref()) {
+        eprintln!("Cannot open log file: {}", err);
         std::process::exit(1);
     }
-    let image_file = Path::new(&args[1]);
 
-    // Init graphics and run main loop
-    run(image_file);
+    if options.list_keys {
+        match KeyMap::load(options.key_config.as_deref()) {
+            Ok(keymap) => println!("{}", keymap.markdown()),
+            Err(err) => {
+                log::error!(target: "ui", "{}", err);
+                std::process::exit(1);
+            },
+        }
+    } else if options.batch || options.headless {
+        match run_offscreen(&options) {
+            Ok(true) => (),
+            Ok(false) => std::process::exit(1),
+            Err(err) => {
+                log::error!(target: "renderer", "{}", err);
+                std::process::exit(1);
+            },
+        }
+    } else {
+        let session = if options.fresh { Session::default() } else { Session::restore() };
+        if options.inputs.is_empty() && session.image.is_none() {
+            eprintln!("Missing image file\n");
+            println!("{}", options::USAGE);
+            std::process::exit(1);
+        }
+        if let Err(err) = run(&options, &session) {
+            log::error!(target: "renderer", "{}", err);
+            std::process::exit(1);
+        }
+    }
 }
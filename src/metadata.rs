@@ -0,0 +1,503 @@
+// This file is part auf the dual-kawase-demo package.
+//
+// (c) 2019 Bernd Busse
+//
+// For the full copyright and license information, please view the README.md file
+// that was distributed with this source code.
+//
+
+use std::convert::TryInto;
+
+/// EXIF tag of the image orientation.
+const TAG_ORIENTATION: u16 = 0x0112;
+/// TIFF tag of an embedded ICC profile.
+pub const TAG_ICC_PROFILE: u16 = 34675;
+
+const JPEG_ICC_MARKER: &[u8] = b"ICC_PROFILE\0";
+/// Largest ICC profile chunk in a JPEG APP2 segment.
+const JPEG_ICC_CHUNK: usize = 65519;
+
+/// Orientation and color profile of an image file.
+#[derive(Debug, Default)]
+pub struct Metadata {
+    /// EXIF orientation, 1 to 8.
+    pub orientation: Option<u16>,
+    pub icc_profile: Option<Vec<u8>>,
+}
+
+/// Read the metadata of a JPEG, PNG, TIFF or WebP file.
+///
+/// Broken or unknown metadata is ignored, it never prevents an image from being shown.
+pub fn read(data: &[u8]) -> Metadata {
+    if data.starts_with(&[0xff, 0xd8]) {
+        read_jpeg(data)
+    } else if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        read_png(data)
+    } else if data.starts_with(b"II*\0") || data.starts_with(b"MM\0*") {
+        read_tiff_file(data)
+    } else if data.starts_with(b"RIFF") && data.get(8..12) == Some(b"WEBP") {
+        read_webp(data)
+    } else {
+        Metadata::default()
+    }
+}
+
+fn read_jpeg(data: &[u8]) -> Metadata {
+    let mut meta = Metadata::default();
+    let mut icc_chunks: Vec<(u8, &[u8])> = Vec::new();
+
+    let mut pos = 2;
+    while pos + 4 <= data.len() && data[pos] == 0xff {
+        let marker = data[pos + 1];
+        // start of scan, no more metadata
+        if marker == 0xda {
+            break;
+        }
+        let len = usize::from(be_u16(&data[pos + 2..]));
+        let segment = match data.get(pos + 4..pos + 2 + len) {
+            Some(segment) if len >= 2 => segment,
+            _ => break,
+        };
+
+        if marker == 0xe1 && segment.starts_with(b"Exif\0\0") {
+            meta.orientation = read_tiff(&segment[6..], TAG_ORIENTATION).and_then(|e| e.short());
+        } else if marker == 0xe2 && segment.starts_with(JPEG_ICC_MARKER) && segment.len() > 14 {
+            icc_chunks.push((segment[12], &segment[14..]));
+        }
+        pos += 2 + len;
+    }
+
+    if !icc_chunks.is_empty() {
+        icc_chunks.sort_by_key(|(seq, _)| *seq);
+        meta.icc_profile = Some(icc_chunks.iter()
+                                          .flat_map(|(_, c)| c.iter().cloned())
+                                          .collect());
+    }
+
+    meta
+}
+
+fn read_png(data: &[u8]) -> Metadata {
+    let mut meta = Metadata::default();
+    for (kind, chunk) in png_chunks(data) {
+        match kind {
+            b"eXIf" => {
+                meta.orientation = read_tiff(chunk, TAG_ORIENTATION).and_then(|e| e.short());
+            },
+            b"iCCP" => {
+                // profile name, null separator, compression method and zlib stream
+                if let Some(end) = chunk.iter().position(|&b| b == 0) {
+                    let compressed = chunk.get(end + 2..).unwrap_or(&[]);
+                    meta.icc_profile = inflate::inflate_bytes_zlib(compressed).ok();
+                }
+            },
+            _ => (),
+        }
+    }
+
+    meta
+}
+
+fn read_tiff_file(data: &[u8]) -> Metadata {
+    let orientation = read_tiff(data, TAG_ORIENTATION).and_then(|e| e.short());
+    let icc_profile = read_tiff(data, TAG_ICC_PROFILE).and_then(|e| e.bytes())
+                                                      .map(<[u8]>::to_vec);
+
+    Metadata { orientation,
+               icc_profile }
+}
+
+fn read_webp(data: &[u8]) -> Metadata {
+    let mut meta = Metadata::default();
+    let mut pos = 12;
+    while pos + 8 <= data.len() {
+        let kind = &data[pos..pos + 4];
+        let len = le_u32(&data[pos + 4..]) as usize;
+        let chunk = match data.get(pos + 8..pos + 8 + len) {
+            Some(chunk) => chunk,
+            None => break,
+        };
+
+        match kind {
+            b"EXIF" => {
+                let exif = chunk.strip_prefix(b"Exif\0\0").unwrap_or(chunk);
+                meta.orientation = read_tiff(exif, TAG_ORIENTATION).and_then(|e| e.short());
+            },
+            b"ICCP" => meta.icc_profile = Some(chunk.to_vec()),
+            _ => (),
+        }
+        // chunks are padded to an even size
+        pos += 8 + len + (len & 1);
+    }
+
+    meta
+}
+
+/// Entry of the first image file directory of a TIFF structure.
+struct TiffEntry<'a> {
+    data: &'a [u8],
+    big_endian: bool,
+    kind: u16,
+    count: usize,
+    value: &'a [u8],
+}
+
+impl<'a> TiffEntry<'a> {
+    fn short(&self) -> Option<u16> {
+        match self.kind {
+            3 if self.count >= 1 => Some(u16_of(self.value, self.big_endian)),
+            _ => None,
+        }
+    }
+
+    /// Value of a BYTE or UNDEFINED entry.
+    fn bytes(&self) -> Option<&'a [u8]> {
+        if self.kind != 1 && self.kind != 7 {
+            return None;
+        }
+        if self.count <= 4 {
+            return self.value.get(..self.count);
+        }
+
+        let offset = u32_of(self.value, self.big_endian) as usize;
+        self.data.get(offset..offset.checked_add(self.count)?)
+    }
+}
+
+/// Look up `tag` in the first directory of the TIFF structure in `data`.
+fn read_tiff(data: &[u8], tag: u16) -> Option<TiffEntry<'_>> {
+    let big_endian = match data.get(..4)? {
+        b"II*\0" => false,
+        b"MM\0*" => true,
+        _ => return None,
+    };
+
+    let ifd = u32_of(data.get(4..8)?, big_endian) as usize;
+    let count = usize::from(u16_of(data.get(ifd..ifd + 2)?, big_endian));
+    for i in 0..count {
+        let entry = data.get(ifd + 2 + i * 12..ifd + 14 + i * 12)?;
+        if u16_of(entry, big_endian) == tag {
+            return Some(TiffEntry { data,
+                                    big_endian,
+                                    kind: u16_of(&entry[2..], big_endian),
+                                    count: u32_of(&entry[4..], big_endian) as usize,
+                                    value: &entry[8..12] });
+        }
+    }
+
+    None
+}
+
+fn png_chunks(data: &[u8]) -> impl Iterator<Item = (&[u8], &[u8])> {
+    let mut pos = 8;
+    std::iter::from_fn(move || {
+        let len = be_u32(data.get(pos..pos + 4)?) as usize;
+        let kind = data.get(pos + 4..pos + 8)?;
+        let chunk = data.get(pos + 8..pos + 8 + len)?;
+        pos += 12 + len;
+        Some((kind, chunk))
+    })
+}
+
+/// Insert `icc_profile` into an encoded PNG file as `iCCP` chunk after the header.
+pub fn embed_png_icc(png: &[u8], icc_profile: &[u8]) -> Vec<u8> {
+    let mut chunk = b"iCCP".to_vec();
+    chunk.extend_from_slice(b"ICC profile\0\0");
+    chunk.extend_from_slice(&deflate::deflate_bytes_zlib(icc_profile));
+
+    insert_png_chunk(png, &chunk)
+}
+
+/// Mark an encoded PNG file as sRGB with an `sRGB` chunk after the header.
+pub fn embed_png_srgb(png: &[u8]) -> Vec<u8> {
+    // perceptual rendering intent
+    insert_png_chunk(png, b"sRGB\0")
+}
+
+/// Insert `chunk`, its type followed by its data, after the header of an encoded PNG file.
+fn insert_png_chunk(png: &[u8], chunk: &[u8]) -> Vec<u8> {
+    // signature and IHDR chunk
+    let header_len = 8 + 12 + be_u32(&png[8..12]) as usize;
+    let mut out = Vec::with_capacity(png.len() + chunk.len() + 8);
+    out.extend_from_slice(&png[..header_len]);
+    out.extend_from_slice(&((chunk.len() - 4) as u32).to_be_bytes());
+    out.extend_from_slice(chunk);
+    let mut crc = crc32fast::Hasher::new();
+    crc.update(chunk);
+    out.extend_from_slice(&crc.finalize().to_be_bytes());
+    out.extend_from_slice(&png[header_len..]);
+
+    out
+}
+
+/// Insert `icc_profile` into an encoded JPEG file as APP2 segments after the JFIF header.
+pub fn embed_jpeg_icc(jpeg: &[u8], icc_profile: &[u8]) -> Vec<u8> {
+    let mut pos = 2;
+    if jpeg.get(2..4) == Some(&[0xff, 0xe0]) {
+        pos += 2 + usize::from(be_u16(&jpeg[4..]));
+    }
+
+    let chunks: Vec<&[u8]> = icc_profile.chunks(JPEG_ICC_CHUNK).collect();
+    // the segments are numbered with a single byte
+    if chunks.len() > usize::from(u8::MAX) {
+        log::warn!(target: "io",
+                   "ICC profile of {} bytes does not fit into a JPEG file, skip it",
+                   icc_profile.len());
+        return jpeg.to_vec();
+    }
+    let mut out = Vec::with_capacity(jpeg.len() + icc_profile.len() + chunks.len() * 18);
+    out.extend_from_slice(&jpeg[..pos]);
+    for (i, chunk) in chunks.iter().enumerate() {
+        let len = 2 + JPEG_ICC_MARKER.len() + 2 + chunk.len();
+        out.extend_from_slice(&[0xff, 0xe2]);
+        out.extend_from_slice(&(len as u16).to_be_bytes());
+        out.extend_from_slice(JPEG_ICC_MARKER);
+        out.extend_from_slice(&[i as u8 + 1, chunks.len() as u8]);
+        out.extend_from_slice(chunk);
+    }
+    out.extend_from_slice(&jpeg[pos..]);
+
+    out
+}
+
+fn u16_of(data: &[u8], big_endian: bool) -> u16 {
+    if big_endian {
+        be_u16(data)
+    } else {
+        u16::from_le_bytes([data[0], data[1]])
+    }
+}
+
+fn u32_of(data: &[u8], big_endian: bool) -> u32 {
+    if big_endian {
+        be_u32(data)
+    } else {
+        le_u32(data)
+    }
+}
+
+fn be_u16(data: &[u8]) -> u16 {
+    u16::from_be_bytes([data[0], data[1]])
+}
+
+fn be_u32(data: &[u8]) -> u32 {
+    u32::from_be_bytes(data[..4].try_into().unwrap())
+}
+
+fn le_u32(data: &[u8]) -> u32 {
+    u32::from_le_bytes(data[..4].try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::ColorType;
+
+    /// ICC profile stand-in, the metadata code does not look into it.
+    fn profile(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 7 % 251) as u8).collect()
+    }
+
+    fn png() -> Vec<u8> {
+        let mut png = Vec::new();
+        image::png::PNGEncoder::new(&mut png).encode(&[128; 4 * 4 * 4], 4, 4, ColorType::RGBA(8))
+                                             .unwrap();
+        png
+    }
+
+    fn jpeg() -> Vec<u8> {
+        let mut jpeg = Vec::new();
+        image::jpeg::JPEGEncoder::new(&mut jpeg).encode(&[128; 8 * 8 * 3], 8, 8, ColorType::RGB(8))
+                                                .unwrap();
+        jpeg
+    }
+
+    /// TIFF header with a single directory of `entries`, `(tag, kind, count, value)`, followed
+    /// by `extra` data at offset 8 + 2 + 12 * entries + 4.
+    fn tiff(big_endian: bool, entries: &[(u16, u16, u32, [u8; 4])], extra: &[u8]) -> Vec<u8> {
+        let u16_bytes = |v: u16| if big_endian { v.to_be_bytes() } else { v.to_le_bytes() };
+        let u32_bytes = |v: u32| if big_endian { v.to_be_bytes() } else { v.to_le_bytes() };
+
+        let mut data = if big_endian { b"MM\0*".to_vec() } else { b"II*\0".to_vec() };
+        data.extend_from_slice(&u32_bytes(8));
+        data.extend_from_slice(&u16_bytes(entries.len() as u16));
+        for &(tag, kind, count, value) in entries {
+            data.extend_from_slice(&u16_bytes(tag));
+            data.extend_from_slice(&u16_bytes(kind));
+            data.extend_from_slice(&u32_bytes(count));
+            data.extend_from_slice(&value);
+        }
+        // no next directory
+        data.extend_from_slice(&[0; 4]);
+        data.extend_from_slice(extra);
+        data
+    }
+
+    /// Orientation as SHORT value, stored in the first two bytes of the value field.
+    fn orientation_entry(big_endian: bool, orientation: u16) -> (u16, u16, u32, [u8; 4]) {
+        let v = if big_endian { orientation.to_be_bytes() } else { orientation.to_le_bytes() };
+        (TAG_ORIENTATION, 3, 1, [v[0], v[1], 0, 0])
+    }
+
+    /// JPEG file with an EXIF segment holding `exif`.
+    fn jpeg_with_exif(exif: &[u8]) -> Vec<u8> {
+        let jpeg = jpeg();
+        let mut out = jpeg[..2].to_vec();
+        out.extend_from_slice(&[0xff, 0xe1]);
+        out.extend_from_slice(&(2 + 6 + exif.len() as u16).to_be_bytes());
+        out.extend_from_slice(b"Exif\0\0");
+        out.extend_from_slice(exif);
+        out.extend_from_slice(&jpeg[2..]);
+        out
+    }
+
+    #[test]
+    fn png_profile_round_trip() {
+        let profile = profile(3000);
+        let png = embed_png_icc(&png(), &profile);
+        assert_eq!(read(&png).icc_profile, Some(profile));
+        assert_eq!(read(&png).orientation, None);
+        assert!(image::load_from_memory(&png).is_ok());
+
+        let png = embed_png_srgb(&png);
+        assert!(image::load_from_memory(&png).is_ok());
+        assert_eq!(read(&embed_png_srgb(&self::png())).icc_profile, None);
+    }
+
+    #[test]
+    fn jpeg_profile_round_trip() {
+        for &len in &[100, JPEG_ICC_CHUNK, JPEG_ICC_CHUNK + 1, 3 * JPEG_ICC_CHUNK + 17] {
+            let profile = profile(len);
+            let jpeg = embed_jpeg_icc(&jpeg(), &profile);
+            assert_eq!(read(&jpeg).icc_profile, Some(profile), "profile of {} bytes", len);
+            assert!(image::load_from_memory(&jpeg).is_ok());
+        }
+    }
+
+    #[test]
+    fn jpeg_skips_oversized_profile() {
+        let jpeg = jpeg();
+        let profile = profile(JPEG_ICC_CHUNK * 255 + 1);
+        assert_eq!(embed_jpeg_icc(&jpeg, &profile), jpeg);
+    }
+
+    #[test]
+    fn tiff_orientation() {
+        for &big_endian in &[false, true] {
+            for orientation in 1..=8 {
+                // behind an image width entry
+                let entries = [(0x0100, 3, 1, [0; 4]), orientation_entry(big_endian, orientation)];
+                let data = tiff(big_endian, &entries, &[]);
+                assert_eq!(read(&data).orientation, Some(orientation));
+                assert_eq!(read(&jpeg_with_exif(&data)).orientation, Some(orientation));
+            }
+        }
+
+        // LONG values are not a valid orientation
+        let data = tiff(false, &[(TAG_ORIENTATION, 4, 1, [6, 0, 0, 0])], &[]);
+        assert_eq!(read(&data).orientation, None);
+    }
+
+    #[test]
+    fn tiff_profile() {
+        for &big_endian in &[false, true] {
+            let profile = profile(300);
+            // single entry directory, the profile follows it
+            let offset = 8 + 2 + 12 + 4u32;
+            let offset = if big_endian { offset.to_be_bytes() } else { offset.to_le_bytes() };
+            let data = tiff(big_endian,
+                            &[(TAG_ICC_PROFILE, 7, profile.len() as u32, offset)],
+                            &profile);
+            assert_eq!(read(&data).icc_profile, Some(profile));
+
+            // the profile is cut off
+            let data = &data[..data.len() - 1];
+            assert_eq!(read(data).icc_profile, None);
+        }
+
+        // values of up to four bytes are stored in the entry
+        let data = tiff(false, &[(TAG_ICC_PROFILE, 7, 3, [1, 2, 3, 0])], &[]);
+        assert_eq!(read(&data).icc_profile, Some(vec![1, 2, 3]));
+    }
+
+    #[test]
+    fn webp_metadata() {
+        let chunk = |kind: &[u8], payload: &[u8]| {
+            let mut chunk = kind.to_vec();
+            chunk.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+            chunk.extend_from_slice(payload);
+            chunk.resize(chunk.len() + payload.len() % 2, 0);
+            chunk
+        };
+        let exif = tiff(false, &[orientation_entry(false, 8)], &[]);
+        let profile = profile(301);
+
+        for prefix in &[&b"Exif\0\0"[..], &[]] {
+            let body = [&b"WEBP"[..],
+                        &chunk(b"VP8X", &[0x28, 0, 0, 0, 0, 0, 0, 0, 0, 0]),
+                        &chunk(b"ICCP", &profile),
+                        &chunk(b"VP8L", &[0x2f, 0, 0, 0, 0]),
+                        &chunk(b"EXIF", &[prefix, &exif[..]].concat())].concat();
+            let mut data = b"RIFF".to_vec();
+            data.extend_from_slice(&(body.len() as u32).to_le_bytes());
+            data.extend_from_slice(&body);
+
+            let meta = read(&data);
+            assert_eq!(meta.orientation, Some(8));
+            assert_eq!(meta.icc_profile.as_ref(), Some(&profile));
+        }
+    }
+
+    #[test]
+    fn truncated_files() {
+        let exif = tiff(true, &[orientation_entry(true, 6)], &[]);
+        let files = [embed_png_icc(&png(), &profile(500)),
+                     embed_jpeg_icc(&jpeg_with_exif(&exif), &profile(500)),
+                     tiff(false,
+                          &[orientation_entry(false, 3), (TAG_ICC_PROFILE, 7, 8, [26, 0, 0, 0])],
+                          &profile(8))];
+        for file in &files {
+            for len in 0..file.len() {
+                read(&file[..len]);
+            }
+        }
+    }
+
+    #[test]
+    fn garbage() {
+        // deterministic pseudo random bytes
+        let mut state = 0x2545_f491_u32;
+        let mut random = |len: usize| -> Vec<u8> {
+            (0..len).map(|_| {
+                        state ^= state << 13;
+                        state ^= state >> 17;
+                        state ^= state << 5;
+                        state as u8
+                    })
+                    .collect()
+        };
+
+        let signatures: [&[u8]; 5] = [&[0xff, 0xd8],
+                                      b"\x89PNG\r\n\x1a\n",
+                                      b"II*\0",
+                                      b"MM\0*",
+                                      b"\x89PNG\r\n\x1a\n\0\0\0\x20iCCPx\0\0"];
+        for signature in &signatures {
+            for len in 0..64 {
+                let mut data = signature.to_vec();
+                data.extend(random(len * 7));
+                read(&data);
+            }
+        }
+
+        // directory and value offsets beyond the end
+        read(b"II*\0\xff\xff\xff\xff");
+        read(&tiff(false, &[(TAG_ICC_PROFILE, 7, u32::MAX, [0xff; 4])], &[]));
+        // segment lengths below their minimum
+        read(&[0xff, 0xd8, 0xff, 0xe2, 0, 0, 0xff, 0xe2, 0, 1]);
+        // broken zlib stream of the profile
+        let mut chunk = b"iCCPICC profile\0\0".to_vec();
+        chunk.extend_from_slice(&random(40));
+        assert_eq!(read(&insert_png_chunk(&png(), &chunk)).icc_profile, None);
+    }
+}
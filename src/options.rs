@@ -0,0 +1,262 @@
+// This file is part auf the dual-kawase-demo package.
+//
+// (c) 2019 Bernd Busse
+//
+// For the full copyright and license information, please view the README.md file
+// that was distributed with this source code.
+//
+
+use std::path::PathBuf;
+
+use log::LevelFilter;
+
+use crate::batch::NameTemplate;
+use crate::blur::MAX_ITERATIONS;
+use crate::export::ExportSize;
+use crate::icc::WorkingSpace;
+use crate::image_io::{self, OutputFormat, SaveOptions};
+use crate::renderer_gl::debug::Severity;
+use crate::tonemap::Operator;
+
+pub const USAGE: &str =
+    "Usage: dual-kawase-demo [OPTIONS] [/path/to/image.(png|jpg|tiff|webp|bmp|tga|hdr|exr)]
+       dual-kawase-demo --batch [OPTIONS] FILE|DIR|'GLOB'...
+       dual-kawase-demo --headless [OPTIONS] [-o FILE|-] FILE|-
+
+    The image file '-' is read from stdin. Without an image file the last image of the
+    previous session is opened.
+
+Options:
+    --shader-dir DIR    Load shaders from DIR and reload them on change
+    --down-kernel FILE  Use FILE as downsample fragment shader
+    --up-kernel FILE    Use FILE as upsample fragment shader
+    --export-size SIZE  Size of images saved with Ctrl-S: 'source' (default), WIDTH or
+                        WIDTHxHEIGHT
+    --save-format FMT   Format of saved images
+                        (png, png16, jpg, tiff, tiff16, hdr, exr; default: png)
+    --jpeg-quality Q    Quality of saved JPEG images, 1 to 100 (default: 90)
+    --tonemap OP        Tone mapping of HDR images (clamp, reinhard, aces; default: aces)
+    --exposure EV       Exposure correction of HDR images in stops (default: 0)
+    --fresh             Ignore the window geometry, image and settings saved on exit
+    --fps N             Frame rate cap of the window, 1 to 200, 0 for no cap and no vsync
+                        (default: 60)
+    --continuous        Start blurring the image in every frame to measure the sustained
+                        cost, toggled with B
+    --color-space CS    Color space of images with an ICC profile: 'srgb' converts them on
+                        load (default), 'source' keeps them and embeds the profile on save
+    --iterations N      Blur iterations, initial value in the window (batch default: 4)
+    --offset F          Blur offset, initial value in the window (batch default: 3.0)
+    --preset NAME       Start with the blur settings of a preset, --iterations and --offset
+                        override them
+    --presets FILE      Load presets from FILE
+                        (default: ~/.config/dual-kawase-demo/presets.toml)
+    --batch             Blur all given files, images in directories and files matching
+                        '*' and '?' in the file name without showing a window
+    --output-dir DIR    Directory of batch results (default: current directory)
+    --name TEMPLATE     File name of batch results with {stem}, {iter}, {offset} and {ext}
+                        (default: {stem}_{iter}_{offset}.{ext})
+    --skip-existing     Skip batch inputs whose result file exists
+    --headless          Blur one image without showing a window and save it
+    -o, --output FILE   Result of --headless, '-' writes it to stdout in the save format
+                        (default: -)
+    --keys FILE         Load key bindings from FILE
+                        (default: ~/.config/dual-kawase-demo/keys.toml)
+    --overlay-style FILE
+                        Load the font, colors and placement of the overlay from FILE
+                        (default: ~/.config/dual-kawase-demo/overlay.toml)
+    --list-keys         Print the key bindings as a Markdown table and exit
+    --gl-debug          Create a debug context and report GL errors and messages
+    --gl-debug-level L  Minimum severity of reported GL messages, implies --gl-debug
+                        (notification, low, medium, high; default: low)
+    --log-level LEVEL   Maximum level of log messages
+                        (off, error, warn, info, debug, trace; default: info)
+    --log-file FILE     Write log messages and blur records as JSON lines to FILE
+    -h, --help          Print this help";
+
+pub struct Options {
+    pub inputs: Vec<PathBuf>,
+    pub shader_dir: Option<PathBuf>,
+    pub down_kernel: Option<PathBuf>,
+    pub up_kernel: Option<PathBuf>,
+    pub export_size: ExportSize,
+    pub save_format: OutputFormat,
+    pub save_options: SaveOptions,
+    pub tonemap: Option<Operator>,
+    pub exposure: Option<f32>,
+    pub fresh: bool,
+    pub fps: u32,
+    pub continuous: bool,
+    pub color_space: WorkingSpace,
+    pub iterations: Option<u32>,
+    pub offset: Option<f32>,
+    pub preset: Option<String>,
+    pub presets_file: Option<PathBuf>,
+    pub batch: bool,
+    pub output_dir: PathBuf,
+    pub name_template: NameTemplate,
+    pub skip_existing: bool,
+    pub headless: bool,
+    pub output: PathBuf,
+    pub key_config: Option<PathBuf>,
+    pub overlay_style: Option<PathBuf>,
+    pub list_keys: bool,
+    pub gl_debug: Option<Severity>,
+    pub log_level: LevelFilter,
+    pub log_file: Option<PathBuf>,
+}
+
+impl Options {
+    pub fn from_args<I: Iterator<Item = String>>(mut args: I) -> Result<Self, String> {
+        let mut inputs = Vec::new();
+        let mut shader_dir = None;
+        let mut down_kernel = None;
+        let mut up_kernel = None;
+        let mut export_size = ExportSize::Source;
+        let mut save_format = OutputFormat::Png;
+        let mut save_options = SaveOptions::default();
+        let mut tonemap = None;
+        let mut exposure = None;
+        let mut fresh = false;
+        let mut fps = 60;
+        let mut continuous = false;
+        let mut color_space = WorkingSpace::Srgb;
+        let mut iterations = None;
+        let mut offset = None;
+        let mut preset = None;
+        let mut presets_file = None;
+        let mut batch = false;
+        let mut output_dir = PathBuf::from(".");
+        let mut name_template = NameTemplate::default();
+        let mut skip_existing = false;
+        let mut headless = false;
+        let mut output = PathBuf::from(image_io::STDIO_PATH);
+        let mut key_config = None;
+        let mut overlay_style = None;
+        let mut list_keys = false;
+        let mut gl_debug = None;
+        let mut log_level = LevelFilter::Info;
+        let mut log_file = None;
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-h" | "--help" => return Err(String::new()),
+                "--shader-dir" => shader_dir = Some(PathBuf::from(value_of(&arg, &mut args)?)),
+                "--down-kernel" => down_kernel = Some(PathBuf::from(value_of(&arg, &mut args)?)),
+                "--up-kernel" => up_kernel = Some(PathBuf::from(value_of(&arg, &mut args)?)),
+                "--export-size" => export_size = value_of(&arg, &mut args)?.parse()?,
+                "--save-format" => save_format = value_of(&arg, &mut args)?.parse()?,
+                "--jpeg-quality" => {
+                    let value = value_of(&arg, &mut args)?;
+                    save_options.jpeg_quality = match value.parse::<u8>() {
+                        Ok(quality) if (1..=100).contains(&quality) => quality,
+                        _ => return Err(format!("Invalid JPEG quality '{}'", value)),
+                    };
+                },
+                "--tonemap" => tonemap = Some(value_of(&arg, &mut args)?.parse()?),
+                "--color-space" => color_space = value_of(&arg, &mut args)?.parse()?,
+                "--exposure" => {
+                    let value = value_of(&arg, &mut args)?;
+                    exposure = Some(value.parse()
+                                         .map_err(|_| format!("Invalid exposure '{}'", value))?);
+                },
+                "--fresh" => fresh = true,
+                "--fps" => {
+                    let value = value_of(&arg, &mut args)?;
+                    fps = match value.parse::<u32>() {
+                        Ok(fps) if fps <= 200 => fps,
+                        _ => return Err(format!("Invalid frame rate '{}'", value)),
+                    };
+                },
+                "--continuous" => continuous = true,
+                "--iterations" => {
+                    let value = value_of(&arg, &mut args)?;
+                    iterations = match value.parse::<u32>() {
+                        Ok(n) if n as usize <= MAX_ITERATIONS => Some(n),
+                        _ => return Err(format!("Invalid iterations '{}'", value)),
+                    };
+                },
+                "--offset" => {
+                    let value = value_of(&arg, &mut args)?;
+                    offset = match value.parse::<f32>() {
+                        Ok(v) if v >= 0.0 => Some(v),
+                        _ => return Err(format!("Invalid offset '{}'", value)),
+                    };
+                },
+                "--preset" => preset = Some(value_of(&arg, &mut args)?),
+                "--presets" => presets_file = Some(PathBuf::from(value_of(&arg, &mut args)?)),
+                "--batch" => batch = true,
+                "--output-dir" => output_dir = PathBuf::from(value_of(&arg, &mut args)?),
+                "--name" => name_template = value_of(&arg, &mut args)?.parse()?,
+                "--skip-existing" => skip_existing = true,
+                "--headless" => headless = true,
+                "-o" | "--output" => output = PathBuf::from(value_of(&arg, &mut args)?),
+                "--keys" => key_config = Some(PathBuf::from(value_of(&arg, &mut args)?)),
+                "--overlay-style" => {
+                    overlay_style = Some(PathBuf::from(value_of(&arg, &mut args)?))
+                },
+                "--list-keys" => list_keys = true,
+                "--gl-debug" => gl_debug = gl_debug.or(Some(Severity::Low)),
+                "--gl-debug-level" => gl_debug = Some(value_of(&arg, &mut args)?.parse()?),
+                "--log-level" => {
+                    let value = value_of(&arg, &mut args)?;
+                    log_level = value.parse()
+                                     .map_err(|_| format!("Unknown log level '{}'", value))?;
+                },
+                "--log-file" => log_file = Some(PathBuf::from(value_of(&arg, &mut args)?)),
+                _ if arg.starts_with('-') && arg != image_io::STDIO_PATH => {
+                    return Err(format!("Unknown option '{}'", arg))
+                },
+                _ => inputs.push(PathBuf::from(arg)),
+            }
+        }
+
+        if inputs.is_empty() && (batch || headless) {
+            return Err(String::from("Missing image file"));
+        }
+        if batch && headless {
+            return Err(String::from("--batch and --headless cannot be combined"));
+        }
+        if batch && inputs.iter().any(|input| image_io::is_stdio(input)) {
+            return Err(String::from("Cannot read images from stdin with --batch"));
+        }
+        if inputs.len() > 1 && !batch {
+            return Err(format!("Unexpected argument '{}', use --batch for multiple images",
+                               inputs[1].display()));
+        }
+
+        Ok(Self { inputs,
+                  shader_dir,
+                  down_kernel,
+                  up_kernel,
+                  export_size,
+                  save_format,
+                  save_options,
+                  tonemap,
+                  exposure,
+                  fresh,
+                  fps,
+                  continuous,
+                  color_space,
+                  iterations,
+                  offset,
+                  preset,
+                  presets_file,
+                  batch,
+                  output_dir,
+                  name_template,
+                  skip_existing,
+                  headless,
+                  output,
+                  key_config,
+                  overlay_style,
+                  list_keys,
+                  gl_debug,
+                  log_level,
+                  log_file })
+    }
+}
+
+fn value_of<I: Iterator<Item = String>>(arg: &str, args: &mut I) -> Result<String, String> {
+    args.next()
+        .ok_or_else(|| format!("Missing value for '{}'", arg))
+}
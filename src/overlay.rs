@@ -6,27 +6,125 @@
 // that was distributed with this source code.
 //
 
+use std::fs;
+use std::path::Path;
+
 use gl::types::{GLfloat, GLint, GLuint, GLvoid};
 use glyph_brush::rusttype::{Point, Rect, Scale};
-use glyph_brush::{BrushAction, BrushError, GlyphBrush, GlyphBrushBuilder, GlyphVertex,
-                  HorizontalAlign, Layout, Section, VerticalAlign};
+use glyph_brush::{BrushAction, BrushError, GlyphBrush, GlyphBrushBuilder, GlyphCruncher,
+                  GlyphVertex, HorizontalAlign, Layout, Section, VerticalAlign};
 
-use crate::blur::BlurContext;
-use crate::renderer_gl::{ArrayBuffer, FragmentShader, Program, VertexArray, VertexShader, Viewport};
+use crate::blur::{BlurContext, Framebuffer};
+use crate::gui::{DrawList, FlatRect, Label};
+use crate::history::History;
+use crate::image_io;
+use crate::preset::Preset;
+use crate::renderer_gl::{debug, ArrayBuffer, ImgSurface, Program, Result, VertexArray,
+                         Viewport};
+use crate::shader_source::{ProgramSpec, ShaderSource};
+use crate::style::{Corner, OverlayStyle};
+use crate::timing::{Timings, HISTORY_LEN};
 
 const INFO_ITERATIONS: &str = "Down-/Upsample Iterations";
 const INFO_OFFSET: &str = "Blur Offset";
+const INFO_PRESET: &str = "Preset";
 const INFO_CPU: &str = "CPU Time";
 const INFO_GPU: &str = "GPU Time";
+const INFO_FRAME: &str = "Frame Time";
+const INFO_GRAPH: &str = "Timings [T]";
+const INFO_FRAME_RATE: &str = "Continuous [B]";
+const INFO_KERNEL: &str = "Kernel Parameters [Tab, +/-]";
+const INFO_HISTORY: &str = "History [Ctrl-Z, Ctrl-Shift-Z, Click]";
+const INFO_IMAGE: &str = "Image [I]";
+const INFO_ORIGINAL: &str = "Original";
+const INFO_WORKING: &str = "Working Size";
+const INFO_LEVELS: &str = "Levels";
+const INFO_DEGENERATE: &str = "Warning: the smallest level is too small, the blur degenerates";
+
+/// Size of the smallest downsampled level below which the blur degenerates.
+const MIN_LEVEL_SIZE: u32 = 4;
+
+/// Number of history entries shown in the overlay.
+const HISTORY_LINES: usize = 5;
+
+const COLOR_ERROR: [f32; 4] = [1.0, 0.4, 0.4, 1.0];
+const COLOR_WARNING: [f32; 4] = [1.0, 0.7, 0.3, 1.0];
+const COLOR_CPU: [f32; 4] = [1.0, 0.8, 0.3, 1.0];
+const COLOR_GPU: [f32; 4] = [0.4, 1.0, 0.4, 1.0];
+const COLOR_FRAME: [f32; 4] = [0.5, 0.7, 1.0, 1.0];
+const COLOR_GRAPH: [f32; 4] = [0.0, 0.0, 0.0, 0.5];
+
+/// Sizes in pixels at a HiDPI scale of 1.
+const MARGIN: f32 = 20.0;
+const SECTION_SPACING: f32 = 16.0;
+const PANEL_PADDING: f32 = 6.0;
+const GRAPH_WIDTH: f32 = 320.0;
+const GRAPH_HEIGHT: f32 = 90.0;
+/// Font size of the controls, which have rows of a fixed height.
+const GUI_FONT_SIZE: f32 = 16.0;
+
+/// Size of the window copy behind blurred panels relative to the window.
+const FROST_DOWNSCALE: u32 = 4;
+
+const GLYPH_PROGRAM: ProgramSpec = ProgramSpec { vert: "glyphs.vert",
+                                                 frag: "glyphs.frag",
+                                                 uniforms: &["transform"] };
+const FLAT_PROGRAM: ProgramSpec = ProgramSpec { vert: "flat_quad.vert",
+                                                frag: "flat_quad.frag",
+                                                uniforms: &["transform"] };
+const FROST_PROGRAM: ProgramSpec = ProgramSpec { vert: "flat_quad.vert",
+                                                 frag: "frosted_panel.frag",
+                                                 uniforms: &["transform",
+                                                             "tex_src",
+                                                             "halfpixel",
+                                                             "screen_size"] };
+const LINE_PROGRAM: ProgramSpec = ProgramSpec { vert: "lines.vert",
+                                                frag: "lines.frag",
+                                                uniforms: &["transform"] };
 
 type Vertex = [GLfloat; 13];
+/// Bounds and color of a flat rectangle.
+type RectVertex = [GLfloat; 8];
+/// Position and color of a point of the timing graph.
+type LineVertex = [GLfloat; 6];
 
 struct InfoSection {
     text: String,
+    /// Corner of the section while the parameters are at the top left.
+    corner: Corner,
     position: (f32, f32),
+    color: [f32; 4],
     layout: Layout<glyph_brush::BuiltInLineBreaker>,
 }
 
+impl InfoSection {
+    fn new(text: String, corner: Corner, color: [f32; 4]) -> Self {
+        Self { text,
+               corner,
+               position: (0.0, 0.0),
+               color,
+               layout: Layout::default_wrap() }
+    }
+
+    /// Align the section to its corner of a viewport of `size`, mirrored for the parameters at
+    /// `params_corner`. The section is moved `offset` pixels away from the top or bottom edge.
+    fn place(&mut self, params_corner: Corner, size: (f32, f32), margin: f32, offset: f32) {
+        let corner = self.corner.mirrored(params_corner);
+        let (x, h_align) = if corner.right() {
+            (size.0 - margin, HorizontalAlign::Right)
+        } else {
+            (margin, HorizontalAlign::Left)
+        };
+        let (y, v_align) = if corner.bottom() {
+            (size.1 - margin - offset, VerticalAlign::Bottom)
+        } else {
+            (margin + offset, VerticalAlign::Top)
+        };
+        self.position = (x, y);
+        self.layout = Layout::default_wrap().h_align(h_align).v_align(v_align);
+    }
+}
+
 pub struct InfoOverlay<'a> {
     brush: GlyphBrush<'a, Vertex>,
     glyph_tex: GLuint,
@@ -36,40 +134,74 @@ pub struct InfoOverlay<'a> {
     program: Program,
     vbo: ArrayBuffer,
     vao: VertexArray,
+    flat_program: Program,
+    frost_program: Program,
+    /// Downsampled copy of the window, blurred behind the panels.
+    frost: Option<Framebuffer>,
+    rect_vbo: ArrayBuffer,
+    rect_vao: VertexArray,
+    max_rects: usize,
+    line_program: Program,
+    line_vbo: ArrayBuffer,
+    line_vao: VertexArray,
+    max_line_vertices: usize,
+    line_vertices: Vec<LineVertex>,
+    /// First vertex and vertex count of each series of the graph.
+    line_ranges: Vec<(i32, i32)>,
+    graph_rects: Vec<FlatRect>,
+    graph_labels: Vec<Label>,
+    show_graph: bool,
+    shown: bool,
+    style: OverlayStyle,
+    /// Ratio of the drawable size to the window size on HiDPI displays.
+    scale: f32,
+    line_height: f32,
+    vp_size: (u32, u32),
+    /// Sustained frame rate while blurring continuously.
+    frame_rate: Option<f32>,
     sec_defaults: Section<'a>,
     sec_params: InfoSection,
     sec_time: InfoSection,
+    sec_status: InfoSection,
+    sec_kernel: InfoSection,
+    sec_help: InfoSection,
+    sec_history: InfoSection,
+    sec_image: InfoSection,
+    show_image: bool,
+    /// Name and size in bytes of the image file, if known.
+    image_file: String,
+    file_size: Option<u64>,
+    /// Dimensions of the image file, the image scaled to the window and its sample format.
+    image_size: (u32, u32),
+    working_size: (u32, u32),
+    image_format: &'static str,
+    /// History entries of the lines of `sec_history` after its title.
+    history_lines: Vec<usize>,
+    show_help: bool,
+    selected_param: usize,
+    preset: Option<Preset>,
 }
 
 impl<'a> InfoOverlay<'a> {
-    pub fn new(blur_ctx: &BlurContext, vp: &Viewport) -> Self {
+    /// Create the overlay with `style` for a viewport at the HiDPI `scale`.
+    pub fn new(blur_ctx: &BlurContext,
+               vp: &Viewport,
+               style: OverlayStyle,
+               scale: f32)
+               -> Result<Self> {
+        // Init GLSL shaders / program
+        let mut program = GLYPH_PROGRAM.build(&ShaderSource::embedded())?;
+        init_glyph_program(&mut program, vp)?;
+
         // Init TTF glyph renderer
-        let font_data: &[u8] = include_bytes!("../assets/UbuntuMono-R.ttf");
-        let glyph_brush = GlyphBrushBuilder::using_font_bytes(font_data).build();
+        let glyph_brush = GlyphBrushBuilder::using_font(style.font.clone()).build();
 
         // Create glyph cache texture
         let dimensions = glyph_brush.texture_dimensions();
-        let glyph_tex = crate::renderer_gl::create_texture_red(dimensions.0, dimensions.1, None);
+        let glyph_tex = crate::renderer_gl::create_texture_red(dimensions.0, dimensions.1, None)?;
+        debug::label(gl::TEXTURE, glyph_tex, "glyph cache");
 
-        let max_tex_size = {
-            let mut value: GLint = 0;
-            unsafe { gl::GetIntegerv(gl::MAX_TEXTURE_SIZE, &mut value) };
-            value as u32
-        };
-
-        // Init GLSL shaders / program
-        let vert_shader = VertexShader::from_source(include_str!("shaders/glyphs.vert"))
-            .expect("Cannot compile glyph vertex shader");
-        let frag_shader = FragmentShader::from_source(include_str!("shaders/glyphs.frag"))
-            .expect("Cannot compile glyph fragment shader");
-        let mut program =
-            Program::from_shaders(&[vert_shader.into(), frag_shader.into()],
-                                  Some(&["transform"])).expect("Cannot link glyph program");
-
-        program.activate();
-        program.set_uniform_mat4f("transform", &vp.transform())
-               .expect("Cannot set 'transform' in glyph program");
-        program.unbind();
+        let max_tex_size = crate::renderer_gl::max_texture_size();
 
         // init vertex buffer / array object
         let vbo = ArrayBuffer::new();
@@ -93,44 +225,323 @@ impl<'a> InfoOverlay<'a> {
         }
         vbo.unbind();
         vao.unbind();
+        vbo.set_label("glyph vertices");
+        vao.set_label("glyphs");
+
+        // Init flat rectangles of the controls
+        let mut flat_program = FLAT_PROGRAM.build(&ShaderSource::embedded())?;
+        init_glyph_program(&mut flat_program, vp)?;
+        let mut frost_program = FROST_PROGRAM.build(&ShaderSource::embedded())?;
+        init_frost_program(&mut frost_program, vp)?;
+        let rect_vbo = ArrayBuffer::new();
+        let rect_vao = VertexArray::new();
+        rect_vao.bind();
+        rect_vbo.bind();
+        for attr in 0..2 {
+            unsafe {
+                gl::VertexAttribPointer(attr,
+                                        4,
+                                        gl::FLOAT,
+                                        gl::FALSE,
+                                        std::mem::size_of::<RectVertex>() as GLint,
+                                        (attr as usize * 4 * std::mem::size_of::<f32>())
+                                        as *const GLvoid);
+                gl::VertexAttribDivisor(attr, 1);
+            }
+        }
+        rect_vbo.unbind();
+        rect_vao.unbind();
+        rect_vbo.set_label("control rectangles");
+        rect_vao.set_label("controls");
 
-        // Set layout defaults
-        let defaults = Section { scale: Scale::uniform(16.0),
-                                 color: [1.0, 1.0, 1.0, 1.0],
+        // Init lines of the timing graph
+        let mut line_program = LINE_PROGRAM.build(&ShaderSource::embedded())?;
+        init_glyph_program(&mut line_program, vp)?;
+        let line_vbo = ArrayBuffer::new();
+        let line_vao = VertexArray::new();
+        line_vao.bind();
+        line_vbo.bind();
+        for (attr, (size, offset)) in [(2, 0), (4, 2)].iter().enumerate() {
+            unsafe {
+                gl::VertexAttribPointer(attr as u32,
+                                        *size,
+                                        gl::FLOAT,
+                                        gl::FALSE,
+                                        std::mem::size_of::<LineVertex>() as GLint,
+                                        (offset * std::mem::size_of::<f32>()) as *const GLvoid);
+            }
+        }
+        line_vbo.unbind();
+        line_vao.unbind();
+        line_vbo.set_label("timing graph vertices");
+        line_vao.set_label("timing graph");
+
+        // Set layout defaults, the font scale follows the HiDPI scale
+        let defaults = Section { color: style.color,
                                  z: 0.1,
                                  ..Section::default() };
 
-        // Pre-compute output sections
-        let sec_params = InfoSection { text: format!("{}: {}\n{}: {:.02}",
-                                                     INFO_ITERATIONS,
-                                                     blur_ctx.iterations(),
-                                                     INFO_OFFSET,
-                                                     blur_ctx.offset()),
-                                       position: (20.0, 20.0),
-                                       layout:
-                                           Layout::default_wrap().h_align(HorizontalAlign::Left)
-                                                                 .v_align(VerticalAlign::Top) };
-        let sec_time = InfoSection { text: format!("{}: {:6.03}ms\n{}: {:6.03}ms",
-                                                   INFO_CPU,
-                                                   blur_ctx.time_cpu(),
-                                                   INFO_GPU,
-                                                   blur_ctx.time_gpu()),
-                                     position: (20.0, vp.height() as f32 - 20.0),
-                                     layout:
-                                         Layout::default_wrap().h_align(HorizontalAlign::Left)
-                                                               .v_align(VerticalAlign::Bottom) };
-
-        Self { brush: glyph_brush,
-               glyph_tex,
-               max_tex_size,
-               vertex_count: 0,
-               max_vertices: 0,
-               program,
-               vbo,
-               vao,
-               sec_defaults: defaults,
-               sec_params,
-               sec_time }
+        // Pre-compute output sections, they are placed in `set_scale`
+        let sec_params = InfoSection::new(format!("{}: {}\n{}: {:.02}",
+                                                  INFO_ITERATIONS,
+                                                  blur_ctx.iterations(),
+                                                  INFO_OFFSET,
+                                                  blur_ctx.offset()),
+                                          Corner::TopLeft,
+                                          style.color);
+        let sec_time = InfoSection::new(format!("{}: {:6.03}ms\n{}: {:6.03}ms",
+                                                INFO_CPU,
+                                                blur_ctx.time_cpu(),
+                                                INFO_GPU,
+                                                blur_ctx.time_gpu()),
+                                        Corner::BottomLeft,
+                                        style.color);
+        let sec_status = InfoSection::new(String::new(), Corner::TopRight, COLOR_ERROR);
+        let sec_kernel = InfoSection::new(String::new(), Corner::TopLeft, style.color);
+        let sec_help = InfoSection::new(String::new(), Corner::TopLeft, style.color);
+        let sec_history = InfoSection::new(String::new(), Corner::BottomRight, style.color);
+        let sec_image = InfoSection::new(String::new(), Corner::TopLeft, style.color);
+
+        let mut overlay = Self { brush: glyph_brush,
+                                 glyph_tex,
+                                 max_tex_size,
+                                 vertex_count: 0,
+                                 max_vertices: 0,
+                                 program,
+                                 vbo,
+                                 vao,
+                                 flat_program,
+                                 frost_program,
+                                 frost: None,
+                                 rect_vbo,
+                                 rect_vao,
+                                 max_rects: 0,
+                                 line_program,
+                                 line_vbo,
+                                 line_vao,
+                                 max_line_vertices: 0,
+                                 line_vertices: Vec::new(),
+                                 line_ranges: Vec::new(),
+                                 graph_rects: Vec::new(),
+                                 graph_labels: Vec::new(),
+                                 show_graph: true,
+                                 shown: true,
+                                 style,
+                                 scale,
+                                 line_height: 0.0,
+                                 vp_size: vp.size(),
+                                 frame_rate: None,
+                                 sec_defaults: defaults,
+                                 sec_params,
+                                 sec_time,
+                                 sec_status,
+                                 sec_kernel,
+                                 sec_help,
+                                 sec_history,
+                                 sec_image,
+                                 show_image: true,
+                                 image_file: String::new(),
+                                 file_size: None,
+                                 image_size: (0, 0),
+                                 working_size: (0, 0),
+                                 image_format: "",
+                                 history_lines: Vec::new(),
+                                 show_help: false,
+                                 selected_param: 0,
+                                 preset: None };
+        overlay.set_scale(vp, scale);
+
+        Ok(overlay)
+    }
+
+    /// Rebuild the glyph, rectangle, panel and line programs if they are affected by the `changed`
+    /// shaders.
+    pub fn reload_program(&mut self,
+                          source: &ShaderSource,
+                          changed: &[String],
+                          vp: &Viewport)
+                          -> Result<()> {
+        if GLYPH_PROGRAM.uses_any(changed) {
+            let mut program = GLYPH_PROGRAM.build(source)?;
+            init_glyph_program(&mut program, vp)?;
+            self.program = program;
+        }
+        if FLAT_PROGRAM.uses_any(changed) {
+            let mut program = FLAT_PROGRAM.build(source)?;
+            init_glyph_program(&mut program, vp)?;
+            self.flat_program = program;
+        }
+        if FROST_PROGRAM.uses_any(changed) {
+            let mut program = FROST_PROGRAM.build(source)?;
+            init_frost_program(&mut program, vp)?;
+            self.frost_program = program;
+        }
+        if LINE_PROGRAM.uses_any(changed) {
+            let mut program = LINE_PROGRAM.build(source)?;
+            init_glyph_program(&mut program, vp)?;
+            self.line_program = program;
+        }
+
+        Ok(())
+    }
+
+    pub fn set_error(&mut self, msg: &str) {
+        self.sec_status.text = msg.to_owned();
+        self.sec_status.color = COLOR_ERROR;
+    }
+
+    pub fn set_info(&mut self, msg: &str) {
+        self.sec_status.text = msg.to_owned();
+        self.sec_status.color = self.style.color;
+    }
+
+    pub fn clear_status(&mut self) {
+        self.sec_status.text.clear();
+    }
+
+    /// Set the text of the help panel, which replaces the parameters while it is shown.
+    pub fn set_help(&mut self, text: String) {
+        self.sec_help.text = text;
+    }
+
+    pub fn toggle_help(&mut self) {
+        self.show_help = !self.show_help;
+    }
+
+    pub fn help_shown(&self) -> bool {
+        self.show_help
+    }
+
+    pub fn toggle_graph(&mut self) {
+        self.show_graph = !self.show_graph;
+    }
+
+    pub fn graph_shown(&self) -> bool {
+        self.show_graph
+    }
+
+    pub fn toggle_image(&mut self) {
+        self.show_image = !self.show_image;
+    }
+
+    pub fn image_shown(&self) -> bool {
+        self.show_image
+    }
+
+    /// Show the name and size of the image `file`, the rest is shown with `set_image`.
+    pub fn set_image_file(&mut self, file: &Path) {
+        self.image_file = if image_io::is_stdio(file) {
+            String::from("<stdin>")
+        } else {
+            file.file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default()
+        };
+        self.file_size = fs::metadata(file).ok().map(|metadata| metadata.len());
+    }
+
+    /// Show the dimensions and format of the image of `surf`, shown after the next `update`.
+    pub fn set_image(&mut self, surf: &ImgSurface) {
+        self.image_size = surf.image().dimensions();
+        self.working_size = surf.size();
+        self.image_format = surf.image().format_name();
+    }
+
+    /// Show or hide the whole overlay including the controls.
+    pub fn toggle(&mut self) {
+        self.shown = !self.shown;
+    }
+
+    pub fn shown(&self) -> bool {
+        self.shown
+    }
+
+    /// Show the sustained frame rate below the blur times, `None` outside continuous mode.
+    pub fn set_frame_rate(&mut self, fps: Option<f32>) {
+        self.frame_rate = fps;
+    }
+
+    /// Plot the series of `timings` next to the time section, scaled to the slowest sample.
+    pub fn update_timings(&mut self, timings: &Timings) {
+        let series = [(&timings.blur_cpu, INFO_CPU, COLOR_CPU),
+                      (&timings.blur_gpu, INFO_GPU, COLOR_GPU),
+                      (&timings.frame, INFO_FRAME, COLOR_FRAME)];
+        // at least 1ms, so idle timings do not fill the graph with noise
+        let scale = series.iter()
+                          .flat_map(|(samples, ..)| samples.samples())
+                          .fold(1.0, f32::max)
+                    * 1.1;
+
+        // above the time section at the bottom, below it at the top, with the labels above
+        let (width, height) = (GRAPH_WIDTH * self.scale, GRAPH_HEIGHT * self.scale);
+        let spacing = SECTION_SPACING * self.scale;
+        let corner = self.sec_time.corner.mirrored(self.style.corner);
+        let time = section_bounds(&mut self.brush, &self.sec_defaults, &self.sec_time);
+        let left = if corner.right() {
+            self.vp_size.0 as f32 - MARGIN * self.scale - width
+        } else {
+            MARGIN * self.scale
+        };
+        let top = if corner.bottom() {
+            time.map_or(self.sec_time.position.1, |bounds| bounds.min.y) - spacing - height
+        } else {
+            let labels_height = (series.len() + 1) as f32 * self.line_height;
+            time.map_or(self.sec_time.position.1, |bounds| bounds.max.y) + spacing + labels_height
+        };
+        let bottom = top + height;
+        self.graph_rects = vec![FlatRect { bounds: [left, top, left + width, bottom],
+                                           color: COLOR_GRAPH }];
+
+        // newest samples on the right
+        let step = width / (HISTORY_LEN - 1) as f32;
+        self.line_vertices.clear();
+        self.line_ranges.clear();
+        for (samples, _, color) in &series {
+            let first = self.line_vertices.len();
+            let count = samples.samples().count();
+            for (i, ms) in samples.samples().enumerate() {
+                let x = left + (HISTORY_LEN - count + i) as f32 * step;
+                let y = bottom - ms.min(scale) / scale * height;
+                self.line_vertices
+                    .push([x, y, color[0], color[1], color[2], color[3]]);
+            }
+            self.line_ranges.push((first as i32, count as i32));
+        }
+
+        // one line per series above the graph, vertically centered
+        let mut y = top - (series.len() as f32 + 0.5) * self.line_height;
+        self.graph_labels.clear();
+        self.graph_labels.push(Label { text: format!("{} (0 - {:.02}ms)", INFO_GRAPH, scale),
+                                       position: (left, y),
+                                       color: self.style.color });
+        for (samples, name, color) in &series {
+            y += self.line_height;
+            let text = match samples.stats() {
+                Some(stats) => format!("{:<10} min {:7.03} avg {:7.03} max {:7.03}ms",
+                                       name, stats.min, stats.avg, stats.max),
+                None => format!("{:<10} -", name),
+            };
+            self.graph_labels.push(Label { text,
+                                           position: (left, y),
+                                           color: *color });
+        }
+    }
+
+    /// Show the name of `preset` while the blur settings match it.
+    pub fn set_preset(&mut self, preset: Option<&Preset>) {
+        self.preset = preset.cloned();
+    }
+
+    pub fn selected_param(&self) -> usize {
+        self.selected_param
+    }
+
+    /// Move the kernel parameter selection by `delta`, wrapping around at both ends.
+    pub fn select_param(&mut self, blur_ctx: &BlurContext, delta: i32) {
+        let count = blur_ctx.kernel_params().len() as i32;
+        if count > 0 {
+            self.selected_param = (self.selected_param as i32 + delta).rem_euclid(count) as usize;
+        }
     }
 
     pub fn update(&mut self, blur_ctx: &BlurContext) {
@@ -139,32 +550,251 @@ impl<'a> InfoOverlay<'a> {
                                        blur_ctx.iterations(),
                                        INFO_OFFSET,
                                        blur_ctx.offset());
+        if let Some(ref preset) = self.preset {
+            if preset.iterations == blur_ctx.iterations() && preset.offset == blur_ctx.offset() {
+                self.sec_params
+                    .text
+                    .push_str(&format!("\n{}: {}", INFO_PRESET, preset.name));
+            }
+        }
         self.sec_time.text = format!("{}: {:6.03}ms\n{}: {:6.03}ms",
                                      INFO_CPU,
                                      blur_ctx.time_cpu(),
                                      INFO_GPU,
                                      blur_ctx.time_gpu());
+        if let Some(fps) = self.frame_rate {
+            self.sec_time
+                .text
+                .push_str(&format!("\n{}: {:6.01} fps", INFO_FRAME_RATE, fps));
+        }
+
+        self.update_image(blur_ctx);
+
+        self.sec_kernel.text.clear();
+        let params = blur_ctx.kernel_params();
+        if !params.is_empty() {
+            self.sec_kernel.text.push_str(INFO_KERNEL);
+            for (i, param) in params.iter().enumerate() {
+                let marker = if i == self.selected_param { '>' } else { ' ' };
+                self.sec_kernel
+                    .text
+                    .push_str(&format!("\n{} {}: {:.03}", marker, param.name, param.value));
+            }
+        }
     }
 
-    pub fn resize(&mut self, vp: &Viewport) {
-        self.sec_time.position.1 = vp.height() as f32 - 20.0;
-        self.program.activate();
-        self.program
-            .set_uniform_mat4f("transform", &vp.transform())
-            .expect("Cannot set 'transform' in glyph program");
-        self.program.unbind();
+    /// Describe the image and the levels of the blur, with a warning if the smallest level is
+    /// too small.
+    fn update_image(&mut self, blur_ctx: &BlurContext) {
+        let file_size = self.file_size
+                            .map(|size| format!(" ({})", format_file_size(size)))
+                            .unwrap_or_default();
+        let levels = blur_ctx.level_sizes();
+        let level_list = if levels.is_empty() {
+            String::from("-")
+        } else {
+            levels.iter()
+                  .map(|(width, height)| format!("{}x{}", width, height))
+                  .collect::<Vec<_>>()
+                  .join(" ")
+        };
+        self.sec_image.text = format!("{}: {}{}\n{}: {}x{}, {}\n{}: {}x{}\n{}: {}",
+                                      INFO_IMAGE,
+                                      self.image_file,
+                                      file_size,
+                                      INFO_ORIGINAL,
+                                      self.image_size.0,
+                                      self.image_size.1,
+                                      self.image_format,
+                                      INFO_WORKING,
+                                      self.working_size.0,
+                                      self.working_size.1,
+                                      INFO_LEVELS,
+                                      level_list);
+
+        match levels.last() {
+            Some(&(width, height)) if width.min(height) < MIN_LEVEL_SIZE => {
+                self.sec_image.text.push('\n');
+                self.sec_image.text.push_str(INFO_DEGENERATE);
+                self.sec_image.color = COLOR_WARNING;
+            },
+            _ => self.sec_image.color = self.style.color,
+        }
+    }
+
+    /// Show the entries of `history` around the current one.
+    pub fn update_history(&mut self, history: &History) {
+        let entries = history.entries();
+        let first = history.current()
+                           .saturating_sub(HISTORY_LINES / 2)
+                           .min(entries.len().saturating_sub(HISTORY_LINES));
+        self.history_lines = (first..entries.len().min(first + HISTORY_LINES)).collect();
+
+        self.sec_history.text = String::from(INFO_HISTORY);
+        for &index in &self.history_lines {
+            let entry = &entries[index];
+            let marker = if index == history.current() { '>' } else { ' ' };
+            self.sec_history
+                .text
+                .push_str(&format!("\n{} {} x {:5.02} {:6.03}ms",
+                                   marker,
+                                   entry.settings.iterations,
+                                   entry.settings.offset,
+                                   entry.time_gpu));
+        }
+    }
+
+    /// History entry shown at the window position `(x, y)`, if any.
+    pub fn history_entry_at(&mut self, x: i32, y: i32) -> Option<usize> {
+        if !self.shown || self.show_help || self.history_lines.is_empty() {
+            return None;
+        }
+
+        let bounds = section_bounds(&mut self.brush, &self.sec_defaults, &self.sec_history)?;
+        let (x, y) = (x as f32 * self.scale, y as f32 * self.scale);
+        if x < bounds.min.x || x > bounds.max.x || y < bounds.min.y || y >= bounds.max.y {
+            return None;
+        }
+
+        // all lines have the same height, the first one is the title
+        let line_count = self.history_lines.len() + 1;
+        let line = ((y - bounds.min.y) / bounds.height() * line_count as f32) as usize;
+        line.checked_sub(1)
+            .and_then(|line| self.history_lines.get(line).copied())
+    }
+
+    /// Resize to the drawable size of `vp`, `scale` is its ratio to the window size.
+    pub fn resize(&mut self, vp: &Viewport, scale: f32) -> Result<()> {
+        self.set_scale(vp, scale);
+        init_glyph_program(&mut self.flat_program, vp)?;
+        init_frost_program(&mut self.frost_program, vp)?;
+        init_glyph_program(&mut self.line_program, vp)?;
+        init_glyph_program(&mut self.program, vp)
     }
 
-    pub fn draw(&mut self, blend: bool) {
-        // Queue sections for drawing
-        self.brush.queue(Section { text: &self.sec_params.text,
-                                   screen_position: self.sec_params.position,
-                                   layout: self.sec_params.layout,
-                                   ..self.sec_defaults });
-        self.brush.queue(Section { text: &self.sec_time.text,
-                                   screen_position: self.sec_time.position,
-                                   layout: self.sec_time.layout,
-                                   ..self.sec_defaults });
+    /// Scale the font and distances by the HiDPI `scale` and place the sections in `vp`.
+    fn set_scale(&mut self, vp: &Viewport, scale: f32) {
+        let font_scale = Scale::uniform(self.style.font_size * scale);
+        let v_metrics = self.style.font.v_metrics(font_scale);
+        self.sec_defaults.scale = font_scale;
+        self.line_height = v_metrics.ascent - v_metrics.descent + v_metrics.line_gap;
+        self.scale = scale;
+        self.vp_size = vp.size();
+
+        let size = (vp.width() as f32, vp.height() as f32);
+        let corner = self.style.corner;
+        for sec in [&mut self.sec_params,
+                    &mut self.sec_time,
+                    &mut self.sec_status,
+                    &mut self.sec_kernel,
+                    &mut self.sec_help,
+                    &mut self.sec_history,
+                    &mut self.sec_image].iter_mut()
+        {
+            sec.place(corner, size, MARGIN * scale, 0.0);
+        }
+    }
+
+    /// Draw the text sections on their panels, the timing graph and the `widgets` of the
+    /// controls, which are given in window coordinates.
+    pub fn draw(&mut self, blend: bool, widgets: &DrawList) -> Result<()> {
+        if !self.shown {
+            return Ok(());
+        }
+
+        // the kernel parameters and the image information follow the blur parameters
+        let size = (self.vp_size.0 as f32, self.vp_size.1 as f32);
+        let (margin, spacing) = (MARGIN * self.scale, SECTION_SPACING * self.scale);
+        let mut offset = section_bounds(&mut self.brush, &self.sec_defaults, &self.sec_params)
+                             .map_or(0.0, |bounds| bounds.height() + spacing);
+        self.sec_kernel
+            .place(self.style.corner, size, margin, offset);
+        offset += section_bounds(&mut self.brush, &self.sec_defaults, &self.sec_kernel)
+                      .map_or(0.0, |bounds| bounds.height() + spacing);
+        self.sec_image
+            .place(self.style.corner, size, margin, offset);
+
+        // Queue sections for drawing, each on a panel of its bounds
+        let mut sections: Vec<&InfoSection> = if self.show_help {
+            vec![&self.sec_help, &self.sec_time, &self.sec_status]
+        } else {
+            vec![&self.sec_params,
+                 &self.sec_time,
+                 &self.sec_status,
+                 &self.sec_kernel,
+                 &self.sec_history]
+        };
+        if self.show_image && !self.show_help {
+            sections.push(&self.sec_image);
+        }
+        let padding = PANEL_PADDING * self.scale;
+        let mut panels: Vec<RectVertex> = Vec::with_capacity(sections.len());
+        for sec in &sections {
+            let section = Section { text: &sec.text,
+                                    screen_position: sec.position,
+                                    color: sec.color,
+                                    layout: sec.layout,
+                                    ..self.sec_defaults };
+            if let Some([cr, cg, cb, ca]) = self.style.panel {
+                if let Some(bounds) = self.brush.glyph_bounds(section) {
+                    panels.push([bounds.min.x - padding,
+                                 bounds.min.y - padding,
+                                 bounds.max.x + padding,
+                                 bounds.max.y + padding,
+                                 cr,
+                                 cg,
+                                 cb,
+                                 ca]);
+                }
+            }
+            self.brush.queue(section);
+        }
+
+        let show_graph = self.show_graph && !self.show_help;
+        let graph_labels: &[Label] = if show_graph { &self.graph_labels } else { &[] };
+        let single_line = Layout::default_single_line().v_align(VerticalAlign::Center);
+        for label in graph_labels {
+            self.brush.queue(Section { text: &label.text,
+                                       screen_position: label.position,
+                                       color: label.color,
+                                       layout: single_line,
+                                       ..self.sec_defaults });
+        }
+        for label in &widgets.labels {
+            let (x, y) = label.position;
+            self.brush.queue(Section { text: &label.text,
+                                       screen_position: (x * self.scale, y * self.scale),
+                                       scale: Scale::uniform(GUI_FONT_SIZE * self.scale),
+                                       color: label.color,
+                                       layout: single_line,
+                                       ..self.sec_defaults });
+        }
+
+        // Draw the panels, widgets and graph below the text
+        if self.style.panel_blur && !panels.is_empty() {
+            self.copy_backdrop()?;
+            self.draw_rects(blend, true, &panels)?;
+        } else {
+            self.draw_rects(blend, false, &panels)?;
+        }
+        let graph_rects: &[FlatRect] = if show_graph { &self.graph_rects } else { &[] };
+        let scale = self.scale;
+        let rects: Vec<RectVertex> = widgets.rects
+                                            .iter()
+                                            .map(|rect| FlatRect { bounds: rect.bounds
+                                                                               .map(|v| v * scale),
+                                                                   color: rect.color })
+                                            .chain(graph_rects.iter().copied())
+                                            .map(|rect| {
+                                                let [l, t, r, b] = rect.bounds;
+                                                let [cr, cg, cb, ca] = rect.color;
+                                                [l, t, r, b, cr, cg, cb, ca]
+                                            })
+                                            .collect();
+        self.draw_rects(blend, false, &rects)?;
+        if show_graph {
+            self.draw_lines(blend);
+        }
 
         let tex = self.glyph_tex;
         // Update part of gpu texture with new glyph alpha values
@@ -204,7 +834,7 @@ impl<'a> InfoOverlay<'a> {
                     crate::renderer_gl::resize_texture_red(self.glyph_tex,
                                                            new_width,
                                                            new_height,
-                                                           None);
+                                                           None)?;
                     self.brush.resize_texture(new_width, new_height);
                 },
             }
@@ -254,7 +884,193 @@ impl<'a> InfoOverlay<'a> {
             gl::BindTexture(gl::TEXTURE_2D, 0);
             gl::Disable(gl::BLEND);
         }
+
+        Ok(())
     }
+
+    /// Copy the window downsampled for the blurred panels.
+    fn copy_backdrop(&mut self) -> Result<()> {
+        let (width, height) = self.vp_size;
+        let size = ((width / FROST_DOWNSCALE).max(1), (height / FROST_DOWNSCALE).max(1));
+        match self.frost {
+            Some(ref mut frost) if frost.size() != size => frost.resize(size.0, size.1)?,
+            Some(_) => {},
+            None => {
+                let mut fbo: GLuint = 0;
+                unsafe {
+                    gl::GenFramebuffers(1, &mut fbo);
+                }
+                let mut frost = Framebuffer::from_fbo(fbo);
+                frost.attach_texture(crate::renderer_gl::create_texture_float(size.0, size.1)?)?;
+                frost.set_label("overlay backdrop");
+                self.frost = Some(frost);
+            },
+        }
+
+        if let Some(ref frost) = self.frost {
+            frost.bind_fbo();
+            unsafe {
+                gl::BindFramebuffer(gl::READ_FRAMEBUFFER, 0);
+                gl::BlitFramebuffer(0,
+                                    0,
+                                    width as i32,
+                                    height as i32,
+                                    0,
+                                    0,
+                                    size.0 as i32,
+                                    size.1 as i32,
+                                    gl::COLOR_BUFFER_BIT,
+                                    gl::LINEAR);
+            }
+            frost.unbind_fbo();
+        }
+        debug::check_error("InfoOverlay::copy_backdrop");
+
+        Ok(())
+    }
+
+    /// Draw rectangles below the text, `frosted` ones blur the copied window behind them.
+    fn draw_rects(&mut self, blend: bool, frosted: bool, vertices: &[RectVertex]) -> Result<()> {
+        if vertices.is_empty() {
+            return Ok(());
+        }
+
+        self.rect_vbo.bind();
+        if self.max_rects < vertices.len() {
+            self.rect_vbo.set_data(vertices, gl::DYNAMIC_DRAW);
+            self.max_rects = vertices.len();
+        } else {
+            self.rect_vbo.update_data(0, vertices);
+        }
+        self.rect_vbo.unbind();
+
+        if blend {
+            unsafe {
+                gl::Enable(gl::BLEND);
+                gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+            }
+        }
+
+        let program = match self.frost {
+            Some(ref frost) if frosted => {
+                let (width, height) = frost.size();
+                self.frost_program.activate();
+                self.frost_program
+                    .set_uniform_2f("halfpixel", (0.5 / width as f32, 0.5 / height as f32))?;
+                self.frost_program
+                    .set_uniform_2f("screen_size",
+                                    (self.vp_size.0 as f32, self.vp_size.1 as f32))?;
+                frost.bind_tex();
+                &self.frost_program
+            },
+            _ => {
+                self.flat_program.activate();
+                &self.flat_program
+            },
+        };
+        self.rect_vao.bind();
+        unsafe {
+            gl::EnableVertexAttribArray(0);
+            gl::EnableVertexAttribArray(1);
+            gl::DrawArraysInstanced(gl::TRIANGLE_STRIP, 0, 4, vertices.len() as i32);
+            gl::DisableVertexAttribArray(0);
+            gl::DisableVertexAttribArray(1);
+        }
+        self.rect_vao.unbind();
+        program.unbind();
+
+        unsafe {
+            gl::BindTexture(gl::TEXTURE_2D, 0);
+            gl::Disable(gl::BLEND);
+        }
+
+        Ok(())
+    }
+
+    /// Draw each series of the timing graph as a line strip.
+    fn draw_lines(&mut self, blend: bool) {
+        if self.line_vertices.is_empty() {
+            return;
+        }
+
+        self.line_vbo.bind();
+        if self.max_line_vertices < self.line_vertices.len() {
+            self.line_vbo.set_data(&self.line_vertices, gl::DYNAMIC_DRAW);
+            self.max_line_vertices = self.line_vertices.len();
+        } else {
+            self.line_vbo.update_data(0, &self.line_vertices);
+        }
+        self.line_vbo.unbind();
+
+        if blend {
+            unsafe {
+                gl::Enable(gl::BLEND);
+                gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+            }
+        }
+
+        self.line_program.activate();
+        self.line_vao.bind();
+        unsafe {
+            gl::EnableVertexAttribArray(0);
+            gl::EnableVertexAttribArray(1);
+            for &(first, count) in &self.line_ranges {
+                gl::DrawArrays(gl::LINE_STRIP, first, count);
+            }
+            gl::DisableVertexAttribArray(0);
+            gl::DisableVertexAttribArray(1);
+        }
+        self.line_vao.unbind();
+        self.line_program.unbind();
+
+        unsafe {
+            gl::Disable(gl::BLEND);
+        }
+    }
+}
+
+fn init_glyph_program(program: &mut Program, vp: &Viewport) -> Result<()> {
+    program.activate();
+    let result = program.set_uniform_mat4f("transform", &vp.transform());
+    program.unbind();
+
+    result
+}
+
+/// File size in bytes, KiB, MiB or GiB.
+fn format_file_size(bytes: u64) -> String {
+    const UNITS: [&str; 3] = ["KiB", "MiB", "GiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < UNITS.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+fn init_frost_program(program: &mut Program, vp: &Viewport) -> Result<()> {
+    program.activate();
+    let result = program.set_uniform_mat4f("transform", &vp.transform())
+                        .and_then(|_| program.set_uniform_1i("tex_src", 0));
+    program.unbind();
+
+    result
+}
+
+/// Bounds of the text of `sec` in pixels, `None` if it is empty.
+fn section_bounds(brush: &mut GlyphBrush<Vertex>,
+                  defaults: &Section,
+                  sec: &InfoSection)
+                  -> Option<Rect<f32>> {
+    brush.glyph_bounds(Section { text: &sec.text,
+                                 screen_position: sec.position,
+                                 layout: sec.layout,
+                                 ..*defaults })
 }
 
 #[rustfmt::skip]
@@ -0,0 +1,267 @@
+// This file is part auf the dual-kawase-demo package.
+//
+// (c) 2019 Bernd Busse
+//
+// For the full copyright and license information, please view the README.md file
+// that was distributed with this source code.
+//
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use toml::value::{Table, Value};
+
+use crate::blur::{BlurContext, MAX_ITERATIONS};
+use crate::config::{self, as_float};
+use crate::options::Options;
+use crate::renderer_gl::{Error, Result};
+
+/// Name of the presets file in the configuration directory.
+pub const CONFIG_FILE: &str = "presets.toml";
+
+const HELP_TITLE: &str = "Presets";
+
+/// Presets used without a presets file.
+const BUILTIN: &[(&str, u32, f32)] = &[("Faint", 1, 1.5),
+                                       ("Light", 1, 2.0),
+                                       ("Soft", 2, 2.5),
+                                       ("Mellow", 2, 3.0),
+                                       ("Smooth", 3, 2.75),
+                                       ("Frosted", 3, 3.5),
+                                       ("Hazy", 3, 4.25),
+                                       ("Strong", 3, 5.0),
+                                       ("Heavy", 4, 3.75)];
+
+/// Named set of blur settings.
+#[derive(Clone, Debug)]
+pub struct Preset {
+    pub name: String,
+    pub iterations: u32,
+    pub offset: f32,
+    /// Opacity of the blurred image, 1.0 if the presets file does not give one.
+    pub opacity: f32,
+    /// Values of kernel parameters by name, unknown names are ignored.
+    pub params: BTreeMap<String, f32>,
+}
+
+impl Preset {
+    /// Current settings of `blur_ctx`.
+    pub fn capture(name: String, blur_ctx: &BlurContext) -> Self {
+        Self { name,
+               iterations: blur_ctx.iterations(),
+               offset: blur_ctx.offset(),
+               opacity: blur_ctx.opacity(),
+               params: blur_ctx.kernel_values() }
+    }
+
+    pub fn apply(&self, blur_ctx: &mut BlurContext) {
+        blur_ctx.set_iterations(self.iterations);
+        blur_ctx.set_offset(self.offset);
+        blur_ctx.set_opacity(self.opacity);
+        self.apply_params(blur_ctx);
+    }
+
+    /// Set the kernel parameters only, within their limits.
+    pub fn apply_params(&self, blur_ctx: &mut BlurContext) {
+        blur_ctx.set_kernel_values(&self.params);
+    }
+
+    /// Short description, e.g. `Soft (2 iterations, offset 2.50)`.
+    pub fn summary(&self) -> String {
+        if self.opacity < 1.0 {
+            format!("{} ({} iterations, offset {:.2}, opacity {:.2})",
+                    self.name, self.iterations, self.offset, self.opacity)
+        } else {
+            format!("{} ({} iterations, offset {:.2})",
+                    self.name, self.iterations, self.offset)
+        }
+    }
+
+    fn from_toml(path: &Path, value: &Value) -> Result<Self> {
+        let invalid = |msg: &str| Error::config(path, format!("Invalid preset: {}", msg));
+        let table = value.as_table().ok_or_else(|| invalid("not a table"))?;
+
+        let name = match table.get("name").and_then(Value::as_str) {
+            Some(name) if !name.trim().is_empty() => name.trim().to_owned(),
+            _ => return Err(invalid("missing name")),
+        };
+        let iterations = match table.get("iterations").and_then(Value::as_integer) {
+            Some(n) if n >= 0 && n as usize <= MAX_ITERATIONS => n as u32,
+            _ => return Err(invalid(&format!("iterations of '{}'", name))),
+        };
+        let offset = match table.get("offset").and_then(as_float) {
+            Some(offset) if offset >= 0.0 => offset,
+            _ => return Err(invalid(&format!("offset of '{}'", name))),
+        };
+        let opacity = match table.get("opacity").map(as_float) {
+            None => 1.0,
+            Some(Some(opacity)) if (0.0..=1.0).contains(&opacity) => opacity,
+            Some(_) => return Err(invalid(&format!("opacity of '{}'", name))),
+        };
+
+        let mut params = BTreeMap::new();
+        if let Some(values) = table.get("params") {
+            let values = values.as_table()
+                               .ok_or_else(|| invalid(&format!("params of '{}'", name)))?;
+            for (param, value) in values {
+                let value = as_float(value).ok_or_else(|| {
+                                               invalid(&format!("parameter '{}' of '{}'",
+                                                                param, name))
+                                           })?;
+                params.insert(param.clone(), value);
+            }
+        }
+
+        Ok(Self { name,
+                  iterations,
+                  offset,
+                  opacity,
+                  params })
+    }
+
+    fn to_toml(&self) -> Value {
+        let mut table = Table::new();
+        table.insert(String::from("name"), Value::String(self.name.clone()));
+        table.insert(String::from("iterations"),
+                     Value::Integer(i64::from(self.iterations)));
+        table.insert(String::from("offset"), Value::Float(f64::from(self.offset)));
+        if self.opacity < 1.0 {
+            table.insert(String::from("opacity"), Value::Float(f64::from(self.opacity)));
+        }
+        if !self.params.is_empty() {
+            let params = self.params
+                             .iter()
+                             .map(|(name, value)| (name.clone(), Value::Float(f64::from(*value))))
+                             .collect();
+            table.insert(String::from("params"), Value::Table(params));
+        }
+
+        Value::Table(table)
+    }
+}
+
+/// Presets of the presets file, selected by number or name.
+pub struct Presets {
+    presets: Vec<Preset>,
+    path: Option<PathBuf>,
+}
+
+impl Presets {
+    /// Load the `[[preset]]` entries of `path` or of the presets file in the configuration
+    /// directory, otherwise use the built-in presets.
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let path = match path.map(Path::to_owned)
+                             .or_else(|| config::default_file(CONFIG_FILE))
+        {
+            Some(path) => path,
+            None => return Ok(Self::builtin()),
+        };
+
+        log::info!(target: "ui", "Load presets from '{}' ...", path.display());
+        let table = config::read_toml(&path)?;
+        let entries = match table.get("preset") {
+            Some(Value::Array(entries)) => entries.as_slice(),
+            Some(_) => return Err(Error::config(&path, "'preset' is not an array of tables")),
+            None => &[],
+        };
+
+        let mut presets: Vec<Preset> = Vec::with_capacity(entries.len());
+        for entry in entries {
+            let preset = Preset::from_toml(&path, entry)?;
+            if presets.iter().any(|p| p.name.eq_ignore_ascii_case(&preset.name)) {
+                return Err(Error::config(&path, format!("Duplicate preset '{}'", preset.name)));
+            }
+            presets.push(preset);
+        }
+
+        Ok(Self { presets,
+                  path: Some(path) })
+    }
+
+    fn builtin() -> Self {
+        let presets = BUILTIN.iter()
+                             .map(|&(name, iterations, offset)| Preset { name: String::from(name),
+                                                                         iterations,
+                                                                         offset,
+                                                                         opacity: 1.0,
+                                                                         params: BTreeMap::new() })
+                             .collect();
+        Self { presets,
+               path: None }
+    }
+
+    /// Preset number `index + 1`.
+    pub fn get(&self, index: usize) -> Option<&Preset> {
+        self.presets.get(index)
+    }
+
+    /// Preset by its name, ignoring case.
+    pub fn find(&self, name: &str) -> Result<&Preset> {
+        self.presets
+            .iter()
+            .find(|preset| preset.name.eq_ignore_ascii_case(name))
+            .ok_or_else(|| Error::UnknownPreset(String::from(name)))
+    }
+
+    /// Save the settings of `blur_ctx` as a new preset and write all presets to the presets
+    /// file.
+    pub fn add(&mut self, blur_ctx: &BlurContext) -> Result<&Preset> {
+        let name = (1..).map(|n| format!("Custom {}", n))
+                        .find(|name| self.find(name).is_err())
+                        .unwrap();
+        self.presets.push(Preset::capture(name, blur_ctx));
+
+        let path = match self.path {
+            Some(ref path) => path.clone(),
+            None => {
+                let dir = config::config_dir().ok_or_else(|| {
+                                                   Error::config(Path::new(CONFIG_FILE),
+                                                                 "No configuration directory")
+                                               })?;
+                fs::create_dir_all(&dir).map_err(|err| Error::write(&dir, err))?;
+                dir.join(CONFIG_FILE)
+            },
+        };
+        if let Err(err) = self.save(&path) {
+            self.presets.pop();
+            return Err(err);
+        }
+        self.path = Some(path);
+
+        Ok(self.presets.last().unwrap())
+    }
+
+    fn save(&self, path: &Path) -> Result<()> {
+        let mut root = Table::new();
+        root.insert(String::from("preset"),
+                    Value::Array(self.presets.iter().map(Preset::to_toml).collect()));
+        let text = toml::to_string(&Value::Table(root)).map_err(|err| {
+                                                          Error::config(path, err.to_string())
+                                                      })?;
+
+        log::info!(target: "io", "Save presets to '{}' ...", path.display());
+        fs::write(path, text).map_err(|err| Error::write(path, err))
+    }
+
+    /// Help text listing all presets with their number.
+    pub fn help_text(&self) -> String {
+        let mut text = String::from(HELP_TITLE);
+        for (i, preset) in self.presets.iter().enumerate() {
+            text.push_str(&format!("\n{:>2}  {}", i + 1, preset.summary()));
+        }
+
+        text
+    }
+}
+
+/// Preset given by `--preset`, if any.
+pub fn selected(options: &Options) -> Result<Option<Preset>> {
+    match options.preset {
+        Some(ref name) => {
+            let presets = Presets::load(options.presets_file.as_deref())?;
+            presets.find(name).map(|preset| Some(preset.clone()))
+        },
+        None => Ok(None),
+    }
+}
@@ -0,0 +1,252 @@
+// This file is part auf the dual-kawase-demo package.
+//
+// (c) 2019 Bernd Busse
+//
+// For the full copyright and license information, please view the README.md file
+// that was distributed with this source code.
+//
+
+use crate::blur::{BlurContext, MAX_ITERATIONS};
+use crate::image_io::SourceImage;
+use crate::renderer_gl::{Result, SampleFormat};
+use crate::tiling;
+
+/// Largest per-channel difference to the reference which is accepted, out of 255.
+pub const TOLERANCE: u8 = 2;
+
+/// Size of the inner rectangle of the tiles used by `verify_tiling`.
+const VERIFY_TILE_SIZE: u32 = 128;
+
+/// Result of comparing two RGBA images.
+pub struct Verification {
+    pub tiles: usize,
+    pub overlap: u32,
+    pub max_diff: u8,
+    pub mean_diff: f32,
+}
+
+impl Verification {
+    pub fn passed(&self) -> bool {
+        self.max_diff <= TOLERANCE
+    }
+}
+
+/// One level of the blur with RGBA values in `0.0..=1.0`.
+struct Level {
+    width: u32,
+    height: u32,
+    data: Vec<f32>,
+}
+
+impl Level {
+    fn new(width: u32, height: u32) -> Self {
+        Self { width,
+               height,
+               data: vec![0.0; width as usize * height as usize * 4] }
+    }
+
+    /// Bilinear lookup with clamp-to-edge like `texture2D` on a `GL_LINEAR` texture.
+    fn sample(&self, u: f32, v: f32) -> [f32; 4] {
+        let x = u * self.width as f32 - 0.5;
+        let y = v * self.height as f32 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+
+        let texel = |tx: f32, ty: f32| {
+            let tx = (tx.max(0.0) as u32).min(self.width - 1) as usize;
+            let ty = (ty.max(0.0) as u32).min(self.height - 1) as usize;
+            let idx = (ty * self.width as usize + tx) * 4;
+            &self.data[idx..idx + 4]
+        };
+
+        let (p00, p10) = (texel(x0, y0), texel(x0 + 1.0, y0));
+        let (p01, p11) = (texel(x0, y0 + 1.0), texel(x0 + 1.0, y0 + 1.0));
+        let mut result = [0.0; 4];
+        for c in 0..4 {
+            let top = p00[c] * (1.0 - fx) + p10[c] * fx;
+            let bottom = p01[c] * (1.0 - fx) + p11[c] * fx;
+            result[c] = top * (1.0 - fy) + bottom * fy;
+        }
+
+        result
+    }
+
+    /// Render every pixel with `kernel(u, v)`.
+    ///
+    /// The intermediate levels are half float framebuffers, so only the final result is
+    /// quantized to 8 bit.
+    fn render<F: Fn(f32, f32) -> [f32; 4]>(&mut self, scale: (f32, f32), kernel: F) {
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let u = (x as f32 + 0.5) * scale.0;
+                let v = (y as f32 + 0.5) * scale.1;
+                let color = kernel(u, v);
+
+                let idx = (y as usize * self.width as usize + x as usize) * 4;
+                self.data[idx..idx + 4].copy_from_slice(&color);
+            }
+        }
+    }
+}
+
+/// Dual-filter blur of RGBA `pixels` on the CPU, following the built-in shaders.
+pub fn dual_kawase(pixels: &[u8], size: (u32, u32), iterations: u32, offset: f32) -> Vec<u8> {
+    let iterations = (iterations as usize).min(MAX_ITERATIONS);
+    let mut levels = Vec::with_capacity(iterations + 1);
+    levels.push(Level { width: size.0,
+                        height: size.1,
+                        data: pixels.iter().map(|&p| f32::from(p) / 255.0).collect() });
+    if iterations == 0 {
+        return to_bytes(&levels[0]);
+    }
+
+    // the quad of level `i` covers `size / 2^i` pixels of the framebuffer
+    let level_scale = |i: usize| {
+        let fac = (1 << i) as f32;
+        (fac / size.0 as f32, fac / size.1 as f32)
+    };
+
+    // Downsample
+    for i in 1..=iterations {
+        let mut level = Level::new((size.0 >> i).max(1), (size.1 >> i).max(1));
+        let src = &levels[i - 1];
+        let hp = (0.5 / level.width as f32 * offset, 0.5 / level.height as f32 * offset);
+        level.render(level_scale(i), |u, v| {
+                 let mut sum = [0.0; 4];
+                 let taps = [(0.0, 0.0, 4.0),
+                             (-hp.0, -hp.1, 1.0),
+                             (hp.0, hp.1, 1.0),
+                             (hp.0, -hp.1, 1.0),
+                             (-hp.0, hp.1, 1.0)];
+                 for (du, dv, weight) in taps.iter() {
+                     accumulate(&mut sum, src.sample(u + du, v + dv), weight / 8.0);
+                 }
+                 sum
+             });
+        levels.push(level);
+    }
+
+    // Upsample
+    for i in (0..iterations).rev() {
+        let mut level = Level::new(levels[i].width, levels[i].height);
+        let src = &levels[i + 1];
+        let hp = (0.5 / level.width as f32 * offset, 0.5 / level.height as f32 * offset);
+        level.render(level_scale(i), |u, v| {
+                 let mut sum = [0.0; 4];
+                 let taps = [(-hp.0 * 2.0, 0.0, 1.0),
+                             (-hp.0, hp.1, 2.0),
+                             (0.0, hp.1 * 2.0, 1.0),
+                             (hp.0, hp.1, 2.0),
+                             (hp.0 * 2.0, 0.0, 1.0),
+                             (hp.0, -hp.1, 2.0),
+                             (0.0, -hp.1 * 2.0, 1.0),
+                             (-hp.0, -hp.1, 2.0)];
+                 for (du, dv, weight) in taps.iter() {
+                     accumulate(&mut sum, src.sample(u + du, v + dv), weight / 12.0);
+                 }
+                 sum[3] = 1.0;
+                 sum
+             });
+        levels[i] = level;
+    }
+
+    to_bytes(&levels[0])
+}
+
+/// Blur `img` in small tiles on the GPU and compare the result to the untiled CPU reference.
+///
+/// Images are compared at 8 bit, HDR images are clamped first.
+pub fn verify_tiling(blur_ctx: &mut BlurContext, img: &SourceImage) -> Result<Verification> {
+    let src = SourceImage::Bgra8(img.to_bgra8());
+    let size = src.dimensions();
+    let iterations = blur_ctx.iterations();
+    let offset = blur_ctx.offset();
+
+    let overlap = tiling::blur_overlap(iterations, offset);
+    let align = tiling::tile_alignment(iterations);
+    let tile_size = VERIFY_TILE_SIZE + 2 * overlap + 2 * align;
+    let tiles = tiling::split(size, tile_size, overlap, align)?;
+
+    let tiled = blur_ctx.offscreen(|blur_ctx| {
+                    tiling::blur_tiled(blur_ctx, &src, &tiles, SampleFormat::U8)
+                })?;
+    let reference = dual_kawase(&src.to_rgba8(), size, iterations, offset);
+
+    let (max_diff, mean_diff) = compare(&tiled, &reference);
+
+    Ok(Verification { tiles: tiles.len(),
+                      overlap,
+                      max_diff,
+                      mean_diff })
+}
+
+fn accumulate(sum: &mut [f32; 4], color: [f32; 4], weight: f32) {
+    for c in 0..4 {
+        sum[c] += color[c] * weight;
+    }
+}
+
+fn to_bytes(level: &Level) -> Vec<u8> {
+    level.data
+         .iter()
+         .map(|&v| (v.clamp(0.0, 1.0) * 255.0).round() as u8)
+         .collect()
+}
+
+/// Largest and mean per-channel difference of two equally sized buffers.
+fn compare(a: &[u8], b: &[u8]) -> (u8, f32) {
+    let mut max_diff = 0;
+    let mut sum: u64 = 0;
+    for (x, y) in a.iter().zip(b.iter()) {
+        let diff = x.abs_diff(*y);
+        max_diff = max_diff.max(diff);
+        sum += u64::from(diff);
+    }
+
+    (max_diff, sum as f32 / a.len().max(1) as f32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zero_iterations_copy_the_image() {
+        let pixels: Vec<u8> = (0..16 * 8 * 4).map(|i| (i * 7 % 256) as u8).collect();
+        assert_eq!(dual_kawase(&pixels, (16, 8), 0, 2.0), pixels);
+    }
+
+    #[test]
+    fn uniform_image_stays_uniform() {
+        let pixels = [40, 120, 200, 255].repeat(64 * 32);
+        for iterations in 1..4 {
+            let blurred = dual_kawase(&pixels, (64, 32), iterations, 1.5);
+            let (max_diff, _) = compare(&blurred, &pixels);
+            assert!(max_diff <= 1, "{} iterations differ by {}", iterations, max_diff);
+        }
+    }
+
+    #[test]
+    fn blur_smooths_edges() {
+        // left half black, right half white
+        let mut pixels = Vec::with_capacity(64 * 16 * 4);
+        for _ in 0..16 {
+            for x in 0..64 {
+                let value = if x < 32 { 0 } else { 255 };
+                pixels.extend_from_slice(&[value, value, value, 255]);
+            }
+        }
+
+        let blurred = dual_kawase(&pixels, (64, 16), 2, 1.0);
+        let at = |x: usize| blurred[(8 * 64 + x) * 4];
+        assert!(at(31) > 0 && at(32) < 255);
+        assert!(at(0) < 5 && at(63) > 250);
+    }
+
+    #[test]
+    fn compare_reports_largest_and_mean_difference() {
+        let (max_diff, mean_diff) = compare(&[0, 10, 20, 30], &[0, 12, 20, 26]);
+        assert_eq!(max_diff, 4);
+        assert!((mean_diff - 1.5).abs() < 1e-6);
+    }
+}
@@ -6,26 +6,59 @@
 // that was distributed with this source code.
 //
 
-use std::path::Path;
-use std::thread;
-
-use gl::types::{GLint, GLuint, GLvoid};
+use gl::types::{GLenum, GLint, GLuint, GLvoid};
 
 mod buffer;
+pub mod debug;
+mod error;
 mod quad;
+mod readback;
 mod shader;
 mod surface;
 mod viewport;
 
 pub use self::buffer::{ArrayBuffer, ElementArrayBuffer, VertexArray};
+pub use self::error::{Error, Result, ShaderStage};
 pub use self::quad::{GLQuad, Quad, TextureQuad};
+pub use self::readback::{Pixels, ReadbackQueue};
 pub use self::shader::{FragmentShader, GlShader, Program, VertexShader};
 pub use self::surface::ImgSurface;
 pub use self::viewport::Viewport;
 
+/// Type of the samples in pixel data transferred to or from textures.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SampleFormat {
+    U8,
+    U16,
+    /// Linear float samples of HDR images.
+    F32,
+}
+
+impl SampleFormat {
+    /// Size of one RGBA pixel in bytes.
+    pub fn pixel_size(self) -> usize {
+        match self {
+            SampleFormat::U8 => 4,
+            SampleFormat::U16 => 8,
+            SampleFormat::F32 => 16,
+        }
+    }
+
+    fn gl_type(self) -> GLenum {
+        match self {
+            SampleFormat::U8 => gl::UNSIGNED_BYTE,
+            SampleFormat::U16 => gl::UNSIGNED_SHORT,
+            SampleFormat::F32 => gl::FLOAT,
+        }
+    }
+}
+
 enum Alignment {
     RED,
     BGRA,
+    RGBA16,
+    RGBA16F,
+    RGBA32F,
 }
 
 impl Alignment {
@@ -33,53 +66,142 @@ impl Alignment {
         match self {
             Alignment::RED => 1,
             Alignment::BGRA => 4,
+            Alignment::RGBA16 | Alignment::RGBA16F | Alignment::RGBA32F => 8,
         }
     }
 }
 
-pub fn create_texture_bgra(width: u32, height: u32, data: Option<Vec<u8>>) -> GLuint {
-    create_texture(width, height, data, Alignment::BGRA)
+fn data_ptr<T>(data: &Option<Vec<T>>) -> *const GLvoid {
+    match data {
+        Some(vec) => vec.as_ptr() as *const GLvoid,
+        None => std::ptr::null(),
+    }
+}
+
+pub fn create_texture_bgra(width: u32, height: u32, data: Option<Vec<u8>>) -> Result<GLuint> {
+    create_texture(width, height, data_ptr(&data), Alignment::BGRA)
 }
 
-pub fn create_texture_red(width: u32, height: u32, data: Option<Vec<u8>>) -> GLuint {
-    create_texture(width, height, data, Alignment::RED)
+pub fn create_texture_red(width: u32, height: u32, data: Option<Vec<u8>>) -> Result<GLuint> {
+    create_texture(width, height, data_ptr(&data), Alignment::RED)
+}
+
+pub fn create_texture_rgba16(width: u32, height: u32, data: Option<Vec<u16>>) -> Result<GLuint> {
+    create_texture(width, height, data_ptr(&data), Alignment::RGBA16)
+}
+
+pub fn create_texture_rgba32f(width: u32, height: u32, data: Option<Vec<f32>>) -> Result<GLuint> {
+    create_texture(width, height, data_ptr(&data), Alignment::RGBA32F)
+}
+
+/// Create a half float texture for intermediate render targets.
+pub fn create_texture_float(width: u32, height: u32) -> Result<GLuint> {
+    create_texture(width, height, std::ptr::null(), Alignment::RGBA16F)
+}
+
+/// Create an empty texture which can be read back with samples of `format`.
+pub fn create_texture_for(width: u32, height: u32, format: SampleFormat) -> Result<GLuint> {
+    match format {
+        SampleFormat::U8 => create_texture_bgra(width, height, None),
+        SampleFormat::U16 => create_texture_rgba16(width, height, None),
+        SampleFormat::F32 => create_texture_rgba32f(width, height, None),
+    }
 }
 
-fn create_texture(width: u32, height: u32, data: Option<Vec<u8>>, align: Alignment) -> GLuint {
+fn create_texture(width: u32,
+                  height: u32,
+                  data: *const GLvoid,
+                  align: Alignment)
+                  -> Result<GLuint> {
     let mut texture: GLuint = 0;
     unsafe {
         gl::GenTextures(1, &mut texture);
     }
 
-    resize_texture(texture, width, height, data, align);
+    if let Err(err) = resize_texture(texture, width, height, data, align) {
+        unsafe {
+            gl::DeleteTextures(1, &texture);
+        }
+        return Err(err);
+    }
 
-    texture
+    Ok(texture)
 }
 
-pub fn resize_texture_bgra(tex: GLuint, width: u32, height: u32, data: Option<Vec<u8>>) {
-    resize_texture(tex, width, height, data, Alignment::BGRA)
+pub fn resize_texture_bgra(tex: GLuint,
+                           width: u32,
+                           height: u32,
+                           data: Option<Vec<u8>>)
+                           -> Result<()> {
+    resize_texture(tex, width, height, data_ptr(&data), Alignment::BGRA)
 }
 
-pub fn resize_texture_red(tex: GLuint, width: u32, height: u32, data: Option<Vec<u8>>) {
-    resize_texture(tex, width, height, data, Alignment::RED)
+pub fn resize_texture_red(tex: GLuint,
+                          width: u32,
+                          height: u32,
+                          data: Option<Vec<u8>>)
+                          -> Result<()> {
+    resize_texture(tex, width, height, data_ptr(&data), Alignment::RED)
 }
 
-fn resize_texture(tex: GLuint, width: u32, height: u32, data: Option<Vec<u8>>, align: Alignment) {
-    let raw_data = match data {
-        Some(vec) => vec.as_ptr() as *const GLvoid,
-        None => std::ptr::null(),
-    };
+pub fn resize_texture_rgba16(tex: GLuint,
+                             width: u32,
+                             height: u32,
+                             data: Option<Vec<u16>>)
+                             -> Result<()> {
+    resize_texture(tex, width, height, data_ptr(&data), Alignment::RGBA16)
+}
+
+pub fn resize_texture_rgba32f(tex: GLuint,
+                              width: u32,
+                              height: u32,
+                              data: Option<Vec<f32>>)
+                              -> Result<()> {
+    resize_texture(tex, width, height, data_ptr(&data), Alignment::RGBA32F)
+}
+
+pub fn resize_texture_float(tex: GLuint, width: u32, height: u32) -> Result<()> {
+    resize_texture(tex, width, height, std::ptr::null(), Alignment::RGBA16F)
+}
+
+/// Resize an empty texture created by `create_texture_for`.
+pub fn resize_texture_for(tex: GLuint,
+                          width: u32,
+                          height: u32,
+                          format: SampleFormat)
+                          -> Result<()> {
+    match format {
+        SampleFormat::U8 => resize_texture_bgra(tex, width, height, None),
+        SampleFormat::U16 => resize_texture_rgba16(tex, width, height, None),
+        SampleFormat::F32 => resize_texture_rgba32f(tex, width, height, None),
+    }
+}
 
+fn resize_texture(tex: GLuint,
+                  width: u32,
+                  height: u32,
+                  raw_data: *const GLvoid,
+                  align: Alignment)
+                  -> Result<()> {
     let components = match align {
         Alignment::RED => gl::RED,
         Alignment::BGRA => gl::RGBA8,
+        Alignment::RGBA16 => gl::RGBA16,
+        Alignment::RGBA16F => gl::RGBA16F,
+        Alignment::RGBA32F => gl::RGBA32F,
     };
     let format = match align {
         Alignment::RED => gl::RED,
         Alignment::BGRA => gl::BGRA,
+        Alignment::RGBA16 | Alignment::RGBA16F | Alignment::RGBA32F => gl::RGBA,
+    };
+    let data_type = match align {
+        Alignment::RED | Alignment::BGRA => gl::UNSIGNED_BYTE,
+        Alignment::RGBA16 => gl::UNSIGNED_SHORT,
+        Alignment::RGBA16F | Alignment::RGBA32F => gl::FLOAT,
     };
 
-    unsafe {
+    let error = unsafe {
         gl::PixelStorei(gl::UNPACK_ALIGNMENT, align.value());
         gl::BindTexture(gl::TEXTURE_2D, tex);
         gl::TexImage2D(gl::TEXTURE_2D,
@@ -89,15 +211,26 @@ fn resize_texture(tex: GLuint, width: u32, height: u32, data: Option<Vec<u8>>, a
                        height as i32,
                        0,
                        format,
-                       gl::UNSIGNED_BYTE,
+                       data_type,
                        raw_data);
+        let error = gl::GetError();
         gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
         gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
         gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAX_LEVEL, 0);
         gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
         gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
         gl::BindTexture(gl::TEXTURE_2D, 0);
+
+        error
+    };
+
+    if error == gl::OUT_OF_MEMORY {
+        return Err(Error::OutOfMemory);
     }
+    debug::report_error(error, "glTexImage2D");
+    debug::check_error("resize_texture");
+
+    Ok(())
 }
 
 pub fn get_texture_size(tex: GLuint) -> (u32, u32) {
@@ -109,51 +242,35 @@ pub fn get_texture_size(tex: GLuint) -> (u32, u32) {
         gl::GetTexLevelParameteriv(gl::TEXTURE_2D, 0, gl::TEXTURE_HEIGHT, &mut height);
         gl::BindTexture(gl::TEXTURE_2D, 0);
     }
+    debug::check_error("get_texture_size");
 
     (width as u32, height as u32)
 }
 
-pub fn save_texture_to_png(tex: GLuint, filename: &Path, in_background: bool) {
-    // get texture size
-    let (width, height) = get_texture_size(tex);
+pub fn max_texture_size() -> u32 {
+    let mut value: GLint = 0;
+    unsafe {
+        gl::GetIntegerv(gl::MAX_TEXTURE_SIZE, &mut value);
+    }
+
+    value as u32
+}
 
-    // get texture pixels
-    let size: usize = width as usize * height as usize * 4;
-    let mut pixel_buf: Vec<u8> = Vec::with_capacity(size);
-    pixel_buf.extend([0u8].iter().cycle().take(size));
+/// Read the RGBA pixels of `tex` synchronously with samples of `format`.
+pub fn read_texture(tex: GLuint, format: SampleFormat) -> Vec<u8> {
+    let (width, height) = get_texture_size(tex);
+    let mut pixels = vec![0u8; width as usize * height as usize * format.pixel_size()];
     unsafe {
+        gl::PixelStorei(gl::PACK_ALIGNMENT, 4);
         gl::BindTexture(gl::TEXTURE_2D, tex);
         gl::GetTexImage(gl::TEXTURE_2D,
                         0,
                         gl::RGBA,
-                        gl::UNSIGNED_BYTE,
-                        pixel_buf.as_ptr() as *mut GLvoid);
+                        format.gl_type(),
+                        pixels.as_mut_ptr() as *mut GLvoid);
         gl::BindTexture(gl::TEXTURE_2D, 0);
     }
+    debug::check_error("read_texture");
 
-    // save pixels to file
-    let fname = filename.to_owned();
-    if in_background {
-        thread::spawn(move || {
-            match image::save_buffer(fname,
-                                     &pixel_buf,
-                                     width as u32,
-                                     height as u32,
-                                     image::RGBA(8)).map_err(|e| e.to_string())
-            {
-                Ok(_) => println!("Save complete"),
-                Err(msg) => eprintln!("Cannot save blurred image: {}", msg),
-            }
-        });
-    } else {
-        match image::save_buffer(fname,
-                                 &pixel_buf,
-                                 width as u32,
-                                 height as u32,
-                                 image::RGBA(8)).map_err(|e| e.to_string())
-        {
-            Ok(_) => println!("Save complete"),
-            Err(msg) => eprintln!("Cannot save blurred image: {}", msg),
-        };
-    }
+    pixels
 }
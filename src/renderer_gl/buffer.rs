@@ -8,6 +8,8 @@
 
 use gl::types::{GLenum, GLsizeiptr, GLuint, GLvoid};
 
+use super::debug;
+
 pub trait BufferType {
     const BUFFER_TYPE: GLuint;
 }
@@ -35,6 +37,7 @@ impl<B: BufferType> Buffer<B> {
                            data.as_ptr() as *const GLvoid,
                            kind);
         }
+        debug::check_error("Buffer::set_data");
     }
 
     pub fn update_data<T>(&mut self, offset: isize, data: &[T]) {
@@ -44,6 +47,12 @@ impl<B: BufferType> Buffer<B> {
                               (data.len() * std::mem::size_of::<T>()) as GLsizeiptr,
                               data.as_ptr() as *const GLvoid);
         }
+        debug::check_error("Buffer::update_data");
+    }
+
+    /// Label the buffer, it has to be bound at least once before.
+    pub fn set_label(&self, label: &str) {
+        debug::label(gl::BUFFER, self.vbo, label);
     }
 
     pub fn bind(&self) {
@@ -94,6 +103,11 @@ impl VertexArray {
         Self { vao }
     }
 
+    /// Label the vertex array, it has to be bound at least once before.
+    pub fn set_label(&self, label: &str) {
+        debug::label(gl::VERTEX_ARRAY, self.vao, label);
+    }
+
     pub fn bind(&self) {
         unsafe {
             gl::BindVertexArray(self.vao);
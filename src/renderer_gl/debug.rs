@@ -0,0 +1,210 @@
+// This file is part auf the dual-kawase-demo package.
+//
+// (c) 2019 Bernd Busse
+//
+// For the full copyright and license information, please view the README.md file
+// that was distributed with this source code.
+//
+
+use std::ffi::{CStr, CString};
+use std::fmt;
+use std::os::raw::c_void;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use gl::types::{GLchar, GLenum, GLint, GLsizei, GLuint};
+use log::Level;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static CALLBACK: AtomicBool = AtomicBool::new(false);
+static MIN_SEVERITY: AtomicUsize = AtomicUsize::new(Severity::Low as usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub enum Severity {
+    Notification,
+    Low,
+    Medium,
+    High,
+}
+
+impl Severity {
+    fn from_gl(severity: GLenum) -> Self {
+        match severity {
+            gl::DEBUG_SEVERITY_HIGH => Severity::High,
+            gl::DEBUG_SEVERITY_MEDIUM => Severity::Medium,
+            gl::DEBUG_SEVERITY_LOW => Severity::Low,
+            _ => Severity::Notification,
+        }
+    }
+
+    fn log_level(self) -> Level {
+        match self {
+            Severity::High => Level::Error,
+            Severity::Medium => Level::Warn,
+            Severity::Low => Level::Info,
+            Severity::Notification => Level::Debug,
+        }
+    }
+}
+
+impl FromStr for Severity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "notification" => Ok(Severity::Notification),
+            "low" => Ok(Severity::Low),
+            "medium" => Ok(Severity::Medium),
+            "high" => Ok(Severity::High),
+            _ => Err(format!("Unknown severity '{}'", s)),
+        }
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Notification => write!(f, "notification"),
+            Severity::Low => write!(f, "low"),
+            Severity::Medium => write!(f, "medium"),
+            Severity::High => write!(f, "high"),
+        }
+    }
+}
+
+/// Enable GL debug output for messages of at least `min_severity`.
+///
+/// Uses the `KHR_debug` message callback if the context supports it and falls back to
+/// `glGetError` checks in `check_error` otherwise. Returns `true` if the callback is used.
+pub fn enable(min_severity: Severity) -> bool {
+    MIN_SEVERITY.store(min_severity as usize, Ordering::Relaxed);
+    ENABLED.store(true, Ordering::Relaxed);
+
+    let has_callback = gl::DebugMessageCallback::is_loaded()
+                       && (has_version(4, 3)
+                           || has_extension("GL_KHR_debug")
+                           || has_extension("GL_ARB_debug_output"));
+    if has_callback {
+        unsafe {
+            gl::Enable(gl::DEBUG_OUTPUT);
+            gl::Enable(gl::DEBUG_OUTPUT_SYNCHRONOUS);
+            gl::DebugMessageCallback(Some(debug_callback), std::ptr::null());
+            gl::DebugMessageControl(gl::DONT_CARE,
+                                    gl::DONT_CARE,
+                                    gl::DONT_CARE,
+                                    0,
+                                    std::ptr::null(),
+                                    gl::TRUE);
+        }
+    }
+    CALLBACK.store(has_callback, Ordering::Relaxed);
+
+    has_callback
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Report pending GL errors of the last call if the debug callback is not available.
+pub fn check_error(context: &str) {
+    if !is_enabled() || CALLBACK.load(Ordering::Relaxed) {
+        return;
+    }
+
+    loop {
+        let error = unsafe { gl::GetError() };
+        if error == gl::NO_ERROR {
+            break;
+        }
+        report_error(error, context);
+    }
+}
+
+/// Report an error already taken from `glGetError`.
+pub fn report_error(error: GLenum, context: &str) {
+    if !is_enabled() || error == gl::NO_ERROR {
+        return;
+    }
+
+    log_message(Severity::High,
+                "error",
+                &format!("{} in {}", error_name(error), context));
+}
+
+/// Attach a human readable `label` to a GL object, shown in debug messages and GL debuggers.
+pub fn label(identifier: GLenum, name: GLuint, label: &str) {
+    if !is_enabled() || !gl::ObjectLabel::is_loaded() || name == 0 {
+        return;
+    }
+
+    let c_label = CString::new(label).unwrap();
+    unsafe {
+        gl::ObjectLabel(identifier, name, -1, c_label.as_ptr() as *const GLchar);
+    }
+    check_error("glObjectLabel");
+}
+
+fn log_message(severity: Severity, kind: &str, msg: &str) {
+    if (severity as usize) < MIN_SEVERITY.load(Ordering::Relaxed) {
+        return;
+    }
+
+    log::log!(target: "renderer", severity.log_level(), "GL {}: {}", kind, msg);
+}
+
+extern "system" fn debug_callback(_source: GLenum,
+                                  kind: GLenum,
+                                  _id: GLuint,
+                                  severity: GLenum,
+                                  _length: GLsizei,
+                                  message: *const GLchar,
+                                  _user_param: *mut c_void) {
+    let kind = match kind {
+        gl::DEBUG_TYPE_ERROR => "error",
+        gl::DEBUG_TYPE_DEPRECATED_BEHAVIOR => "deprecated",
+        gl::DEBUG_TYPE_UNDEFINED_BEHAVIOR => "undefined",
+        gl::DEBUG_TYPE_PORTABILITY => "portability",
+        gl::DEBUG_TYPE_PERFORMANCE => "performance",
+        _ => "other",
+    };
+    let msg = unsafe { CStr::from_ptr(message) };
+
+    log_message(Severity::from_gl(severity), kind, &msg.to_string_lossy());
+}
+
+fn has_version(major: GLint, minor: GLint) -> bool {
+    let mut ctx_major: GLint = 0;
+    let mut ctx_minor: GLint = 0;
+    unsafe {
+        gl::GetIntegerv(gl::MAJOR_VERSION, &mut ctx_major);
+        gl::GetIntegerv(gl::MINOR_VERSION, &mut ctx_minor);
+    }
+
+    (ctx_major, ctx_minor) >= (major, minor)
+}
+
+fn has_extension(name: &str) -> bool {
+    let mut count: GLint = 0;
+    unsafe {
+        gl::GetIntegerv(gl::NUM_EXTENSIONS, &mut count);
+    }
+
+    (0..count as GLuint).any(|i| {
+                            let ext = unsafe { gl::GetStringi(gl::EXTENSIONS, i) };
+                            !ext.is_null()
+                            && unsafe { CStr::from_ptr(ext as *const GLchar) }.to_bytes()
+                               == name.as_bytes()
+                        })
+}
+
+fn error_name(error: GLenum) -> String {
+    match error {
+        gl::INVALID_ENUM => String::from("GL_INVALID_ENUM"),
+        gl::INVALID_VALUE => String::from("GL_INVALID_VALUE"),
+        gl::INVALID_OPERATION => String::from("GL_INVALID_OPERATION"),
+        gl::INVALID_FRAMEBUFFER_OPERATION => String::from("GL_INVALID_FRAMEBUFFER_OPERATION"),
+        gl::OUT_OF_MEMORY => String::from("GL_OUT_OF_MEMORY"),
+        _ => format!("GL error 0x{:04x}", error),
+    }
+}
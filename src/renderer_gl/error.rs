@@ -0,0 +1,169 @@
+// This file is part auf the dual-kawase-demo package.
+//
+// (c) 2019 Bernd Busse
+//
+// For the full copyright and license information, please view the README.md file
+// that was distributed with this source code.
+//
+
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use gl::types::GLenum;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ShaderStage {
+    Vertex,
+    Fragment,
+}
+
+impl ShaderStage {
+    pub fn from_gl(kind: GLenum) -> Self {
+        match kind {
+            gl::VERTEX_SHADER => ShaderStage::Vertex,
+            _ => ShaderStage::Fragment,
+        }
+    }
+}
+
+impl fmt::Display for ShaderStage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ShaderStage::Vertex => write!(f, "vertex"),
+            ShaderStage::Fragment => write!(f, "fragment"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    /// `gl::CompileShader` failed with the shader info log.
+    ShaderCompile { stage: ShaderStage, log: String },
+    /// `gl::LinkProgram` failed with the program info log.
+    Link { log: String },
+    /// Uniform is not active in the program.
+    MissingUniform(String),
+    /// Framebuffer is not complete, holds the status returned by `gl::CheckFramebufferStatus`.
+    IncompleteFramebuffer(GLenum),
+    /// Texture storage cannot be allocated.
+    OutOfMemory,
+    /// Pixel buffer of the readback for the image file cannot be mapped.
+    Readback(PathBuf),
+    /// Tiles of `tile_size` pixels cannot hold the blur overlap on both sides.
+    TileOverlap { overlap: u32, tile_size: u32 },
+    /// Error while building the named program.
+    Program { name: String, error: Box<Error> },
+    /// Shader name is not known.
+    UnknownShader(String),
+    /// Preset name is not known.
+    UnknownPreset(String),
+    /// Reading a file failed.
+    Io { path: PathBuf, error: io::Error },
+    /// Writing a file or creating a directory failed.
+    Write { path: PathBuf, error: io::Error },
+    /// Decoding an image file failed.
+    ImageLoad {
+        path: PathBuf,
+        error: image::ImageError,
+    },
+    /// Encoding an image file failed.
+    ImageSave {
+        path: PathBuf,
+        error: image::ImageError,
+    },
+    /// Configuration file is malformed.
+    Config { path: PathBuf, msg: String },
+}
+
+impl Error {
+    pub fn io(path: &Path, error: io::Error) -> Self {
+        Error::Io { path: path.to_owned(),
+                    error }
+    }
+
+    pub fn write(path: &Path, error: io::Error) -> Self {
+        Error::Write { path: path.to_owned(),
+                       error }
+    }
+
+    pub fn config<S: Into<String>>(path: &Path, msg: S) -> Self {
+        Error::Config { path: path.to_owned(),
+                        msg: msg.into() }
+    }
+
+    pub fn program(name: &str, error: Error) -> Self {
+        Error::Program { name: String::from(name),
+                         error: Box::new(error) }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::ShaderCompile { stage, log } => {
+                write!(f, "Cannot compile {} shader: {}", stage, log.trim_end())
+            },
+            Error::Link { log } => write!(f, "Cannot link program: {}", log.trim_end()),
+            Error::MissingUniform(name) => write!(f, "Uniform location '{}' not found", name),
+            Error::IncompleteFramebuffer(status) => match framebuffer_status_name(*status) {
+                Some(name) => write!(f, "Framebuffer incomplete: {}", name),
+                None => write!(f, "Framebuffer incomplete: unknown status 0x{:04x}", status),
+            },
+            Error::OutOfMemory => write!(f, "Out of GPU memory"),
+            Error::Readback(path) => {
+                write!(f, "Cannot read back image '{}' from the GPU", path.display())
+            },
+            Error::TileOverlap { overlap, tile_size } => {
+                write!(f,
+                       "Blur overlap of {}px does not fit into {}px tiles",
+                       overlap, tile_size)
+            },
+            Error::Program { name, error } => write!(f, "[{}] {}", name, error),
+            Error::UnknownShader(name) => write!(f, "Unknown shader '{}'", name),
+            Error::UnknownPreset(name) => write!(f, "Unknown preset '{}'", name),
+            Error::Io { path, error } => write!(f, "Cannot read '{}': {}", path.display(), error),
+            Error::Write { path, error } => {
+                write!(f, "Cannot write '{}': {}", path.display(), error)
+            },
+            Error::ImageLoad { path, error } => {
+                write!(f, "Cannot load image '{}': {}", path.display(), error)
+            },
+            Error::ImageSave { path, error } => {
+                write!(f, "Cannot save image '{}': {}", path.display(), error)
+            },
+            Error::Config { path, msg } => {
+                write!(f, "Invalid config file '{}': {}", path.display(), msg)
+            },
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Program { error, .. } => Some(error.as_ref()),
+            Error::Io { error, .. } | Error::Write { error, .. } => Some(error),
+            Error::ImageLoad { error, .. } | Error::ImageSave { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+fn framebuffer_status_name(status: GLenum) -> Option<&'static str> {
+    match status {
+        gl::FRAMEBUFFER_UNDEFINED => Some("GL_FRAMEBUFFER_UNDEFINED"),
+        gl::FRAMEBUFFER_INCOMPLETE_ATTACHMENT => Some("GL_FRAMEBUFFER_INCOMPLETE_ATTACHMENT"),
+        gl::FRAMEBUFFER_INCOMPLETE_MISSING_ATTACHMENT => {
+            Some("GL_FRAMEBUFFER_INCOMPLETE_MISSING_ATTACHMENT")
+        },
+        gl::FRAMEBUFFER_INCOMPLETE_DRAW_BUFFER => Some("GL_FRAMEBUFFER_INCOMPLETE_DRAW_BUFFER"),
+        gl::FRAMEBUFFER_INCOMPLETE_READ_BUFFER => Some("GL_FRAMEBUFFER_INCOMPLETE_READ_BUFFER"),
+        gl::FRAMEBUFFER_UNSUPPORTED => Some("GL_FRAMEBUFFER_UNSUPPORTED"),
+        gl::FRAMEBUFFER_INCOMPLETE_MULTISAMPLE => Some("GL_FRAMEBUFFER_INCOMPLETE_MULTISAMPLE"),
+        gl::FRAMEBUFFER_INCOMPLETE_LAYER_TARGETS => Some("GL_FRAMEBUFFER_INCOMPLETE_LAYER_TARGETS"),
+        _ => None,
+    }
+}
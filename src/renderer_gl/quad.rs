@@ -10,7 +10,7 @@ use std::ops::{Deref, DerefMut};
 
 use gl::types::{GLint, GLuint, GLvoid};
 
-use super::{ArrayBuffer, ElementArrayBuffer, VertexArray};
+use super::{debug, ArrayBuffer, ElementArrayBuffer, Result, SampleFormat, VertexArray};
 
 pub struct Quad {
     width: u32,
@@ -96,6 +96,13 @@ impl Quad {
                vao }
     }
 
+    /// Label the vertex and index buffers and the vertex array of the quad.
+    pub fn set_label(&self, label: &str) {
+        self.vbo.set_label(&format!("{} vertices", label));
+        self.ebo.set_label(&format!("{} indices", label));
+        self.vao.set_label(label);
+    }
+
     pub fn size(&self) -> (u32, u32) {
         (self.width, self.height)
     }
@@ -172,6 +179,7 @@ impl Quad {
             gl::DisableVertexAttribArray(1);
         }
         self.vao.unbind();
+        debug::check_error("Quad::draw");
 
         unsafe {
             gl::Disable(gl::BLEND);
@@ -188,20 +196,41 @@ pub trait TextureQuad<T> {
 
 pub struct GLQuad {
     texture: GLuint,
+    format: SampleFormat,
     quad: Quad,
 }
 
 impl GLQuad {
-    pub fn new_with_texture(x: i32, y: i32, width: u32, height: u32, vp_size: (u32, u32)) -> Self {
-        let texture = super::create_texture_bgra(width, height, None);
+    /// Quad with a target texture which keeps the precision of `format`.
+    pub fn new_with_format(x: i32,
+                           y: i32,
+                           width: u32,
+                           height: u32,
+                           vp_size: (u32, u32),
+                           format: SampleFormat)
+                           -> Result<Self> {
+        let texture = super::create_texture_for(width, height, format)?;
         let quad = Quad::new(x, y, width as u32, height as u32, vp_size, false, false);
 
-        Self { texture, quad }
+        Ok(Self { texture,
+                  format,
+                  quad })
     }
 
-    pub fn resize(&mut self, width: u32, height: u32) {
-        super::resize_texture_bgra(self.texture, width, height, None);
+    pub fn format(&self) -> SampleFormat {
+        self.format
+    }
+
+    /// Label the target texture and the buffers of the quad.
+    pub fn set_label(&self, label: &str) {
+        debug::label(gl::TEXTURE, self.texture, &format!("{} texture", label));
+        self.quad.set_label(label);
+    }
+
+    pub fn resize(&mut self, width: u32, height: u32) -> Result<()> {
+        super::resize_texture_for(self.texture, width, height, self.format)?;
         self.quad.resize(width, height);
+        Ok(())
     }
 }
 
@@ -223,7 +252,9 @@ impl TextureQuad<GLuint> for GLQuad {
     fn from_texture(tex: GLuint, x: i32, y: i32, vp_size: (u32, u32)) -> Self {
         let (width, height) = super::get_texture_size(tex);
         let quad = Quad::new(x, y, width, height, vp_size, false, true);
-        Self { texture: tex, quad }
+        Self { texture: tex,
+               format: SampleFormat::U8,
+               quad }
     }
 
     fn texture(&self) -> &GLuint {
@@ -0,0 +1,197 @@
+// This file is part auf the dual-kawase-demo package.
+//
+// (c) 2019 Bernd Busse
+//
+// For the full copyright and license information, please view the README.md file
+// that was distributed with this source code.
+//
+
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use gl::types::{GLsizeiptr, GLsync, GLuint};
+
+use super::{debug, Error, Result, SampleFormat};
+
+/// Maximum number of readbacks in flight.
+const MAX_PENDING: usize = 4;
+
+/// RGBA pixels of a finished readback and the file they should be saved to.
+///
+/// 16 bit samples are stored in native byte order.
+pub struct Pixels {
+    pub path: PathBuf,
+    pub data: Vec<u8>,
+    pub width: u32,
+    pub height: u32,
+    pub format: SampleFormat,
+    /// ICC profile of the pixels, `None` for sRGB.
+    pub icc_profile: Option<Arc<Vec<u8>>>,
+}
+
+enum Entry {
+    Gpu(Readback),
+    Ready(Pixels),
+}
+
+struct Readback {
+    pbo: GLuint,
+    fence: GLsync,
+    path: PathBuf,
+    width: u32,
+    height: u32,
+    format: SampleFormat,
+    icc_profile: Option<Arc<Vec<u8>>>,
+}
+
+impl Readback {
+    fn size(&self) -> usize {
+        self.width as usize * self.height as usize * self.format.pixel_size()
+    }
+}
+
+impl Drop for Readback {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteSync(self.fence);
+            gl::DeleteBuffers(1, &self.pbo);
+        }
+    }
+}
+
+/// Asynchronous texture readback into pixel buffer objects.
+///
+/// `queue` only records the copy into a PBO and a fence. The pixels are mapped by `poll`
+/// once the GPU signalled the fence, usually a few frames later. Pixels read by other means
+/// can be queued with `push_ready` to keep the order of saved images.
+pub struct ReadbackQueue {
+    pending: VecDeque<Entry>,
+    icc_profile: Option<Arc<Vec<u8>>>,
+}
+
+impl ReadbackQueue {
+    pub fn new() -> Self {
+        Self { pending: VecDeque::with_capacity(MAX_PENDING),
+               icc_profile: None }
+    }
+
+    /// Color profile of the source image, it is attached to all following readbacks.
+    pub fn set_icc_profile(&mut self, icc_profile: Option<Arc<Vec<u8>>>) {
+        self.icc_profile = icc_profile;
+    }
+
+    pub fn icc_profile(&self) -> Option<Arc<Vec<u8>>> {
+        self.icc_profile.clone()
+    }
+
+    pub fn has_capacity(&self) -> bool {
+        self.pending.len() < MAX_PENDING
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Start the readback of `tex` with samples of `format` which will be saved to `path`.
+    pub fn queue(&mut self, tex: GLuint, path: PathBuf, format: SampleFormat) -> Result<()> {
+        let (width, height) = super::get_texture_size(tex);
+        let size = width as usize * height as usize * format.pixel_size();
+
+        let mut pbo: GLuint = 0;
+        let error = unsafe {
+            gl::GenBuffers(1, &mut pbo);
+            gl::BindBuffer(gl::PIXEL_PACK_BUFFER, pbo);
+            gl::BufferData(gl::PIXEL_PACK_BUFFER,
+                           size as GLsizeiptr,
+                           std::ptr::null(),
+                           gl::STREAM_READ);
+            let error = gl::GetError();
+
+            gl::BindTexture(gl::TEXTURE_2D, tex);
+            gl::GetTexImage(gl::TEXTURE_2D,
+                            0,
+                            gl::RGBA,
+                            format.gl_type(),
+                            std::ptr::null_mut());
+            gl::BindTexture(gl::TEXTURE_2D, 0);
+            gl::BindBuffer(gl::PIXEL_PACK_BUFFER, 0);
+
+            error
+        };
+
+        if error == gl::OUT_OF_MEMORY {
+            unsafe {
+                gl::DeleteBuffers(1, &pbo);
+            }
+            return Err(Error::OutOfMemory);
+        }
+        debug::label(gl::BUFFER, pbo, &format!("readback {}", path.display()));
+
+        let fence = unsafe { gl::FenceSync(gl::SYNC_GPU_COMMANDS_COMPLETE, 0) };
+        debug::check_error("ReadbackQueue::queue");
+
+        self.pending.push_back(Entry::Gpu(Readback { pbo,
+                                                     fence,
+                                                     path,
+                                                     width,
+                                                     height,
+                                                     format,
+                                                     icc_profile: self.icc_profile() }));
+
+        Ok(())
+    }
+
+    /// Queue pixels which are already in memory.
+    pub fn push_ready(&mut self, pixels: Pixels) {
+        self.pending.push_back(Entry::Ready(pixels));
+    }
+
+    /// Return the oldest readback if the GPU has finished it.
+    ///
+    /// A readback whose buffer cannot be mapped is returned as `Err` with the path its pixels
+    /// should have been saved to, so every queued save is resolved.
+    pub fn poll(&mut self) -> Option<std::result::Result<Pixels, PathBuf>> {
+        let signaled = match self.pending.front()? {
+            Entry::Gpu(readback) => {
+                let status = unsafe { gl::ClientWaitSync(readback.fence, 0, 0) };
+                status == gl::ALREADY_SIGNALED || status == gl::CONDITION_SATISFIED
+            },
+            Entry::Ready(_) => true,
+        };
+        if !signaled {
+            return None;
+        }
+
+        let readback = match self.pending.pop_front()? {
+            Entry::Gpu(readback) => readback,
+            Entry::Ready(pixels) => return Some(Ok(pixels)),
+        };
+        let size = readback.size();
+        let mut data: Vec<u8> = Vec::with_capacity(size);
+        unsafe {
+            gl::BindBuffer(gl::PIXEL_PACK_BUFFER, readback.pbo);
+            let ptr = gl::MapBufferRange(gl::PIXEL_PACK_BUFFER,
+                                         0,
+                                         size as GLsizeiptr,
+                                         gl::MAP_READ_BIT) as *const u8;
+            if !ptr.is_null() {
+                data.extend_from_slice(std::slice::from_raw_parts(ptr, size));
+                gl::UnmapBuffer(gl::PIXEL_PACK_BUFFER);
+            }
+            gl::BindBuffer(gl::PIXEL_PACK_BUFFER, 0);
+        }
+        debug::check_error("ReadbackQueue::poll");
+
+        if data.len() != size {
+            return Some(Err(readback.path.clone()));
+        }
+
+        Some(Ok(Pixels { path: readback.path.clone(),
+                         data,
+                         width: readback.width,
+                         height: readback.height,
+                         format: readback.format,
+                         icc_profile: readback.icc_profile.clone() }))
+    }
+}
@@ -11,6 +11,7 @@ use std::ffi::{CStr, CString};
 
 use gl::types::{GLboolean, GLchar, GLenum, GLint, GLuint};
 
+use super::{debug, Error, Result, ShaderStage};
 use crate::utils::Matrix4f;
 
 pub struct Program {
@@ -19,7 +20,7 @@ pub struct Program {
 }
 
 impl Program {
-    pub fn from_shaders(shaders: &[GlShader], uniforms: Option<&[&str]>) -> Result<Self, String> {
+    pub fn from_shaders(shaders: &[GlShader], uniforms: Option<&[&str]>) -> Result<Self> {
         // generate new program id
         let program_id = unsafe { gl::CreateProgram() };
 
@@ -56,7 +57,10 @@ impl Program {
                                       error.as_ptr() as *mut GLchar);
             }
 
-            return Err(error.to_string_lossy().into_owned());
+            unsafe {
+                gl::DeleteProgram(program_id);
+            }
+            return Err(Error::Link { log: error.to_string_lossy().into_owned() });
         }
 
         // store uniform locations
@@ -69,7 +73,10 @@ impl Program {
                 let loc =
                     unsafe { gl::GetUniformLocation(program_id, c_name.as_ptr() as *const GLchar) };
                 if loc < 0 {
-                    return Err(format!("Cannot find location of uniform '{}'", *uniform));
+                    unsafe {
+                        gl::DeleteProgram(program_id);
+                    }
+                    return Err(Error::MissingUniform(String::from(*uniform)));
                 }
                 uniform_map.insert(String::from(*uniform), loc);
             }
@@ -82,10 +89,32 @@ impl Program {
             }
         }
 
+        debug::check_error("Program::from_shaders");
+
         Ok(Self { id: program_id,
                   uniform_map })
     }
 
+    pub fn set_label(&self, label: &str) {
+        debug::label(gl::PROGRAM, self.id, label);
+    }
+
+    /// Store the location of an optional uniform, return `false` if it is not active.
+    pub fn lookup_uniform(&mut self, name: &str) -> bool {
+        let c_name = CString::new(name).unwrap();
+        let loc = unsafe { gl::GetUniformLocation(self.id, c_name.as_ptr() as *const GLchar) };
+        if loc < 0 {
+            return false;
+        }
+
+        self.uniform_map.insert(String::from(name), loc);
+        true
+    }
+
+    pub fn has_uniform(&self, name: &str) -> bool {
+        self.uniform_map.contains_key(name)
+    }
+
     pub fn _id(&self) -> GLuint {
         self.id
     }
@@ -102,43 +131,47 @@ impl Program {
         }
     }
 
-    pub fn set_uniform_1i(&mut self, name: &str, value: i32) -> Result<(), String> {
+    pub fn set_uniform_1i(&mut self, name: &str, value: i32) -> Result<()> {
         let loc = self.uniform_map
                       .get(name)
-                      .ok_or_else(|| format!("Uniform location '{}' not found", name))?;
+                      .ok_or_else(|| Error::MissingUniform(String::from(name)))?;
         unsafe {
             gl::Uniform1i(*loc, value);
         }
+        debug::check_error("Program::set_uniform_1i");
         Ok(())
     }
 
-    pub fn set_uniform_1f(&mut self, name: &str, value: f32) -> Result<(), String> {
+    pub fn set_uniform_1f(&mut self, name: &str, value: f32) -> Result<()> {
         let loc = self.uniform_map
                       .get(name)
-                      .ok_or_else(|| format!("Uniform location '{}' not found", name))?;
+                      .ok_or_else(|| Error::MissingUniform(String::from(name)))?;
         unsafe {
             gl::Uniform1f(*loc, value);
         }
+        debug::check_error("Program::set_uniform_1f");
         Ok(())
     }
 
-    pub fn set_uniform_2f(&mut self, name: &str, values: (f32, f32)) -> Result<(), String> {
+    pub fn set_uniform_2f(&mut self, name: &str, values: (f32, f32)) -> Result<()> {
         let loc = self.uniform_map
                       .get(name)
-                      .ok_or_else(|| format!("Uniform location '{}' not found", name))?;
+                      .ok_or_else(|| Error::MissingUniform(String::from(name)))?;
         unsafe {
             gl::Uniform2f(*loc, values.0, values.1);
         }
+        debug::check_error("Program::set_uniform_2f");
         Ok(())
     }
 
-    pub fn set_uniform_mat4f(&mut self, name: &str, values: &Matrix4f) -> Result<(), String> {
+    pub fn set_uniform_mat4f(&mut self, name: &str, values: &Matrix4f) -> Result<()> {
         let loc = self.uniform_map
                       .get(name)
-                      .ok_or_else(|| format!("Uniform location '{}' not found", name))?;
+                      .ok_or_else(|| Error::MissingUniform(String::from(name)))?;
         unsafe {
             gl::UniformMatrix4fv(*loc, 1, false as GLboolean, values.as_ptr());
         }
+        debug::check_error("Program::set_uniform_mat4f");
         Ok(())
     }
 }
@@ -161,7 +194,7 @@ pub struct Shader<S: ShaderType> {
 }
 
 impl<S: ShaderType> Shader<S> {
-    pub fn from_source(source: &str) -> Result<Self, String> {
+    pub fn from_source(source: &str) -> Result<Self> {
         // convert source into c-string
         let raw_source = CString::new(source).unwrap();
 
@@ -224,7 +257,7 @@ impl From<FragmentShader> for GlShader {
     }
 }
 
-fn shader_from_source(source: &CStr, kind: GLenum) -> Result<GLuint, String> {
+fn shader_from_source(source: &CStr, kind: GLenum) -> Result<GLuint> {
     // generate new shader id
     let id: GLuint = unsafe { gl::CreateShader(kind) };
 
@@ -252,7 +285,11 @@ fn shader_from_source(source: &CStr, kind: GLenum) -> Result<GLuint, String> {
             gl::GetShaderInfoLog(id, len, std::ptr::null_mut(), error.as_ptr() as *mut GLchar);
         }
 
-        return Err(error.to_string_lossy().into_owned());
+        unsafe {
+            gl::DeleteShader(id);
+        }
+        return Err(Error::ShaderCompile { stage: ShaderStage::from_gl(kind),
+                                          log: error.to_string_lossy().into_owned() });
     }
 
     Ok(id)
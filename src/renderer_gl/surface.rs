@@ -6,52 +6,100 @@
 // that was distributed with this source code.
 //
 
+use std::sync::Arc;
+
 use gl::types::GLuint;
-use image::{DynamicImage, FilterType, GenericImageView};
+use image::FilterType;
+
+use super::{debug, Result};
+use crate::image_io::SourceImage;
 
 pub struct ImgSurface {
-    img: DynamicImage,
-    img_scaled: DynamicImage,
+    img: Arc<SourceImage>,
+    img_scaled: SourceImage,
     width: u32,
     height: u32,
+    max_size: u32,
     tex: GLuint,
+    /// The scaled image changed since the last texture upload.
+    dirty: bool,
 }
 
 impl ImgSurface {
-    pub fn new_from_image(img: &DynamicImage, width: u32, height: u32) -> Self {
-        // convert image file to internal format
-        let img_internal = DynamicImage::ImageBgra8(img.to_bgra());
-
-        // create new texture with scaled image
-        let (scaled_width, scaled_height) =
-            crate::utils::scale_keep_aspect(img.width(), img.height(), width, height);
+    pub fn new_from_image(img: SourceImage, width: u32, height: u32) -> Result<Self> {
+        // create new texture with scaled image, which has to fit into a single texture
+        let max_size = super::max_texture_size();
         let img_scaled =
-            img_internal.resize_exact(scaled_width, scaled_height, FilterType::CatmullRom);
-        let tex =
-            super::create_texture_bgra(scaled_width, scaled_height, Some(img_scaled.raw_pixels()));
-
-        Self { img: img_internal,
-               img_scaled,
-               width: scaled_width,
-               height: scaled_height,
-               tex }
+            Self::scale_to_fit(&img, (width, height), max_size, FilterType::CatmullRom);
+        let tex = img_scaled.create_texture()?;
+        debug::label(gl::TEXTURE, tex, "image surface");
+
+        let (scaled_width, scaled_height) = img_scaled.dimensions();
+        Ok(Self { img: Arc::new(img),
+                  img_scaled,
+                  width: scaled_width,
+                  height: scaled_height,
+                  max_size,
+                  tex,
+                  dirty: false })
     }
 
-    pub fn resize_image(&mut self, width: u32, height: u32) {
-        let (scaled_width, scaled_height) =
-            crate::utils::scale_keep_aspect(self.img.width(), self.img.height(), width, height);
-        self.img_scaled = self.img
-                              .resize_exact(scaled_width, scaled_height, FilterType::Triangle);
+    /// Scale `img` to fit into `size` and a texture of `max_size`, keeping its aspect ratio.
+    pub fn scale_to_fit(img: &SourceImage,
+                        size: (u32, u32),
+                        max_size: u32,
+                        filter: FilterType)
+                        -> SourceImage {
+        let (img_width, img_height) = img.dimensions();
+        let (scaled_width, scaled_height) = crate::utils::scale_keep_aspect(img_width,
+                                                                            img_height,
+                                                                            size.0.min(max_size),
+                                                                            size.1.min(max_size));
+        img.resize_exact(scaled_width, scaled_height, filter)
+    }
+
+    /// Replace the image and its version scaled with `scale_to_fit`, the texture is updated by
+    /// the next `refresh_texture`.
+    pub fn set_image(&mut self, img: SourceImage, img_scaled: SourceImage) {
+        self.img = Arc::new(img);
+        self.set_scaled_image(img_scaled);
+    }
 
+    /// Replace the image at display resolution, which has to be scaled from `source()`.
+    pub fn set_scaled_image(&mut self, img_scaled: SourceImage) {
+        let (scaled_width, scaled_height) = img_scaled.dimensions();
+        self.img_scaled = img_scaled;
         self.width = scaled_width;
         self.height = scaled_height;
+        self.dirty = true;
+    }
+
+    /// Upload the scaled image to the texture if it changed since the last upload.
+    pub fn refresh_texture(&mut self) -> Result<()> {
+        if self.dirty {
+            self.img_scaled.upload(self.tex)?;
+            self.dirty = false;
+        }
+        Ok(())
+    }
+
+    /// The image at source resolution and precision.
+    pub fn image(&self) -> &SourceImage {
+        &self.img
+    }
+
+    /// Shared handle of the image at source resolution, for scaling it without holding a lock.
+    pub fn source(&self) -> Arc<SourceImage> {
+        self.img.clone()
+    }
+
+    pub fn max_size(&self) -> u32 {
+        self.max_size
     }
 
-    pub fn refresh_texture(&mut self) {
-        super::resize_texture_bgra(self.tex,
-                                   self.width,
-                                   self.height,
-                                   Some(self.img_scaled.raw_pixels()));
+    /// The image at display resolution.
+    pub fn scaled_image(&self) -> &SourceImage {
+        &self.img_scaled
     }
 
     pub fn size(&self) -> (u32, u32) {
@@ -0,0 +1,133 @@
+// This file is part auf the dual-kawase-demo package.
+//
+// (c) 2019 Bernd Busse
+//
+// For the full copyright and license information, please view the README.md file
+// that was distributed with this source code.
+//
+
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+
+use image::FilterType;
+
+use crate::renderer_gl::ImgSurface;
+
+/// Filters of the progressive rescale, a fast preview first.
+const PASSES: [FilterType; 2] = [FilterType::Nearest, FilterType::CatmullRom];
+
+/// Latest resize request, older ones are overwritten.
+#[derive(Default)]
+struct Request {
+    size: Option<(u32, u32)>,
+    generation: usize,
+    shutdown: bool,
+}
+
+struct Shared {
+    request: Mutex<Request>,
+    wakeup: Condvar,
+}
+
+impl Shared {
+    fn is_stale(&self, generation: usize) -> bool {
+        let request = self.request.lock().unwrap();
+        request.generation != generation || request.shutdown
+    }
+}
+
+/// Background worker which rescales the image of the surface to the window size.
+///
+/// Only the latest size is scaled, requests made while a resize is running cancel it between
+/// the passes. Every finished pass replaces the scaled image and emits a redraw event; the
+/// texture update is done in the main thread.
+pub struct SurfaceResizer {
+    shared: Arc<Shared>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl SurfaceResizer {
+    pub fn new(surface: Arc<Mutex<ImgSurface>>, redraw: Arc<Mutex<bool>>) -> Self {
+        let shared = Arc::new(Shared { request: Mutex::new(Request::default()),
+                                       wakeup: Condvar::new() });
+
+        let worker_shared = shared.clone();
+        let worker = thread::Builder::new().name(String::from("resizer"))
+                                           .spawn(move || worker(&worker_shared, &surface, &redraw))
+                                           .expect("Cannot spawn resize thread");
+
+        Self { shared,
+               worker: Some(worker) }
+    }
+
+    /// Rescale the image to fit into `size`, replacing any pending request.
+    pub fn resize(&self, size: (u32, u32)) {
+        let mut request = self.shared.request.lock().unwrap();
+        request.size = Some(size);
+        request.generation += 1;
+        self.shared.wakeup.notify_one();
+    }
+}
+
+impl Drop for SurfaceResizer {
+    fn drop(&mut self) {
+        self.shared.request.lock().unwrap().shutdown = true;
+        self.shared.wakeup.notify_one();
+        if let Some(handle) = self.worker.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+fn worker(shared: &Shared, surface: &Mutex<ImgSurface>, redraw: &Mutex<bool>) {
+    loop {
+        let (size, generation) = {
+            let mut request = shared.request.lock().unwrap();
+            while request.size.is_none() && !request.shutdown {
+                request = shared.wakeup.wait(request).unwrap();
+            }
+            if request.shutdown {
+                break;
+            }
+            (request.size.take().unwrap(), request.generation)
+        };
+
+        resize(shared, surface, redraw, size, generation);
+    }
+}
+
+fn resize(shared: &Shared,
+          surface: &Mutex<ImgSurface>,
+          redraw: &Mutex<bool>,
+          size: (u32, u32),
+          generation: usize) {
+    let (mut img, max_size) = {
+        let surf = surface.lock().unwrap();
+        (surf.source(), surf.max_size())
+    };
+
+    let mut passes = PASSES.iter();
+    while let Some(&filter) = passes.next() {
+        if shared.is_stale(generation) {
+            return;
+        }
+        // scale without holding a lock, so the window keeps drawing meanwhile
+        let scaled = ImgSurface::scale_to_fit(&img, size, max_size, filter);
+
+        // Block further redraw events
+        let mut update_lock = redraw.lock().unwrap();
+        let mut surf = surface.lock().unwrap();
+        if !Arc::ptr_eq(&img, &surf.source()) {
+            // another image was loaded meanwhile, start over with it
+            img = surf.source();
+            passes = PASSES.iter();
+            continue;
+        }
+        if shared.is_stale(generation) {
+            return;
+        }
+
+        surf.set_scaled_image(scaled);
+        *update_lock = true;
+    }
+}
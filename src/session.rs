@@ -0,0 +1,240 @@
+// This file is part auf the dual-kawase-demo package.
+//
+// (c) 2019 Bernd Busse
+//
+// For the full copyright and license information, please view the README.md file
+// that was distributed with this source code.
+//
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use toml::value::{Table, Value};
+
+use crate::blur::MAX_ITERATIONS;
+use crate::config::{self, as_float};
+use crate::renderer_gl::{Error, Result};
+use crate::tonemap::Operator;
+use crate::{WIN_HEIGHT, WIN_WIDTH};
+
+/// Name of the session file in the state directory.
+pub const STATE_FILE: &str = "session.toml";
+
+/// Size and position of the window outside of fullscreen mode.
+#[derive(Clone, Copy, Debug)]
+pub struct Geometry {
+    pub width: u32,
+    pub height: u32,
+    /// Position of the window, centered by the window manager if unknown.
+    pub position: Option<(i32, i32)>,
+    pub fullscreen: bool,
+}
+
+impl Default for Geometry {
+    fn default() -> Self {
+        Self { width: WIN_WIDTH,
+               height: WIN_HEIGHT,
+               position: None,
+               fullscreen: false }
+    }
+}
+
+/// State of the window saved on exit and restored on the next start.
+#[derive(Clone, Debug)]
+pub struct Session {
+    pub window: Geometry,
+    pub image: Option<PathBuf>,
+    pub iterations: u32,
+    pub offset: f32,
+    pub opacity: f32,
+    /// Values of kernel parameters by name.
+    pub params: BTreeMap<String, f32>,
+    pub tonemap: Operator,
+    pub exposure: f32,
+    pub show_help: bool,
+    pub show_controls: bool,
+    pub show_graph: bool,
+    pub show_overlay: bool,
+    pub show_image_info: bool,
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Self { window: Geometry::default(),
+               image: None,
+               iterations: 0,
+               offset: 0.0,
+               opacity: 1.0,
+               params: BTreeMap::new(),
+               tonemap: Operator::Aces,
+               exposure: 0.0,
+               show_help: false,
+               show_controls: true,
+               show_graph: true,
+               show_overlay: true,
+               show_image_info: true }
+    }
+}
+
+impl Session {
+    /// Session saved by the last run, or the defaults if there is none or it cannot be read.
+    pub fn restore() -> Self {
+        let path = match config::state_dir().map(|dir| dir.join(STATE_FILE)) {
+            Some(ref path) if path.is_file() => path.clone(),
+            _ => return Self::default(),
+        };
+
+        log::info!(target: "ui", "Restore session from '{}' ...", path.display());
+        match Self::load(&path) {
+            Ok(session) => session,
+            Err(err) => {
+                log::warn!(target: "ui", "Cannot restore session: {}", err);
+                Self::default()
+            },
+        }
+    }
+
+    fn load(path: &Path) -> Result<Self> {
+        let table = config::read_toml(path)?;
+        let invalid = |key: &str| Error::config(path, format!("Invalid value of '{}'", key));
+        let section = |name: &str| match table.get(name) {
+            Some(Value::Table(section)) => Ok(Some(section)),
+            Some(_) => Err(invalid(name)),
+            None => Ok(None),
+        };
+        let mut session = Self::default();
+
+        if let Some(window) = section("window")? {
+            let size = |key: &str| match window.get(key).and_then(Value::as_integer) {
+                Some(n) if n > 0 && n <= i64::from(u16::MAX) => Ok(n as u32),
+                _ => Err(invalid(key)),
+            };
+            session.window.width = size("width")?;
+            session.window.height = size("height")?;
+            let coordinate = |key: &str| window.get(key).and_then(Value::as_integer);
+            if let (Some(x), Some(y)) = (coordinate("x"), coordinate("y")) {
+                session.window.position = Some((x as i32, y as i32));
+            }
+            session.window.fullscreen = window.get("fullscreen")
+                                              .and_then(Value::as_bool)
+                                              .unwrap_or(false);
+        }
+
+        session.image = table.get("image")
+                             .and_then(Value::as_str)
+                             .map(PathBuf::from)
+                             .filter(|path| path.is_file());
+
+        if let Some(blur) = section("blur")? {
+            session.iterations = match blur.get("iterations").and_then(Value::as_integer) {
+                Some(n) if n >= 0 && n as usize <= MAX_ITERATIONS => n as u32,
+                _ => return Err(invalid("iterations")),
+            };
+            session.offset = match blur.get("offset").and_then(as_float) {
+                Some(offset) if offset >= 0.0 => offset,
+                _ => return Err(invalid("offset")),
+            };
+            if let Some(opacity) = blur.get("opacity") {
+                session.opacity = match as_float(opacity) {
+                    Some(opacity) if (0.0..=1.0).contains(&opacity) => opacity,
+                    _ => return Err(invalid("opacity")),
+                };
+            }
+            if let Some(params) = blur.get("params").and_then(Value::as_table) {
+                session.params = params.iter()
+                                       .filter_map(|(name, value)| {
+                                           as_float(value).map(|v| (name.clone(), v))
+                                       })
+                                       .collect();
+            }
+        }
+
+        if let Some(display) = section("display")? {
+            if let Some(name) = display.get("tonemap").and_then(Value::as_str) {
+                session.tonemap = name.parse().map_err(|_| invalid("tonemap"))?;
+            }
+            if let Some(exposure) = display.get("exposure").and_then(as_float) {
+                session.exposure = exposure;
+            }
+            session.show_help = display.get("show_help")
+                                       .and_then(Value::as_bool)
+                                       .unwrap_or(false);
+            session.show_controls = display.get("show_controls")
+                                           .and_then(Value::as_bool)
+                                           .unwrap_or(true);
+            session.show_graph = display.get("show_graph")
+                                        .and_then(Value::as_bool)
+                                        .unwrap_or(true);
+            session.show_overlay = display.get("show_overlay")
+                                          .and_then(Value::as_bool)
+                                          .unwrap_or(true);
+            session.show_image_info = display.get("show_image_info")
+                                             .and_then(Value::as_bool)
+                                             .unwrap_or(true);
+        }
+
+        Ok(session)
+    }
+
+    /// Write the session to the state directory.
+    pub fn save(&self) -> Result<()> {
+        let dir = config::state_dir().ok_or_else(|| {
+                                         Error::config(Path::new(STATE_FILE),
+                                                       "No state directory")
+                                     })?;
+        fs::create_dir_all(&dir).map_err(|err| Error::write(&dir, err))?;
+        let path = dir.join(STATE_FILE);
+
+        let text = toml::to_string(&self.to_toml()).map_err(|err| {
+                                                       Error::config(&path, err.to_string())
+                                                   })?;
+        log::info!(target: "ui", "Save session to '{}' ...", path.display());
+        fs::write(&path, text).map_err(|err| Error::write(&path, err))
+    }
+
+    fn to_toml(&self) -> Value {
+        let mut window = Table::new();
+        window.insert(String::from("width"), Value::Integer(i64::from(self.window.width)));
+        window.insert(String::from("height"), Value::Integer(i64::from(self.window.height)));
+        if let Some((x, y)) = self.window.position {
+            window.insert(String::from("x"), Value::Integer(i64::from(x)));
+            window.insert(String::from("y"), Value::Integer(i64::from(y)));
+        }
+        window.insert(String::from("fullscreen"), Value::Boolean(self.window.fullscreen));
+
+        let mut blur = Table::new();
+        blur.insert(String::from("iterations"), Value::Integer(i64::from(self.iterations)));
+        blur.insert(String::from("offset"), Value::Float(f64::from(self.offset)));
+        blur.insert(String::from("opacity"), Value::Float(f64::from(self.opacity)));
+        if !self.params.is_empty() {
+            let params = self.params
+                             .iter()
+                             .map(|(name, value)| (name.clone(), Value::Float(f64::from(*value))))
+                             .collect();
+            blur.insert(String::from("params"), Value::Table(params));
+        }
+
+        let mut display = Table::new();
+        display.insert(String::from("tonemap"), Value::String(self.tonemap.to_string()));
+        display.insert(String::from("exposure"), Value::Float(f64::from(self.exposure)));
+        display.insert(String::from("show_help"), Value::Boolean(self.show_help));
+        display.insert(String::from("show_controls"),
+                       Value::Boolean(self.show_controls));
+        display.insert(String::from("show_graph"), Value::Boolean(self.show_graph));
+        display.insert(String::from("show_overlay"), Value::Boolean(self.show_overlay));
+        display.insert(String::from("show_image_info"),
+                       Value::Boolean(self.show_image_info));
+
+        let mut root = Table::new();
+        if let Some(ref image) = self.image {
+            root.insert(String::from("image"),
+                        Value::String(image.to_string_lossy().into_owned()));
+        }
+        root.insert(String::from("window"), Value::Table(window));
+        root.insert(String::from("blur"), Value::Table(blur));
+        root.insert(String::from("display"), Value::Table(display));
+
+        Value::Table(root)
+    }
+}
@@ -0,0 +1,172 @@
+// This file is part auf the dual-kawase-demo package.
+//
+// (c) 2019 Bernd Busse
+//
+// For the full copyright and license information, please view the README.md file
+// that was distributed with this source code.
+//
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::renderer_gl::{Error, FragmentShader, Program, Result, VertexShader};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+const EMBEDDED_SHADERS: &[(&str, &str)] =
+    &[("tex_quad.vert", include_str!("shaders/tex_quad.vert")),
+      ("tex_quad.frag", include_str!("shaders/tex_quad.frag")),
+      ("blur_copy.frag", include_str!("shaders/blur_copy.frag")),
+      ("tonemap.frag", include_str!("shaders/tonemap.frag")),
+      ("dual_kawase_down.vert", include_str!("shaders/dual_kawase_down.vert")),
+      ("dual_kawase_down.frag", include_str!("shaders/dual_kawase_down.frag")),
+      ("dual_kawase_up.vert", include_str!("shaders/dual_kawase_up.vert")),
+      ("dual_kawase_up.frag", include_str!("shaders/dual_kawase_up.frag")),
+      ("glyphs.vert", include_str!("shaders/glyphs.vert")),
+      ("glyphs.frag", include_str!("shaders/glyphs.frag")),
+      ("flat_quad.vert", include_str!("shaders/flat_quad.vert")),
+      ("flat_quad.frag", include_str!("shaders/flat_quad.frag")),
+      ("frosted_panel.frag", include_str!("shaders/frosted_panel.frag")),
+      ("lines.vert", include_str!("shaders/lines.vert")),
+      ("lines.frag", include_str!("shaders/lines.frag"))];
+
+/// Vertex/fragment shader pair and uniforms of a single program.
+pub struct ProgramSpec {
+    pub vert: &'static str,
+    pub frag: &'static str,
+    pub uniforms: &'static [&'static str],
+}
+
+impl ProgramSpec {
+    pub fn uses_any(&self, names: &[String]) -> bool {
+        names.iter().any(|n| n == self.vert || n == self.frag)
+    }
+
+    pub fn build(&self, source: &ShaderSource) -> Result<Program> {
+        self.build_with_frag(source, self.frag, &source.load(self.frag)?)
+    }
+
+    /// Build the program with a replacement fragment shader.
+    pub fn build_with_frag(&self,
+                           source: &ShaderSource,
+                           frag_name: &str,
+                           frag_source: &str)
+                           -> Result<Program> {
+        let name = format!("{} + {}", self.vert, frag_name);
+        let error = |error| Error::program(&name, error);
+
+        let vert_source = source.load(self.vert)?;
+        let vert_shader = VertexShader::from_source(&vert_source).map_err(error)?;
+        let frag_shader = FragmentShader::from_source(frag_source).map_err(error)?;
+
+        let program = Program::from_shaders(&[vert_shader.into(), frag_shader.into()],
+                                            Some(self.uniforms)).map_err(error)?;
+        program.set_label(&name);
+
+        Ok(program)
+    }
+}
+
+/// Lookup of shader sources, either embedded in the binary or read from a directory.
+pub struct ShaderSource {
+    dir: Option<PathBuf>,
+}
+
+impl ShaderSource {
+    pub fn embedded() -> Self {
+        Self { dir: None }
+    }
+
+    pub fn from_dir(dir: &Path) -> Self {
+        Self { dir: Some(dir.to_owned()) }
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        EMBEDDED_SHADERS.iter()
+                        .map(|(name, _)| String::from(*name))
+                        .collect()
+    }
+
+    pub fn load(&self, name: &str) -> Result<Cow<'static, str>> {
+        if let Some(ref dir) = self.dir {
+            let path = dir.join(name);
+            if path.exists() {
+                let source = fs::read_to_string(&path);
+                return source.map(Cow::Owned)
+                             .map_err(|error| Error::io(&path, error));
+            }
+        }
+
+        EMBEDDED_SHADERS.iter()
+                        .find(|(embedded, _)| *embedded == name)
+                        .map(|(_, src)| Cow::Borrowed(*src))
+                        .ok_or_else(|| Error::UnknownShader(String::from(name)))
+    }
+}
+
+/// Poll the modification times of all known shaders in a directory and single files.
+pub struct ShaderWatcher {
+    files: Vec<(String, PathBuf)>,
+    mtimes: HashMap<String, SystemTime>,
+    last_poll: Instant,
+}
+
+impl ShaderWatcher {
+    pub fn new() -> Self {
+        Self { files: Vec::with_capacity(EMBEDDED_SHADERS.len()),
+               mtimes: HashMap::with_capacity(EMBEDDED_SHADERS.len()),
+               last_poll: Instant::now() }
+    }
+
+    /// Watch all shaders known by name in `dir`.
+    pub fn watch_dir(&mut self, dir: &Path) {
+        for (name, _) in EMBEDDED_SHADERS {
+            self.files.push((String::from(*name), dir.join(name)));
+        }
+        self.scan();
+    }
+
+    /// Watch a single file, which is reported by its path.
+    pub fn watch_file(&mut self, path: &Path) {
+        self.files
+            .push((path.display().to_string(), path.to_owned()));
+        self.scan();
+    }
+
+    /// Return the names of all shaders changed or deleted since the last call.
+    pub fn poll(&mut self) -> Vec<String> {
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return Vec::new();
+        }
+        self.last_poll = Instant::now();
+
+        self.scan()
+    }
+
+    fn scan(&mut self) -> Vec<String> {
+        let mut changed = Vec::new();
+        for (name, path) in &self.files {
+            let mtime = match fs::metadata(path).and_then(|m| m.modified()) {
+                Ok(mtime) => mtime,
+                // a deleted shader falls back to the embedded one, a deleted kernel reports
+                // the error on reload
+                Err(_) => {
+                    if self.mtimes.remove(name).is_some() {
+                        log::warn!(target: "io", "Shader '{}' was removed", path.display());
+                        changed.push(name.clone());
+                    }
+                    continue;
+                },
+            };
+
+            if self.mtimes.insert(name.clone(), mtime) != Some(mtime) {
+                changed.push(name.clone());
+            }
+        }
+
+        changed
+    }
+}
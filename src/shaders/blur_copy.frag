@@ -0,0 +1,14 @@
+#version 330 core
+
+uniform sampler2D tex_src;
+uniform float opacity;
+
+in VS_OUT {
+    vec2 texcoord;
+} IN;
+
+layout(location = 0) out vec4 Color;
+
+void main() {
+    Color = vec4(texture2D(tex_src, IN.texcoord).rgb, opacity);
+}
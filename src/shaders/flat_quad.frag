@@ -0,0 +1,11 @@
+#version 330 core
+
+in VS_OUT {
+    vec4 color;
+} IN;
+
+layout(location = 0) out vec4 Color;
+
+void main() {
+    Color = IN.color;
+}
@@ -0,0 +1,39 @@
+#version 330 core
+
+const mat4 INVERT_Y_AXIS = mat4(
+    vec4(1.0, 0.0, 0.0, 0.0),
+    vec4(0.0, -1.0, 0.0, 0.0),
+    vec4(0.0, 0.0, 1.0, 0.0),
+    vec4(0.0, 0.0, 0.0, 1.0)
+);
+
+uniform mat4 transform;
+
+layout(location = 0) in vec4 bounds;
+layout(location = 1) in vec4 color;
+
+out VS_OUT {
+    vec4 color;
+} OUT;
+
+void main() {
+    OUT.color = color;
+
+    vec2 pos = vec2(0.0);
+    switch (gl_VertexID) {
+        case 0:
+            pos = bounds.xy;
+            break;
+        case 1:
+            pos = bounds.zy;
+            break;
+        case 2:
+            pos = bounds.xw;
+            break;
+        case 3:
+            pos = bounds.zw;
+            break;
+    }
+
+    gl_Position = INVERT_Y_AXIS * transform * vec4(pos, 0.0, 1.0);
+}
@@ -0,0 +1,29 @@
+#version 330 core
+
+uniform sampler2D tex_src;
+uniform vec2 halfpixel;
+uniform vec2 screen_size;
+
+in VS_OUT {
+    vec4 color;
+} IN;
+
+layout(location = 0) out vec4 Color;
+
+const float OFFSET = 2.0;
+
+void main() {
+    // the downsampled window content is blurred with the upsample kernel
+    vec2 uv = gl_FragCoord.xy / screen_size;
+
+    vec4 sum = texture(tex_src, uv + vec2(-halfpixel.x * 2.0, 0.0) * OFFSET);
+    sum += texture(tex_src, uv + vec2(-halfpixel.x, halfpixel.y) * OFFSET) * 2.0;
+    sum += texture(tex_src, uv + vec2(0.0, halfpixel.y * 2.0) * OFFSET);
+    sum += texture(tex_src, uv + vec2(halfpixel.x, halfpixel.y) * OFFSET) * 2.0;
+    sum += texture(tex_src, uv + vec2(halfpixel.x * 2.0, 0.0) * OFFSET);
+    sum += texture(tex_src, uv + vec2(halfpixel.x, -halfpixel.y) * OFFSET) * 2.0;
+    sum += texture(tex_src, uv + vec2(0.0, -halfpixel.y * 2.0) * OFFSET);
+    sum += texture(tex_src, uv + vec2(-halfpixel.x, -halfpixel.y) * OFFSET) * 2.0;
+
+    Color = vec4(mix(sum.rgb / 12.0, IN.color.rgb, IN.color.a), 1.0);
+}
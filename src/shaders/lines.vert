@@ -0,0 +1,22 @@
+#version 330 core
+
+const mat4 INVERT_Y_AXIS = mat4(
+    vec4(1.0, 0.0, 0.0, 0.0),
+    vec4(0.0, -1.0, 0.0, 0.0),
+    vec4(0.0, 0.0, 1.0, 0.0),
+    vec4(0.0, 0.0, 0.0, 1.0)
+);
+
+uniform mat4 transform;
+
+layout(location = 0) in vec2 position;
+layout(location = 1) in vec4 color;
+
+out VS_OUT {
+    vec4 color;
+} OUT;
+
+void main() {
+    OUT.color = color;
+    gl_Position = INVERT_Y_AXIS * transform * vec4(position, 0.0, 1.0);
+}
@@ -0,0 +1,35 @@
+#version 330 core
+
+uniform sampler2D tex_src;
+uniform float exposure;
+uniform int tonemap;
+
+in VS_OUT {
+    vec2 texcoord;
+} IN;
+
+layout(location = 0) out vec4 Color;
+
+// ACES filmic curve fitted by Krzysztof Narkowicz
+vec3 aces(vec3 x) {
+    return (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14);
+}
+
+vec3 encode_srgb(vec3 linear) {
+    vec3 low = linear * 12.92;
+    vec3 high = 1.055 * pow(linear, vec3(1.0 / 2.4)) - 0.055;
+    return mix(high, low, vec3(lessThanEqual(linear, vec3(0.0031308))));
+}
+
+void main() {
+    vec4 hdr = texture2D(tex_src, IN.texcoord);
+    vec3 color = hdr.rgb * exp2(exposure);
+
+    if (tonemap == 1) {
+        color = color / (1.0 + color);
+    } else if (tonemap == 2) {
+        color = aces(color);
+    }
+
+    Color = vec4(encode_srgb(clamp(color, 0.0, 1.0)), hdr.a);
+}
@@ -0,0 +1,191 @@
+// This file is part auf the dual-kawase-demo package.
+//
+// (c) 2019 Bernd Busse
+//
+// For the full copyright and license information, please view the README.md file
+// that was distributed with this source code.
+//
+
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use glyph_brush::rusttype::Font;
+use toml::Value;
+
+use crate::config::{self, as_float};
+use crate::renderer_gl::{Error, Result};
+
+/// Name of the overlay style file in the configuration directory.
+pub const CONFIG_FILE: &str = "overlay.toml";
+
+const DEFAULT_FONT: &[u8] = include_bytes!("../assets/UbuntuMono-R.ttf");
+const DEFAULT_FONT_SIZE: f32 = 16.0;
+const MAX_FONT_SIZE: f32 = 72.0;
+const DEFAULT_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+const DEFAULT_PANEL: [f32; 4] = [0.0, 0.0, 0.0, 0.5];
+
+/// Corner of the window with the blur parameters, the other sections are mirrored around it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl Corner {
+    pub fn right(self) -> bool {
+        self == Corner::TopRight || self == Corner::BottomRight
+    }
+
+    pub fn bottom(self) -> bool {
+        self == Corner::BottomLeft || self == Corner::BottomRight
+    }
+
+    /// Corner of a section placed at `self` in the default layout, when the parameters are at
+    /// `corner` instead of the top left.
+    pub fn mirrored(self, corner: Corner) -> Self {
+        match (self.right() != corner.right(), self.bottom() != corner.bottom()) {
+            (false, false) => Corner::TopLeft,
+            (true, false) => Corner::TopRight,
+            (false, true) => Corner::BottomLeft,
+            (true, true) => Corner::BottomRight,
+        }
+    }
+}
+
+impl FromStr for Corner {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "top-left" => Ok(Corner::TopLeft),
+            "top-right" => Ok(Corner::TopRight),
+            "bottom-left" => Ok(Corner::BottomLeft),
+            "bottom-right" => Ok(Corner::BottomRight),
+            _ => Err(format!("Unknown corner '{}', expected top-left, top-right, bottom-left \
+                              or bottom-right",
+                             s)),
+        }
+    }
+}
+
+impl fmt::Display for Corner {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Corner::TopLeft => "top-left",
+            Corner::TopRight => "top-right",
+            Corner::BottomLeft => "bottom-left",
+            Corner::BottomRight => "bottom-right",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Font, colors and placement of the overlay text.
+pub struct OverlayStyle {
+    pub font: Font<'static>,
+    /// Font size in pixels at a HiDPI scale of 1.
+    pub font_size: f32,
+    pub color: [f32; 4],
+    pub corner: Corner,
+    /// Color of the panel behind each text section, `None` draws the text without panels.
+    pub panel: Option<[f32; 4]>,
+    /// Whether the panels blur the image behind them.
+    pub panel_blur: bool,
+}
+
+impl Default for OverlayStyle {
+    fn default() -> Self {
+        Self { font: Font::from_bytes(DEFAULT_FONT).expect("Invalid embedded font"),
+               font_size: DEFAULT_FONT_SIZE,
+               color: DEFAULT_COLOR,
+               corner: Corner::TopLeft,
+               panel: Some(DEFAULT_PANEL),
+               panel_blur: false }
+    }
+}
+
+impl OverlayStyle {
+    /// Default style, overridden by the `[overlay]` table of `path` or the overlay style file
+    /// in the configuration directory.
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let mut style = Self::default();
+        let path = match path.map(Path::to_owned)
+                             .or_else(|| config::default_file(CONFIG_FILE))
+        {
+            Some(path) => path,
+            None => return Ok(style),
+        };
+
+        log::info!(target: "ui", "Load overlay style from '{}' ...", path.display());
+        let table = config::read_toml(&path)?;
+        let overlay = match table.get("overlay") {
+            Some(Value::Table(overlay)) => overlay,
+            Some(_) => return Err(Error::config(&path, "'overlay' is not a table")),
+            None => return Ok(style),
+        };
+        let invalid = |key: &str| Error::config(&path, format!("Invalid value of '{}'", key));
+        let color = |key: &str, value: &Value| {
+            value.as_str()
+                 .and_then(parse_color)
+                 .ok_or_else(|| invalid(key))
+        };
+
+        for (key, value) in overlay {
+            match key.as_str() {
+                "font" => {
+                    let file = value.as_str().ok_or_else(|| invalid(key))?;
+                    // relative to the style file
+                    let file = path.parent().unwrap_or_else(|| Path::new("")).join(file);
+                    let data = fs::read(&file).map_err(|err| Error::io(&file, err))?;
+                    style.font = Font::from_bytes(data).map_err(|err| {
+                                                           Error::config(&file, err.to_string())
+                                                       })?;
+                },
+                "font_size" => {
+                    style.font_size = match as_float(value) {
+                        Some(size) if (1.0..=MAX_FONT_SIZE).contains(&size) => size,
+                        _ => return Err(invalid(key)),
+                    };
+                },
+                "color" => style.color = color(key, value)?,
+                "corner" => {
+                    style.corner = value.as_str()
+                                        .ok_or_else(|| invalid(key))?
+                                        .parse()
+                                        .map_err(|msg: String| Error::config(&path, msg))?;
+                },
+                "panel" => {
+                    style.panel = match value {
+                        Value::Boolean(false) => None,
+                        Value::Boolean(true) => Some(DEFAULT_PANEL),
+                        _ => Some(color(key, value)?),
+                    };
+                },
+                "panel_blur" => style.panel_blur = value.as_bool().ok_or_else(|| invalid(key))?,
+                _ => return Err(Error::config(&path, format!("Unknown key '{}'", key))),
+            }
+        }
+
+        Ok(style)
+    }
+}
+
+/// Parse a color of the form `#rrggbb` or `#rrggbbaa`.
+fn parse_color(s: &str) -> Option<[f32; 4]> {
+    let hex = s.strip_prefix('#')?;
+    if (hex.len() != 6 && hex.len() != 8) || !hex.is_ascii() {
+        return None;
+    }
+
+    let mut color = [1.0; 4];
+    for (i, channel) in color.iter_mut().enumerate().take(hex.len() / 2) {
+        let value = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).ok()?;
+        *channel = f32::from(value) / 255.0;
+    }
+
+    Some(color)
+}
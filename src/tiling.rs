@@ -0,0 +1,295 @@
+// This file is part auf the dual-kawase-demo package.
+//
+// (c) 2019 Bernd Busse
+//
+// For the full copyright and license information, please view the README.md file
+// that was distributed with this source code.
+//
+
+use crate::blur::BlurContext;
+use crate::image_io::SourceImage;
+use crate::renderer_gl::{self, Error, GLQuad, Result, SampleFormat, TextureQuad};
+
+/// Part of an image which is blurred on its own.
+///
+/// The outer rectangle is uploaded and blurred, only the inner rectangle is kept. The margin
+/// between both covers the reach of the blur kernel, so the tiles can be stitched without seams.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tile {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    pub inner_x: u32,
+    pub inner_y: u32,
+    pub inner_width: u32,
+    pub inner_height: u32,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Span {
+    start: u32,
+    len: u32,
+    inner_start: u32,
+    inner_len: u32,
+}
+
+/// Number of pixels a dual-filter blur reaches into its neighbourhood.
+///
+/// Downsample pass `i` samples `offset + 1` and upsample pass `i` samples `offset + 2` pixels
+/// of level `i`, where each level pixel covers `2^i` source pixels.
+pub fn blur_overlap(iterations: u32, offset: f32) -> u32 {
+    if iterations == 0 {
+        return 0;
+    }
+
+    let levels = (1u64 << iterations) - 1;
+    (levels as f32 * (2.0 * offset.abs() + 3.0)).ceil() as u32
+}
+
+/// Alignment of tile origins which keeps the downsample grid of all tiles identical.
+pub fn tile_alignment(iterations: u32) -> u32 {
+    1 << iterations
+}
+
+/// Split an image of `size` into tiles of at most `max_size` pixels per side.
+///
+/// The stitched tiles match the untiled blur if `size` is a multiple of `align`. Otherwise the
+/// rounded down level sizes of the untiled image scale it slightly, and the tiles may deviate.
+pub fn split(size: (u32, u32), max_size: u32, overlap: u32, align: u32) -> Result<Vec<Tile>> {
+    let error = || Error::TileOverlap { overlap,
+                                        tile_size: max_size };
+    let columns = spans(size.0, max_size, overlap, align).ok_or_else(error)?;
+    let rows = spans(size.1, max_size, overlap, align).ok_or_else(error)?;
+
+    let mut tiles = Vec::with_capacity(columns.len() * rows.len());
+    for row in &rows {
+        for col in &columns {
+            tiles.push(Tile { x: col.start,
+                              y: row.start,
+                              width: col.len,
+                              height: row.len,
+                              inner_x: col.inner_start,
+                              inner_y: row.inner_start,
+                              inner_width: col.inner_len,
+                              inner_height: row.inner_len });
+        }
+    }
+
+    Ok(tiles)
+}
+
+fn spans(len: u32, max_size: u32, overlap: u32, align: u32) -> Option<Vec<Span>> {
+    if len <= max_size {
+        return Some(vec![Span { start: 0,
+                                len,
+                                inner_start: 0,
+                                inner_len: len }]);
+    }
+
+    // leave room for the margins and the alignment of the outer rectangle
+    let step = align_down(max_size.checked_sub(2 * overlap + 2 * align)?, align);
+    if step == 0 {
+        return None;
+    }
+
+    let mut spans = Vec::with_capacity((len / step + 1) as usize);
+    let mut inner_start = 0;
+    while inner_start < len {
+        let inner_len = step.min(len - inner_start);
+        let start = align_down(inner_start.saturating_sub(overlap), align);
+        let end = align_up(inner_start + inner_len + overlap, align).min(len);
+
+        spans.push(Span { start,
+                          len: end - start,
+                          inner_start,
+                          inner_len });
+        inner_start += inner_len;
+    }
+
+    Some(spans)
+}
+
+fn align_down(value: u32, align: u32) -> u32 {
+    value / align * align
+}
+
+fn align_up(value: u32, align: u32) -> u32 {
+    value.div_ceil(align) * align
+}
+
+/// Blur a single texture sized image into the texture of a new quad with samples of `format`.
+pub fn blur_tile(blur_ctx: &mut BlurContext,
+                 img: &SourceImage,
+                 format: SampleFormat)
+                 -> Result<GLQuad> {
+    let size = img.dimensions();
+    let src_tex = img.create_texture()?;
+    let result = GLQuad::new_with_format(0, 0, size.0, size.1, size, format).and_then(|target| {
+                     blur_ctx.resize(size.0, size.1)?;
+                     blur_ctx.blur_texture(src_tex, size, &target)?;
+                     Ok(target)
+                 });
+
+    unsafe {
+        gl::DeleteTextures(1, &src_tex);
+    }
+
+    result
+}
+
+/// Blur `img` tile by tile and stitch the inner rectangles into one RGBA buffer with samples
+/// of `format`.
+pub fn blur_tiled(blur_ctx: &mut BlurContext,
+                  img: &SourceImage,
+                  tiles: &[Tile],
+                  format: SampleFormat)
+                  -> Result<Vec<u8>> {
+    let (width, height) = img.dimensions();
+    let mut data = vec![0u8; width as usize * height as usize * format.pixel_size()];
+    for tile in tiles {
+        let tile_img = img.crop(tile.x, tile.y, tile.width, tile.height);
+        let target = blur_tile(blur_ctx, &tile_img, format)?;
+        let pixels = renderer_gl::read_texture(*target.texture(), format);
+        copy_inner(tile, &pixels, &mut data, width, format.pixel_size());
+    }
+
+    Ok(data)
+}
+
+/// Copy the inner rectangle of the `tile_pixels` into `data` of an image `width` wide.
+fn copy_inner(tile: &Tile, tile_pixels: &[u8], data: &mut [u8], width: u32, pixel_size: usize) {
+    let row_len = tile.inner_width as usize * pixel_size;
+    for y in 0..tile.inner_height {
+        let src_x = (tile.inner_x - tile.x) as usize;
+        let src_y = (tile.inner_y - tile.y + y) as usize;
+        let src = (src_y * tile.width as usize + src_x) * pixel_size;
+        let dst =
+            ((tile.inner_y + y) as usize * width as usize + tile.inner_x as usize) * pixel_size;
+
+        data[dst..dst + row_len].copy_from_slice(&tile_pixels[src..src + row_len]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reference;
+
+    /// RGBA test image with edges and gradients in every direction.
+    fn pattern(width: u32, height: u32) -> Vec<u8> {
+        let mut pixels = Vec::with_capacity(width as usize * height as usize * 4);
+        for y in 0..height {
+            for x in 0..width {
+                let checker = if (x / 8 + y / 8) % 2 == 0 { 255 } else { 0 };
+                pixels.extend_from_slice(&[(x * 255 / width) as u8,
+                                           (y * 255 / height) as u8,
+                                           checker,
+                                           255]);
+            }
+        }
+
+        pixels
+    }
+
+    fn crop(pixels: &[u8], width: u32, tile: &Tile) -> Vec<u8> {
+        let mut data = Vec::with_capacity(tile.width as usize * tile.height as usize * 4);
+        for y in tile.y..tile.y + tile.height {
+            let start = (y as usize * width as usize + tile.x as usize) * 4;
+            data.extend_from_slice(&pixels[start..start + tile.width as usize * 4]);
+        }
+
+        data
+    }
+
+    /// Blur the tiles with the CPU reference and compare the stitched result to the untiled
+    /// blur.
+    fn assert_stitched(size: (u32, u32), inner_size: u32, iterations: u32, offset: f32) {
+        let pixels = pattern(size.0, size.1);
+        let overlap = blur_overlap(iterations, offset);
+        let align = tile_alignment(iterations);
+        let tiles = split(size, inner_size + 2 * overlap + 2 * align, overlap, align).unwrap();
+        assert!(tiles.len() > 1);
+
+        let mut stitched = vec![0u8; pixels.len()];
+        for tile in &tiles {
+            let blurred = reference::dual_kawase(&crop(&pixels, size.0, tile),
+                                                 (tile.width, tile.height),
+                                                 iterations,
+                                                 offset);
+            copy_inner(tile, &blurred, &mut stitched, size.0, 4);
+        }
+        let untiled = reference::dual_kawase(&pixels, size, iterations, offset);
+
+        let max_diff = stitched.iter()
+                               .zip(untiled.iter())
+                               .map(|(a, b)| a.abs_diff(*b))
+                               .max()
+                               .unwrap();
+        assert!(max_diff <= reference::TOLERANCE,
+                "max difference {} with {} iterations and offset {}",
+                max_diff,
+                iterations,
+                offset);
+    }
+
+    #[test]
+    fn stitched_tiles_match_untiled_blur() {
+        assert_stitched((160, 96), 32, 1, 1.0);
+        assert_stitched((160, 96), 32, 2, 2.5);
+        assert_stitched((200, 136), 48, 3, 1.5);
+        assert_stitched((256, 64), 40, 4, 0.5);
+    }
+
+    #[test]
+    fn tiles_cover_image_once() {
+        let size = (203, 131);
+        let (overlap, align) = (blur_overlap(3, 1.5), tile_alignment(3));
+        let tiles = split(size, 48 + 2 * overlap + 2 * align, overlap, align).unwrap();
+
+        let mut covered = vec![0; size.0 as usize * size.1 as usize];
+        for tile in &tiles {
+            assert_eq!(tile.x % align, 0);
+            assert_eq!(tile.y % align, 0);
+            assert!(tile.inner_x >= tile.x && tile.inner_y >= tile.y);
+            assert!(tile.inner_x + tile.inner_width <= tile.x + tile.width);
+            assert!(tile.inner_y + tile.inner_height <= tile.y + tile.height);
+            for y in tile.inner_y..tile.inner_y + tile.inner_height {
+                for x in tile.inner_x..tile.inner_x + tile.inner_width {
+                    covered[(y * size.0 + x) as usize] += 1;
+                }
+            }
+        }
+        assert!(covered.iter().all(|&n| n == 1));
+    }
+
+    #[test]
+    fn small_image_is_single_tile() {
+        let tiles = split((64, 32), 64, 10, 4).unwrap();
+        assert_eq!(tiles,
+                   vec![Tile { x: 0,
+                               y: 0,
+                               width: 64,
+                               height: 32,
+                               inner_x: 0,
+                               inner_y: 0,
+                               inner_width: 64,
+                               inner_height: 32 }]);
+    }
+
+    #[test]
+    fn tiles_too_small_for_overlap() {
+        assert!(split((512, 512), 64, 30, 4).is_err());
+    }
+
+    #[test]
+    fn overlap_grows_with_iterations_and_offset() {
+        assert_eq!(blur_overlap(0, 5.0), 0);
+        for iterations in 1..8 {
+            assert!(blur_overlap(iterations + 1, 1.0) > blur_overlap(iterations, 1.0));
+            assert!(blur_overlap(iterations, 2.0) > blur_overlap(iterations, 1.0));
+        }
+        assert_eq!(blur_overlap(2, -1.0), blur_overlap(2, 1.0));
+        assert_eq!(tile_alignment(3), 8);
+    }
+}
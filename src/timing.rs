@@ -0,0 +1,112 @@
+// This file is part auf the dual-kawase-demo package.
+//
+// (c) 2019 Bernd Busse
+//
+// For the full copyright and license information, please view the README.md file
+// that was distributed with this source code.
+//
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Number of samples kept per series.
+pub const HISTORY_LEN: usize = 240;
+
+/// Interval of the frame rate measurement.
+const FRAME_RATE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Minimum, average and maximum of a series in milliseconds.
+#[derive(Clone, Copy, Debug)]
+pub struct Stats {
+    pub min: f32,
+    pub avg: f32,
+    pub max: f32,
+}
+
+/// Last `HISTORY_LEN` timings in milliseconds, oldest first.
+#[derive(Default)]
+pub struct TimeSeries {
+    samples: VecDeque<f32>,
+}
+
+impl TimeSeries {
+    pub fn push(&mut self, ms: f32) {
+        if self.samples.len() == HISTORY_LEN {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(ms);
+    }
+
+    pub fn samples(&self) -> impl Iterator<Item = f32> + '_ {
+        self.samples.iter().copied()
+    }
+
+    pub fn stats(&self) -> Option<Stats> {
+        if self.samples.is_empty() {
+            return None;
+        }
+
+        let (min, max, sum) =
+            self.samples()
+                .fold((f32::MAX, f32::MIN, 0.0), |(min, max, sum), ms| {
+                    (min.min(ms), max.max(ms), sum + ms)
+                });
+        Some(Stats { min,
+                     avg: sum / self.samples.len() as f32,
+                     max })
+    }
+}
+
+/// Timings of the blur passes and of whole frames, shown as a graph in the overlay.
+#[derive(Default)]
+pub struct Timings {
+    pub blur_cpu: TimeSeries,
+    pub blur_gpu: TimeSeries,
+    /// Time of a frame including the buffer swap, but without waiting for the frame rate.
+    pub frame: TimeSeries,
+}
+
+impl Timings {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+/// Sustained frames per second, measured over `FRAME_RATE_INTERVAL`.
+pub struct FrameRate {
+    frames: u32,
+    since: Instant,
+    fps: Option<f32>,
+}
+
+impl FrameRate {
+    pub fn new() -> Self {
+        Self { frames: 0,
+               since: Instant::now(),
+               fps: None }
+    }
+
+    /// Start a new measurement.
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+
+    /// Count a frame, returns `true` if the frame rate was updated.
+    pub fn tick(&mut self) -> bool {
+        self.frames += 1;
+        let elapsed = self.since.elapsed();
+        if elapsed < FRAME_RATE_INTERVAL {
+            return false;
+        }
+
+        self.fps = Some(self.frames as f32 / elapsed.as_secs_f32());
+        self.frames = 0;
+        self.since = Instant::now();
+        true
+    }
+
+    /// Frames per second of the last interval, if one has passed.
+    pub fn fps(&self) -> Option<f32> {
+        self.fps
+    }
+}
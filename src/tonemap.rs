@@ -0,0 +1,136 @@
+// This file is part auf the dual-kawase-demo package.
+//
+// (c) 2019 Bernd Busse
+//
+// For the full copyright and license information, please view the README.md file
+// that was distributed with this source code.
+//
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::renderer_gl::{Program, Result};
+use crate::shader_source::{ProgramSpec, ShaderSource};
+
+const TONEMAP_PROGRAM: ProgramSpec =
+    ProgramSpec { vert: "tex_quad.vert",
+                  frag: "tonemap.frag",
+                  uniforms: &["transform", "exposure", "tonemap"] };
+
+/// Largest exposure correction in stops.
+pub const MAX_EXPOSURE: f32 = 16.0;
+
+/// Curve which maps linear HDR values to the displayable range.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operator {
+    Clamp,
+    Reinhard,
+    Aces,
+}
+
+impl Operator {
+    fn id(self) -> i32 {
+        match self {
+            Operator::Clamp => 0,
+            Operator::Reinhard => 1,
+            Operator::Aces => 2,
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            Operator::Clamp => Operator::Reinhard,
+            Operator::Reinhard => Operator::Aces,
+            Operator::Aces => Operator::Clamp,
+        }
+    }
+}
+
+impl FromStr for Operator {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "clamp" => Ok(Operator::Clamp),
+            "reinhard" => Ok(Operator::Reinhard),
+            "aces" => Ok(Operator::Aces),
+            _ => Err(format!("Unknown tonemap operator '{}', expected clamp, reinhard or aces",
+                             s)),
+        }
+    }
+}
+
+impl fmt::Display for Operator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Operator::Clamp => "clamp",
+            Operator::Reinhard => "reinhard",
+            Operator::Aces => "aces",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Display pass of HDR images with exposure correction and tone mapping.
+///
+/// The blur runs on linear values, the tone mapping is only applied when the blurred texture
+/// is drawn to the window.
+pub struct Tonemapper {
+    program: Program,
+    operator: Operator,
+    exposure: f32,
+}
+
+impl Tonemapper {
+    pub fn new(source: &ShaderSource, operator: Operator, exposure: f32) -> Result<Self> {
+        let program = build_program(source)?;
+
+        Ok(Self { program,
+                  operator,
+                  exposure: exposure.clamp(-MAX_EXPOSURE, MAX_EXPOSURE) })
+    }
+
+    pub fn reload_program(&mut self, source: &ShaderSource, changed: &[String]) -> Result<()> {
+        if TONEMAP_PROGRAM.uses_any(changed) {
+            self.program = build_program(source)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn operator(&self) -> Operator {
+        self.operator
+    }
+
+    pub fn next_operator(&mut self) {
+        self.operator = self.operator.next();
+    }
+
+    pub fn exposure(&self) -> f32 {
+        self.exposure
+    }
+
+    pub fn inc_exposure(&mut self, delta: f32) {
+        self.exposure = (self.exposure + delta).clamp(-MAX_EXPOSURE, MAX_EXPOSURE);
+    }
+
+    /// Bind the program with the current exposure and operator.
+    pub fn activate(&mut self) -> Result<()> {
+        self.program.activate();
+        self.program.set_uniform_1f("exposure", self.exposure)?;
+        self.program.set_uniform_1i("tonemap", self.operator.id())
+    }
+
+    pub fn unbind(&self) {
+        self.program.unbind();
+    }
+}
+
+fn build_program(source: &ShaderSource) -> Result<Program> {
+    let mut program = TONEMAP_PROGRAM.build(source)?;
+    program.activate();
+    let result = program.set_uniform_mat4f("transform", &crate::utils::matrix4f_identity());
+    program.unbind();
+
+    result.map(|()| program)
+}
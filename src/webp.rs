@@ -0,0 +1,255 @@
+// This file is part auf the dual-kawase-demo package.
+//
+// (c) 2019 Bernd Busse
+//
+// For the full copyright and license information, please view the README.md file
+// that was distributed with this source code.
+//
+
+use std::convert::TryInto;
+
+use image::{ImageError, ImageResult, RgbaImage};
+
+mod lossless;
+mod lossy;
+mod tables;
+
+// Flags of the extended format header.
+const ANIMATION_FLAG: u8 = 0x02;
+
+/// Whether `data` starts like a WebP file.
+pub fn is_webp(data: &[u8]) -> bool {
+    data.starts_with(b"RIFF") && data.get(8..12) == Some(b"WEBP")
+}
+
+/// Decode a still WebP image into RGBA samples.
+///
+/// Lossy, lossless and extended files with an alpha channel are read, lossy images are converted
+/// to RGB like libwebp does. Animations are not supported.
+pub fn decode(data: &[u8]) -> ImageResult<RgbaImage> {
+    if !is_webp(data) {
+        return Err(format_error("missing RIFF header"));
+    }
+    // the RIFF size ends the chunks, trailing data is ignored
+    let size = le_u32(&data[4..]) as usize;
+    let chunks = read_chunks(&data[12..data.len().min(size.saturating_add(8))])?;
+
+    match chunks.first() {
+        Some((b"VP8 ", payload)) => lossy::decode(payload),
+        Some((b"VP8L", payload)) => lossless::decode(payload),
+        Some((b"VP8X", payload)) => decode_extended(payload, &chunks[1..]),
+        Some((fourcc, _)) => {
+            let msg = format!("unknown chunk {}", String::from_utf8_lossy(&fourcc[..]));
+            Err(format_error(&msg))
+        },
+        None => Err(format_error("missing image data")),
+    }
+}
+
+/// Decode the image of an extended file with the `header` of its VP8X chunk.
+fn decode_extended(header: &[u8], chunks: &[(&[u8; 4], &[u8])]) -> ImageResult<RgbaImage> {
+    if header.len() < 10 {
+        return Err(format_error("truncated VP8X chunk"));
+    }
+    if header[0] & ANIMATION_FLAG != 0 {
+        let msg = "Animated WebP images are not supported";
+        return Err(ImageError::UnsupportedError(msg.to_owned()));
+    }
+    let canvas = (le_u24(&header[4..]) + 1, le_u24(&header[7..]) + 1);
+
+    let mut alpha = None;
+    for &(fourcc, payload) in chunks {
+        let image = match fourcc {
+            b"ALPH" => {
+                alpha = alpha.or(Some(payload));
+                continue;
+            },
+            b"VP8 " => {
+                let mut image = lossy::decode(payload)?;
+                if let Some(alpha) = alpha {
+                    let samples = decode_alpha(alpha, image.width(), image.height())?;
+                    for (pixel, &sample) in image.pixels_mut().zip(&samples) {
+                        pixel[3] = sample;
+                    }
+                }
+                image
+            },
+            // lossless images carry their own alpha
+            b"VP8L" => lossless::decode(payload)?,
+            b"ANIM" | b"ANMF" => {
+                let msg = "Animated WebP images are not supported";
+                return Err(ImageError::UnsupportedError(msg.to_owned()));
+            },
+            // metadata is read by `metadata::read`
+            _ => continue,
+        };
+
+        if image.dimensions() != canvas {
+            return Err(format_error("image size differs from the canvas"));
+        }
+        return Ok(image);
+    }
+
+    Err(format_error("missing image data"))
+}
+
+/// Split `data` into the fourcc and payload of each chunk.
+fn read_chunks(mut data: &[u8]) -> ImageResult<Vec<(&[u8; 4], &[u8])>> {
+    let mut chunks = Vec::new();
+    while data.len() >= 8 {
+        let size = le_u32(&data[4..]) as usize;
+        let payload = data.get(8..8 + size).ok_or_else(|| format_error("truncated chunk"))?;
+        chunks.push((data[..4].try_into().unwrap(), payload));
+        // chunks are padded to even sizes
+        data = data.get(8 + size + size % 2..).unwrap_or(&[]);
+    }
+    Ok(chunks)
+}
+
+/// Decode the alpha samples of an ALPH chunk.
+fn decode_alpha(data: &[u8], width: u32, height: u32) -> ImageResult<Vec<u8>> {
+    let (width, height) = (width as usize, height as usize);
+    let (&header, data) = data.split_first()
+                              .ok_or_else(|| format_error("truncated ALPH chunk"))?;
+
+    let mut alpha = match header & 3 {
+        0 => data.get(..width * height)
+                 .ok_or_else(|| format_error("truncated ALPH chunk"))?
+                 .to_vec(),
+        // the samples are the green channel of a headerless lossless image
+        1 => lossless::decode_argb(data, width, height)?.iter()
+                                                        .map(|&pixel| (pixel >> 8) as u8)
+                                                        .collect(),
+        compression => {
+            let msg = format!("unknown alpha compression {}", compression);
+            return Err(format_error(&msg));
+        },
+    };
+    unfilter(&mut alpha, width, header >> 2 & 3);
+
+    Ok(alpha)
+}
+
+/// Undo the horizontal (1), vertical (2) or gradient (3) prediction of the alpha samples.
+fn unfilter(alpha: &mut [u8], width: usize, filter: u8) {
+    if filter == 0 {
+        return;
+    }
+
+    for i in 0..alpha.len() {
+        let (x, y) = (i % width, i / width);
+        // the first row is predicted horizontally, the first column from above
+        let prediction = match (x, y) {
+            (0, 0) => 0,
+            (_, 0) => alpha[i - 1],
+            (0, _) => alpha[i - width],
+            _ => match filter {
+                1 => alpha[i - 1],
+                2 => alpha[i - width],
+                _ => {
+                    let gradient = i32::from(alpha[i - 1]) + i32::from(alpha[i - width])
+                                   - i32::from(alpha[i - width - 1]);
+                    gradient.clamp(0, 255) as u8
+                },
+            },
+        };
+        alpha[i] = alpha[i].wrapping_add(prediction);
+    }
+}
+
+fn format_error(msg: &str) -> ImageError {
+    ImageError::FormatError(format!("Invalid WebP file: {}", msg))
+}
+
+fn le_u32(data: &[u8]) -> u32 {
+    u32::from_le_bytes(data[..4].try_into().unwrap())
+}
+
+fn le_u24(data: &[u8]) -> u32 {
+    u32::from(data[0]) | u32::from(data[1]) << 8 | u32::from(data[2]) << 16
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(fourcc: &[u8], payload: &[u8]) -> Vec<u8> {
+        let mut chunk = fourcc.to_vec();
+        chunk.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        chunk.extend_from_slice(payload);
+        if payload.len() % 2 == 1 {
+            chunk.push(0);
+        }
+        chunk
+    }
+
+    fn riff(chunks: &[Vec<u8>]) -> Vec<u8> {
+        let body: Vec<u8> = chunks.concat();
+        let mut data = b"RIFF".to_vec();
+        data.extend_from_slice(&(4 + body.len() as u32).to_le_bytes());
+        data.extend_from_slice(b"WEBP");
+        data.extend_from_slice(&body);
+        data
+    }
+
+    fn extended(flags: u8, width: u32, height: u32) -> Vec<u8> {
+        let mut header = vec![flags, 0, 0, 0];
+        header.extend_from_slice(&(width - 1).to_le_bytes()[..3]);
+        header.extend_from_slice(&(height - 1).to_le_bytes()[..3]);
+        chunk(b"VP8X", &header)
+    }
+
+    #[test]
+    fn decode_simple_file() {
+        let data = riff(&[chunk(b"VP8 ", &lossy::tests::frame(3, 2))]);
+        assert!(is_webp(&data));
+
+        let image = decode(&data).unwrap();
+        assert_eq!(image.dimensions(), (3, 2));
+        assert!(image.pixels().all(|p| p.0 == [136, 136, 136, 255]));
+    }
+
+    #[test]
+    fn decode_alpha_chunk() {
+        // samples 0, 50, 100 and 255, 200, 150 filtered horizontally
+        let alpha = [1 << 2, 0, 50, 50, 255, 201, 206];
+        let data = riff(&[extended(0x10, 3, 2),
+                          chunk(b"ICCP", &[0; 4]),
+                          chunk(b"ALPH", &alpha),
+                          chunk(b"VP8 ", &lossy::tests::frame(3, 2))]);
+
+        let image = decode(&data).unwrap();
+        let samples: Vec<u8> = image.pixels().map(|p| p[3]).collect();
+        assert_eq!(samples, vec![0, 50, 100, 255, 200, 150]);
+        assert_eq!(image.get_pixel(1, 1).0, [136, 136, 136, 200]);
+    }
+
+    #[test]
+    fn unfilter_alpha() {
+        // 10, 20, 30 and 40, 60, 90
+        let mut gradient = [10, 10, 10, 30, 10, 20];
+        unfilter(&mut gradient, 3, 3);
+        assert_eq!(gradient, [10, 20, 30, 40, 60, 90]);
+
+        let mut vertical = [10, 10, 10, 30, 40, 60];
+        unfilter(&mut vertical, 3, 2);
+        assert_eq!(vertical, [10, 20, 30, 40, 60, 90]);
+    }
+
+    #[test]
+    fn reject_unsupported_files() {
+        let frame = chunk(b"VP8 ", &lossy::tests::frame(3, 2));
+
+        let animated = riff(&[extended(0x02, 3, 2), chunk(b"ANIM", &[0; 6])]);
+        assert!(matches!(decode(&animated), Err(ImageError::UnsupportedError(_))));
+
+        let mismatch = riff(&[extended(0, 4, 2), frame.clone()]);
+        assert!(matches!(decode(&mismatch), Err(ImageError::FormatError(_))));
+
+        let truncated = riff(&[frame]);
+        assert!(decode(&truncated[..truncated.len() - 4]).is_err());
+        assert!(decode(&riff(&[chunk(b"JUNK", &[0; 4])])).is_err());
+        assert!(!is_webp(b"RIFF\0\0\0\0WAVE"));
+    }
+}
@@ -0,0 +1,724 @@
+// This file is part auf the dual-kawase-demo package.
+//
+// (c) 2019 Bernd Busse
+//
+// For the full copyright and license information, please view the README.md file
+// that was distributed with this source code.
+//
+
+use image::{ImageBuffer, ImageResult, RgbaImage};
+
+use super::format_error;
+
+const SIGNATURE: u8 = 0x2f;
+
+/// Number of green symbols which are literals or length prefixes.
+const LITERAL_SYMBOLS: usize = 256;
+const LENGTH_SYMBOLS: usize = 24;
+const DISTANCE_SYMBOLS: usize = 40;
+/// Longest code of a prefix code.
+const MAX_CODE_LENGTH: u32 = 15;
+/// Codes up to this length are decoded with a single table lookup.
+const LOOKUP_BITS: u32 = 8;
+
+/// Order of the code lengths of the code length code.
+const CODE_LENGTH_ORDER: [usize; 19] = [17, 18, 0, 1, 2, 3, 4, 5, 16, 6, 7, 8, 9, 10, 11, 12, 13,
+                                        14, 15];
+
+/// Offset of the 120 shortest distance codes as `dy << 4 | (8 - dx)`.
+#[rustfmt::skip]
+const DISTANCE_MAP: [u8; 120] = [
+    0x18, 0x07, 0x17, 0x19, 0x28, 0x06, 0x27, 0x29, 0x16, 0x1a, 0x26, 0x2a, 0x38, 0x05, 0x37, 0x39,
+    0x15, 0x1b, 0x36, 0x3a, 0x25, 0x2b, 0x48, 0x04, 0x47, 0x49, 0x14, 0x1c, 0x35, 0x3b, 0x46, 0x4a,
+    0x24, 0x2c, 0x58, 0x45, 0x4b, 0x34, 0x3c, 0x03, 0x57, 0x59, 0x13, 0x1d, 0x56, 0x5a, 0x23, 0x2d,
+    0x44, 0x4c, 0x55, 0x5b, 0x33, 0x3d, 0x68, 0x02, 0x67, 0x69, 0x12, 0x1e, 0x66, 0x6a, 0x22, 0x2e,
+    0x54, 0x5c, 0x43, 0x4d, 0x65, 0x6b, 0x32, 0x3e, 0x78, 0x01, 0x77, 0x79, 0x53, 0x5d, 0x11, 0x1f,
+    0x64, 0x6c, 0x42, 0x4e, 0x76, 0x7a, 0x21, 0x2f, 0x75, 0x7b, 0x31, 0x3f, 0x63, 0x6d, 0x52, 0x5e,
+    0x00, 0x74, 0x7c, 0x41, 0x4f, 0x10, 0x20, 0x62, 0x6e, 0x30, 0x73, 0x7d, 0x51, 0x5f, 0x40, 0x72,
+    0x7e, 0x61, 0x6f, 0x50, 0x71, 0x7f, 0x60, 0x70,
+];
+
+const BLACK: u32 = 0xff00_0000;
+
+/// Reader of the bits of a VP8L stream, least significant bit first.
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    buffer: u64,
+    count: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data,
+               pos: 0,
+               buffer: 0,
+               count: 0 }
+    }
+
+    /// The next `bits` bits without consuming them, missing bits past the end are zero.
+    fn peek(&mut self, bits: u32) -> u32 {
+        while self.count <= 56 && self.pos < self.data.len() {
+            self.buffer |= u64::from(self.data[self.pos]) << self.count;
+            self.pos += 1;
+            self.count += 8;
+        }
+        (self.buffer & ((1 << bits) - 1)) as u32
+    }
+
+    fn consume(&mut self, bits: u32) -> ImageResult<()> {
+        if bits > self.count {
+            return Err(format_error("truncated VP8L stream"));
+        }
+        self.buffer >>= bits;
+        self.count -= bits;
+        Ok(())
+    }
+
+    fn read(&mut self, bits: u32) -> ImageResult<u32> {
+        let value = self.peek(bits);
+        self.consume(bits)?;
+        Ok(value)
+    }
+}
+
+/// Canonical prefix code.
+struct PrefixCode {
+    /// Symbol and code length by the next `LOOKUP_BITS` bits, the length is zero for longer
+    /// codes.
+    lookup: Vec<(u16, u8)>,
+    /// Number of codes by length.
+    counts: [u16; MAX_CODE_LENGTH as usize + 1],
+    /// Symbols ordered by code.
+    symbols: Vec<u16>,
+}
+
+impl PrefixCode {
+    fn new(lengths: &[u8]) -> ImageResult<Self> {
+        let mut counts = [0; MAX_CODE_LENGTH as usize + 1];
+        for &length in lengths {
+            counts[usize::from(length)] += 1;
+        }
+        counts[0] = 0;
+
+        let mut symbols: Vec<u16> = (0..lengths.len() as u16).filter(|&s| {
+                                                                 lengths[usize::from(s)] > 0
+                                                             })
+                                                             .collect();
+        symbols.sort_by_key(|&s| lengths[usize::from(s)]);
+        match symbols.len() {
+            0 => return Err(format_error("empty prefix code")),
+            // a single symbol takes no bits
+            1 => {
+                return Ok(Self { lookup: vec![(symbols[0], 0); 1 << LOOKUP_BITS],
+                                 counts,
+                                 symbols })
+            },
+            _ => (),
+        }
+
+        let mut left = 1i32;
+        for &count in &counts[1..] {
+            left = 2 * left - i32::from(count);
+            if left < 0 {
+                return Err(format_error("over-subscribed prefix code"));
+            }
+        }
+        if left != 0 {
+            return Err(format_error("incomplete prefix code"));
+        }
+
+        let mut lookup = vec![(0, 0); 1 << LOOKUP_BITS];
+        let mut code = 0u32;
+        let mut length = 0;
+        for &symbol in &symbols {
+            let symbol_length = u32::from(lengths[usize::from(symbol)]);
+            code <<= symbol_length - length;
+            length = symbol_length;
+            if length <= LOOKUP_BITS {
+                // the stream holds codes most significant bit first
+                let reversed = (code.reverse_bits() >> (32 - length)) as usize;
+                for entry in lookup.iter_mut().skip(reversed).step_by(1 << length) {
+                    *entry = (symbol, length as u8);
+                }
+            }
+            code += 1;
+        }
+
+        Ok(Self { lookup,
+                  counts,
+                  symbols })
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> ImageResult<usize> {
+        let bits = reader.peek(MAX_CODE_LENGTH);
+        let (symbol, length) = self.lookup[(bits & ((1 << LOOKUP_BITS) - 1)) as usize];
+        if length > 0 || self.symbols.len() == 1 {
+            reader.consume(u32::from(length))?;
+            return Ok(usize::from(symbol));
+        }
+
+        // walk the canonical codes of each length
+        let (mut code, mut first, mut index) = (0, 0, 0);
+        for length in 1..=MAX_CODE_LENGTH {
+            code |= ((bits >> (length - 1)) & 1) as i32;
+            let count = i32::from(self.counts[length as usize]);
+            if code - first < count {
+                reader.consume(length)?;
+                return Ok(usize::from(self.symbols[(index + code - first) as usize]));
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+
+        unreachable!("complete prefix codes decode every bit sequence")
+    }
+}
+
+/// Prefix codes of the green, red, blue, alpha and distance symbols.
+type CodeGroup = [PrefixCode; 5];
+
+/// Inverse transform applied to the decoded pixels.
+enum Transform {
+    Predictor { bits: u32, modes: Vec<u32> },
+    CrossColor { bits: u32, factors: Vec<u32> },
+    SubtractGreen,
+    ColorIndexing { bits: u32, palette: Vec<u32> },
+}
+
+/// Decode a lossless WebP image into RGBA samples.
+pub fn decode(data: &[u8]) -> ImageResult<RgbaImage> {
+    if data.len() < 5 || data[0] != SIGNATURE {
+        return Err(format_error("invalid VP8L signature"));
+    }
+    let header = u32::from_le_bytes([data[1], data[2], data[3], data[4]]);
+    let width = (header & 0x3fff) as usize + 1;
+    let height = (header >> 14 & 0x3fff) as usize + 1;
+    // bit 28 hints whether alpha is used, it does not change decoding
+    if header >> 29 != 0 {
+        return Err(format_error(&format!("unknown VP8L version {}", header >> 29)));
+    }
+
+    let argb = decode_argb(&data[5..], width, height)?;
+    let mut rgba = Vec::with_capacity(argb.len() * 4);
+    for pixel in argb {
+        let [b, g, r, a] = pixel.to_le_bytes();
+        rgba.extend_from_slice(&[r, g, b, a]);
+    }
+
+    Ok(ImageBuffer::from_raw(width as u32, height as u32, rgba).unwrap())
+}
+
+/// Decode a VP8L image stream without the header into ARGB pixels.
+pub fn decode_argb(data: &[u8], width: usize, height: usize) -> ImageResult<Vec<u32>> {
+    let mut reader = BitReader::new(data);
+
+    let mut transforms = Vec::new();
+    let mut seen = 0;
+    let mut packed_width = width;
+    while reader.read(1)? == 1 {
+        let kind = reader.read(2)?;
+        if seen & 1 << kind != 0 {
+            return Err(format_error("repeated VP8L transform"));
+        }
+        seen |= 1 << kind;
+
+        // the width of the image each transform produces
+        let transform_width = packed_width;
+        let transform = match kind {
+            0 | 1 => {
+                let bits = reader.read(3)? + 2;
+                let data = read_image(&mut reader,
+                                      subsample(packed_width, bits),
+                                      subsample(height, bits),
+                                      false)?;
+                if kind == 0 {
+                    Transform::Predictor { bits, modes: data }
+                } else {
+                    Transform::CrossColor { bits, factors: data }
+                }
+            },
+            2 => Transform::SubtractGreen,
+            _ => {
+                let size = reader.read(8)? as usize + 1;
+                let mut palette = read_image(&mut reader, size, 1, false)?;
+                for i in 1..size {
+                    palette[i] = add_pixels(palette[i], palette[i - 1]);
+                }
+                // small palettes pack several indices into one pixel
+                let bits = match size {
+                    1..=2 => 3,
+                    3..=4 => 2,
+                    5..=16 => 1,
+                    _ => 0,
+                };
+                packed_width = subsample(packed_width, bits);
+                Transform::ColorIndexing { bits, palette }
+            },
+        };
+        transforms.push((transform, transform_width));
+    }
+
+    let mut pixels = read_image(&mut reader, packed_width, height, true)?;
+    for (transform, width) in transforms.iter().rev() {
+        match transform {
+            Transform::Predictor { bits, modes } => unpredict(&mut pixels, *width, *bits, modes),
+            Transform::CrossColor { bits, factors } => {
+                uncorrelate(&mut pixels, *width, *bits, factors)
+            },
+            Transform::SubtractGreen => {
+                for pixel in pixels.iter_mut() {
+                    let green = *pixel >> 8 & 0xff;
+                    *pixel = add_pixels(*pixel, green << 16 | green);
+                }
+            },
+            Transform::ColorIndexing { bits, palette } => {
+                pixels = unpack_indices(&pixels, subsample(*width, *bits), *width, *bits, palette)
+            },
+        }
+    }
+
+    Ok(pixels)
+}
+
+/// Read an image of prefix coded pixels. Only the main image may use several code groups.
+fn read_image(reader: &mut BitReader,
+              width: usize,
+              height: usize,
+              main: bool)
+              -> ImageResult<Vec<u32>> {
+    let cache_bits = if reader.read(1)? == 1 {
+        let bits = reader.read(4)?;
+        if !(1..=11).contains(&bits) {
+            return Err(format_error("invalid color cache size"));
+        }
+        bits
+    } else {
+        0
+    };
+    let cache_size = if cache_bits > 0 { 1 << cache_bits } else { 0 };
+
+    let (group_bits, group_map) = if main && reader.read(1)? == 1 {
+        let bits = reader.read(3)? + 2;
+        let map = read_image(reader, subsample(width, bits), subsample(height, bits), false)?;
+        (bits, map.iter().map(|&p| (p >> 8 & 0xffff) as usize).collect())
+    } else {
+        (0, vec![0])
+    };
+    let group_count = group_map.iter().max().map_or(1, |&max| max + 1);
+    let mut groups = Vec::with_capacity(group_count);
+    for _ in 0..group_count {
+        let green = LITERAL_SYMBOLS + LENGTH_SYMBOLS + cache_size;
+        groups.push([read_code(reader, green)?,
+                     read_code(reader, 256)?,
+                     read_code(reader, 256)?,
+                     read_code(reader, 256)?,
+                     read_code(reader, DISTANCE_SYMBOLS)?]);
+    }
+
+    let group_width = subsample(width, group_bits);
+    let group_at = |pos: usize| -> &CodeGroup {
+        let (x, y) = (pos % width, pos / width);
+        &groups[group_map[(y >> group_bits) * group_width + (x >> group_bits)]]
+    };
+
+    let mut pixels = vec![0; width * height];
+    let mut cache = vec![0; cache_size];
+    let insert = |cache: &mut [u32], pixel: u32| {
+        if cache_bits > 0 {
+            cache[(0x1e35_a7bd_u32.wrapping_mul(pixel) >> (32 - cache_bits)) as usize] = pixel;
+        }
+    };
+
+    let mut pos = 0;
+    while pos < pixels.len() {
+        let group = if group_bits == 0 { &groups[0] } else { group_at(pos) };
+        let green = group[0].decode(reader)?;
+        if green < LITERAL_SYMBOLS {
+            let red = group[1].decode(reader)? as u32;
+            let blue = group[2].decode(reader)? as u32;
+            let alpha = group[3].decode(reader)? as u32;
+            pixels[pos] = alpha << 24 | red << 16 | (green as u32) << 8 | blue;
+            insert(&mut cache, pixels[pos]);
+            pos += 1;
+        } else if green < LITERAL_SYMBOLS + LENGTH_SYMBOLS {
+            let length = read_prefixed_value(reader, green - LITERAL_SYMBOLS)?;
+            let distance_prefix = group[4].decode(reader)?;
+            let distance_code = read_prefixed_value(reader, distance_prefix)?;
+            let distance = plane_distance(width, distance_code);
+            if distance > pos || length > pixels.len() - pos {
+                return Err(format_error("invalid backward reference"));
+            }
+            for i in pos..pos + length {
+                pixels[i] = pixels[i - distance];
+                insert(&mut cache, pixels[i]);
+            }
+            pos += length;
+        } else {
+            pixels[pos] = cache[green - LITERAL_SYMBOLS - LENGTH_SYMBOLS];
+            insert(&mut cache, pixels[pos]);
+            pos += 1;
+        }
+    }
+
+    Ok(pixels)
+}
+
+/// Read a prefix code of `alphabet` symbols.
+fn read_code(reader: &mut BitReader, alphabet: usize) -> ImageResult<PrefixCode> {
+    let mut lengths = vec![0; alphabet];
+
+    if reader.read(1)? == 1 {
+        // one or two symbols of length one
+        let count = reader.read(1)? + 1;
+        let first_bits = if reader.read(1)? == 1 { 8 } else { 1 };
+        let mut symbols = vec![reader.read(first_bits)? as usize];
+        if count == 2 {
+            symbols.push(reader.read(8)? as usize);
+        }
+        for symbol in symbols {
+            *lengths.get_mut(symbol).ok_or_else(|| format_error("invalid prefix code"))? = 1;
+        }
+        return PrefixCode::new(&lengths);
+    }
+
+    let mut code_lengths = [0; 19];
+    let count = reader.read(4)? as usize + 4;
+    for &symbol in &CODE_LENGTH_ORDER[..count] {
+        code_lengths[symbol] = reader.read(3)? as u8;
+    }
+    let length_code = PrefixCode::new(&code_lengths)?;
+
+    let mut max_symbol = alphabet;
+    if reader.read(1)? == 1 {
+        let bits = 2 + 2 * reader.read(3)?;
+        max_symbol = 2 + reader.read(bits)? as usize;
+        if max_symbol > alphabet {
+            return Err(format_error("invalid prefix code"));
+        }
+    }
+
+    let mut previous = 8;
+    let mut symbol = 0;
+    while symbol < alphabet && max_symbol > 0 {
+        max_symbol -= 1;
+        let code = length_code.decode(reader)?;
+        if code < 16 {
+            lengths[symbol] = code as u8;
+            symbol += 1;
+            if code != 0 {
+                previous = code as u8;
+            }
+            continue;
+        }
+
+        let (extra_bits, offset) = [(2, 3), (3, 3), (7, 11)][code - 16];
+        let repeat = reader.read(extra_bits)? as usize + offset;
+        if symbol + repeat > alphabet {
+            return Err(format_error("invalid prefix code"));
+        }
+        let length = if code == 16 { previous } else { 0 };
+        for length_of in &mut lengths[symbol..symbol + repeat] {
+            *length_of = length;
+        }
+        symbol += repeat;
+    }
+
+    PrefixCode::new(&lengths)
+}
+
+/// Read the value of a length or distance prefix symbol and its extra bits.
+fn read_prefixed_value(reader: &mut BitReader, prefix: usize) -> ImageResult<usize> {
+    if prefix < 4 {
+        return Ok(prefix + 1);
+    }
+    let extra_bits = (prefix - 2) >> 1;
+    let offset = (2 + (prefix & 1)) << extra_bits;
+    Ok(offset + reader.read(extra_bits as u32)? as usize + 1)
+}
+
+/// Linear distance of a distance code, the shortest codes map to nearby pixels in 2D.
+fn plane_distance(width: usize, code: usize) -> usize {
+    if code > DISTANCE_MAP.len() {
+        return code - DISTANCE_MAP.len();
+    }
+    let offset = DISTANCE_MAP[code - 1];
+    let (dy, dx) = (usize::from(offset >> 4), 8 - i64::from(offset & 15));
+    ((dy * width) as i64 + dx).max(1) as usize
+}
+
+/// Add the predictions of the `modes` of each block to the residues in `pixels`.
+fn unpredict(pixels: &mut [u32], width: usize, bits: u32, modes: &[u32]) {
+    let blocks_per_row = subsample(width, bits);
+    for i in 0..pixels.len() {
+        let (x, y) = (i % width, i / width);
+        let prediction = match (x, y) {
+            (0, 0) => BLACK,
+            (_, 0) => pixels[i - 1],
+            (0, _) => pixels[i - width],
+            _ => {
+                let mode = modes[(y >> bits) * blocks_per_row + (x >> bits)] >> 8 & 15;
+                // the pixel right above the last column is the first one of the row
+                let (l, t) = (pixels[i - 1], pixels[i - width]);
+                let (tl, tr) = (pixels[i - width - 1], pixels[i - width + 1]);
+                predict(mode, l, t, tl, tr)
+            },
+        };
+        pixels[i] = add_pixels(pixels[i], prediction);
+    }
+}
+
+/// Prediction of a pixel from its left, top, top left and top right neighbours.
+fn predict(mode: u32, l: u32, t: u32, tl: u32, tr: u32) -> u32 {
+    match mode {
+        1 => l,
+        2 => t,
+        3 => tr,
+        4 => tl,
+        5 => average(average(l, tr), t),
+        6 => average(l, tl),
+        7 => average(l, t),
+        8 => average(tl, t),
+        9 => average(t, tr),
+        10 => average(average(l, tl), average(t, tr)),
+        11 => {
+            let distance = |a: u32, b: u32| -> i32 {
+                a.to_le_bytes()
+                 .iter()
+                 .zip(&b.to_le_bytes())
+                 .map(|(&a, &b)| (i32::from(a) - i32::from(b)).abs())
+                 .sum()
+            };
+            if distance(l, tl) <= distance(t, tl) {
+                t
+            } else {
+                l
+            }
+        },
+        12 => map_channels(l, t, tl, |l, t, tl| l + t - tl),
+        13 => map_channels(average(l, t), tl, 0, |a, tl, _| a + (a - tl) / 2),
+        _ => BLACK,
+    }
+}
+
+/// Undo the decorrelation of the red and blue channels by the `factors` of each block.
+fn uncorrelate(pixels: &mut [u32], width: usize, bits: u32, factors: &[u32]) {
+    let blocks_per_row = subsample(width, bits);
+    let delta = |factor: u32, color: u32| {
+        (i32::from(factor as u8 as i8) * i32::from(color as u8 as i8)) >> 5
+    };
+    for (i, pixel) in pixels.iter_mut().enumerate() {
+        let (x, y) = (i % width, i / width);
+        let factor = factors[(y >> bits) * blocks_per_row + (x >> bits)];
+        let (green_to_red, green_to_blue, red_to_blue) = (factor, factor >> 8, factor >> 16);
+
+        let green = *pixel >> 8;
+        let red = ((*pixel >> 16) as i32 + delta(green_to_red, green)) as u32 & 0xff;
+        let blue = (*pixel as i32 + delta(green_to_blue, green) + delta(red_to_blue, red)) as u32
+                   & 0xff;
+        *pixel = *pixel & 0xff00_ff00 | red << 16 | blue;
+    }
+}
+
+/// Look up the palette entries of the indices packed into the green channel of `pixels`.
+fn unpack_indices(pixels: &[u32],
+                  packed_width: usize,
+                  width: usize,
+                  bits: u32,
+                  palette: &[u32])
+                  -> Vec<u32> {
+    let height = pixels.len() / packed_width;
+    let index_bits = 8 >> bits;
+    let mask = (1 << index_bits) - 1;
+    let mut out = Vec::with_capacity(width * height);
+    for y in 0..height {
+        for x in 0..width {
+            let packed = pixels[y * packed_width + (x >> bits)] >> 8;
+            let index = packed >> ((x & ((1 << bits) - 1)) as u32 * index_bits) & mask;
+            // out of range indices are transparent black
+            out.push(palette.get(index as usize).copied().unwrap_or(0));
+        }
+    }
+    out
+}
+
+/// Apply `f` to each channel of the pixels and clamp the result.
+fn map_channels(a: u32, b: u32, c: u32, f: impl Fn(i32, i32, i32) -> i32) -> u32 {
+    (0..32).step_by(8).fold(0, |out, shift| {
+                          let channel = |pixel: u32| (pixel >> shift & 0xff) as i32;
+                          let value = f(channel(a), channel(b), channel(c)).clamp(0, 255);
+                          out | (value as u32) << shift
+                      })
+}
+
+/// Channel wise average, rounded down.
+fn average(a: u32, b: u32) -> u32 {
+    (((a ^ b) & 0xfefe_fefe) >> 1) + (a & b)
+}
+
+/// Channel wise sum modulo 256.
+fn add_pixels(a: u32, b: u32) -> u32 {
+    let alpha_green = (a & 0xff00_ff00).wrapping_add(b & 0xff00_ff00) & 0xff00_ff00;
+    let red_blue = (a & 0x00ff_00ff).wrapping_add(b & 0x00ff_00ff) & 0x00ff_00ff;
+    alpha_green | red_blue
+}
+
+/// Size of a dimension divided into blocks of `1 << bits`.
+fn subsample(size: usize, bits: u32) -> usize {
+    (size + (1 << bits) - 1) >> bits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::ImageError;
+
+    /// Writer of bits, least significant bit first.
+    #[derive(Default)]
+    struct BitWriter {
+        bytes: Vec<u8>,
+        len: usize,
+    }
+
+    impl BitWriter {
+        /// Stream header of an image of `width` by `height` pixels.
+        fn new(width: u32, height: u32) -> Self {
+            let mut writer = Self::default();
+            writer.write(u32::from(SIGNATURE), 8);
+            writer.write(width - 1, 14);
+            writer.write(height - 1, 14);
+            writer.write(0, 4);
+            writer
+        }
+
+        fn write(&mut self, value: u32, bits: u32) {
+            for i in 0..bits {
+                if self.len.is_multiple_of(8) {
+                    self.bytes.push(0);
+                }
+                *self.bytes.last_mut().unwrap() |= ((value >> i & 1) as u8) << (self.len % 8);
+                self.len += 1;
+            }
+        }
+
+        /// Write a prefix code, most significant bit first.
+        fn write_code(&mut self, code: u32, length: u32) {
+            for i in (0..length).rev() {
+                self.write(code >> i & 1, 1);
+            }
+        }
+
+        /// Simple prefix code of a single symbol, which takes no bits.
+        fn write_single_symbol(&mut self, symbol: u32) {
+            self.write(0b101, 3);
+            self.write(symbol, 8);
+        }
+    }
+
+    fn pixels(image: &RgbaImage) -> Vec<[u8; 4]> {
+        image.pixels().map(|p| p.0).collect()
+    }
+
+    #[test]
+    fn decode_simple_codes() {
+        let mut stream = BitWriter::new(2, 2);
+        // subtract green transform
+        stream.write(1, 1);
+        stream.write(2, 2);
+        stream.write(0, 1);
+        // no color cache and a single code group
+        stream.write(0, 2);
+        // green symbols 10 and 20 of one bit
+        stream.write(0b111, 3);
+        stream.write(10, 8);
+        stream.write(20, 8);
+        for &symbol in &[100, 1, 255, 0] {
+            stream.write_single_symbol(symbol);
+        }
+        stream.write(0b0110, 4);
+
+        let image = decode(&stream.bytes).unwrap();
+        assert_eq!(image.dimensions(), (2, 2));
+        assert_eq!(pixels(&image),
+                   vec![[110, 10, 11, 255], [120, 20, 21, 255], [120, 20, 21, 255],
+                        [110, 10, 11, 255]]);
+    }
+
+    #[test]
+    fn decode_backward_reference() {
+        let mut stream = BitWriter::new(4, 1);
+        stream.write(0, 1 + 2);
+
+        // code length code of 0, 1 and 18 as the lengths of 17, 18, 0 and 1
+        stream.write(0, 1 + 4);
+        for &length in &[0, 1, 2, 2] {
+            stream.write(length, 3);
+        }
+        let zeros = |stream: &mut BitWriter, count: u32| {
+            stream.write_code(0, 1);
+            stream.write(count - 11, 7);
+        };
+        // green symbols 7 and 258, the length prefix of 3, of one bit
+        stream.write(0, 1);
+        for _ in 0..7 {
+            stream.write_code(0b10, 2);
+        }
+        stream.write_code(0b11, 2);
+        zeros(&mut stream, 138);
+        zeros(&mut stream, 112);
+        stream.write_code(0b11, 2);
+        zeros(&mut stream, 21);
+
+        for &symbol in &[50, 60, 255] {
+            stream.write_single_symbol(symbol);
+        }
+        // distance prefix 1, the pixel to the left
+        stream.write(0b1001, 4);
+
+        // a literal and a copy of three pixels
+        stream.write(0b10, 2);
+
+        assert_eq!(pixels(&decode(&stream.bytes).unwrap()), vec![[50, 7, 60, 255]; 4]);
+        assert!(decode(&stream.bytes[..stream.bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn reject_invalid_codes() {
+        // the distance symbol 50 is outside of its alphabet
+        let mut stream = BitWriter::new(1, 1);
+        stream.write(0, 3);
+        for &symbol in &[10, 20, 30, 255, 50] {
+            stream.write_single_symbol(symbol);
+        }
+        assert!(matches!(decode(&stream.bytes),
+                         Err(ImageError::FormatError(msg))
+                             if msg.ends_with("invalid prefix code")));
+
+        // two symbols of length one and one of length two
+        assert!(PrefixCode::new(&[1, 1, 2]).is_err());
+        // a single symbol of length one leaves half of the codes unused
+        assert!(PrefixCode::new(&[0, 1]).is_ok());
+        assert!(PrefixCode::new(&[0, 1, 2]).is_err());
+        assert!(PrefixCode::new(&[0, 0]).is_err());
+    }
+
+    #[test]
+    fn inverse_transforms() {
+        assert_eq!(add_pixels(0xff80_0102, 0x0180_ff01), 0x0000_0003);
+        assert_eq!(average(0x0002_04ff, 0x0004_0301), 0x0003_0380);
+        assert_eq!(plane_distance(10, 1), 10);
+        assert_eq!(plane_distance(10, 2), 1);
+        assert_eq!(plane_distance(10, 121), 1);
+
+        let (l, t, tl) = (0xff10_2030, 0xff20_1040, 0xff18_1838);
+        assert_eq!(predict(11, l, t, tl, 0), t);
+        assert_eq!(predict(12, l, t, tl, 0), 0xff18_1838);
+        assert_eq!(predict(13, l, t, tl, 0), 0xff18_1838);
+
+        // indices 2, 3, 1 and 0 of two bits, the second one is out of range
+        let palette = [0xff00_0000, 0xff11_1111, 0xff22_2222];
+        assert_eq!(unpack_indices(&[0x0000_1e00], 1, 4, 2, &palette),
+                   vec![0xff22_2222, 0, 0xff11_1111, 0xff00_0000]);
+    }
+}
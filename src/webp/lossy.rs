@@ -0,0 +1,1308 @@
+// This file is part auf the dual-kawase-demo package.
+//
+// (c) 2019 Bernd Busse
+//
+// For the full copyright and license information, please view the README.md file
+// that was distributed with this source code.
+//
+
+use image::{ImageBuffer, ImageResult, RgbaImage};
+
+use super::format_error;
+use super::tables::{TokenProbs, AC_QUANT, COEFF_PROBS, COEFF_UPDATE_PROBS, DC_QUANT,
+                    SUBBLOCK_MODE_PROBS};
+
+// Intra prediction modes. The macroblock modes share the number of the matching subblock mode.
+const DC_PRED: u8 = 0;
+const TM_PRED: u8 = 1;
+const V_PRED: u8 = 2;
+const H_PRED: u8 = 3;
+const LD_PRED: u8 = 4;
+const RD_PRED: u8 = 5;
+const VR_PRED: u8 = 6;
+const VL_PRED: u8 = 7;
+const HD_PRED: u8 = 8;
+const HU_PRED: u8 = 9;
+
+/// Decoding tree of the subblock modes, leaves hold the negated mode.
+const SUBBLOCK_MODE_TREE: [i8; 18] = [0, 2, -1, 4, -2, 6, 8, 12, -3, 10, -5, -6, -4, 14, -7, 16,
+                                      -8, -9];
+
+// Plane types selecting the token probabilities.
+const Y_AFTER_Y2: usize = 0;
+const Y2: usize = 1;
+const CHROMA: usize = 2;
+const Y_WITH_DC: usize = 3;
+
+/// Band of the token probabilities by coefficient position.
+const COEFF_BANDS: [usize; 16] = [0, 1, 2, 3, 6, 4, 5, 6, 6, 6, 6, 6, 6, 6, 6, 7];
+const ZIGZAG: [usize; 16] = [0, 1, 4, 8, 5, 2, 3, 6, 9, 12, 13, 10, 7, 11, 14, 15];
+/// Probabilities of the extra bits of the token categories 3 to 6.
+const LARGE_CATEGORY_PROBS: [&[u8]; 4] = [&[173, 148, 140],
+                                          &[176, 155, 140, 135],
+                                          &[180, 157, 141, 134, 130],
+                                          &[254, 254, 243, 230, 196, 177, 153, 140, 133, 130,
+                                            129]];
+
+/// Row stride of the work area of luma predictions. It holds the column left of the macroblock
+/// and the four samples above and right of it.
+const LUMA_STRIDE: usize = 1 + 16 + 4;
+const CHROMA_STRIDE: usize = 1 + 8;
+
+/// Boolean entropy decoder of RFC 6386.
+struct BoolReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    value: u32,
+    range: u32,
+    bit_count: u32,
+    /// Number of bytes read past the end of `data`.
+    overrun: usize,
+}
+
+impl<'a> BoolReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        let mut reader = Self { data,
+                                pos: 0,
+                                value: 0,
+                                range: 255,
+                                bit_count: 0,
+                                overrun: 0 };
+        reader.value = reader.next_byte() << 8 | reader.next_byte();
+        reader
+    }
+
+    /// Whether bits past the end of the data were needed.
+    fn is_exhausted(&self) -> bool {
+        // two bytes are read ahead
+        self.overrun > 2
+    }
+
+    fn next_byte(&mut self) -> u32 {
+        match self.data.get(self.pos) {
+            Some(&byte) => {
+                self.pos += 1;
+                u32::from(byte)
+            },
+            None => {
+                self.overrun += 1;
+                0
+            },
+        }
+    }
+
+    /// Read a bit which is zero with a probability of `prob / 256`.
+    fn read_bool(&mut self, prob: u8) -> bool {
+        let split = 1 + (((self.range - 1) * u32::from(prob)) >> 8);
+        let big_split = split << 8;
+        let bit = if self.value >= big_split {
+            self.range -= split;
+            self.value -= big_split;
+            true
+        } else {
+            self.range = split;
+            false
+        };
+
+        while self.range < 128 {
+            self.value <<= 1;
+            self.range <<= 1;
+            self.bit_count += 1;
+            if self.bit_count == 8 {
+                self.bit_count = 0;
+                self.value |= self.next_byte();
+            }
+        }
+
+        bit
+    }
+
+    fn read_flag(&mut self) -> bool {
+        self.read_bool(128)
+    }
+
+    /// Unsigned value of `bits` bits, most significant bit first.
+    fn read_literal(&mut self, bits: u32) -> u32 {
+        (0..bits).fold(0, |value, _| value << 1 | u32::from(self.read_flag()))
+    }
+
+    /// Value of `bits` bits and a sign if its flag is set, zero otherwise.
+    fn read_optional_signed(&mut self, bits: u32) -> i32 {
+        if !self.read_flag() {
+            return 0;
+        }
+        let value = self.read_literal(bits) as i32;
+        if self.read_flag() {
+            -value
+        } else {
+            value
+        }
+    }
+
+    fn read_tree(&mut self, tree: &[i8], probs: &[u8]) -> u8 {
+        let mut i = 0;
+        loop {
+            let next = tree[i + usize::from(self.read_bool(probs[i / 2]))];
+            if next <= 0 {
+                return -next as u8;
+            }
+            i = next as usize;
+        }
+    }
+}
+
+/// Dequantization factors of the DC and AC coefficients of a segment.
+#[derive(Clone, Copy, Default)]
+struct Quantizer {
+    y: [i32; 2],
+    y2: [i32; 2],
+    uv: [i32; 2],
+}
+
+impl Quantizer {
+    /// Factors of quantizer `index` with the `deltas` of the Y DC, Y2 DC, Y2 AC, UV DC and UV AC
+    /// coefficients.
+    fn new(index: i32, deltas: [i32; 5]) -> Self {
+        let step = |table: &[i32; 128], delta: i32, max: i32| {
+            table[(index + delta).max(0).min(max) as usize]
+        };
+        let [y_dc, y2_dc, y2_ac, uv_dc, uv_ac] = deltas;
+
+        Self { y: [step(&DC_QUANT, y_dc, 127), step(&AC_QUANT, 0, 127)],
+               y2: [2 * step(&DC_QUANT, y2_dc, 127),
+                    (step(&AC_QUANT, y2_ac, 127) * 155 / 100).max(8)],
+               uv: [step(&DC_QUANT, uv_dc, 117), step(&AC_QUANT, uv_ac, 127)] }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum FilterType {
+    None,
+    Simple,
+    Normal,
+}
+
+/// Loop filter strength of a macroblock.
+#[derive(Clone, Copy, Default)]
+struct FilterParams {
+    /// Edge limit of the subblock edges, macroblock edges use a higher one. Zero disables
+    /// filtering.
+    limit: i32,
+    interior_limit: i32,
+    hev_threshold: i32,
+}
+
+impl FilterParams {
+    fn new(level: i32, sharpness: i32) -> Self {
+        let level = level.clamp(0, 63);
+        if level == 0 {
+            return Self::default();
+        }
+
+        let mut interior_limit = level;
+        if sharpness > 0 {
+            interior_limit >>= if sharpness > 4 { 2 } else { 1 };
+            interior_limit = interior_limit.min(9 - sharpness);
+        }
+        let interior_limit = interior_limit.max(1);
+        let hev_threshold = match level {
+            40..=63 => 2,
+            15..=39 => 1,
+            _ => 0,
+        };
+
+        Self { limit: 2 * level + interior_limit,
+               interior_limit,
+               hev_threshold }
+    }
+}
+
+/// Frame header fields needed to decode the macroblocks.
+struct FrameHeader {
+    /// Probabilities of the segment tree, if the macroblocks are assigned to segments.
+    segment_probs: Option<[u8; 3]>,
+    skip_prob: Option<u8>,
+    partitions: usize,
+    quantizers: [Quantizer; 4],
+    filter_type: FilterType,
+    /// Filter strength by segment and whether the macroblock is predicted by subblocks.
+    filter_params: [[FilterParams; 2]; 4],
+    token_probs: TokenProbs,
+}
+
+/// Prediction modes of a macroblock.
+struct Macroblock {
+    segment: usize,
+    skip: bool,
+    /// Luma mode, `None` if each subblock is predicted with its own mode.
+    luma_mode: Option<u8>,
+    subblock_modes: [u8; 16],
+    chroma_mode: u8,
+}
+
+/// Flags of the blocks along a macroblock edge which have non-zero coefficients.
+#[derive(Clone, Copy, Default)]
+struct NonZero {
+    y: [bool; 4],
+    /// U and V blocks.
+    uv: [[bool; 2]; 2],
+    y2: bool,
+}
+
+/// Loop filter of a macroblock.
+#[derive(Clone, Copy)]
+struct MacroblockFilter {
+    params: FilterParams,
+    simple: bool,
+    left: bool,
+    top: bool,
+    /// Whether the edges between the subblocks are filtered.
+    inner: bool,
+}
+
+impl MacroblockFilter {
+    /// Filter the edges of the block of `size` samples at `origin`.
+    fn apply(self, plane: &mut [u8], origin: usize, stride: usize, size: usize) {
+        let macroblock_limit = 2 * (self.params.limit + 4) + 1;
+        let subblock_limit = 2 * self.params.limit + 1;
+        let inner_edges = (4..size).step_by(4);
+
+        if self.left {
+            for i in 0..size {
+                self.filter(plane, origin + i * stride, 1, macroblock_limit, true);
+            }
+        }
+        if self.inner {
+            for x in inner_edges.clone() {
+                for i in 0..size {
+                    self.filter(plane, origin + i * stride + x, 1, subblock_limit, false);
+                }
+            }
+        }
+        if self.top {
+            for i in 0..size {
+                self.filter(plane, origin + i, stride, macroblock_limit, true);
+            }
+        }
+        if self.inner {
+            for y in inner_edges {
+                for i in 0..size {
+                    self.filter(plane, origin + y * stride + i, stride, subblock_limit, false);
+                }
+            }
+        }
+    }
+
+    /// Filter the samples across the edge before `pos`, `step` apart.
+    fn filter(self, plane: &mut [u8], pos: usize, step: usize, limit: i32, macroblock_edge: bool) {
+        let index = |k: isize| (pos as isize + k * step as isize) as usize;
+        let sample = |k: isize| i32::from(plane[index(k)]);
+        let (p1, p0, q0, q1) = (sample(-2), sample(-1), sample(0), sample(1));
+        if 4 * (p0 - q0).abs() + (p1 - q1).abs() > limit {
+            return;
+        }
+
+        let clamp = |value: i32, min: i32, max: i32| value.max(min).min(max);
+        if !self.simple {
+            let (p3, p2, q2, q3) = (sample(-4), sample(-3), sample(2), sample(3));
+            let interior = [p3 - p2, p2 - p1, p1 - p0, q3 - q2, q2 - q1, q1 - q0];
+            if interior.iter().any(|d| d.abs() > self.params.interior_limit) {
+                return;
+            }
+
+            let threshold = self.params.hev_threshold;
+            let high_variance = (p1 - p0).abs() > threshold || (q1 - q0).abs() > threshold;
+            if !high_variance && macroblock_edge {
+                let a = clamp(3 * (q0 - p0) + clamp(p1 - q1, -128, 127), -128, 127);
+                let (a1, a2, a3) = ((27 * a + 63) >> 7, (18 * a + 63) >> 7, (9 * a + 63) >> 7);
+                for (k, value) in [p2 + a3, p1 + a2, p0 + a1, q0 - a1, q1 - a2, q2 - a3].iter()
+                                                                                      .enumerate()
+                {
+                    plane[index(k as isize - 3)] = clip(*value);
+                }
+                return;
+            }
+            if !high_variance {
+                let a = 3 * (q0 - p0);
+                let a1 = clamp((a + 4) >> 3, -16, 15);
+                let a2 = clamp((a + 3) >> 3, -16, 15);
+                let a3 = (a1 + 1) >> 1;
+                for (k, value) in [p1 + a3, p0 + a2, q0 - a1, q1 - a3].iter().enumerate() {
+                    plane[index(k as isize - 2)] = clip(*value);
+                }
+                return;
+            }
+        }
+
+        let a = 3 * (q0 - p0) + clamp(p1 - q1, -128, 127);
+        let a1 = clamp((a + 4) >> 3, -16, 15);
+        let a2 = clamp((a + 3) >> 3, -16, 15);
+        plane[index(-1)] = clip(p0 + a2);
+        plane[index(0)] = clip(q0 - a1);
+    }
+}
+
+/// Decoded planes, padded to whole macroblocks.
+struct Planes {
+    y: Vec<u8>,
+    u: Vec<u8>,
+    v: Vec<u8>,
+    mb_width: usize,
+    mb_height: usize,
+}
+
+impl Planes {
+    fn new(mb_width: usize, mb_height: usize) -> Self {
+        let luma = mb_width * mb_height * 256;
+        Self { y: vec![0; luma],
+               u: vec![0; luma / 4],
+               v: vec![0; luma / 4],
+               mb_width,
+               mb_height }
+    }
+
+    /// Predict a macroblock and add its residue.
+    fn reconstruct(&mut self, mbx: usize, mby: usize, mb: &Macroblock, coeffs: &[i32; 384]) {
+        let stride = self.mb_width * 16;
+        let mut work = [0; LUMA_STRIDE * 17];
+        load_edges(&self.y, stride, 16, (mbx, mby), &mut work, LUMA_STRIDE);
+        // the samples right of the macroblock above are used by the right column of subblocks
+        let top_right = if mby == 0 {
+            [127; 4]
+        } else if mbx + 1 < self.mb_width {
+            let pos = (mby * 16 - 1) * stride + mbx * 16 + 16;
+            [self.y[pos], self.y[pos + 1], self.y[pos + 2], self.y[pos + 3]]
+        } else {
+            [self.y[(mby * 16 - 1) * stride + mbx * 16 + 15]; 4]
+        };
+        for row in (0..16).step_by(4) {
+            work[row * LUMA_STRIDE + 17..][..4].copy_from_slice(&top_right);
+        }
+
+        if let Some(mode) = mb.luma_mode {
+            predict_block(&mut work, LUMA_STRIDE, 16, mode, (mbx > 0, mby > 0));
+        }
+        for (i, block) in coeffs[..256].chunks_exact(16).enumerate() {
+            let origin = LUMA_STRIDE * (1 + i / 4 * 4) + 1 + i % 4 * 4;
+            if mb.luma_mode.is_none() {
+                predict_subblock(&mut work, origin, LUMA_STRIDE, mb.subblock_modes[i]);
+            }
+            add_residue(&mut work, origin, LUMA_STRIDE, block);
+        }
+        store(&work, LUMA_STRIDE, &mut self.y, stride, 16, (mbx, mby));
+
+        let stride = self.mb_width * 8;
+        for (plane, coeffs) in [&mut self.u, &mut self.v].iter_mut()
+                                                         .zip(coeffs[256..].chunks_exact(64))
+        {
+            let mut work = [0; CHROMA_STRIDE * 9];
+            load_edges(plane, stride, 8, (mbx, mby), &mut work, CHROMA_STRIDE);
+            predict_block(&mut work, CHROMA_STRIDE, 8, mb.chroma_mode, (mbx > 0, mby > 0));
+            for (i, block) in coeffs.chunks_exact(16).enumerate() {
+                let origin = CHROMA_STRIDE * (1 + i / 2 * 4) + 1 + i % 2 * 4;
+                add_residue(&mut work, origin, CHROMA_STRIDE, block);
+            }
+            store(&work, CHROMA_STRIDE, plane, stride, 8, (mbx, mby));
+        }
+    }
+
+    /// Run the loop filter over all macroblocks, `params` holds their strength and whether
+    /// their inner edges are filtered.
+    fn filter(&mut self, filter_type: FilterType, params: &[(FilterParams, bool)]) {
+        let (y_stride, uv_stride) = (self.mb_width * 16, self.mb_width * 8);
+        for mby in 0..self.mb_height {
+            for mbx in 0..self.mb_width {
+                let (params, inner) = params[mby * self.mb_width + mbx];
+                if params.limit == 0 {
+                    continue;
+                }
+
+                let filter = MacroblockFilter { params,
+                                                simple: filter_type == FilterType::Simple,
+                                                left: mbx > 0,
+                                                top: mby > 0,
+                                                inner };
+                filter.apply(&mut self.y, mby * 16 * y_stride + mbx * 16, y_stride, 16);
+                if !filter.simple {
+                    let origin = mby * 8 * uv_stride + mbx * 8;
+                    filter.apply(&mut self.u, origin, uv_stride, 8);
+                    filter.apply(&mut self.v, origin, uv_stride, 8);
+                }
+            }
+        }
+    }
+
+    /// Convert to RGBA. The chroma samples are interpolated like the "fancy upsampling" of
+    /// libwebp: the nearer chroma row and column are weighted by three quarters.
+    fn to_rgba(&self, width: usize, height: usize) -> RgbaImage {
+        let (y_stride, uv_stride) = (self.mb_width * 16, self.mb_width * 8);
+        let last_row = (height - 1) / 2;
+        let mut rgba = vec![255; width * height * 4];
+        let mut u = vec![0; width];
+        let mut v = vec![0; width];
+
+        for (y, out) in rgba.chunks_exact_mut(width * 4).enumerate() {
+            let near = y / 2 * uv_stride;
+            let far = if y % 2 == 1 { y / 2 + 1 } else { (y / 2).saturating_sub(1) };
+            let far = far.min(last_row) * uv_stride;
+            upsample_row(&self.u[near..], &self.u[far..], &mut u);
+            upsample_row(&self.v[near..], &self.v[far..], &mut v);
+
+            let luma = &self.y[y * y_stride..];
+            for (x, pixel) in out.chunks_exact_mut(4).enumerate() {
+                pixel[..3].copy_from_slice(&yuv_to_rgb(luma[x], u[x], v[x]));
+            }
+        }
+
+        ImageBuffer::from_raw(width as u32, height as u32, rgba).unwrap()
+    }
+}
+
+/// Decode the key frame of a lossy WebP image into RGBA samples with opaque alpha.
+pub fn decode(data: &[u8]) -> ImageResult<RgbaImage> {
+    if data.len() < 10 {
+        return Err(format_error("truncated VP8 frame"));
+    }
+    let tag = u32::from(data[0]) | u32::from(data[1]) << 8 | u32::from(data[2]) << 16;
+    if tag & 1 != 0 {
+        return Err(format_error("VP8 frame is not a key frame"));
+    }
+    if (tag >> 1) & 7 > 3 {
+        return Err(format_error(&format!("unknown VP8 version {}", (tag >> 1) & 7)));
+    }
+    if data[3..6] != [0x9d, 0x01, 0x2a] {
+        return Err(format_error("invalid VP8 start code"));
+    }
+    // the upper two bits hold an upscaling hint
+    let width = usize::from(u16::from_le_bytes([data[6], data[7]]) & 0x3fff);
+    let height = usize::from(u16::from_le_bytes([data[8], data[9]]) & 0x3fff);
+    if width == 0 || height == 0 {
+        return Err(format_error("empty VP8 frame"));
+    }
+
+    let first_size = (tag >> 5) as usize;
+    if first_size > data.len() - 10 {
+        return Err(format_error("truncated VP8 frame"));
+    }
+    let (first, rest) = data[10..].split_at(first_size);
+    let mut reader = BoolReader::new(first);
+    let header = read_frame_header(&mut reader);
+    let mut partitions = split_partitions(rest, header.partitions)?;
+
+    let (mb_width, mb_height) = (width.div_ceil(16), height.div_ceil(16));
+    let mut planes = Planes::new(mb_width, mb_height);
+    let mut filter_params = Vec::with_capacity(mb_width * mb_height);
+    let mut top_modes = vec![[DC_PRED; 4]; mb_width];
+    let mut top_non_zero = vec![NonZero::default(); mb_width];
+    for mby in 0..mb_height {
+        let count = partitions.len();
+        let tokens = &mut partitions[mby % count];
+        let mut left_modes = [DC_PRED; 4];
+        let mut left_non_zero = NonZero::default();
+
+        for mbx in 0..mb_width {
+            let mb = read_macroblock_header(&mut reader,
+                                            &header,
+                                            &mut top_modes[mbx],
+                                            &mut left_modes);
+            let mut coeffs = [0; 384];
+            let has_coeffs = if mb.skip {
+                let keep_y2 = mb.luma_mode.is_none();
+                for non_zero in [&mut top_non_zero[mbx], &mut left_non_zero].iter_mut() {
+                    **non_zero = NonZero { y2: non_zero.y2 && keep_y2,
+                                           ..NonZero::default() };
+                }
+                false
+            } else {
+                read_residue(tokens,
+                             &header,
+                             &mb,
+                             (&mut top_non_zero[mbx], &mut left_non_zero),
+                             &mut coeffs)
+            };
+
+            planes.reconstruct(mbx, mby, &mb, &coeffs);
+            let subblocks = mb.luma_mode.is_none();
+            filter_params.push((header.filter_params[mb.segment][usize::from(subblocks)],
+                                subblocks || has_coeffs));
+        }
+
+        if tokens.is_exhausted() {
+            return Err(format_error("truncated VP8 frame"));
+        }
+    }
+    if reader.is_exhausted() {
+        return Err(format_error("truncated VP8 frame header"));
+    }
+
+    if header.filter_type != FilterType::None {
+        planes.filter(header.filter_type, &filter_params);
+    }
+    Ok(planes.to_rgba(width, height))
+}
+
+fn read_frame_header(reader: &mut BoolReader) -> FrameHeader {
+    // color space and clamping type, neither changes the decoding of key frames
+    reader.read_literal(2);
+
+    let segmentation = reader.read_flag();
+    let mut segment_probs = None;
+    let mut absolute = true;
+    let mut segment_quantizers = [0; 4];
+    let mut segment_levels = [0; 4];
+    if segmentation {
+        let update_map = reader.read_flag();
+        if reader.read_flag() {
+            absolute = reader.read_flag();
+            for quantizer in segment_quantizers.iter_mut() {
+                *quantizer = reader.read_optional_signed(7);
+            }
+            for level in segment_levels.iter_mut() {
+                *level = reader.read_optional_signed(6);
+            }
+        }
+        if update_map {
+            let mut probs = [255; 3];
+            for prob in probs.iter_mut() {
+                if reader.read_flag() {
+                    *prob = reader.read_literal(8) as u8;
+                }
+            }
+            segment_probs = Some(probs);
+        }
+    }
+
+    let simple = reader.read_flag();
+    let level = reader.read_literal(6) as i32;
+    let sharpness = reader.read_literal(3) as i32;
+    // only the deltas of intra frames and subblock prediction apply to key frames
+    let (mut frame_delta, mut subblock_delta) = (0, 0);
+    if reader.read_flag() && reader.read_flag() {
+        for i in 0..4 {
+            let delta = reader.read_optional_signed(6);
+            if i == 0 {
+                frame_delta = delta;
+            }
+        }
+        for i in 0..4 {
+            let delta = reader.read_optional_signed(6);
+            if i == 0 {
+                subblock_delta = delta;
+            }
+        }
+    }
+    let filter_type = match (level, simple) {
+        (0, _) => FilterType::None,
+        (_, true) => FilterType::Simple,
+        (_, false) => FilterType::Normal,
+    };
+
+    let partitions = 1 << reader.read_literal(2);
+
+    let base_quantizer = reader.read_literal(7) as i32;
+    let mut deltas = [0; 5];
+    for delta in deltas.iter_mut() {
+        *delta = reader.read_optional_signed(4);
+    }
+
+    let mut quantizers = [Quantizer::default(); 4];
+    let mut filter_params = [[FilterParams::default(); 2]; 4];
+    for segment in 0..4 {
+        let (quantizer, level) = match (segmentation, absolute) {
+            (false, _) => (base_quantizer, level),
+            (true, true) => (segment_quantizers[segment], segment_levels[segment]),
+            (true, false) => {
+                (base_quantizer + segment_quantizers[segment], level + segment_levels[segment])
+            },
+        };
+        quantizers[segment] = Quantizer::new(quantizer, deltas);
+        filter_params[segment] = [FilterParams::new(level + frame_delta, sharpness),
+                                  FilterParams::new(level + frame_delta + subblock_delta,
+                                                    sharpness)];
+    }
+
+    // whether the probabilities are kept for the next frame
+    reader.read_flag();
+
+    let mut token_probs = COEFF_PROBS;
+    for (probs, update_probs) in token_probs.iter_mut()
+                                            .flat_map(|p| p.iter_mut())
+                                            .flat_map(|p| p.iter_mut())
+                                            .flat_map(|p| p.iter_mut())
+                                            .zip(COEFF_UPDATE_PROBS.iter()
+                                                                   .flat_map(|p| p.iter())
+                                                                   .flat_map(|p| p.iter())
+                                                                   .flat_map(|p| p.iter()))
+    {
+        if reader.read_bool(*update_probs) {
+            *probs = reader.read_literal(8) as u8;
+        }
+    }
+    let skip_prob = if reader.read_flag() {
+        Some(reader.read_literal(8) as u8)
+    } else {
+        None
+    };
+
+    FrameHeader { segment_probs,
+                  skip_prob,
+                  partitions,
+                  quantizers,
+                  filter_type,
+                  filter_params,
+                  token_probs }
+}
+
+/// Split the data behind the first partition into the token partitions.
+fn split_partitions(data: &[u8], count: usize) -> ImageResult<Vec<BoolReader<'_>>> {
+    let sizes_len = 3 * (count - 1);
+    if data.len() < sizes_len {
+        return Err(format_error("truncated VP8 frame"));
+    }
+
+    let (sizes, mut data) = data.split_at(sizes_len);
+    let mut partitions = Vec::with_capacity(count);
+    for size in sizes.chunks_exact(3) {
+        let size = usize::from(size[0]) | usize::from(size[1]) << 8 | usize::from(size[2]) << 16;
+        let (partition, rest) = data.split_at(size.min(data.len()));
+        partitions.push(BoolReader::new(partition));
+        data = rest;
+    }
+    partitions.push(BoolReader::new(data));
+
+    Ok(partitions)
+}
+
+/// Read the modes of a macroblock, `top` and `left` hold the subblock modes along its edges.
+fn read_macroblock_header(reader: &mut BoolReader,
+                          header: &FrameHeader,
+                          top: &mut [u8; 4],
+                          left: &mut [u8; 4])
+                          -> Macroblock {
+    let segment = match header.segment_probs {
+        Some(probs) if reader.read_bool(probs[0]) => 2 + usize::from(reader.read_bool(probs[2])),
+        Some(probs) => usize::from(reader.read_bool(probs[1])),
+        None => 0,
+    };
+    let skip = header.skip_prob.is_some_and(|prob| reader.read_bool(prob));
+
+    let mut subblock_modes = [DC_PRED; 16];
+    let luma_mode = if reader.read_bool(145) {
+        let mode = if reader.read_bool(156) {
+            if reader.read_bool(128) {
+                TM_PRED
+            } else {
+                H_PRED
+            }
+        } else if reader.read_bool(163) {
+            V_PRED
+        } else {
+            DC_PRED
+        };
+        *top = [mode; 4];
+        *left = [mode; 4];
+        Some(mode)
+    } else {
+        for (i, subblock_mode) in subblock_modes.iter_mut().enumerate() {
+            let (x, y) = (i % 4, i / 4);
+            let probs = &SUBBLOCK_MODE_PROBS[usize::from(top[x])][usize::from(left[y])];
+            let mode = reader.read_tree(&SUBBLOCK_MODE_TREE, probs);
+            top[x] = mode;
+            left[y] = mode;
+            *subblock_mode = mode;
+        }
+        None
+    };
+
+    let chroma_mode = if !reader.read_bool(142) {
+        DC_PRED
+    } else if !reader.read_bool(114) {
+        V_PRED
+    } else if reader.read_bool(183) {
+        TM_PRED
+    } else {
+        H_PRED
+    };
+
+    Macroblock { segment,
+                 skip,
+                 luma_mode,
+                 subblock_modes,
+                 chroma_mode }
+}
+
+/// Read the dequantized coefficients of the 16 luma and 8 chroma blocks of a macroblock.
+///
+/// Returns whether any block has non-zero coefficients.
+fn read_residue(reader: &mut BoolReader,
+                header: &FrameHeader,
+                mb: &Macroblock,
+                (top, left): (&mut NonZero, &mut NonZero),
+                coeffs: &mut [i32; 384])
+                -> bool {
+    let probs = &header.token_probs;
+    let quantizer = &header.quantizers[mb.segment];
+
+    let (first, luma_probs) = if mb.luma_mode.is_some() {
+        // the DC coefficients of the luma blocks are coded in the separate Y2 block
+        let mut y2 = [0; 16];
+        let context = usize::from(top.y2) + usize::from(left.y2);
+        let end = read_coefficients(reader, &probs[Y2], context, quantizer.y2, 0, &mut y2);
+        top.y2 = end > 0;
+        left.y2 = end > 0;
+        for (i, dc) in inverse_wht(&y2).iter().enumerate() {
+            coeffs[i * 16] = *dc;
+        }
+        (1, &probs[Y_AFTER_Y2])
+    } else {
+        (0, &probs[Y_WITH_DC])
+    };
+
+    let mut has_coeffs = false;
+    for (i, block) in coeffs[..256].chunks_exact_mut(16).enumerate() {
+        let (x, y) = (i % 4, i / 4);
+        let context = usize::from(top.y[x]) + usize::from(left.y[y]);
+        let end = read_coefficients(reader, luma_probs, context, quantizer.y, first, block);
+        top.y[x] = end > first;
+        left.y[y] = end > first;
+        has_coeffs |= end > 1 || block[0] != 0;
+    }
+
+    for (i, block) in coeffs[256..].chunks_exact_mut(16).enumerate() {
+        let (plane, x, y) = (i / 4, i % 2, i / 2 % 2);
+        let context = usize::from(top.uv[plane][x]) + usize::from(left.uv[plane][y]);
+        let end = read_coefficients(reader, &probs[CHROMA], context, quantizer.uv, 0, block);
+        top.uv[plane][x] = end > 0;
+        left.uv[plane][y] = end > 0;
+        has_coeffs |= end > 1 || block[0] != 0;
+    }
+
+    has_coeffs
+}
+
+/// Read the tokens of a block from position `first` on and store the dequantized coefficients
+/// in `block`.
+///
+/// Returns the position behind the last token.
+fn read_coefficients(reader: &mut BoolReader,
+                     probs: &[[[u8; 11]; 3]; 8],
+                     context: usize,
+                     quantizer: [i32; 2],
+                     first: usize,
+                     block: &mut [i32])
+                     -> usize {
+    let mut pos = first;
+    let mut p = &probs[COEFF_BANDS[pos]][context];
+    while pos < 16 {
+        if !reader.read_bool(p[0]) {
+            // end of block
+            return pos;
+        }
+        // zero tokens are never followed by the end of block
+        while !reader.read_bool(p[1]) {
+            pos += 1;
+            if pos == 16 {
+                return 16;
+            }
+            p = &probs[COEFF_BANDS[pos]][0];
+        }
+
+        let (value, context) = if !reader.read_bool(p[2]) {
+            (1, 1)
+        } else {
+            (read_large_value(reader, p), 2)
+        };
+        let value = if reader.read_flag() { -value } else { value };
+        // coefficients are 16 bit
+        block[ZIGZAG[pos]] = (value * quantizer[usize::from(pos > 0)]) as i16 as i32;
+
+        pos += 1;
+        if pos < 16 {
+            p = &probs[COEFF_BANDS[pos]][context];
+        }
+    }
+
+    16
+}
+
+/// Value of a token larger than one.
+fn read_large_value(reader: &mut BoolReader, p: &[u8; 11]) -> i32 {
+    if !reader.read_bool(p[3]) {
+        if !reader.read_bool(p[4]) {
+            2
+        } else {
+            3 + i32::from(reader.read_bool(p[5]))
+        }
+    } else if !reader.read_bool(p[6]) {
+        if !reader.read_bool(p[7]) {
+            5 + i32::from(reader.read_bool(159))
+        } else {
+            7 + 2 * i32::from(reader.read_bool(165)) + i32::from(reader.read_bool(145))
+        }
+    } else {
+        let high = usize::from(reader.read_bool(p[8]));
+        let category = 2 * high + usize::from(reader.read_bool(p[9 + high]));
+        let extra = LARGE_CATEGORY_PROBS[category].iter()
+                                                  .fold(0, |value, &prob| {
+                                                      value << 1
+                                                      | i32::from(reader.read_bool(prob))
+                                                  });
+        3 + (8 << category) + extra
+    }
+}
+
+/// Inverse Walsh-Hadamard transform of the Y2 block into the DC coefficients of the luma blocks.
+fn inverse_wht(input: &[i32; 16]) -> [i32; 16] {
+    let mut tmp = [0; 16];
+    for i in 0..4 {
+        let a0 = input[i] + input[12 + i];
+        let a1 = input[4 + i] + input[8 + i];
+        let a2 = input[4 + i] - input[8 + i];
+        let a3 = input[i] - input[12 + i];
+        tmp[i] = a0 + a1;
+        tmp[8 + i] = a0 - a1;
+        tmp[4 + i] = a3 + a2;
+        tmp[12 + i] = a3 - a2;
+    }
+
+    let mut out = [0; 16];
+    for (row, out) in tmp.chunks_exact(4).zip(out.chunks_exact_mut(4)) {
+        let dc = row[0] + 3;
+        let a0 = dc + row[3];
+        let a1 = row[1] + row[2];
+        let a2 = row[1] - row[2];
+        let a3 = dc - row[3];
+        for (out, value) in out.iter_mut().zip(&[a0 + a1, a3 + a2, a0 - a1, a3 - a2]) {
+            *out = (value >> 3) as i16 as i32;
+        }
+    }
+
+    out
+}
+
+/// Add the inverse DCT of `coeffs` to the 4x4 block at `origin`.
+fn add_residue(work: &mut [u8], origin: usize, stride: usize, coeffs: &[i32]) {
+    if coeffs.iter().all(|&c| c == 0) {
+        return;
+    }
+
+    // multiplications by sqrt(2) * cos(pi / 8) and sqrt(2) * sin(pi / 8) in 16 bit fixed point
+    let mul1 = |a: i32| ((i64::from(a) * 20091) >> 16) as i32 + a;
+    let mul2 = |a: i32| ((i64::from(a) * 35468) >> 16) as i32;
+
+    let mut tmp = [0; 16];
+    for i in 0..4 {
+        let a = coeffs[i] + coeffs[8 + i];
+        let b = coeffs[i] - coeffs[8 + i];
+        let c = mul2(coeffs[4 + i]) - mul1(coeffs[12 + i]);
+        let d = mul1(coeffs[4 + i]) + mul2(coeffs[12 + i]);
+        tmp[i * 4..i * 4 + 4].copy_from_slice(&[a + d, b + c, b - c, a - d]);
+    }
+
+    for i in 0..4 {
+        let dc = tmp[i] + 4;
+        let a = dc + tmp[8 + i];
+        let b = dc - tmp[8 + i];
+        let c = mul2(tmp[4 + i]) - mul1(tmp[12 + i]);
+        let d = mul1(tmp[4 + i]) + mul2(tmp[12 + i]);
+        let row = &mut work[origin + i * stride..][..4];
+        for (sample, value) in row.iter_mut().zip(&[a + d, b + c, b - c, a - d]) {
+            *sample = clip(i32::from(*sample) + (value >> 3));
+        }
+    }
+}
+
+/// Copy the edges of the macroblock at `(mbx, mby)` into the first row and column of `work`.
+///
+/// Edges outside of the frame are 127 above and 129 left of it.
+fn load_edges(plane: &[u8],
+              stride: usize,
+              size: usize,
+              (mbx, mby): (usize, usize),
+              work: &mut [u8],
+              work_stride: usize) {
+    let (x0, y0) = (mbx * size, mby * size);
+    if mby > 0 {
+        let above = (y0 - 1) * stride + x0;
+        work[1..=size].copy_from_slice(&plane[above..above + size]);
+        work[0] = if mbx > 0 { plane[above - 1] } else { 129 };
+    } else {
+        for sample in work[..work_stride].iter_mut() {
+            *sample = 127;
+        }
+    }
+
+    for row in 0..size {
+        work[(row + 1) * work_stride] = if mbx > 0 {
+            plane[(y0 + row) * stride + x0 - 1]
+        } else {
+            129
+        };
+    }
+}
+
+/// Copy the reconstructed macroblock from `work` into the plane.
+fn store(work: &[u8],
+         work_stride: usize,
+         plane: &mut [u8],
+         stride: usize,
+         size: usize,
+         (mbx, mby): (usize, usize)) {
+    for row in 0..size {
+        let pos = (mby * size + row) * stride + mbx * size;
+        plane[pos..pos + size].copy_from_slice(&work[(row + 1) * work_stride + 1..][..size]);
+    }
+}
+
+/// Predict a whole macroblock, `available` tells whether there are macroblocks left of and
+/// above it.
+fn predict_block(work: &mut [u8],
+                 stride: usize,
+                 size: usize,
+                 mode: u8,
+                 (has_left, has_top): (bool, bool)) {
+    let mut above = [0; 16];
+    above[..size].copy_from_slice(&work[1..=size]);
+    let corner = i32::from(work[0]);
+
+    let shift = size.trailing_zeros();
+    let sum_above: u32 = above[..size].iter().map(|&s| u32::from(s)).sum();
+    let sum_left: u32 = (1..=size).map(|row| u32::from(work[row * stride])).sum();
+    let dc = match (has_left, has_top) {
+        (true, true) => (sum_above + sum_left + size as u32) >> (shift + 1),
+        (false, true) => (sum_above + size as u32 / 2) >> shift,
+        (true, false) => (sum_left + size as u32 / 2) >> shift,
+        (false, false) => 128,
+    } as u8;
+
+    for row in 0..size {
+        let line = (row + 1) * stride;
+        let left = work[line];
+        for (col, &top) in above[..size].iter().enumerate() {
+            work[line + 1 + col] = match mode {
+                V_PRED => top,
+                H_PRED => left,
+                TM_PRED => clip(i32::from(left) + i32::from(top) - corner),
+                _ => dc,
+            };
+        }
+    }
+}
+
+/// Predict the 4x4 subblock at `origin`.
+fn predict_subblock(work: &mut [u8], origin: usize, stride: usize, mode: u8) {
+    let edge = |pos: usize| i32::from(work[pos]);
+    let p = edge(origin - stride - 1);
+    let mut a = [0; 8];
+    for (i, sample) in a.iter_mut().enumerate() {
+        *sample = edge(origin - stride + i);
+    }
+    let l = [edge(origin - 1),
+             edge(origin + stride - 1),
+             edge(origin + 2 * stride - 1),
+             edge(origin + 3 * stride - 1)];
+
+    let rows = match mode {
+        TM_PRED => {
+            let mut rows = [[0; 4]; 4];
+            for (row, &left) in rows.iter_mut().zip(&l) {
+                for (sample, &top) in row.iter_mut().zip(&a) {
+                    *sample = clip(left + top - p);
+                }
+            }
+            rows
+        },
+        V_PRED => {
+            [[avg3(p, a[0], a[1]), avg3(a[0], a[1], a[2]), avg3(a[1], a[2], a[3]),
+              avg3(a[2], a[3], a[4])]; 4]
+        },
+        H_PRED => [[avg3(p, l[0], l[1]); 4],
+                   [avg3(l[0], l[1], l[2]); 4],
+                   [avg3(l[1], l[2], l[3]); 4],
+                   [avg3(l[2], l[3], l[3]); 4]],
+        LD_PRED => {
+            let mut rows = [[0; 4]; 4];
+            for (y, row) in rows.iter_mut().enumerate() {
+                for (x, sample) in row.iter_mut().enumerate() {
+                    let i = x + y;
+                    *sample = avg3(a[i], a[i + 1], a[(i + 2).min(7)]);
+                }
+            }
+            rows
+        },
+        RD_PRED => {
+            let e = [l[3], l[2], l[1], l[0], p, a[0], a[1], a[2], a[3]];
+            let mut rows = [[0; 4]; 4];
+            for (y, row) in rows.iter_mut().enumerate() {
+                for (x, sample) in row.iter_mut().enumerate() {
+                    let i = 4 + x - y;
+                    *sample = avg3(e[i - 1], e[i], e[i + 1]);
+                }
+            }
+            rows
+        },
+        VR_PRED => [[avg2(p, a[0]), avg2(a[0], a[1]), avg2(a[1], a[2]), avg2(a[2], a[3])],
+                    [avg3(l[0], p, a[0]),
+                     avg3(p, a[0], a[1]),
+                     avg3(a[0], a[1], a[2]),
+                     avg3(a[1], a[2], a[3])],
+                    [avg3(l[1], l[0], p), avg2(p, a[0]), avg2(a[0], a[1]), avg2(a[1], a[2])],
+                    [avg3(l[2], l[1], l[0]),
+                     avg3(l[0], p, a[0]),
+                     avg3(p, a[0], a[1]),
+                     avg3(a[0], a[1], a[2])]],
+        VL_PRED => [[avg2(a[0], a[1]), avg2(a[1], a[2]), avg2(a[2], a[3]), avg2(a[3], a[4])],
+                    [avg3(a[0], a[1], a[2]),
+                     avg3(a[1], a[2], a[3]),
+                     avg3(a[2], a[3], a[4]),
+                     avg3(a[3], a[4], a[5])],
+                    [avg2(a[1], a[2]), avg2(a[2], a[3]), avg2(a[3], a[4]), avg3(a[4], a[5], a[6])],
+                    [avg3(a[1], a[2], a[3]),
+                     avg3(a[2], a[3], a[4]),
+                     avg3(a[3], a[4], a[5]),
+                     avg3(a[5], a[6], a[7])]],
+        HD_PRED => [[avg2(l[0], p),
+                     avg3(l[0], p, a[0]),
+                     avg3(p, a[0], a[1]),
+                     avg3(a[0], a[1], a[2])],
+                    [avg2(l[1], l[0]), avg3(l[1], l[0], p), avg2(l[0], p), avg3(l[0], p, a[0])],
+                    [avg2(l[2], l[1]),
+                     avg3(l[2], l[1], l[0]),
+                     avg2(l[1], l[0]),
+                     avg3(l[1], l[0], p)],
+                    [avg2(l[3], l[2]),
+                     avg3(l[3], l[2], l[1]),
+                     avg2(l[2], l[1]),
+                     avg3(l[2], l[1], l[0])]],
+        HU_PRED => {
+            let last = l[3] as u8;
+            [[avg2(l[0], l[1]), avg3(l[0], l[1], l[2]), avg2(l[1], l[2]), avg3(l[1], l[2], l[3])],
+             [avg2(l[1], l[2]), avg3(l[1], l[2], l[3]), avg2(l[2], l[3]), avg3(l[2], l[3], l[3])],
+             [avg2(l[2], l[3]), avg3(l[2], l[3], l[3]), last, last],
+             [last; 4]]
+        },
+        _ => [[((a[..4].iter().sum::<i32>() + l.iter().sum::<i32>() + 4) >> 3) as u8; 4]; 4],
+    };
+
+    for (y, row) in rows.iter().enumerate() {
+        work[origin + y * stride..][..4].copy_from_slice(row);
+    }
+}
+
+/// Interpolate a row of chroma samples to the luma width of `out`, from the `near` row and
+/// the `far` row on the other side of the luma row.
+fn upsample_row(near: &[u8], far: &[u8], out: &mut [u8]) {
+    let width = out.len();
+    let (near, far) = (|x: usize| u32::from(near[x]), |x: usize| u32::from(far[x]));
+    let edge = |x: usize| ((3 * near(x) + far(x) + 2) >> 2) as u8;
+
+    out[0] = edge(0);
+    for x in 1..=(width - 1) / 2 {
+        let sum = near(x - 1) + near(x) + far(x - 1) + far(x) + 8;
+        let left_diagonal = (sum + 2 * (near(x) + far(x - 1))) >> 3;
+        let right_diagonal = (sum + 2 * (near(x - 1) + far(x))) >> 3;
+        out[2 * x - 1] = ((left_diagonal + near(x - 1)) >> 1) as u8;
+        out[2 * x] = ((right_diagonal + near(x)) >> 1) as u8;
+    }
+    if width.is_multiple_of(2) {
+        out[width - 1] = edge((width - 1) / 2);
+    }
+}
+
+/// Convert BT.601 limited range YUV to RGB in 14 bit fixed point like libwebp.
+fn yuv_to_rgb(y: u8, u: u8, v: u8) -> [u8; 3] {
+    let mul = |sample: u8, coeff: i32| (i32::from(sample) * coeff) >> 8;
+    let clip = |value: i32| match value {
+        0..=16383 => (value >> 6) as u8,
+        _ if value < 0 => 0,
+        _ => 255,
+    };
+
+    let luma = mul(y, 19077);
+    [clip(luma + mul(v, 26149) - 14234),
+     clip(luma - mul(u, 6419) - mul(v, 13320) + 8708),
+     clip(luma + mul(u, 33050) - 17685)]
+}
+
+fn avg2(a: i32, b: i32) -> u8 {
+    ((a + b + 1) >> 1) as u8
+}
+
+fn avg3(a: i32, b: i32, c: i32) -> u8 {
+    ((a + 2 * b + c + 2) >> 2) as u8
+}
+
+fn clip(value: i32) -> u8 {
+    value.clamp(0, 255) as u8
+}
+
+
+#[cfg(test)]
+pub(super) mod tests {
+    use super::*;
+
+    /// Boolean entropy encoder of RFC 6386.
+    struct BoolWriter {
+        out: Vec<u8>,
+        range: u32,
+        bottom: u32,
+        bit_count: u32,
+    }
+
+    impl BoolWriter {
+        fn new() -> Self {
+            Self { out: Vec::new(),
+                   range: 255,
+                   bottom: 0,
+                   bit_count: 24 }
+        }
+
+        fn write_bool(&mut self, prob: u8, bit: bool) {
+            let split = 1 + (((self.range - 1) * u32::from(prob)) >> 8);
+            if bit {
+                self.bottom += split;
+                self.range -= split;
+            } else {
+                self.range = split;
+            }
+
+            while self.range < 128 {
+                self.range <<= 1;
+                if self.bottom & 1 << 31 != 0 {
+                    // carry into the bytes already written
+                    for byte in self.out.iter_mut().rev() {
+                        *byte = byte.wrapping_add(1);
+                        if *byte != 0 {
+                            break;
+                        }
+                    }
+                }
+                self.bottom <<= 1;
+                self.bit_count -= 1;
+                if self.bit_count == 0 {
+                    self.out.push((self.bottom >> 24) as u8);
+                    self.bottom &= (1 << 24) - 1;
+                    self.bit_count = 8;
+                }
+            }
+        }
+
+        fn write_literal(&mut self, value: u32, bits: u32) {
+            for i in (0..bits).rev() {
+                self.write_bool(128, value >> i & 1 == 1);
+            }
+        }
+
+        fn finish(mut self) -> Vec<u8> {
+            self.write_literal(0, 32);
+            self.out
+        }
+    }
+
+    /// Key frame of a single macroblock predicted from DC, whose Y2 block holds a DC token of
+    /// one at the coarsest quantizer. All pixels decode to Y = 133 and U = V = 128.
+    pub fn frame(width: u16, height: u16) -> Vec<u8> {
+        assert!(width <= 16 && height <= 16);
+
+        let mut header = BoolWriter::new();
+        // color space, clamping type and segmentation
+        header.write_literal(0, 3);
+        // loop filter type, level, sharpness and deltas
+        header.write_literal(0, 1 + 6 + 3 + 1);
+        // a single token partition
+        header.write_literal(0, 2);
+        // quantizer index without deltas
+        header.write_literal(127, 7);
+        header.write_literal(0, 5);
+        // refresh flag and token probability updates
+        header.write_literal(0, 1);
+        for prob in COEFF_UPDATE_PROBS.iter().flatten().flatten().flatten() {
+            header.write_bool(*prob, false);
+        }
+        // no skip probability
+        header.write_literal(0, 1);
+        // luma and chroma modes
+        header.write_bool(145, true);
+        header.write_bool(156, false);
+        header.write_bool(163, false);
+        header.write_bool(142, false);
+        let header = header.finish();
+
+        let mut tokens = BoolWriter::new();
+        // no end of block, not zero, one, positive and end of block
+        let p = &COEFF_PROBS[Y2][0][0];
+        tokens.write_bool(p[0], true);
+        tokens.write_bool(p[1], true);
+        tokens.write_bool(p[2], false);
+        tokens.write_bool(128, false);
+        tokens.write_bool(COEFF_PROBS[Y2][COEFF_BANDS[1]][1][0], false);
+        for _ in 0..16 {
+            tokens.write_bool(COEFF_PROBS[Y_AFTER_Y2][COEFF_BANDS[1]][0][0], false);
+        }
+        for _ in 0..8 {
+            tokens.write_bool(COEFF_PROBS[CHROMA][0][0][0], false);
+        }
+        let tokens = tokens.finish();
+
+        // shown key frame of version 0
+        let tag = (header.len() as u32) << 5 | 1 << 4;
+        let mut frame = tag.to_le_bytes()[..3].to_vec();
+        frame.extend_from_slice(&[0x9d, 0x01, 0x2a]);
+        frame.extend_from_slice(&width.to_le_bytes());
+        frame.extend_from_slice(&height.to_le_bytes());
+        frame.extend_from_slice(&header);
+        frame.extend_from_slice(&tokens);
+        frame
+    }
+
+    #[test]
+    fn decode_key_frame() {
+        let image = decode(&frame(16, 11)).unwrap();
+        assert_eq!(image.dimensions(), (16, 11));
+        assert!(image.pixels().all(|p| p.0 == [136, 136, 136, 255]));
+    }
+
+    #[test]
+    fn reject_invalid_frames() {
+        let mut inter = frame(16, 16);
+        inter[0] |= 1;
+        assert!(decode(&inter).is_err());
+
+        let mut empty = frame(16, 16);
+        empty[6..10].copy_from_slice(&[0, 0, 0, 0]);
+        assert!(decode(&empty).is_err());
+
+        assert!(decode(&frame(16, 16)[..12]).is_err());
+    }
+
+    #[test]
+    fn predict_subblocks() {
+        // edges left of and above a subblock at (1, 1), top right samples included
+        let stride = 9;
+        let mut work = [0; 9 * 5];
+        work[..9].copy_from_slice(&[10, 20, 30, 40, 50, 60, 70, 80, 90]);
+        for (row, &left) in [30, 40, 50, 60].iter().enumerate() {
+            work[(row + 1) * stride] = left;
+        }
+        let block = |work: &[u8]| -> Vec<u8> {
+            (0..4).flat_map(|y| work[(y + 1) * stride + 1..][..4].to_vec()).collect()
+        };
+
+        predict_subblock(&mut work, stride + 1, stride, DC_PRED);
+        assert_eq!(block(&work), vec![40; 16]);
+        predict_subblock(&mut work, stride + 1, stride, TM_PRED);
+        assert_eq!(block(&work)[..8], [40, 50, 60, 70, 50, 60, 70, 80]);
+        predict_subblock(&mut work, stride + 1, stride, VL_PRED);
+        assert_eq!(block(&work)[..4], [25, 35, 45, 55]);
+        assert_eq!(block(&work)[12..], [40, 50, 60, 80]);
+        predict_subblock(&mut work, stride + 1, stride, HU_PRED);
+        assert_eq!(block(&work)[12..], [60; 4]);
+    }
+
+    #[test]
+    fn convert_yuv() {
+        assert_eq!(yuv_to_rgb(16, 128, 128), [0, 0, 0]);
+        assert_eq!(yuv_to_rgb(235, 128, 128), [255, 255, 255]);
+        assert_eq!(yuv_to_rgb(81, 90, 240), [254, 0, 0]);
+    }
+}